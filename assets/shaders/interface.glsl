@@ -0,0 +1,145 @@
+// Generated by build.rs from src/back/interface.rs, do not edit
+
+#define OBJECT_TYPE_SPHERE 1u
+#define OBJECT_TYPE_SDF 2u
+#define SDF_SHAPE_TORUS 1u
+#define SDF_SHAPE_GYROID 2u
+#define SDF_SHAPE_MANDELBULB 3u
+#define LIGHT_TYPE_POINT 1u
+#define LIGHT_TYPE_DIRECTIONAL 2u
+#define LIGHT_TYPE_AREA 3u
+#define NO_TEXTURE 4294967295u
+#define NO_OBJECT 4294967295u
+#define NO_PIXEL 4294967295u
+#define HISTOGRAM_BINS 256u
+#define MAX_PATH_VERTICES 16
+#define TILE_SIZE 16
+
+struct Config
+{
+    mat4 camera_transform;
+    mat4 prev_camera_transform;
+    float camera_fov;
+    uint objects_count;
+    uint samples_count;
+    uint max_bounces;
+    vec4 sky_color_top;
+    vec4 sky_color_bottom;
+    vec4 ground_color;
+    uint blue_noise;
+    uint blue_noise_texture;
+    float camera_aperture;
+    float camera_focus_distance;
+    uint lights_count;
+    uint next_event_estimation;
+    uint instances_count;
+    uint volumes_count;
+    float volumes_majorant;
+    uint environment;
+    uint environment_texture;
+    uint environment_width;
+    uint environment_height;
+    float environment_intensity;
+    uint temporal_accumulation;
+    float jitter_strength;
+    uint converged_samples;
+};
+
+struct Object
+{
+    uint object_type;
+    uint material_index;
+    uint instanced;
+    uint sdf_shape;
+    vec4 data1;
+    vec4 data2;
+    uvec4 textures;
+};
+
+struct Instance
+{
+    uint object_index;
+    mat4 world_to_object;
+};
+
+struct Volume
+{
+    vec4 box_min;
+    vec4 box_max;
+    vec4 extinction;
+    vec4 scattering;
+};
+
+struct Material
+{
+    vec4 albedo;
+    vec4 emission;
+};
+
+struct Light
+{
+    uint light_type;
+    vec4 color;
+    vec4 data1;
+    vec4 data2;
+    vec4 data3;
+};
+
+struct path_vertex_s
+{
+    vec4 origin;
+    vec4 direction;
+    vec4 point;
+    vec4 normal;
+    vec4 albedo;
+    vec4 emission;
+    vec4 throughput;
+    vec4 radiance;
+    uvec4 info;
+};
+
+struct PickedPixel
+{
+    float depth;
+    uint vertex_count;
+    uvec2 pixel;
+    uint object;
+    path_vertex_s vertices[16];
+};
+
+struct InvalidPixels
+{
+    uint nan_count;
+    uint inf_count;
+    uint first_pixel;
+};
+
+struct TileQueue
+{
+    uint groups_x;
+    uint groups_y;
+    uint groups_z;
+};
+
+struct Exposure
+{
+    float exposure;
+    uint initialized;
+};
+
+struct Constants
+{
+    uint frame_index;
+    uint accumulated_samples;
+    uint seed;
+    uint invalidate;
+    uint reproject;
+    int pick_x;
+    int pick_y;
+    int region_x;
+    int region_y;
+    uint image_width;
+    uint image_height;
+    uint frame_width;
+    uint frame_height;
+};
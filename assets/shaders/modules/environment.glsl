@@ -0,0 +1,94 @@
+// Sky and the environment map with its importance sampling
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+vec3 sky_color(vec3 direction)
+{
+    float t = 0.5 * (direction.y + 1.0);
+    vec3 sky = mix(in_config.sky_color_bottom.rgb, in_config.sky_color_top.rgb, t);
+
+    float ground_to_sky = smoothstep(-0.1, 0.1, direction.y);
+    vec3 ground = in_config.ground_color.rgb;
+
+    return mix(ground, sky, ground_to_sky);
+}
+
+// Equirectangular mapping of the environment, +Y is up
+vec2 environment_uv(vec3 direction)
+{
+    float phi = atan(direction.z, direction.x);
+    float theta = acos(clamp(direction.y, -1.0, 1.0));
+    return vec2(phi / (2.0 * PI) + 0.5, theta / PI);
+}
+
+vec3 environment_direction(vec2 uv)
+{
+    float phi = (uv.x - 0.5) * 2.0 * PI;
+    float theta = uv.y * PI;
+    return vec3(sin(theta) * cos(phi), cos(theta), sin(theta) * sin(phi));
+}
+
+vec3 environment_radiance(vec3 direction)
+{
+    vec2 uv = environment_uv(direction);
+    return textureLod(textures[in_config.environment_texture], uv, 0.0).rgb * in_config.environment_intensity;
+}
+
+// Probability of the bin of the CDF starting at the offset
+float environment_bin(uint offset, uint index)
+{
+    return environment_cdf[offset + index] - (index > 0u ? environment_cdf[offset + index - 1u] : 0.0);
+}
+
+// First bin of the CDF starting at the offset whose value exceeds xi
+uint environment_search(uint offset, uint count, float xi)
+{
+    uint low = 0u;
+    uint high = count - 1u;
+    while (low < high)
+    {
+        uint middle = (low + high) / 2u;
+        if (environment_cdf[offset + middle] > xi)
+        {
+            high = middle;
+        }
+        else
+        {
+            low = middle + 1u;
+        }
+    }
+    return low;
+}
+
+// Solid angle density of the texel at uv picked by sample_environment
+float environment_texel_pdf(uint x, uint y, vec2 uv)
+{
+    uint width = in_config.environment_width;
+    uint height = in_config.environment_height;
+    float sine = sin(uv.y * PI);
+    if (sine <= 0.0)
+    {
+        return 0.0;
+    }
+    float pdf_uv = environment_bin(0u, y) * environment_bin(height + y * width, x) * float(width * height);
+    return pdf_uv / (2.0 * PI * PI * sine);
+}
+
+float environment_pdf(vec3 direction)
+{
+    vec2 uv = environment_uv(direction);
+    uint x = min(uint(uv.x * float(in_config.environment_width)), in_config.environment_width - 1u);
+    uint y = min(uint(uv.y * float(in_config.environment_height)), in_config.environment_height - 1u);
+    return environment_texel_pdf(x, y, uv);
+}
+
+// Direction towards the environment, bright texels are picked more often
+vec3 sample_environment(inout uint seed, out float pdf)
+{
+    uint width = in_config.environment_width;
+    uint height = in_config.environment_height;
+    uint y = environment_search(0u, height, rand(seed));
+    uint x = environment_search(height + y * width, width, rand(seed));
+    vec2 uv = (vec2(x, y) + vec2(rand(seed), rand(seed))) / vec2(width, height);
+    pdf = environment_texel_pdf(x, y, uv);
+    return environment_direction(uv);
+}
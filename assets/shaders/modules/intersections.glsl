@@ -0,0 +1,248 @@
+// Ray intersections with the spheres, SDF objects and instances
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+// Sphere tracing limits, the surface is hit closer than the epsilon
+#define SDF_MAX_STEPS 256
+#define SDF_EPSILON 0.0002
+
+float hits_sphere(vec3 center, float radius, vec3 ray_origin, vec3 ray_direction, minmax_s bounds)
+{
+    vec3 oc = ray_origin - center;
+    float a = dot(ray_direction, ray_direction);
+    float b = 2.0 * dot(oc, ray_direction);
+    float c = dot(oc, oc) - radius * radius;
+    float discriminant = b * b - 4.0 * a * c;
+
+    // No intersection
+    if (discriminant < 0.0)
+    {
+        return -1.0;
+    }
+
+    // Find the nearest t that is in front of the ray
+    float t1 = (-b - sqrt(discriminant)) / (2.0 * a);
+    if (t1 > bounds.min && t1 < bounds.max)
+    {
+        return t1;
+    }
+
+    float t2 = (-b + sqrt(discriminant)) / (2.0 * a);
+    if (t2 > bounds.min && t2 < bounds.max)
+    {
+        return t2;
+    }
+
+    return -1.0;
+}
+
+float sdf_torus(vec3 p, float major_radius, float minor_radius)
+{
+    vec2 q = vec2(length(p.xz) - major_radius, p.y);
+    return length(q) - minor_radius;
+}
+
+float sdf_gyroid(vec3 p, float scale, float thickness, float radius)
+{
+    vec3 q = p * scale * 2.0 * PI;
+    // Not an exact distance, scaled down so that the tracing does not overshoot
+    float gyroid = (abs(dot(sin(q), cos(q.zxy))) / (scale * 2.0 * PI) - thickness) * 0.5;
+    return max(gyroid, length(p) - radius);
+}
+
+float sdf_mandelbulb(vec3 p, float power, int iterations)
+{
+    vec3 z = p;
+    float dr = 1.0;
+    float r = length(z);
+    for (int i = 0; i < iterations && r < 2.0 && r > 1e-6; i++)
+    {
+        float theta = acos(clamp(z.z / r, -1.0, 1.0)) * power;
+        float phi = atan(z.y, z.x) * power;
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+        z = pow(r, power) * vec3(sin(theta) * cos(phi), sin(phi) * sin(theta), cos(theta)) + p;
+        r = length(z);
+    }
+    return 0.5 * log(max(r, 1e-6)) * r / dr;
+}
+
+// Distance from the point relative to the object center
+float sdf_evaluate(Object obj, vec3 p)
+{
+    vec4 parameters = obj.data2;
+    if (obj.sdf_shape == SDF_SHAPE_TORUS)
+    {
+        return sdf_torus(p, parameters.x, parameters.y);
+    }
+    if (obj.sdf_shape == SDF_SHAPE_GYROID)
+    {
+        return sdf_gyroid(p, parameters.x, parameters.y, parameters.z);
+    }
+    if (obj.sdf_shape == SDF_SHAPE_MANDELBULB)
+    {
+        float radius = parameters.z;
+        return sdf_mandelbulb(p / radius, parameters.x, int(parameters.y)) * radius;
+    }
+    return 1e20;
+}
+
+vec3 sdf_normal(Object obj, vec3 p)
+{
+    const vec2 e = vec2(SDF_EPSILON, 0.0);
+    return normalize(vec3(
+        sdf_evaluate(obj, p + e.xyy) - sdf_evaluate(obj, p - e.xyy),
+        sdf_evaluate(obj, p + e.yxy) - sdf_evaluate(obj, p - e.yxy),
+        sdf_evaluate(obj, p + e.yyx) - sdf_evaluate(obj, p - e.yyx)
+    ));
+}
+
+// Sphere traces the surface within its bounding sphere
+float hits_sdf(Object obj, vec3 ray_origin, vec3 ray_direction, minmax_s bounds)
+{
+    vec3 center = obj.data1.xyz;
+    float bounding_radius = obj.data1.w;
+
+    // Marched in unit steps, the instanced rays are not normalized
+    float scale = length(ray_direction);
+    vec3 direction = ray_direction / scale;
+
+    vec3 oc = ray_origin - center;
+    float b = dot(oc, direction);
+    float c = dot(oc, oc) - bounding_radius * bounding_radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0)
+    {
+        return -1.0;
+    }
+
+    float t = max(-b - sqrt(discriminant), bounds.min * scale);
+    float t_max = min(-b + sqrt(discriminant), bounds.max * scale);
+    for (int i = 0; i < SDF_MAX_STEPS && t < t_max; i++)
+    {
+        float distance = sdf_evaluate(obj, oc + t * direction);
+        // Scattered rays start on the surface, they have to leave it first
+        if (distance < SDF_EPSILON && i > 0)
+        {
+            return t / scale;
+        }
+        t += max(distance, SDF_EPSILON);
+    }
+
+    return -1.0;
+}
+
+void set_face_normal(inout hit_s hit, vec3 ray_direction, vec3 outward_normal)
+{
+    hit.front_face = dot(ray_direction, outward_normal) < 0.0;
+    hit.normal = hit.front_face ? outward_normal : -outward_normal;
+}
+
+bool hits_object(Object obj, vec3 ray_origin, vec3 ray_direction, minmax_s bounds, out hit_s hit)
+{
+    if (obj.object_type == OBJECT_TYPE_SPHERE)
+    {
+        vec3 center = obj.data1.xyz;
+        float radius = obj.data2.x;
+
+        float t = hits_sphere(center, radius, ray_origin, ray_direction, bounds);
+        if (t > 0.0)
+        {
+            hit.t = t;
+            hit.point = ray_origin + t * ray_direction;
+            hit.normal = normalize(hit.point - center);
+            set_material_properties(hit, obj);
+            set_sphere_textures(hit, obj);
+            set_face_normal(hit, ray_direction, hit.normal);
+            return true;
+        }
+    }
+    else if (HAS_SDF && obj.object_type == OBJECT_TYPE_SDF)
+    {
+        float t = hits_sdf(obj, ray_origin, ray_direction, bounds);
+        if (t > 0.0)
+        {
+            hit.t = t;
+            hit.point = ray_origin + t * ray_direction;
+            hit.normal = sdf_normal(obj, hit.point - obj.data1.xyz);
+            set_material_properties(hit, obj);
+            set_face_normal(hit, ray_direction, hit.normal);
+            return true;
+        }
+    }
+
+    return false;
+}
+
+// The ray is moved into the object space of the instance. The direction
+// is not normalized there, so the ray distance stays the same.
+bool hits_instance(Instance instance, vec3 ray_origin, vec3 ray_direction, minmax_s bounds, out hit_s hit)
+{
+    vec3 local_origin = (instance.world_to_object * vec4(ray_origin, 1.0)).xyz;
+    vec3 local_direction = mat3(instance.world_to_object) * ray_direction;
+    if (!hits_object(objects[instance.object_index], local_origin, local_direction, bounds, hit))
+    {
+        return false;
+    }
+
+    hit.point = ray_origin + hit.t * ray_direction;
+    hit.normal = normalize(transpose(mat3(instance.world_to_object)) * hit.normal);
+    return true;
+}
+
+bool hits_world(vec3 ray_origin, vec3 ray_direction, minmax_s bounds, out hit_s hit)
+{
+    bool hit_anything = false;
+
+    for (int i = 0; i < int(in_config.objects_count); i++)
+    {
+        hit_s temp_hit;
+        if (objects[i].instanced == 0u && hits_object(objects[i], ray_origin, ray_direction, bounds, temp_hit))
+        {
+            hit = temp_hit;
+            hit.object_index = uint(i);
+            hit_anything = true;
+            bounds.max = temp_hit.t;
+        }
+    }
+
+    for (int i = 0; i < int(in_config.instances_count); i++)
+    {
+        hit_s temp_hit;
+        if (hits_instance(instances[i], ray_origin, ray_direction, bounds, temp_hit))
+        {
+            hit = temp_hit;
+            hit.object_index = instances[i].object_index;
+            hit_anything = true;
+            bounds.max = temp_hit.t;
+        }
+    }
+
+    return hit_anything;
+}
+
+// Whether anything lies between the origin and the given distance
+bool occluded(vec3 ray_origin, vec3 ray_direction, float max_distance)
+{
+    minmax_s bounds;
+    bounds.min = 0.001;
+    bounds.max = max_distance;
+
+    for (int i = 0; i < int(in_config.objects_count); i++)
+    {
+        hit_s temp_hit;
+        if (objects[i].instanced == 0u && hits_object(objects[i], ray_origin, ray_direction, bounds, temp_hit))
+        {
+            return true;
+        }
+    }
+
+    for (int i = 0; i < int(in_config.instances_count); i++)
+    {
+        hit_s temp_hit;
+        if (hits_instance(instances[i], ray_origin, ray_direction, bounds, temp_hit))
+        {
+            return true;
+        }
+    }
+
+    return false;
+}
@@ -0,0 +1,113 @@
+// Next event estimation towards the lights and the environment
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+// Direction, distance and unoccluded irradiance of a single light sample
+bool sample_light(Light light, vec3 origin, inout uint seed, out vec3 to_light, out float light_distance, out vec3 irradiance)
+{
+    vec3 emitted = light.color.rgb * light.color.w;
+    if (light.light_type == LIGHT_TYPE_POINT)
+    {
+        vec3 offset = light.data1.xyz - origin;
+        light_distance = length(offset);
+        to_light = offset / light_distance;
+        irradiance = emitted / (light_distance * light_distance);
+        return true;
+    }
+    if (light.light_type == LIGHT_TYPE_DIRECTIONAL)
+    {
+        to_light = -light.data1.xyz;
+        light_distance = 1e20;
+        irradiance = emitted;
+        return true;
+    }
+    if (light.light_type == LIGHT_TYPE_AREA)
+    {
+        // Single uniform sample of the parallelogram
+        vec3 target = light.data1.xyz + rand(seed) * light.data2.xyz + rand(seed) * light.data3.xyz;
+        vec3 offset = target - origin;
+        light_distance = length(offset);
+        to_light = offset / light_distance;
+
+        vec3 light_normal = cross(light.data2.xyz, light.data3.xyz);
+        float area = length(light_normal);
+        float light_cosine = abs(dot(light_normal / area, to_light));
+        irradiance = emitted * light_cosine * area / (light_distance * light_distance);
+        return true;
+    }
+    return false;
+}
+
+// Fraction of the light reaching the origin: blocked by objects, attenuated by media
+vec3 visibility(vec3 origin, vec3 direction, float max_distance)
+{
+    if (occluded(origin, direction, max_distance))
+    {
+        return vec3(0.0);
+    }
+    return HAS_VOLUMES && in_config.volumes_count > 0u ? transmittance(origin, direction, max_distance) : vec3(1.0);
+}
+
+// Next event estimation: radiance reflected by the hit towards the ray,
+// gathered from every light with a shadow ray.
+// Uses the same diffuse BRDF as the scattering, albedo / (2 * PI).
+vec3 sample_lights(hit_s hit, inout uint seed)
+{
+    vec3 radiance = vec3(0.0);
+    vec3 brdf = hit.material.albedo / (2.0 * PI);
+    vec3 origin = hit.point + 0.001 * hit.normal;
+
+    for (uint i = 0u; i < in_config.lights_count; i++)
+    {
+        vec3 to_light;
+        float light_distance;
+        vec3 irradiance;
+        if (!sample_light(lights[i], origin, seed, to_light, light_distance, irradiance))
+        {
+            continue;
+        }
+
+        float cosine = dot(hit.normal, to_light);
+        if (cosine <= 0.0)
+        {
+            continue;
+        }
+        radiance += brdf * irradiance * cosine * visibility(origin, to_light, light_distance);
+    }
+
+    if (HAS_ENVIRONMENT && in_config.environment != 0u)
+    {
+        // Weighted against the scattered ray escaping to the environment
+        float pdf;
+        vec3 to_environment = sample_environment(seed, pdf);
+        float cosine = dot(hit.normal, to_environment);
+        if (pdf > 0.0 && cosine > 0.0)
+        {
+            float weight = mis_weight(pdf, SCATTER_PDF);
+            radiance += brdf * environment_radiance(to_environment) * cosine * weight / pdf
+                * visibility(origin, to_environment, 1e20);
+        }
+    }
+
+    return radiance;
+}
+
+// Next event estimation inside a medium, the phase function replaces the BRDF
+vec3 sample_lights_medium(vec3 point, vec3 direction, float anisotropy, inout uint seed)
+{
+    vec3 radiance = vec3(0.0);
+    for (uint i = 0u; i < in_config.lights_count; i++)
+    {
+        vec3 to_light;
+        float light_distance;
+        vec3 irradiance;
+        if (!sample_light(lights[i], point, seed, to_light, light_distance, irradiance))
+        {
+            continue;
+        }
+
+        float phase = henyey_greenstein(dot(direction, to_light), anisotropy);
+        radiance += phase * irradiance * visibility(point, to_light, light_distance);
+    }
+
+    return radiance;
+}
@@ -0,0 +1,44 @@
+// Surface properties of the hits from the materials and textures
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+void set_material_properties(inout hit_s hit, Object obj)
+{
+    Material material = materials[obj.material_index];
+    hit.material.albedo = material.albedo.rgb;
+    hit.material.emission_color = material.emission.rgb;
+    hit.material.emission_strength = material.emission.w;
+    hit.material.roughness = 1.0;
+}
+
+// Samples the textures of the sphere at the point with the given outward normal
+// and perturbs the normal by the normal map. Same mapping as the environment.
+void set_sphere_textures(inout hit_s hit, Object obj)
+{
+    if (obj.textures == uvec4(NO_TEXTURE))
+    {
+        return;
+    }
+
+    vec3 normal = hit.normal;
+    vec2 uv = vec2(atan(normal.z, normal.x) / (2.0 * PI) + 0.5, acos(clamp(normal.y, -1.0, 1.0)) / PI);
+    if (obj.textures.x != NO_TEXTURE)
+    {
+        // Albedo textures are stored in sRGB
+        vec3 albedo = textureLod(textures[nonuniformEXT(obj.textures.x)], uv, 0.0).rgb;
+        hit.material.albedo *= pow(albedo, vec3(2.2));
+    }
+    if (obj.textures.y != NO_TEXTURE)
+    {
+        // Tangent along the increasing u, bitangent along the increasing v
+        vec3 tangent = vec3(-normal.z, 0.0, normal.x);
+        tangent = length(tangent) > 1e-6 ? normalize(tangent) : vec3(0.0, 0.0, 1.0);
+        vec3 bitangent = cross(normal, tangent);
+        vec3 local = textureLod(textures[nonuniformEXT(obj.textures.y)], uv, 0.0).xyz * 2.0 - 1.0;
+        // Green points up in the image, towards the decreasing v
+        hit.normal = normalize(local.x * tangent - local.y * bitangent + local.z * normal);
+    }
+    if (obj.textures.z != NO_TEXTURE)
+    {
+        hit.material.roughness = textureLod(textures[nonuniformEXT(obj.textures.z)], uv, 0.0).r;
+    }
+}
@@ -0,0 +1,131 @@
+// Participating media: transmittance, delta tracking and the phase function
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+// Upper bound of the delta tracking steps through the media
+#define MEDIUM_MAX_STEPS 256
+
+// Overlap of the ray with the box as [t0, t1], empty if t0 >= t1
+vec2 intersect_box(vec3 origin, vec3 direction, vec3 box_min, vec3 box_max)
+{
+    vec3 inverse = 1.0 / direction;
+    vec3 a = (box_min - origin) * inverse;
+    vec3 b = (box_max - origin) * inverse;
+    vec3 near = min(a, b);
+    vec3 far = max(a, b);
+    return vec2(max(max(near.x, near.y), near.z), min(min(far.x, far.y), far.z));
+}
+
+// Coefficients of the media at the point, overlapping volumes add up.
+// The anisotropy is taken from the first volume.
+void medium_at(vec3 point, out vec3 extinction, out vec3 scattering, out float anisotropy)
+{
+    extinction = vec3(0.0);
+    scattering = vec3(0.0);
+    anisotropy = 0.0;
+    bool first = true;
+    for (uint i = 0u; i < in_config.volumes_count; i++)
+    {
+        Volume volume = volumes[i];
+        if (all(greaterThanEqual(point, volume.box_min.xyz)) && all(lessThanEqual(point, volume.box_max.xyz)))
+        {
+            extinction += volume.extinction.rgb;
+            scattering += volume.scattering.rgb;
+            if (first)
+            {
+                anisotropy = volume.box_min.w;
+                first = false;
+            }
+        }
+    }
+}
+
+// Fraction of the light passing through the media along the segment
+vec3 transmittance(vec3 origin, vec3 direction, float max_t)
+{
+    vec3 optical_depth = vec3(0.0);
+    for (uint i = 0u; i < in_config.volumes_count; i++)
+    {
+        Volume volume = volumes[i];
+        vec2 span = intersect_box(origin, direction, volume.box_min.xyz, volume.box_max.xyz);
+        span = vec2(max(span.x, 0.0), min(span.y, max_t));
+        if (span.y > span.x)
+        {
+            optical_depth += volume.extinction.rgb * (span.y - span.x);
+        }
+    }
+    return exp(-optical_depth);
+}
+
+// Delta tracking against the majorant of all volumes. Returns true with the
+// scattering point if the ray scatters before max_t. The chromatic
+// coefficients are handled by weighting the throughput (spectral tracking).
+bool sample_medium(vec3 origin, vec3 direction, float max_t, inout uint seed, inout vec3 throughput, out vec3 point, out float anisotropy)
+{
+    // Only the span covered by the volumes is tracked
+    float t = max_t;
+    float t_exit = 0.0;
+    for (uint i = 0u; i < in_config.volumes_count; i++)
+    {
+        vec2 span = intersect_box(origin, direction, volumes[i].box_min.xyz, volumes[i].box_max.xyz);
+        if (span.y > max(span.x, 0.0))
+        {
+            t = min(t, max(span.x, 0.0));
+            t_exit = max(t_exit, span.y);
+        }
+    }
+    max_t = min(max_t, t_exit);
+
+    float majorant = in_config.volumes_majorant;
+    for (int i = 0; i < MEDIUM_MAX_STEPS; i++)
+    {
+        t -= log(1.0 - rand(seed)) / majorant;
+        if (t >= max_t)
+        {
+            return false;
+        }
+
+        point = origin + t * direction;
+        vec3 extinction;
+        vec3 scattering;
+        medium_at(point, extinction, scattering, anisotropy);
+        float average = (extinction.r + extinction.g + extinction.b) / 3.0;
+        if (rand(seed) * majorant < average)
+        {
+            // Real collision, absorption is accounted for by the weight
+            throughput *= scattering / average;
+            return true;
+        }
+        // Null collision
+        throughput *= (majorant - extinction) / max(majorant - average, 1e-6);
+    }
+
+    return false;
+}
+
+float henyey_greenstein(float cosine, float g)
+{
+    float denominator = 1.0 + g * g - 2.0 * g * cosine;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// Direction scattered by the phase function, g > 0 scatters forward
+vec3 sample_henyey_greenstein(vec3 direction, float g, inout uint seed)
+{
+    float xi = rand(seed);
+    float cosine;
+    if (abs(g) < 1e-3)
+    {
+        cosine = 1.0 - 2.0 * xi;
+    }
+    else
+    {
+        float square = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        cosine = (1.0 + g * g - square * square) / (2.0 * g);
+    }
+    float sine = sqrt(max(0.0, 1.0 - cosine * cosine));
+    float phi = 2.0 * PI * rand(seed);
+
+    vec3 u = normalize(cross(abs(direction.x) > 0.9 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0), direction));
+    vec3 v = cross(direction, u);
+    return normalize(sine * cos(phi) * u + sine * sin(phi) * v + cosine * direction);
+}
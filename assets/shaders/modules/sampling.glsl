@@ -0,0 +1,78 @@
+// Random numbers, sampling of directions and blue noise
+// Part of shader.comp, relies on its bindings and the modules included before it
+
+#define PI 3.14159265359
+// Solid angle density of the uniform hemisphere scattering
+#define SCATTER_PDF (1.0 / (2.0 * PI))
+
+// Returns a random float in [0,1)
+// PCG (Permuted Congruential Generator) algorithm
+float rand(inout uint seed)
+{
+    seed = seed * 747796405u + 2891336453u;
+    uint word = ((seed >> ((seed >> 28u) + 4u)) ^ seed) * 277803737u;
+    word = (word >> 22u) ^ word;
+    return float(word) / 4294967296.0;
+}
+
+float rand_normal(inout uint seed)
+{
+    const float mean = 0.0;
+    const float stddev = 1.0;
+
+    float theta = 2.0 * 3.14159265359 * rand(seed);
+    float u = clamp(rand(seed), 1e-7, 1.0 - 1e-7);
+    float rho = sqrt(-2.0 * log(1.0 - u));
+    return mean + stddev * rho * cos(theta);
+}
+
+vec3 rand_normal_vec3(inout uint seed)
+{
+    return normalize(vec3(
+        rand_normal(seed),
+        rand_normal(seed),
+        rand_normal(seed)
+    ));
+}
+
+// Uniformly distributed point in the unit disk
+vec2 rand_disk(inout uint seed)
+{
+    float r = sqrt(rand(seed));
+    float theta = 2.0 * PI * rand(seed);
+    return r * vec2(cos(theta), sin(theta));
+}
+
+vec3 rand_hemisphere(vec3 normal, inout uint seed)
+{
+    vec3 in_unit_sphere = rand_normal_vec3(seed);
+    return sign(dot(in_unit_sphere, normal)) * in_unit_sphere;
+}
+
+// Same distribution as rand_hemisphere, but from a given pair of uniform values
+vec3 sample_hemisphere(vec3 normal, vec2 xi)
+{
+    float z = 1.0 - 2.0 * xi.x;
+    float r = sqrt(max(0.0, 1.0 - z * z));
+    float phi = 2.0 * PI * xi.y;
+    vec3 on_unit_sphere = vec3(r * cos(phi), r * sin(phi), z);
+    return sign(dot(on_unit_sphere, normal)) * on_unit_sphere;
+}
+
+// Four uniform values of the pixel for the given sample.
+// The tiled blue noise is shifted by the R1 sequence for each sample,
+// which keeps the error blue across frames. Fixed-point golden ratio
+// is used to not lose precision for the large sample indices.
+vec4 blue_noise(ivec2 pixel_coords, uint sample_index)
+{
+    ivec2 size = textureSize(textures[in_config.blue_noise_texture], 0);
+    vec4 noise = texelFetch(textures[in_config.blue_noise_texture], pixel_coords % size, 0);
+    float shift = float((sample_index * 2654435769u) >> 8u) / 16777216.0;
+    return fract(noise + shift);
+}
+
+// Power heuristic of multiple importance sampling
+float mis_weight(float pdf, float other_pdf)
+{
+    return pdf * pdf / max(pdf * pdf + other_pdf * other_pdf, 1e-12);
+}
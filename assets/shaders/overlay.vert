@@ -0,0 +1,17 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec4 in_color;
+
+layout (push_constant) uniform constants
+{
+    // World to clip space, the same mapping the tracer uses for the rays
+    mat4 view_projection;
+} in_overlay;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+    gl_Position = in_overlay.view_projection * vec4(in_position, 1.0);
+    color = in_color;
+}
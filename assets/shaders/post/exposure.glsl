@@ -0,0 +1,34 @@
+// Shared by the passes of the auto exposure, see src/back/exposure.rs
+
+// Structs and constants shared with the host, see src/back/interface.rs
+#include "../interface.glsl"
+
+// Range of log2 luminance the histogram covers. The first bin counts the
+// pixels too dark to matter and is ignored, brighter ones than the range
+// fall into the last bin.
+const float HISTOGRAM_MIN_LOG = -12.0;
+const float HISTOGRAM_MAX_LOG = 8.0;
+// Fractions of the darkest and brightest pixels left out of the average,
+// so a small light or a dark corner does not swing the exposure
+const float HISTOGRAM_LOW = 0.1;
+const float HISTOGRAM_HIGH = 0.9;
+// Luminance the average of the image is mapped to
+const float MIDDLE_GREY = 0.18;
+
+layout (std430, set = 0, binding = 3) buffer exposure
+{
+    Exposure state;
+    uint bins[];
+};
+
+float luminance(vec3 color)
+{
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Log2 luminance at the center of the bin
+float bin_log(uint bin)
+{
+    float t = (float(bin) - 0.5) / float(HISTOGRAM_BINS - 1u);
+    return HISTOGRAM_MIN_LOG + t * (HISTOGRAM_MAX_LOG - HISTOGRAM_MIN_LOG);
+}
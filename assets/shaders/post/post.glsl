@@ -0,0 +1,20 @@
+// Shared by the post-processing passes, see src/back/post.rs. Every pass
+// reads the image of the previous one and writes every pixel of the next.
+
+layout (local_size_x = 16, local_size_y = 16) in;
+layout (set = 0, binding = 0, rgba32f) uniform readonly image2D source_image;
+layout (set = 0, binding = 1, rgba32f) uniform writeonly image2D target_image;
+// Binding 2 is the bloom image, declared by the bloom composite only, and
+// binding 3 the auto exposure, see exposure.glsl
+
+// Settings of the pass, their meaning is up to the shader
+layout (push_constant) uniform constants
+{
+    vec4 params;
+} in_pass;
+
+// Out of bounds coordinates read the nearest edge pixel
+vec4 load_clamped(ivec2 coords)
+{
+    return imageLoad(source_image, clamp(coords, ivec2(0), imageSize(source_image) - 1));
+}
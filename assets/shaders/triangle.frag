@@ -1,18 +1,66 @@
 #version 450
 
 layout (set=0, binding = 0, rgba32f) uniform readonly image2D img;
+// Image traced with the compared settings, the main one again without
+layout (set=1, binding = 0, rgba32f) uniform readonly image2D compare_img;
+
+layout (push_constant) uniform constants
+{
+    // Normalized position of the A/B split, 1 shows only the main image
+    float divider;
+    // Set for UNORM swapchains, SRGB ones encode on store
+    uint encode_srgb;
+} in_split;
 
 layout(location = 0) out vec4 out_color;
 layout(location = 0) in vec2 uv;
 
+// Both images have the same size
+vec4 load(bool compare, ivec2 coords)
+{
+    ivec2 clamped = clamp(coords, ivec2(0), imageSize(img) - 1);
+    return compare ? imageLoad(compare_img, clamped) : imageLoad(img, clamped);
+}
+
+// Bilinear upscale, the tracer may render at a reduced internal resolution.
+// At the full resolution samples land on texel centers and it's a plain load.
+vec4 load_bilinear(bool compare, vec2 uv)
+{
+    vec2 coords = uv * vec2(imageSize(img)) - 0.5;
+    ivec2 base = ivec2(floor(coords));
+    vec2 f = fract(coords);
+
+    vec4 c00 = load(compare, base);
+    vec4 c10 = load(compare, base + ivec2(1, 0));
+    vec4 c01 = load(compare, base + ivec2(0, 1));
+    vec4 c11 = load(compare, base + ivec2(1, 1));
+    return mix(mix(c00, c10, f.x), mix(c01, c11, f.x), f.y);
+}
+
+// Same as common::color::linear_to_srgb
+vec3 linear_to_srgb(vec3 color)
+{
+    color = clamp(color, 0.0, 1.0);
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
 void main() {
-    ivec2 img_size = imageSize(img);
-    ivec2 pixel_coords = ivec2(uv * vec2(img_size));
+    vec4 pixel_color = load_bilinear(uv.x >= in_split.divider, uv);
 
-    vec4 pixel_color = imageLoad(img, pixel_coords);
+    // The image is linear, encoded exactly once: here or by the swapchain
+    if (in_split.encode_srgb != 0u)
+    {
+        pixel_color.rgb = linear_to_srgb(pixel_color.rgb);
+    }
 
-    // Simple gamma correction
-    pixel_color.rgb = pow(pixel_color.rgb, vec3(1.0 / 2.2));
+    // A line a pixel wide along the split
+    if (in_split.divider < 1.0 && abs(uv.x - in_split.divider) < fwidth(uv.x))
+    {
+        pixel_color.rgb = vec3(1.0);
+    }
 
-    out_color = pixel_color;
+    // Alpha holds the accumulated samples count
+    out_color = vec4(pixel_color.rgb, 1.0);
 }
\ No newline at end of file
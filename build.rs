@@ -1,3 +1,29 @@
+#[path = "src/back/interface.rs"]
+#[allow(dead_code)]
+mod interface;
+
+// Committed and compiled by the Makefile together with the shaders. The
+// build only checks it, the sources may be read-only.
+const INTERFACE_HEADER: &str = "assets/shaders/interface.glsl";
+// Set to rewrite a stale header instead of failing
+const REGENERATE_VAR: &str = "PATHRS_REGENERATE_INTERFACE";
+
 fn main() {
     build_info_build::build_script();
+
+    println!("cargo:rerun-if-changed=src/back/interface.rs");
+    println!("cargo:rerun-if-changed={}", INTERFACE_HEADER);
+    println!("cargo:rerun-if-env-changed={}", REGENERATE_VAR);
+    let header = interface::header();
+    if std::fs::read_to_string(INTERFACE_HEADER).ok().as_deref() == Some(header.as_str()) {
+        return;
+    }
+    if std::env::var_os(REGENERATE_VAR).is_some() {
+        std::fs::write(INTERFACE_HEADER, header).expect("Failed to write the shader interface");
+    } else {
+        panic!(
+            "{} does not match src/back/interface.rs, run `{}=1 cargo build` and `make` to regenerate it",
+            INTERFACE_HEADER, REGENERATE_VAR
+        );
+    }
 }
@@ -1,7 +1,34 @@
-use log::{debug, info};
+use crate::error::{TracerError, TracerResult};
+use log::{debug, info, warn};
 use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::Instant;
+
+/// Shader sources by logical name. The Makefile compiles every one into
+/// the `.spv` artifact next to it, along with a `.spv.d` depfile listing
+/// the modules it includes (see assets/shaders/modules).
+const SHADER_MANIFEST: [(&str, &str); 15] = [
+    ("trace", "shaders/shader.comp"),
+    ("invalid_pixels", "shaders/invalid_pixels.comp"),
+    ("classify_tiles", "shaders/classify_tiles.comp"),
+    ("present_vertex", "shaders/triangle.vert"),
+    ("present_fragment", "shaders/triangle.frag"),
+    ("overlay_vertex", "shaders/overlay.vert"),
+    ("overlay_fragment", "shaders/overlay.frag"),
+    ("post_tonemap", "shaders/post/tonemap.comp"),
+    (
+        "post_exposure_histogram",
+        "shaders/post/exposure_histogram.comp",
+    ),
+    ("post_exposure_adapt", "shaders/post/exposure_adapt.comp"),
+    ("post_bloom_threshold", "shaders/post/bloom_threshold.comp"),
+    ("post_bloom_blur", "shaders/post/bloom_blur.comp"),
+    ("post_bloom_composite", "shaders/post/bloom_composite.comp"),
+    ("post_vignette", "shaders/post/vignette.comp"),
+    ("post_fxaa", "shaders/post/fxaa.comp"),
+];
 
 #[allow(dead_code)]
 pub struct AssetMeta {
@@ -11,6 +38,8 @@ pub struct AssetMeta {
 
 pub enum AssetData {
     SPIRVShader(Vec<u8>),
+    Image(image::RgbaImage),
+    HdrImage(image::Rgba32FImage),
 }
 
 pub struct Asset {
@@ -20,10 +49,27 @@ pub struct Asset {
 
 #[allow(unreachable_patterns)]
 impl Asset {
-    pub fn get_spirv(&self) -> anyhow::Result<&[u8]> {
+    pub fn get_spirv(&self) -> TracerResult<&[u8]> {
         match &self.data {
             AssetData::SPIRVShader(shader) => Ok(shader),
-            _ => anyhow::bail!("Asset {} is not a SPIRV shader", self.meta.id),
+            _ => Err(TracerError::AssetType(
+                self.meta.id.clone(),
+                "a SPIRV shader",
+            )),
+        }
+    }
+
+    pub fn get_image(&self) -> TracerResult<&image::RgbaImage> {
+        match &self.data {
+            AssetData::Image(image) => Ok(image),
+            _ => Err(TracerError::AssetType(self.meta.id.clone(), "an image")),
+        }
+    }
+
+    pub fn get_hdr_image(&self) -> TracerResult<&image::Rgba32FImage> {
+        match &self.data {
+            AssetData::HdrImage(image) => Ok(image),
+            _ => Err(TracerError::AssetType(self.meta.id.clone(), "an HDR image")),
         }
     }
 }
@@ -34,53 +80,176 @@ pub struct AssetManagerInner {
 
 impl AssetManagerInner {
     // Traverses upwards until it finds a directory with an asset subdirectory
-    fn find_assets_dir(pwd: &Path) -> anyhow::Result<PathBuf> {
+    fn find_assets_dir(pwd: &Path) -> TracerResult<PathBuf> {
         debug!("Looking for assets directory in {}", pwd.display());
         let assets_dir = pwd.join("assets");
         if assets_dir.exists() {
             Ok(assets_dir)
         } else {
-            Self::find_assets_dir(pwd.parent().ok_or_else(|| {
-                anyhow::anyhow!("Could not find assets directory in current or parent directories")
-            })?)
+            Self::find_assets_dir(pwd.parent().ok_or(TracerError::NoAssetsDirectory)?)
         }
     }
 
-    fn new_from_pwd(pwd: &Path) -> anyhow::Result<Self> {
+    fn new_from_pwd(pwd: &Path) -> TracerResult<Self> {
         let assets_dir = Self::find_assets_dir(pwd)?;
         info!("Using assets directory: {}", assets_dir.display());
         Ok(Self { assets_dir })
     }
 
-    fn load_asset(&self, id: &str) -> anyhow::Result<Asset> {
-        let asset_path = self.assets_dir.join(id);
+    fn load_asset(&self, id: &str) -> TracerResult<Asset> {
+        Self::load_from(&self.assets_dir, id)
+    }
+
+    fn load_from(assets_dir: &Path, id: &str) -> TracerResult<Asset> {
+        let asset_path = assets_dir.join(id);
         if !asset_path.exists() {
-            anyhow::bail!("Asset not found: {}", id);
+            return Err(TracerError::AssetMissing(id.to_string()));
         }
 
         let meta = AssetMeta {
             id: id.to_string(),
             path: asset_path.clone(),
         };
-        // For simplicity, we assume all assets are SPIRV shaders in this example
-        let data = AssetData::SPIRVShader(std::fs::read(&asset_path)?);
+        let data = match asset_path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => AssetData::Image(image::open(&asset_path)?.into_rgba8()),
+            Some("hdr") => AssetData::HdrImage(image::open(&asset_path)?.into_rgba32f()),
+            // Everything else is assumed to be a SPIRV shader
+            _ => AssetData::SPIRVShader(std::fs::read(&asset_path)?),
+        };
 
         info!("Loaded asset: {}", id);
         Ok(Asset { meta, data })
     }
+
+    fn load_shader(&self, name: &str) -> TracerResult<Asset> {
+        let source = SHADER_MANIFEST
+            .iter()
+            .find(|(shader, _)| *shader == name)
+            .map(|(_, source)| *source)
+            .ok_or_else(|| TracerError::UnknownShader(name.to_string()))?;
+        let artifact = format!("{}.spv", source);
+        if self.is_stale(source, &artifact) {
+            warn!(
+                "Shader {} is older than its sources, rebuild the shaders with make",
+                name
+            );
+        }
+        self.load_asset(&artifact)
+    }
+
+    // Whether the source or a module it includes changed after the artifact
+    // was compiled. Only the source is known before the first build.
+    fn is_stale(&self, source: &str, artifact: &str) -> bool {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified());
+        let Ok(compiled) = modified(&self.assets_dir.join(artifact)) else {
+            return false;
+        };
+        // "artifact: source module..." with line continuations, the paths
+        // are relative to the directory make runs in, the assets parent
+        let depfile = std::fs::read_to_string(self.assets_dir.join(format!("{}.d", artifact)))
+            .unwrap_or_default();
+        let dependencies = depfile
+            .split_once(": ")
+            .map(|(_, dependencies)| dependencies.replace("\\\n", " "))
+            .unwrap_or_default();
+        std::iter::once(self.assets_dir.join(source))
+            .chain(
+                dependencies
+                    .split_whitespace()
+                    .map(|path| self.assets_dir.join("..").join(path)),
+            )
+            .any(|path| modified(&path).is_ok_and(|time| time > compiled))
+    }
+}
+
+/// Asset loaded on a worker thread, see `AssetManager::load_async`
+pub struct AssetLoad<T> {
+    id: String,
+    receiver: Receiver<TracerResult<T>>,
+    result: Option<TracerResult<T>>,
+}
+
+impl<T> AssetLoad<T> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The result once the asset is loaded, None while it is still loading.
+    /// Returned only once.
+    pub fn poll(&mut self) -> Option<TracerResult<T>> {
+        if self.result.is_none() {
+            self.result = match self.receiver.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err(TracerError::AssetWorker(self.id.clone())))
+                }
+            };
+        }
+        self.result.take()
+    }
+
+    /// Blocks until the asset is loaded, the next `poll` returns it
+    pub fn wait(&mut self) {
+        if self.result.is_none() {
+            let result = self.receiver.recv();
+            self.result =
+                Some(result.unwrap_or_else(|_| Err(TracerError::AssetWorker(self.id.clone()))));
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct AssetManager(Rc<RefCell<AssetManagerInner>>);
 
 impl AssetManager {
-    pub fn new_from_pwd(pwd: &Path) -> anyhow::Result<Self> {
+    pub fn new_from_pwd(pwd: &Path) -> TracerResult<Self> {
         Ok(Self(Rc::new(RefCell::new(
             AssetManagerInner::new_from_pwd(pwd)?,
         ))))
     }
 
-    pub fn load_asset(&self, id: &str) -> anyhow::Result<Asset> {
+    pub fn load_asset(&self, id: &str) -> TracerResult<Asset> {
         self.0.borrow_mut().load_asset(id)
     }
+
+    /// Loads the asset on a worker thread and converts it there with `f`,
+    /// so that a large asset does not block the caller. Dropping the load
+    /// discards the asset once it is loaded.
+    pub fn load_async<T, F>(&self, id: &str, f: F) -> AssetLoad<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Asset) -> TracerResult<T> + Send + 'static,
+    {
+        info!("Loading asset {} in the background", id);
+        let assets_dir = self.0.borrow().assets_dir.clone();
+        let (sender, receiver) = channel();
+        let worker_id = id.to_string();
+        let spawned = std::thread::Builder::new()
+            .name("asset-loader".to_string())
+            .spawn(move || {
+                let start = Instant::now();
+                let result = AssetManagerInner::load_from(&assets_dir, &worker_id).and_then(f);
+                if result.is_ok() {
+                    info!(
+                        "Asset {} ready after {:.2} s",
+                        worker_id,
+                        start.elapsed().as_secs_f32()
+                    );
+                }
+                // Fails only if the load was dropped meanwhile
+                let _ = sender.send(result);
+            });
+
+        AssetLoad {
+            id: id.to_string(),
+            receiver,
+            result: spawned.err().map(|e| Err(e.into())),
+        }
+    }
+
+    /// Loads the compiled shader of the logical name, see `SHADER_MANIFEST`
+    pub fn load_shader(&self, name: &str) -> TracerResult<Asset> {
+        self.0.borrow_mut().load_shader(name)
+    }
 }
@@ -0,0 +1,183 @@
+use crate::common::descriptor::DescriptorAllocator;
+use crate::error::{TracerError, TracerResult};
+use crate::tracer::Bundle;
+use ash::vk;
+use log::debug;
+
+// Upper bound of the textures array, only the used slots have to be valid
+const MAX_TEXTURES: u32 = 1024;
+
+pub const PICK_BINDING: u32 = 0;
+pub const LIGHTS_BINDING: u32 = 1;
+pub const MATERIALS_BINDING: u32 = 2;
+pub const INSTANCES_BINDING: u32 = 3;
+pub const VOLUMES_BINDING: u32 = 4;
+pub const ENVIRONMENT_BINDING: u32 = 5;
+pub const INVALID_PIXELS_BINDING: u32 = 6;
+pub const TILE_QUEUE_BINDING: u32 = 7;
+// Must stay the last binding, it has a variable descriptor count
+pub const TEXTURES_BINDING: u32 = 8;
+
+/// Single descriptor set (set = 1) holding the scene data: the per-light,
+/// per-material, per-instance and per-volume buffers, the environment
+/// sampling tables, the pick and invalid pixels readback buffers, the tile
+/// queue and a bindless table of all textures. The config and objects are updated every frame, so they live
+/// in the per-frame set instead (see `FrameData`).
+/// Entries are written individually, so changing textures does not require
+/// reallocating the set.
+pub struct BindlessTable {
+    pub layout: vk::DescriptorSetLayout,
+    pub set: vk::DescriptorSet,
+    descriptors: DescriptorAllocator,
+
+    next_texture: u32,
+    free_textures: Vec<u32>,
+}
+
+impl BindlessTable {
+    pub unsafe fn new(bundle: Bundle) -> TracerResult<Self> {
+        debug!(
+            "Creating bindless table with {} texture slots",
+            MAX_TEXTURES
+        );
+        let bindings = [
+            // (set = 1, binding = 0) buffer picked_pixel
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(PICK_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 1) buffer world_lights
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(LIGHTS_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 2) buffer world_materials
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(MATERIALS_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 3) buffer world_instances
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(INSTANCES_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 4) buffer world_volumes
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(VOLUMES_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 5) buffer environment_tables
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(ENVIRONMENT_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 6) buffer invalid_pixels
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(INVALID_PIXELS_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 7) buffer tile_queue
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(TILE_QUEUE_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 1, binding = 8) uniform sampler2D textures[]
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(TEXTURES_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(MAX_TEXTURES)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
+        let binding_flags = [
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+        ];
+        let descriptors = DescriptorAllocator::new(bundle, &bindings, &binding_flags, 1)?;
+
+        Ok(Self {
+            layout: descriptors.layout,
+            set: descriptors.sets()[0],
+            descriptors,
+            next_texture: 0,
+            free_textures: vec![],
+        })
+    }
+
+    pub unsafe fn write_buffer(&self, bundle: Bundle, binding: u32, buffer: vk::Buffer) {
+        let buffer_info = vk::DescriptorBufferInfo::default()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(std::slice::from_ref(&buffer_info));
+        bundle.device.update_descriptor_sets(&[write], &[]);
+    }
+
+    /// Registers the texture sampled in the layout and returns its index in
+    /// the `textures` array
+    pub unsafe fn add_texture(
+        &mut self,
+        bundle: Bundle,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    ) -> TracerResult<u32> {
+        let index = match self.free_textures.pop() {
+            Some(index) => index,
+            None if self.next_texture < MAX_TEXTURES => {
+                self.next_texture += 1;
+                self.next_texture - 1
+            }
+            None => {
+                return Err(TracerError::Unsupported(format!(
+                    "number of textures, the bindless table holds {}",
+                    MAX_TEXTURES
+                )))
+            }
+        };
+
+        let image_info = vk::DescriptorImageInfo::default()
+            .image_view(image_view)
+            .sampler(sampler)
+            .image_layout(layout);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(TEXTURES_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+        bundle.device.update_descriptor_sets(&[write], &[]);
+
+        Ok(index)
+    }
+
+    /// Releases the slot. The stale descriptor is left in place, the binding is
+    /// partially bound so it's fine as long as the shader does not index it.
+    pub fn remove_texture(&mut self, index: u32) {
+        self.free_textures.push(index);
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        self.descriptors.destroy(bundle);
+    }
+}
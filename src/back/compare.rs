@@ -0,0 +1,156 @@
+use crate::assets::AssetManager;
+use crate::back::environment::EnvironmentMap;
+use crate::back::pipeline::TracerPipeline;
+use crate::back::push_constants::PushConstantsData;
+use crate::back::{BackQueues, CompareSlot};
+use crate::common::frame_graph::SyncPoint;
+use crate::config::{CompareConfig, PostPass, TracerConfigInner};
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+
+/// Second accumulation of the A/B view, see `CompareConfig`. Traces the
+/// scene of the main config with the compared settings into images and
+/// buffers of its own, dispatched along with the main pipeline.
+pub(crate) struct ComparePipeline {
+    pipeline: TracerPipeline,
+    // Overrides the current config data was built with, None until the
+    // first frame, which also uploads the scene
+    overrides: Option<Vec<String>>,
+    frame_index: u64,
+    samples_count: u32,
+    // Slot of the last present, released along with the main one
+    index: usize,
+}
+
+impl ComparePipeline {
+    pub unsafe fn new(
+        bundle: Bundle,
+        asset_manager: &AssetManager,
+        size: glam::UVec2,
+        queues: BackQueues,
+        images_custom_usage: vk::ImageUsageFlags,
+        config: &TracerConfigInner,
+    ) -> TracerResult<Self> {
+        debug!("Creating compare pipeline of {:?}", size);
+        // The watchdog of the main pipeline already watches the device
+        let mut pipeline = TracerPipeline::new(
+            bundle,
+            asset_manager.clone(),
+            size,
+            queues,
+            images_custom_usage,
+            0.0,
+            false,
+        )?;
+
+        // The main pipeline has its map already, it is loaded once more
+        if let Some(environment) = &config.environment {
+            let mut load = EnvironmentMap::load_async(asset_manager, environment);
+            load.wait();
+            let map = load.poll().and_then(|map| {
+                map.map_err(|e| warn!("Failed to load environment {}: {}", environment.asset, e))
+                    .ok()
+            });
+            if let Err(e) = pipeline.set_environment(bundle, map) {
+                pipeline.destroy(bundle);
+                return Err(e);
+            }
+        }
+
+        Ok(Self {
+            pipeline,
+            overrides: None,
+            frame_index: 0,
+            samples_count: config.samples_count,
+            index: 0,
+        })
+    }
+
+    pub unsafe fn set_environment(
+        &mut self,
+        bundle: Bundle,
+        map: Option<EnvironmentMap>,
+    ) -> TracerResult<()> {
+        self.pipeline.set_environment(bundle, map)
+    }
+
+    /// Both sides are post-processed alike
+    pub unsafe fn set_post(&mut self, bundle: Bundle, passes: &[PostPass]) -> TracerResult<()> {
+        self.pipeline.set_post(bundle, passes)
+    }
+
+    pub unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> TracerResult<()> {
+        self.pipeline.resize(bundle, size)
+    }
+
+    /// Traces the compared side along with the main one. The flags are the
+    /// updates of the main side, which the compared one follows.
+    pub unsafe fn present(
+        &mut self,
+        bundle: Bundle,
+        compare: &CompareConfig,
+        config: &TracerConfigInner,
+        (config_updated, scene_updated, edited): (bool, bool, &[usize]),
+        size: glam::UVec2,
+        dispatches: u32,
+        invalidate: bool,
+        reproject: bool,
+    ) -> TracerResult<CompareSlot> {
+        let first = self.overrides.is_none();
+        // Moving the divider alone keeps the samples
+        let changed = self.overrides.as_ref() != Some(&compare.overrides);
+        let config_data = if config_updated || changed {
+            let compared = compare.apply(config).unwrap_or_else(|e| {
+                warn!("Failed to apply compared settings: {:#}", e);
+                config.clone()
+            });
+            self.samples_count = compared.samples_count;
+            self.overrides = Some(compare.overrides.clone());
+            Some(compared.as_config())
+        } else {
+            None
+        };
+        let scene_data = (scene_updated || first).then(|| config.as_scene());
+        let edited_objects = if scene_data.is_some() {
+            vec![]
+        } else {
+            config.as_edited_objects(edited)
+        };
+
+        let invalidate = invalidate || changed;
+        if invalidate {
+            self.frame_index = 0;
+        }
+        let push_constants =
+            PushConstantsData::new(self.frame_index as u32, self.samples_count, size);
+        self.frame_index += dispatches as u64;
+
+        let slot = self.pipeline.present(
+            bundle,
+            config_data,
+            scene_data,
+            edited_objects,
+            push_constants,
+            dispatches,
+            invalidate,
+            reproject && !invalidate,
+            None,
+        )?;
+        self.index = slot.index;
+        Ok(CompareSlot {
+            descriptor_set: slot.descriptor_set,
+            ready: slot.ready,
+            divider: compare.divider.clamp(0.0, 1.0),
+        })
+    }
+
+    pub fn release(&mut self, point: SyncPoint) {
+        self.pipeline.release(self.index, point);
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        self.pipeline.destroy(bundle);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::assets::{AssetLoad, AssetManager};
+use crate::config::Environment;
+use crate::error::TracerResult;
+use log::debug;
+
+/// Environment image together with the tables to importance sample it.
+/// Texels are picked proportionally to their luminance weighted by the
+/// solid angle they cover, so bright spots like the sun are found by the
+/// next event estimation instead of only by chance.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    pub image: image::Rgba32FImage,
+    // Inclusive CDF over the rows, followed by the CDF over the columns
+    // of every row. Both are normalized, the last value of each is 1.
+    pub cdf: Vec<f32>,
+}
+
+impl EnvironmentMap {
+    /// Loads the image and builds its tables on a worker thread
+    pub fn load_async(asset_manager: &AssetManager, environment: &Environment) -> AssetLoad<Self> {
+        asset_manager.load_async(&environment.asset, |asset| {
+            Ok(Self::new(asset.get_hdr_image()?.clone()))
+        })
+    }
+
+    pub fn new(image: image::Rgba32FImage) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        debug!("Building environment CDF of {}x{}", width, height);
+
+        let mut cdf = vec![0.0; height + width * height];
+        let (marginal, conditional) = cdf.split_at_mut(height);
+        for (y, row) in conditional.chunks_exact_mut(width).enumerate() {
+            // Rows near the poles are squeezed in the equirectangular mapping
+            let theta = std::f32::consts::PI * (y as f32 + 0.5) / height as f32;
+            for (x, value) in row.iter_mut().enumerate() {
+                let [r, g, b, _] = image.get_pixel(x as u32, y as u32).0;
+                *value = (0.2126 * r + 0.7152 * g + 0.0722 * b).max(0.0) * theta.sin();
+            }
+            marginal[y] = Self::accumulate(row);
+        }
+        Self::accumulate(marginal);
+
+        Self { image, cdf }
+    }
+
+    // Turns the weights into a normalized inclusive CDF, returns their sum.
+    // All zero weights become a uniform distribution.
+    fn accumulate(weights: &mut [f32]) -> f32 {
+        let total = weights.iter().sum::<f32>();
+        let count = weights.len() as f32;
+        let mut sum = 0.0;
+        for (i, weight) in weights.iter_mut().enumerate() {
+            sum += *weight;
+            *weight = if total > 0.0 {
+                sum / total
+            } else {
+                (i + 1) as f32 / count
+            };
+        }
+        // Rounding must not leave the last value below 1
+        if let Some(last) = weights.last_mut() {
+            *last = 1.0;
+        }
+        total
+    }
+}
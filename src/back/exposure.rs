@@ -0,0 +1,148 @@
+use crate::back::interface::{SSBOExposureData, HISTOGRAM_BINS};
+use crate::common::command_buffer::CommandBuffer;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::time::Instant;
+
+// Longest time adapted over at once, e.g. after the tracing was paused
+const MAX_FRAME: f32 = 0.1;
+
+/// State of the auto exposure (see `AutoExposure`), the adapted exposure
+/// followed by the luminance histogram of the frame. The histogram is
+/// built by post/exposure_histogram.comp and emptied again by
+/// post/exposure_adapt.comp, which adapts the exposure the tonemapping
+/// reads. Shared by the frames in flight, so the exposure carries over.
+pub(crate) struct ExposureBuffer {
+    pub buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    // Set until the buffer is zeroed, which restarts the adaptation
+    reset: bool,
+    last: Option<Instant>,
+    destroyed: bool,
+}
+
+impl ExposureBuffer {
+    pub fn size() -> vk::DeviceSize {
+        (size_of::<SSBOExposureData>() + HISTOGRAM_BINS as usize * size_of::<u32>())
+            as vk::DeviceSize
+    }
+
+    pub unsafe fn new(bundle: Bundle) -> TracerResult<Self> {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(Self::size())
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let requirements = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = match bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Exposure Buffer",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        }) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                bundle.device.destroy_buffer(buffer, None);
+                return Err(e.into());
+            }
+        };
+        let mut exposure = Self {
+            buffer,
+            allocation: Some(allocation),
+            reset: true,
+            last: None,
+            destroyed: false,
+        };
+        let allocation = exposure.allocation.as_ref().unwrap();
+        if let Err(e) =
+            bundle
+                .device
+                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        {
+            exposure.destroy(bundle);
+            return Err(e.into());
+        }
+        Ok(exposure)
+    }
+
+    /// The next frame jumps to its exposure instead of adapting to it
+    pub fn reset(&mut self) {
+        self.reset = true;
+        self.last = None;
+    }
+
+    /// Orders the frame after the previous one using the buffer, zeroing
+    /// it if reset. Returns the seconds since the previous frame.
+    pub unsafe fn record_begin(&mut self, bundle: Bundle, command_buffer: &CommandBuffer) -> f32 {
+        let now = Instant::now();
+        let elapsed = self
+            .last
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_FRAME));
+        self.last = Some(now);
+
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::SHADER_WRITE
+                    | vk::AccessFlags::TRANSFER_WRITE,
+            );
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+        if self.reset {
+            self.reset = false;
+            bundle.device.cmd_fill_buffer(
+                command_buffer.as_inner(),
+                self.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            let barrier = vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+            bundle.device.cmd_pipeline_barrier(
+                command_buffer.as_inner(),
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+        elapsed
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            if let Some(allocation) = self.allocation.take() {
+                if let Err(e) = bundle.allocator().free(allocation) {
+                    warn!("Failed to free exposure memory: {}", e);
+                }
+            }
+            bundle.device.destroy_buffer(self.buffer, None);
+            self.destroyed = true;
+        }
+    }
+}
+
+impl Drop for ExposureBuffer {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked ExposureBuffer");
+        }
+    }
+}
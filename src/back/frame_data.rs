@@ -0,0 +1,245 @@
+use crate::back::ssbo::config::{SSBOConfig, SSBOConfigData};
+use crate::back::ssbo::objects::{SSBOObjectData, SSBOObjects, SSBOObjectsData};
+use crate::back::ssbo::SSBOUploadQueue;
+use crate::common::descriptor::DescriptorAllocator;
+use crate::error::{Context, TracerResult};
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+
+pub const CONFIG_BINDING: u32 = 0;
+pub const OBJECTS_BINDING: u32 = 1;
+
+const INITIAL_OBJECTS_CAPACITY: usize = 64;
+
+// What of the objects a frame has not seen yet
+enum ObjectsState {
+    Current,
+    // Indices edited since the last upload into the frame
+    Edited(Vec<usize>),
+    // Replaced or grown, uploaded as a whole
+    Stale,
+}
+
+struct FrameBuffers {
+    config: SSBOConfig,
+    objects: SSBOObjects,
+    set: vk::DescriptorSet,
+    config_current: bool,
+    objects_state: ObjectsState,
+}
+
+/// Config and objects buffers (set = 2), one copy per frame in flight.
+/// The host only writes into the copy of the frame about to be dispatched,
+/// whose previous dispatch has completed, so a frame still being traced
+/// never sees its data change. Every copy catches up with the latest
+/// data once its frame comes next.
+pub struct FrameData {
+    descriptors: DescriptorAllocator,
+    frames: Vec<FrameBuffers>,
+    queue_families: Vec<u32>,
+
+    config: SSBOConfigData,
+    objects: SSBOObjectsData,
+    destroyed: bool,
+}
+
+impl FrameData {
+    pub unsafe fn new(bundle: Bundle, count: usize, queue_families: &[u32]) -> TracerResult<Self> {
+        debug!("Creating frame data for {} frames in flight", count);
+        let bindings = [
+            // (set = 2, binding = 0) buffer config
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(CONFIG_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 2, binding = 1) buffer world_objects
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(OBJECTS_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
+        let descriptors = DescriptorAllocator::new(bundle, &bindings, &[], count)?;
+
+        let mut frames = Vec::with_capacity(count);
+        for (i, set) in descriptors.sets().iter().enumerate() {
+            let config = SSBOConfig::new(bundle, Some(&format!("Config SSBO Buffer {}", i)))
+                .context("Failed to create config SSBO")?;
+            let objects = SSBOObjects::new_array(
+                bundle,
+                INITIAL_OBJECTS_CAPACITY,
+                queue_families,
+                Some(&format!("Objects SSBO Buffer {}", i)),
+            )
+            .context("Failed to create objects SSBO")?;
+            Self::write_buffer(bundle, *set, CONFIG_BINDING, config.buffer);
+            Self::write_buffer(bundle, *set, OBJECTS_BINDING, objects.buffer);
+            frames.push(FrameBuffers {
+                config,
+                objects,
+                set: *set,
+                config_current: false,
+                objects_state: ObjectsState::Stale,
+            });
+        }
+
+        Ok(Self {
+            descriptors,
+            frames,
+            queue_families: queue_families.to_vec(),
+            config: SSBOConfigData::default(),
+            objects: vec![],
+            destroyed: false,
+        })
+    }
+
+    unsafe fn write_buffer(
+        bundle: Bundle,
+        set: vk::DescriptorSet,
+        binding: u32,
+        buffer: vk::Buffer,
+    ) {
+        let buffer_info = vk::DescriptorBufferInfo::default()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(std::slice::from_ref(&buffer_info));
+        bundle.device.update_descriptor_sets(&[write], &[]);
+    }
+
+    /// Memory of the config and objects buffers of all the frames
+    pub fn allocated_bytes(&self) -> u64 {
+        self.frames
+            .iter()
+            .map(|frame| frame.config.allocated_bytes() + frame.objects.allocated_bytes())
+            .sum()
+    }
+
+    pub fn layout(&self) -> vk::DescriptorSetLayout {
+        self.descriptors.layout
+    }
+
+    /// Latest config, the frames may not have caught up with it yet
+    pub fn config(&self) -> &SSBOConfigData {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SSBOConfigData) {
+        self.config = config;
+        for frame in &mut self.frames {
+            frame.config_current = false;
+        }
+    }
+
+    pub fn set_objects(&mut self, objects: SSBOObjectsData) {
+        self.objects = objects;
+        for frame in &mut self.frames {
+            frame.objects_state = ObjectsState::Stale;
+        }
+    }
+
+    /// Replaces the objects at the indices, the frames only upload these.
+    /// Texture slots are kept from `set_objects`, editing an object does not
+    /// change them.
+    pub fn edit_objects(&mut self, edited: Vec<(usize, SSBOObjectData)>) {
+        let mut indices = Vec::with_capacity(edited.len());
+        for (index, mut object) in edited {
+            // The scene may have been replaced since the object was edited
+            let Some(uploaded) = self.objects.get_mut(index) else {
+                continue;
+            };
+            object.textures = uploaded.textures;
+            *uploaded = object;
+            indices.push(index);
+        }
+
+        for frame in &mut self.frames {
+            match &mut frame.objects_state {
+                ObjectsState::Current => {
+                    frame.objects_state = ObjectsState::Edited(indices.clone());
+                }
+                ObjectsState::Edited(edited) => edited.extend(&indices),
+                ObjectsState::Stale => {}
+            }
+        }
+    }
+
+    /// Brings the buffers of the frame up to date and returns its descriptor
+    /// set. The previous dispatch of the frame must have completed.
+    pub unsafe fn prepare(
+        &mut self,
+        bundle: Bundle,
+        upload_queue: &mut SSBOUploadQueue,
+        index: usize,
+    ) -> TracerResult<vk::DescriptorSet> {
+        let frame = &mut self.frames[index];
+        if !frame.config_current {
+            frame
+                .config
+                .update(bundle, upload_queue, self.config.clone())
+                .context("Failed to update config SSBO")?;
+            frame.config_current = true;
+        }
+
+        match std::mem::replace(&mut frame.objects_state, ObjectsState::Current) {
+            ObjectsState::Current => {}
+            ObjectsState::Edited(mut indices) => {
+                indices.sort_unstable();
+                indices.dedup();
+                // Adjacent objects are written with a single copy
+                for run in indices.chunk_by(|a, b| a + 1 == *b) {
+                    let range = run[0]..run[run.len() - 1] + 1;
+                    debug!("Updating objects {:?} of frame {}", range, index);
+                    frame
+                        .objects
+                        .update_range(bundle, upload_queue, range.start, &self.objects[range])
+                        .context("Failed to update objects SSBO")?;
+                }
+            }
+            ObjectsState::Stale => {
+                if self.objects.len() > frame.objects.capacity {
+                    let capacity = self.objects.len().next_power_of_two();
+                    debug!(
+                        "Growing objects SSBO of frame {} from {} to {} elements",
+                        index, frame.objects.capacity, capacity
+                    );
+                    let grown = SSBOObjects::new_array(
+                        bundle,
+                        capacity,
+                        &self.queue_families,
+                        Some(&format!("Objects SSBO Buffer {}", index)),
+                    )
+                    .context("Failed to create objects SSBO")?;
+                    let mut old = std::mem::replace(&mut frame.objects, grown);
+                    old.destroy(bundle);
+                    Self::write_buffer(bundle, frame.set, OBJECTS_BINDING, frame.objects.buffer);
+                }
+                frame
+                    .objects
+                    .update_slice(bundle, upload_queue, &self.objects)
+                    .context("Failed to update objects SSBO")?;
+            }
+        }
+
+        Ok(frame.set)
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            for frame in &mut self.frames {
+                frame.config.destroy(bundle);
+                frame.objects.destroy(bundle);
+            }
+            self.descriptors.destroy(bundle);
+            self.destroyed = true;
+        } else {
+            warn!("FrameData already destroyed");
+        }
+    }
+}
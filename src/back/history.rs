@@ -0,0 +1,335 @@
+use crate::back::{DEPTH_BINDING, DEPTH_HISTORY_BINDING, HISTORY_BINDING, OBJECT_ID_BINDING};
+use crate::common::command_buffer::{CommandBuffer, UploadBatch};
+use crate::common::image_pool::{ImagePool, PooledMemory};
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+
+struct HistoryImage {
+    image: vk::Image,
+    view: vk::ImageView,
+    memory: Option<PooledMemory>,
+}
+
+/// Images used by the temporal reprojection. The tracer writes the primary
+/// hit distance of every pixel into `depth`. Before a reprojected dispatch
+/// the previous accumulation and its depth are copied into the history
+/// images, which the shader then samples at the reprojected coordinates.
+/// Next to the depth the tracer writes the object hit by every pixel into
+/// `object_id`, a pixel of which is copied out for picking.
+pub struct TemporalHistory {
+    depth: HistoryImage,
+    history: HistoryImage,
+    depth_history: HistoryImage,
+    object_id: HistoryImage,
+    destroyed: bool,
+}
+
+impl TemporalHistory {
+    /// The transition of the images to the general layout is recorded into
+    /// the batch, they may not be used before the batch is submitted
+    pub unsafe fn new(
+        bundle: Bundle,
+        batch: &UploadBatch,
+        pool: &mut ImagePool,
+        size: glam::UVec2,
+    ) -> TracerResult<Self> {
+        debug!("Creating temporal history of {}x{}", size.x, size.y);
+        let depth = Self::create_image(
+            bundle,
+            pool,
+            size,
+            vk::Format::R32_SFLOAT,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            "Depth Image",
+        )?;
+        let history = Self::create_image(
+            bundle,
+            pool,
+            size,
+            vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::TRANSFER_DST,
+            "History Image",
+        )?;
+        let depth_history = Self::create_image(
+            bundle,
+            pool,
+            size,
+            vk::Format::R32_SFLOAT,
+            vk::ImageUsageFlags::TRANSFER_DST,
+            "Depth History Image",
+        )?;
+        let object_id = Self::create_image(
+            bundle,
+            pool,
+            size,
+            vk::Format::R32_UINT,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            "Object ID Image",
+        )?;
+
+        let history = Self {
+            depth,
+            history,
+            depth_history,
+            object_id,
+            destroyed: false,
+        };
+        history.record_transition_to_general(bundle, batch);
+
+        Ok(history)
+    }
+
+    fn subresource_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+
+    unsafe fn create_image(
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        size: glam::UVec2,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        name: &str,
+    ) -> TracerResult<HistoryImage> {
+        let create_image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: size.x,
+                height: size.y,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::STORAGE | usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = bundle.device.create_image(&create_image_info, None)?;
+
+        let memory = pool.bind(bundle, image, name)?;
+
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(Self::subresource_range());
+        let view = bundle.device.create_image_view(&image_view_info, None)?;
+
+        Ok(HistoryImage {
+            image,
+            view,
+            memory: Some(memory),
+        })
+    }
+
+    fn images(&self) -> [&HistoryImage; 4] {
+        [
+            &self.depth,
+            &self.history,
+            &self.depth_history,
+            &self.object_id,
+        ]
+    }
+
+    unsafe fn record_transition_to_general(&self, bundle: Bundle, batch: &UploadBatch) {
+        let barriers = self.images().map(|image| {
+            vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image.image)
+                .subresource_range(Self::subresource_range())
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+        });
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+    }
+
+    /// Points the history bindings of the slot descriptor set to these images
+    pub unsafe fn write_descriptor_set(&self, bundle: Bundle, descriptor_set: vk::DescriptorSet) {
+        let bindings = [
+            (DEPTH_BINDING, &self.depth),
+            (HISTORY_BINDING, &self.history),
+            (DEPTH_HISTORY_BINDING, &self.depth_history),
+            (OBJECT_ID_BINDING, &self.object_id),
+        ];
+        let image_infos = bindings.map(|(_, image)| {
+            vk::DescriptorImageInfo::default()
+                .image_view(image.view)
+                .image_layout(vk::ImageLayout::GENERAL)
+        });
+        let writes: Vec<_> = bindings
+            .iter()
+            .zip(image_infos.iter())
+            .map(|((binding, _), image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(*binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(std::slice::from_ref(image_info))
+            })
+            .collect();
+        bundle.device.update_descriptor_sets(&writes, &[]);
+    }
+
+    /// Records copying of the previous accumulation and depth into the
+    /// history images, ordered after the previous and before the next dispatch
+    pub unsafe fn record_copy(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        previous: vk::Image,
+        extent: vk::Extent2D,
+    ) {
+        let before = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[before],
+            &[],
+            &[],
+        );
+
+        let layers = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        let region = vk::ImageCopy::default()
+            .src_subresource(layers)
+            .dst_subresource(layers)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        for (src, dst) in [
+            (previous, self.history.image),
+            (self.depth.image, self.depth_history.image),
+        ] {
+            bundle.device.cmd_copy_image(
+                command_buffer.as_inner(),
+                src,
+                vk::ImageLayout::GENERAL,
+                dst,
+                vk::ImageLayout::GENERAL,
+                &[region],
+            );
+        }
+
+        let after = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[after],
+            &[],
+            &[],
+        );
+    }
+
+    /// Records copying of the object id of the pixel into the buffer at the
+    /// offset, ordered after the dispatch writing it
+    pub unsafe fn record_object_id_copy(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        pixel: glam::UVec2,
+        buffer: vk::Buffer,
+        offset: usize,
+    ) {
+        let before = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[before],
+            &[],
+            &[],
+        );
+
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(offset as vk::DeviceSize)
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_offset(vk::Offset3D {
+                x: pixel.x as i32,
+                y: pixel.y as i32,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            });
+        bundle.device.cmd_copy_image_to_buffer(
+            command_buffer.as_inner(),
+            self.object_id.image,
+            vk::ImageLayout::GENERAL,
+            buffer,
+            &[region],
+        );
+    }
+
+    /// Hands the memory of the images back to the pool
+    pub unsafe fn destroy(&mut self, bundle: Bundle, pool: &mut ImagePool) {
+        if !self.destroyed {
+            for image in [
+                &mut self.depth,
+                &mut self.history,
+                &mut self.depth_history,
+                &mut self.object_id,
+            ] {
+                bundle.device.destroy_image_view(image.view, None);
+                bundle.device.destroy_image(image.image, None);
+                if let Some(memory) = image.memory.take() {
+                    pool.release(memory);
+                }
+            }
+            self.destroyed = true;
+        } else {
+            warn!("TemporalHistory already destroyed");
+        }
+    }
+}
+
+impl Drop for TemporalHistory {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked TemporalHistory");
+        }
+    }
+}
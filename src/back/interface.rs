@@ -0,0 +1,461 @@
+//! Layouts of the buffers and push constants shared with the shader.
+//!
+//! Every struct here is declared once through `glsl_struct!`, which also
+//! produces its GLSL declaration and asserts at compile time that each field
+//! sits at the offset std430 gives it. `build.rs` includes this file and
+//! checks the declarations against the committed
+//! `assets/shaders/interface.glsl`, rewriting it with
+//! `PATHRS_REGENERATE_INTERFACE=1`, so the shader never redeclares them by
+//! hand.
+//!
+//! The file is compiled by the build script as well, it must only depend
+//! on the standard library.
+
+use std::mem::{align_of, offset_of, size_of};
+
+pub const OBJECT_TYPE_SPHERE: u32 = 1;
+pub const OBJECT_TYPE_SDF: u32 = 2;
+
+pub const SDF_SHAPE_TORUS: u32 = 1;
+pub const SDF_SHAPE_GYROID: u32 = 2;
+pub const SDF_SHAPE_MANDELBULB: u32 = 3;
+
+pub const LIGHT_TYPE_POINT: u32 = 1;
+pub const LIGHT_TYPE_DIRECTIONAL: u32 = 2;
+pub const LIGHT_TYPE_AREA: u32 = 3;
+
+// Texture slot without a texture
+pub const NO_TEXTURE: u32 = u32::MAX;
+// Object id of pixels that hit nothing
+pub const NO_OBJECT: u32 = u32::MAX;
+// First invalid pixel index of an image without any
+pub const NO_PIXEL: u32 = u32::MAX;
+pub const MAX_PATH_VERTICES: usize = 16;
+// Pixels along the side of a tile, the workgroup size of the tracing shader
+pub const TILE_SIZE: u32 = 16;
+// Bins of the luminance histogram of the auto exposure, one per thread of
+// the workgroup adapting the exposure
+pub const HISTOGRAM_BINS: u32 = 256;
+
+/// Field type with a GLSL counterpart under std430
+pub trait GlslType {
+    const ALIGN: usize;
+    const SIZE: usize;
+
+    /// Declaration of a field of the type, None if std430 inserts it by itself
+    fn declare(name: &str) -> Option<String>;
+}
+
+/// Struct declared through `glsl_struct!`
+pub trait GlslStruct {
+    const NAME: &'static str;
+    // Largest alignment of the fields
+    const ALIGN: usize;
+
+    #[allow(dead_code)] // Only called by the build script
+    fn declare_struct() -> String;
+}
+
+macro_rules! glsl_types {
+    ($($ty:ty => $glsl:literal, $align:literal, $size:literal;)*) => {
+        $(
+            impl GlslType for $ty {
+                const ALIGN: usize = $align;
+                const SIZE: usize = $size;
+
+                fn declare(name: &str) -> Option<String> {
+                    Some(format!("{} {}", $glsl, name))
+                }
+            }
+        )*
+    };
+}
+
+glsl_types! {
+    f32 => "float", 4, 4;
+    u32 => "uint", 4, 4;
+    i32 => "int", 4, 4;
+    [u32; 2] => "uvec2", 8, 8;
+    [u32; 4] => "uvec4", 16, 16;
+    [f32; 4] => "vec4", 16, 16;
+    [[f32; 4]; 4] => "mat4", 16, 64;
+}
+
+impl<T: GlslStruct, const N: usize> GlslType for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+    const SIZE: usize = N * size_of::<T>();
+
+    fn declare(name: &str) -> Option<String> {
+        Some(format!("{} {}[{}]", T::NAME, name, N))
+    }
+}
+
+/// Explicit padding in front of a field std430 aligns further than repr(C)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Padding<const N: usize>([u32; N]);
+
+impl<const N: usize> Default for Padding<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> GlslType for Padding<N> {
+    // Takes no room in the GLSL struct, the next field is aligned instead
+    const ALIGN: usize = 1;
+    const SIZE: usize = 0;
+
+    fn declare(_: &str) -> Option<String> {
+        None
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+macro_rules! glsl_struct {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident as $glsl:ident {
+            $(pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl GlslStruct for $name {
+            const NAME: &'static str = stringify!($glsl);
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(align = max(align, <$ty as GlslType>::ALIGN);)*
+                align
+            };
+
+            fn declare_struct() -> String {
+                let mut glsl = format!("struct {}\n{{\n", Self::NAME);
+                $(
+                    if let Some(declaration) = <$ty as GlslType>::declare(stringify!($field)) {
+                        glsl += &format!("    {};\n", declaration);
+                    }
+                )*
+                glsl += "};\n";
+                glsl
+            }
+        }
+
+        const _: () = {
+            let mut offset: usize = 0;
+            $(
+                offset = offset.next_multiple_of(<$ty as GlslType>::ALIGN);
+                assert!(
+                    offset_of!($name, $field) == offset,
+                    concat!(
+                        stringify!($name), ".", stringify!($field),
+                        " is not at its std430 offset"
+                    )
+                );
+                offset += <$ty as GlslType>::SIZE;
+            )*
+            // No trailing fields unknown to the shader
+            let align = max(align_of::<$name>(), <$name as GlslStruct>::ALIGN);
+            assert!(
+                size_of::<$name>() == offset.next_multiple_of(align),
+                concat!(stringify!($name), " is larger than its std430 layout")
+            );
+        };
+    };
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug, PartialEq)]
+    #[repr(C)]
+    #[repr(align(128))]
+    pub struct SSBOConfigData as Config {
+        pub camera_transform: [[f32; 4]; 4],
+        // Filled in by the pipeline, camera the history was traced with
+        pub prev_camera_transform: [[f32; 4]; 4],
+        pub camera_fov: f32,
+        pub objects_count: u32,
+        pub samples_count: u32,
+        pub max_bounces: u32,
+        pub sky_color_top: [f32; 4],
+        pub sky_color_bottom: [f32; 4],
+        pub ground_color: [f32; 4],
+        // If set, jitter and first bounce are driven by blue noise
+        pub blue_noise: u32,
+        // Index in the bindless textures array, filled in by the pipeline
+        pub blue_noise_texture: u32,
+        // Thin lens diameter, 0 for a pinhole camera
+        pub camera_aperture: f32,
+        pub camera_focus_distance: f32,
+        pub lights_count: u32,
+        // If set, the lights are sampled at every bounce
+        pub next_event_estimation: u32,
+        // Total number of instance transforms
+        pub instances_count: u32,
+        pub volumes_count: u32,
+        // Upper bound of the extinction, sum of the volume maximums
+        pub volumes_majorant: f32,
+        // Filled in by the pipeline once the environment map is loaded
+        pub environment: u32,
+        pub environment_texture: u32,
+        pub environment_width: u32,
+        pub environment_height: u32,
+        pub environment_intensity: f32,
+        // If unset, every frame replaces the image instead of adding to it
+        pub temporal_accumulation: u32,
+        // Scale of the subpixel jitter of the camera rays, 0 samples the
+        // pixel centers only
+        pub jitter_strength: f32,
+        // Samples per pixel after which a tile is converged and no longer
+        // traced, 0 traces every tile
+        pub converged_samples: u32,
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    #[derive(Copy)]
+    pub struct SSBOObjectData as Object {
+        pub object_type: u32,
+        // Index in the materials SSBO
+        pub material_index: u32,
+        // If set, the object is rendered only through its instances
+        pub instanced: u32,
+        pub sdf_shape: u32,
+        // Position, for SDFs: bounding radius in w component
+        pub data1: [f32; 4],
+        // For spheres: radius in x component, for SDFs: shape parameters
+        pub data2: [f32; 4],
+        // Albedo, normal and roughness textures. Index in the scene textures,
+        // replaced by the index in the bindless textures array on upload.
+        pub textures: [u32; 4],
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    #[derive(Copy)]
+    pub struct SSBOInstanceData as Instance {
+        // Index of the instanced object
+        pub object_index: u32,
+        pub _pad: Padding<3>,
+        // Rays are intersected in the object space of the instance
+        pub world_to_object: [[f32; 4]; 4],
+    }
+}
+
+glsl_struct! {
+    // Homogeneous medium filling an axis aligned box
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    #[derive(Copy)]
+    pub struct SSBOVolumeData as Volume {
+        // w: anisotropy of the phase function
+        pub box_min: [f32; 4],
+        pub box_max: [f32; 4],
+        // Absorption + scattering
+        pub extinction: [f32; 4],
+        pub scattering: [f32; 4],
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    #[derive(Copy)]
+    pub struct SSBOMaterialData as Material {
+        pub albedo: [f32; 4],
+        // rgb: emission color, w: emission strength
+        pub emission: [f32; 4],
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    #[derive(Copy)]
+    pub struct SSBOLightData as Light {
+        pub light_type: u32,
+        pub _pad: Padding<3>,
+        // rgb: color, w: intensity
+        pub color: [f32; 4],
+        // Point: position, directional: direction, area: corner
+        pub data1: [f32; 4],
+        // Area: first edge
+        pub data2: [f32; 4],
+        // Area: second edge
+        pub data3: [f32; 4],
+    }
+}
+
+glsl_struct! {
+    // Single bounce of the picked path, written for the ray debugger
+    #[derive(Default, Clone, Copy, Debug)]
+    #[repr(C)]
+    #[repr(align(16))]
+    pub struct SSBOPathVertexData as path_vertex_s {
+        pub origin: [f32; 4],
+        pub direction: [f32; 4],
+        // w: ray distance, negative on miss
+        pub point: [f32; 4],
+        // w: 1 if the front face was hit
+        pub normal: [f32; 4],
+        // w: cosine term applied to the throughput
+        pub albedo: [f32; 4],
+        pub emission: [f32; 4],
+        // After the bounce
+        pub throughput: [f32; 4],
+        // Gathered so far
+        pub radiance: [f32; 4],
+        // x: hit object index
+        pub info: [u32; 4],
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    pub struct SSBOPickData as PickedPixel {
+        // Distance to the surface under the picked pixel along the camera
+        // view axis, negative if nothing was hit
+        pub depth: f32,
+        pub vertex_count: u32,
+        pub pixel: [u32; 2],
+        // Copied from the object id image, NO_OBJECT if nothing was hit
+        pub object: u32,
+        // Path of the first sample of the pixel
+        pub vertices: [SSBOPathVertexData; MAX_PATH_VERTICES],
+    }
+}
+
+glsl_struct! {
+    #[derive(Default, Clone, Debug)]
+    #[repr(C)]
+    pub struct SSBOInvalidPixelsData as InvalidPixels {
+        pub nan_count: u32,
+        pub inf_count: u32,
+        // Smallest row-major index of an invalid pixel, NO_PIXEL if none
+        pub first_pixel: u32,
+    }
+}
+
+glsl_struct! {
+    /// Header of the tile queue, followed by the indices of the queued
+    /// tiles, `y << 16 | x` each
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C)]
+    pub struct SSBOTileQueueData as TileQueue {
+        // VkDispatchIndirectCommand of the tracing, a workgroup per tile
+        pub groups_x: u32,
+        pub groups_y: u32,
+        pub groups_z: u32,
+    }
+}
+
+glsl_struct! {
+    /// Exposure adapted by the auto exposure, followed by the
+    /// HISTOGRAM_BINS pixel counts of the luminance histogram
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C)]
+    pub struct SSBOExposureData as Exposure {
+        // In stops, added to the exposure of the tonemapping
+        pub exposure: f32,
+        // Unset until the first adaptation, which jumps to the target
+        pub initialized: u32,
+    }
+}
+
+glsl_struct! {
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C)]
+    #[repr(align(128))]
+    pub struct PushConstantsData as Constants {
+        // Reset when the accumulated history is invalidated
+        pub frame_index: u32,
+        // Samples per pixel accumulated before this dispatch
+        pub accumulated_samples: u32,
+        // Random number generator seed of the dispatch
+        pub seed: u32,
+        // If set, the pixel is overwritten instead of blended
+        pub invalidate: u32,
+        // If set, the pixel is blended with the history reprojected from
+        // the previous camera
+        pub reproject: u32,
+        // Pixel which writes its depth and path into the pick buffer, negative for none
+        pub pick_x: i32,
+        pub pick_y: i32,
+        // Offset of the traced region in the whole frame, see `FrameRegion`
+        pub region_x: i32,
+        pub region_y: i32,
+        // Size of the traced image
+        pub image_width: u32,
+        pub image_height: u32,
+        // Size of the whole frame, the image size unless tracing a region
+        pub frame_width: u32,
+        pub frame_height: u32,
+    }
+}
+
+/// Contents of `assets/shaders/interface.glsl`
+#[allow(dead_code)] // Only called by the build script
+pub fn header() -> String {
+    let mut glsl =
+        String::from("// Generated by build.rs from src/back/interface.rs, do not edit\n\n");
+    macro_rules! define {
+        ($glsl:ident; $($name:ident),*) => {
+            $($glsl += &format!("#define {} {}u\n", stringify!($name), $name);)*
+        };
+    }
+    define!(
+        glsl;
+        OBJECT_TYPE_SPHERE,
+        OBJECT_TYPE_SDF,
+        SDF_SHAPE_TORUS,
+        SDF_SHAPE_GYROID,
+        SDF_SHAPE_MANDELBULB,
+        LIGHT_TYPE_POINT,
+        LIGHT_TYPE_DIRECTIONAL,
+        LIGHT_TYPE_AREA,
+        NO_TEXTURE,
+        NO_OBJECT,
+        NO_PIXEL,
+        HISTOGRAM_BINS
+    );
+    glsl += &format!("#define MAX_PATH_VERTICES {}\n", MAX_PATH_VERTICES);
+    glsl += &format!("#define TILE_SIZE {}\n", TILE_SIZE);
+
+    for declaration in [
+        SSBOConfigData::declare_struct(),
+        SSBOObjectData::declare_struct(),
+        SSBOInstanceData::declare_struct(),
+        SSBOVolumeData::declare_struct(),
+        SSBOMaterialData::declare_struct(),
+        SSBOLightData::declare_struct(),
+        SSBOPathVertexData::declare_struct(),
+        SSBOPickData::declare_struct(),
+        SSBOInvalidPixelsData::declare_struct(),
+        SSBOTileQueueData::declare_struct(),
+        SSBOExposureData::declare_struct(),
+        PushConstantsData::declare_struct(),
+    ] {
+        glsl += "\n";
+        glsl += &declaration;
+    }
+    glsl
+}
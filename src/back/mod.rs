@@ -1,18 +1,40 @@
+mod bindless;
+mod compare;
+mod environment;
+mod exposure;
+mod frame_data;
+mod history;
+mod interface;
 pub mod pipeline;
+mod post;
 mod push_constants;
+mod scheduler;
 mod ssbo;
+mod tiles;
+mod variants;
 
-use crate::assets::AssetManager;
-use crate::back::pipeline::TracerPipeline;
+use crate::assets::{AssetLoad, AssetManager};
+use crate::back::compare::ComparePipeline;
+use crate::back::environment::EnvironmentMap;
+use crate::back::pipeline::{SceneData, TracerPipeline};
 use crate::back::push_constants::PushConstantsData;
+use crate::back::scheduler::SampleScheduler;
 use crate::back::ssbo::config::SSBOConfigData;
-use crate::back::ssbo::objects::{SSBOObjectData, SSBOObjectsData, MAX_OBJECTS};
+use crate::back::ssbo::instances::{SSBOInstanceData, SSBOInstancesData};
+use crate::back::ssbo::lights::{SSBOLightData, SSBOLightsData};
+use crate::back::ssbo::materials::{SSBOMaterialData, SSBOMaterialsData};
+use crate::back::ssbo::objects::{SSBOObjectData, SSBOObjectsData, NO_TEXTURE};
+use crate::back::ssbo::volumes::{SSBOVolumeData, SSBOVolumesData};
+use crate::camera;
 use crate::common::capabilities::{DeviceCapabilities, InstanceCapabilities};
+use crate::common::frame_graph::SyncPoint;
 use crate::common::queue::QueueFamily;
-use crate::config::{TracerConfig, TracerConfigInner};
+use crate::config::{Light, Object, ObjectTextures, PickResult, TracerConfig, TracerConfigInner};
+use crate::error::{TracerError, TracerResult};
 use crate::front::QueueFamilyIndices;
 use crate::tracer::{Bundle, TracerProfile};
 use ash::{vk, Device, Entry, Instance};
+use log::{info, warn};
 use std::ffi::c_char;
 
 #[allow(dead_code)]
@@ -27,11 +49,72 @@ pub struct TracerSlotImage {
     pub format: vk::Format,
 }
 
+/// Traced image handed to the front-end. The dispatch writing it may
+/// still be running: every submission reading the image waits for `ready`
+/// on the GPU, CPU reads wait for it on the host. The image is not
+/// traced into again before the point returned by `Front::present`.
 #[allow(dead_code)]
 pub struct TracerSlot {
     pub image: TracerSlotImage,
     pub descriptor_set: vk::DescriptorSet,
     pub index: usize,
+    // Reached once the image is fully traced
+    pub ready: SyncPoint,
+    // Second image of the A/B view, only shown by the windowed front
+    pub compare: Option<CompareSlot>,
+}
+
+/// Image traced with the compared settings, see `CompareConfig`. Same
+/// size and descriptor set layout as the main image of the slot.
+pub struct CompareSlot {
+    pub descriptor_set: vk::DescriptorSet,
+    pub ready: SyncPoint,
+    // Normalized position of the split, the main image is on the left
+    pub divider: f32,
+}
+
+pub const OUTPUT_BINDING: u32 = 0;
+pub const DEPTH_BINDING: u32 = 1;
+pub const HISTORY_BINDING: u32 = 2;
+pub const DEPTH_HISTORY_BINDING: u32 = 3;
+pub const OBJECT_ID_BINDING: u32 = 4;
+
+impl TracerSlot {
+    /// Layout of the slot descriptor set, shared by every pipeline reading the output image
+    pub fn descriptor_set_layout_bindings() -> [vk::DescriptorSetLayoutBinding<'static>; 5] {
+        [
+            // (set = 0, binding = 0, rgba32f) uniform image2D output_image;
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(OUTPUT_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT),
+            // (set = 0, binding = 1, r32f) uniform image2D depth_image;
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(DEPTH_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 0, binding = 2, rgba32f) uniform image2D history_image;
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(HISTORY_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 0, binding = 3, r32f) uniform image2D depth_history_image;
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(DEPTH_HISTORY_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            // (set = 0, binding = 4, r32ui) uniform uimage2D object_id_image;
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(OBJECT_ID_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ]
+    }
 }
 
 impl QueueFamilyIndices for BackQueueFamilyIndices {
@@ -45,35 +128,125 @@ impl QueueFamilyIndices for BackQueueFamilyIndices {
             },
             QueueFamily {
                 index: self.compute_family,
+                priorities: if self.background_compute {
+                    vec![1.0, BACKGROUND_COMPUTE_PRIORITY]
+                } else {
+                    vec![1.0]
+                },
+            },
+            QueueFamily {
+                index: self.transfer_family,
                 priorities: vec![1.0],
             },
         ]
     }
 
-    unsafe fn into_queues(self, device: &Device) -> anyhow::Result<BackQueues> {
+    unsafe fn into_queues(self, device: &Device) -> TracerResult<BackQueues> {
         let graphics_queue = device.get_device_queue(self.graphics_family, 0);
         let compute_queue = device.get_device_queue(self.compute_family, 0);
+        let background_compute_queue = if self.background_compute {
+            device.get_device_queue(self.compute_family, 1)
+        } else {
+            compute_queue
+        };
+        let transfer_queue = device.get_device_queue(self.transfer_family, 0);
 
         Ok(BackQueues {
             indices: self,
             graphics_queue,
             compute_queue,
+            background_compute_queue,
+            transfer_queue,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BackQueueFamilyIndices {
     pub graphics_family: u32,
     pub compute_family: u32,
+    // Dedicated transfer family if the device has one, compute family otherwise
+    pub transfer_family: u32,
+    // Second, lower priority queue of the compute family is requested
+    pub background_compute: bool,
+}
+
+impl BackQueueFamilyIndices {
+    /// Picks the families from their properties. The last family of every
+    /// kind wins, so a compute-only family (async compute) is preferred
+    /// over the graphics one when the device has it.
+    pub fn select(
+        properties: &[vk::QueueFamilyProperties],
+        low_latency: bool,
+    ) -> TracerResult<Self> {
+        let mut graphics_queue_index = None;
+        let mut compute_queue_index = None;
+
+        for (i, queue_family) in properties.iter().enumerate() {
+            if queue_family.queue_count > 0 {
+                if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+                    graphics_queue_index = Some(i as u32);
+                }
+
+                if queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                    && queue_family.timestamp_valid_bits > 0
+                {
+                    compute_queue_index = Some(i as u32);
+                }
+            }
+        }
+
+        let compute_family = compute_queue_index.ok_or(TracerError::NoQueueFamily("compute"))?;
+        let transfer_family =
+            QueueFamily::find_dedicated_transfer(properties).unwrap_or(compute_family);
+        let background_compute = low_latency && properties[compute_family as usize].queue_count > 1;
+        if low_latency && !background_compute {
+            warn!("Low latency mode requires a compute family with more than one queue");
+        }
+
+        Ok(Self {
+            graphics_family: graphics_queue_index.ok_or(TracerError::NoQueueFamily("graphics"))?,
+            compute_family,
+            transfer_family,
+            background_compute,
+        })
+    }
+
+    pub fn has_dedicated_transfer(&self) -> bool {
+        self.transfer_family != self.compute_family
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct BackQueues {
     pub indices: BackQueueFamilyIndices,
     pub graphics_queue: vk::Queue,
     pub compute_queue: vk::Queue,
+    // Accumulation frames, same as `compute_queue` without the low latency mode
+    pub background_compute_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
+}
+
+// Priority of the background compute queue, the others get 1.0
+const BACKGROUND_COMPUTE_PRIORITY: f32 = 0.5;
+
+// Lowest allowed internal resolution scale
+pub const MIN_RESOLUTION_SCALE: f32 = 0.5;
+
+/// Part of a larger frame traced by the back-end, so several back-ends
+/// can split a frame between them. The viewport is the size of the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRegion {
+    pub offset: glam::UVec2,
+    pub frame_size: glam::UVec2,
+}
+
+// Accumulation to continue from, see `Back::restore_accumulation`
+struct RestoredAccumulation {
+    size: glam::UVec2,
+    pixels: Vec<u8>,
+    frame_index: u64,
 }
 
 pub struct Back {
@@ -81,35 +254,76 @@ pub struct Back {
 
     config: TracerConfig,
     frame_index: u64,
+    // Presented frames since the last dispatch, see `frames_per_dispatch`
+    frames_since_dispatch: u32,
+    // Dispatches per frame with `adaptive_samples`
+    scheduler: SampleScheduler,
+    viewport: glam::UVec2,
+    resolution_scale: f32,
+    // Camera aspect ratio override, the image is letterboxed in the viewport
+    aspect_ratio: Option<f32>,
+    // Traced size independent of the viewport, see `TracerConfigInner`
+    render_size: Option<glam::UVec2>,
+    // Size of the traced images
+    size: glam::UVec2,
+    // Config of the last update, to tell camera movement from other changes
+    last_config: Option<SSBOConfigData>,
+    // Set when the accumulated images were recreated
+    invalidate_history: bool,
+    region: Option<FrameRegion>,
+    asset_manager: AssetManager,
+    // Asset of the current environment map, rebuilt only once it changes
+    environment_asset: Option<String>,
+    // Environment map still being loaded, traced without it meanwhile
+    environment_load: Option<AssetLoad<EnvironmentMap>>,
+    // Whether to trace while the assets load instead of waiting for them
+    async_assets: bool,
+    // Kept to create the compare pipeline with
+    queues: BackQueues,
+    images_custom_usage: vk::ImageUsageFlags,
+    // Second accumulation of the A/B view, None unless `compare` is set
+    compare: Option<ComparePipeline>,
+    restore: Option<RestoredAccumulation>,
 }
 
 impl Back {
     pub unsafe fn get_required_instance_extensions(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
     pub unsafe fn get_required_instance_layers(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
     pub unsafe fn get_required_device_extensions(
-        _available: &Vec<String>,
-        _capabilities: &mut DeviceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
-        Ok(vec![ash::ext::buffer_device_address::NAME.as_ptr()])
+        available: &Vec<String>,
+        capabilities: &mut DeviceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        let mut required = vec![
+            ash::ext::buffer_device_address::NAME.as_ptr(),
+            ash::ext::descriptor_indexing::NAME.as_ptr(),
+        ];
+
+        // Optional, only needed to import textures from other processes
+        if available.contains(&ash::khr::external_memory_fd::NAME.to_str()?.to_string()) {
+            info!("External memory extension required");
+            required.push(ash::khr::external_memory_fd::NAME.as_ptr());
+            capabilities.external_memory_fd = true;
+        }
+        Ok(required)
     }
 
     pub unsafe fn is_device_suitable(
         _entry: &Entry,
         _instance: &Instance,
         _physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<bool> {
+    ) -> TracerResult<bool> {
         Ok(true)
     }
 
@@ -118,15 +332,25 @@ impl Back {
         _instance: &Instance,
         _physical_device: vk::PhysicalDevice,
         create_info: vk::DeviceCreateInfo,
-        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> anyhow::Result<Device>,
-    ) -> anyhow::Result<Device> {
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
         let mut device_address_info =
             vk::PhysicalDeviceBufferDeviceAddressFeatures::default().buffer_device_address(true);
         let mut host_query_reset_info =
             vk::PhysicalDeviceHostQueryResetFeatures::default().host_query_reset(true);
+        let mut timeline_semaphore_info =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true);
+        let mut descriptor_indexing_info = vk::PhysicalDeviceDescriptorIndexingFeatures::default()
+            .runtime_descriptor_array(true)
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .descriptor_binding_partially_bound(true)
+            .descriptor_binding_variable_descriptor_count(true)
+            .descriptor_binding_sampled_image_update_after_bind(true);
         let create_info = create_info
             .push_next(&mut device_address_info)
-            .push_next(&mut host_query_reset_info);
+            .push_next(&mut host_query_reset_info)
+            .push_next(&mut timeline_semaphore_info)
+            .push_next(&mut descriptor_indexing_info);
         on_patched(create_info)
     }
 
@@ -134,31 +358,10 @@ impl Back {
         _entry: &Entry,
         instance: &Instance,
         device: vk::PhysicalDevice,
-    ) -> anyhow::Result<BackQueueFamilyIndices> {
-        let mut graphics_queue_index = None;
-        let mut compute_queue_index = None;
-
+        low_latency: bool,
+    ) -> TracerResult<BackQueueFamilyIndices> {
         let queue_family_properties = instance.get_physical_device_queue_family_properties(device);
-        for (i, queue_family) in queue_family_properties.iter().enumerate() {
-            if queue_family.queue_count > 0 {
-                if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    graphics_queue_index = Some(i as u32);
-                }
-
-                if queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE)
-                    && queue_family.timestamp_valid_bits > 0
-                {
-                    compute_queue_index = Some(i as u32);
-                }
-            }
-        }
-
-        Ok(BackQueueFamilyIndices {
-            graphics_family: graphics_queue_index
-                .ok_or_else(|| anyhow::anyhow!("No graphics queue family found"))?,
-            compute_family: compute_queue_index
-                .ok_or_else(|| anyhow::anyhow!("No compute queue family found"))?,
-        })
+        BackQueueFamilyIndices::select(&queue_family_properties, low_latency)
     }
 
     pub unsafe fn new(
@@ -168,33 +371,230 @@ impl Back {
         queues: BackQueues,
         config: TracerConfig,
         images_custom_usage: vk::ImageUsageFlags,
-    ) -> anyhow::Result<Self> {
-        let pipeline =
-            TracerPipeline::new(bundle, asset_manager, viewport, queues, images_custom_usage)?;
+    ) -> TracerResult<Self> {
+        let resolution_scale = config.0.borrow().resolution_scale;
+        let aspect_ratio = config.0.borrow().camera.aspect_ratio;
+        let render_size = config.0.borrow().render_size;
+        let size = Self::internal_size(
+            camera::fit_aspect(render_size.unwrap_or(viewport), aspect_ratio),
+            resolution_scale,
+        );
+        let pipeline = TracerPipeline::new(
+            bundle,
+            asset_manager.clone(),
+            size,
+            queues.clone(),
+            images_custom_usage,
+            config.0.borrow().watchdog_timeout,
+            config.0.borrow().profiler_friendly,
+        )?;
 
         Ok(Self {
             pipeline,
             config,
             frame_index: 0,
+            frames_since_dispatch: 0,
+            scheduler: SampleScheduler::default(),
+            viewport,
+            resolution_scale,
+            aspect_ratio,
+            render_size,
+            size,
+            last_config: None,
+            invalidate_history: false,
+            region: None,
+            asset_manager,
+            environment_asset: None,
+            environment_load: None,
+            async_assets: false,
+            queues,
+            images_custom_usage,
+            compare: None,
+            restore: None,
         })
     }
 
-    pub unsafe fn present(&mut self, bundle: Bundle) -> anyhow::Result<TracerSlot> {
+    fn internal_size(viewport: glam::UVec2, resolution_scale: f32) -> glam::UVec2 {
+        let scale = resolution_scale.clamp(MIN_RESOLUTION_SCALE, 1.0);
+        (viewport.as_vec2() * scale)
+            .round()
+            .as_uvec2()
+            .max(glam::UVec2::ONE)
+    }
+
+    // Size of the image before the resolution scale. Letterboxed into the
+    // viewport, the rest are black bars. A region is a part of a frame,
+    // only the whole frame can be letterboxed or have a fixed size.
+    fn image_size(&self) -> glam::UVec2 {
+        self.image_size_of(self.render_size, self.aspect_ratio)
+    }
+
+    fn image_size_of(
+        &self,
+        render_size: Option<glam::UVec2>,
+        aspect_ratio: Option<f32>,
+    ) -> glam::UVec2 {
+        let aspect_ratio = aspect_ratio.filter(|_| self.region.is_none());
+        let outer = render_size
+            .filter(|_| self.region.is_none())
+            .unwrap_or(self.viewport);
+        camera::fit_aspect(outer, aspect_ratio)
+    }
+
+    /// Size the next frame is traced at, the config changes it only once
+    /// presented
+    pub fn traced_size(&self) -> glam::UVec2 {
+        let config = self.config.0.borrow();
+        Self::internal_size(
+            self.image_size_of(config.render_size, config.camera.aspect_ratio),
+            config.resolution_scale,
+        )
+    }
+
+    unsafe fn resize_pipeline(&mut self, bundle: Bundle) -> TracerResult<()> {
+        self.size = Self::internal_size(self.image_size(), self.resolution_scale);
+        self.pipeline.resize(bundle, self.size)?;
+        if let Some(compare) = &mut self.compare {
+            compare.resize(bundle, self.size)?;
+        }
+
+        // New images hold no accumulated samples
+        self.invalidate_history = true;
+        Ok(())
+    }
+
+    pub unsafe fn present(&mut self, bundle: Bundle) -> TracerResult<TracerSlot> {
+        let (resolution_scale, aspect_ratio, render_size) = {
+            let config = self.config.0.borrow();
+            (
+                config.resolution_scale,
+                config.camera.aspect_ratio,
+                config.render_size,
+            )
+        };
+        self.resolution_scale = resolution_scale;
+        self.aspect_ratio = aspect_ratio;
+        self.render_size = render_size;
+        // Also catches a region set since the last frame
+        if Self::internal_size(self.image_size(), self.resolution_scale) != self.size {
+            self.resize_pipeline(bundle)?;
+        }
+
         let mut config = self.config.0.borrow_mut();
 
-        let invalidate = config.updated || config.objects_updated;
-        if invalidate {
-            self.frame_index = 0;
+        match (&config.compare, &mut self.compare) {
+            (Some(_), None) => {
+                self.compare = Some(ComparePipeline::new(
+                    bundle,
+                    &self.asset_manager,
+                    self.size,
+                    self.queues.clone(),
+                    self.images_custom_usage,
+                    &config,
+                )?);
+            }
+            (None, Some(compare)) => {
+                compare.destroy(bundle);
+                self.compare = None;
+            }
+            _ => {}
+        }
+
+        self.pipeline
+            .set_check_invalid_pixels(config.check_invalid_pixels);
+        self.pipeline.set_post(bundle, &config.post)?;
+        if let Some(compare) = &mut self.compare {
+            compare.set_post(bundle, &config.post)?;
+        }
+        if std::mem::take(&mut config.defragment_request) {
+            self.pipeline.defragment(bundle)?;
+            self.invalidate_history = true;
+        }
+        if let Some(picked) = self.pipeline.take_picked(bundle)? {
+            config.picked = Some(config.with_object_indices(picked.as_result()));
+        }
+        let size = self.size;
+        // The pick position is relative to the whole viewport, clicks
+        // on the letterbox bars hit nothing
+        let (_, shown) = camera::letterbox(self.viewport, self.image_size());
+        let image = shown.as_vec2() / self.viewport.as_vec2();
+        let pick = config
+            .pick_request
+            .take()
+            .map(|position| (position - (1.0 - image) * 0.5) / image)
+            .filter(|position| {
+                position.cmpge(glam::Vec2::ZERO).all() && position.cmplt(glam::Vec2::ONE).all()
+            })
+            .map(|position| {
+                (position * size.as_vec2())
+                    .as_uvec2()
+                    .min(size - glam::UVec2::ONE)
+            });
+
+        let environment_asset = config
+            .environment
+            .as_ref()
+            .map(|environment| environment.asset.clone());
+        if environment_asset != self.environment_asset {
+            // Replaces a load still in progress
+            self.environment_load = config
+                .environment
+                .as_ref()
+                .map(|environment| EnvironmentMap::load_async(&self.asset_manager, environment));
+            if self.environment_load.is_none() {
+                self.pipeline.set_environment(bundle, None)?;
+                if let Some(compare) = &mut self.compare {
+                    compare.set_environment(bundle, None)?;
+                }
+                config.updated = true;
+            }
+            self.environment_asset = environment_asset;
+        }
+        if let Some(load) = &mut self.environment_load {
+            if !self.async_assets {
+                load.wait();
+            }
+            if let Some(map) = load.poll() {
+                // A broken environment should not take the whole scene down
+                let map = map
+                    .map_err(|e| warn!("Failed to load environment {}: {}", load.id(), e))
+                    .ok();
+                if let Some(compare) = &mut self.compare {
+                    compare.set_environment(bundle, map.clone())?;
+                }
+                self.pipeline.set_environment(bundle, map)?;
+                self.environment_load = None;
+                // The config references the map
+                config.updated = true;
+            }
         }
-        let push_constants = PushConstantsData::new(self.frame_index as u32);
 
-        // For now do not support changing objects in runtime
-        let objects_data = if config.objects_updated {
+        for imported in std::mem::take(&mut config.import_requests) {
+            // A texture failing to import should not take the whole scene down
+            if let Err(e) = self.pipeline.import_texture(bundle, &imported) {
+                warn!("Failed to import texture {}: {}", imported.name, e);
+            }
+            // The objects referencing it are uploaded again
+            config.objects_updated = true;
+        }
+
+        // Instances, lights and materials are uploaded along with the
+        // objects, all of them are scene changes
+        let scene_data = if config.objects_updated {
             config.objects_updated = false;
-            Some(config.as_objects())
+            Some(config.as_scene())
         } else {
             None
         };
+        let mut edited = std::mem::take(&mut config.objects_edited);
+        edited.sort_unstable();
+        edited.dedup();
+        edited.retain(|index| *index < config.objects.len());
+        let edited_objects: Vec<_> = if scene_data.is_some() {
+            vec![]
+        } else {
+            config.as_edited_objects(&edited)
+        };
 
         let config_data = if config.updated {
             config.updated = false;
@@ -203,61 +603,458 @@ impl Back {
             None
         };
 
-        self.frame_index += 1;
+        // If only the camera has moved, the accumulated samples are
+        // reprojected into the new view instead of being thrown away
+        let mut reproject = config.temporal_reprojection
+            && !self.invalidate_history
+            && scene_data.is_none()
+            && edited_objects.is_empty()
+            && match (&config_data, &self.last_config) {
+                (Some(new), Some(old)) => new.is_camera_moved(old),
+                _ => false,
+            };
+        let mut invalidate = std::mem::take(&mut self.invalidate_history)
+            || scene_data.is_some()
+            || !edited_objects.is_empty()
+            || (config_data.is_some() && !reproject);
+        // The environment map would invalidate the restored samples again
+        if self.environment_load.is_none() {
+            if let Some(restore) = self.restore.take() {
+                if restore.size == size {
+                    self.pipeline
+                        .restore_accumulation(bundle, &restore.pixels)?;
+                    self.frame_index = restore.frame_index;
+                    invalidate = false;
+                    reproject = false;
+                } else {
+                    warn!(
+                        "Restored accumulation is {:?}, traced images are {:?}, starting over",
+                        restore.size, size
+                    );
+                }
+            }
+        }
+        if invalidate {
+            self.frame_index = 0;
+        }
+        if config_data.is_some() {
+            self.last_config = config_data.clone();
+        }
+        let mut push_constants =
+            PushConstantsData::new(self.frame_index as u32, config.samples_count, size);
+        if let Some(region) = self.region {
+            // The internal resolution scales the whole frame
+            let scale = size.as_vec2() / self.viewport.as_vec2();
+            push_constants = push_constants.with_region(
+                (region.offset.as_vec2() * scale).round().as_uvec2(),
+                (region.frame_size.as_vec2() * scale).round().as_uvec2(),
+            );
+        }
+
+        // Updates are traced right away, the accumulation only every
+        // `frames_per_dispatch` presented frames
+        let updated = config_data.is_some()
+            || scene_data.is_some()
+            || !edited_objects.is_empty()
+            || invalidate
+            || pick.is_some();
+        self.frames_since_dispatch += 1;
+        let dispatches =
+            if updated || self.frames_since_dispatch >= config.frames_per_dispatch.max(1) {
+                self.frames_since_dispatch = 0;
+                match &config.adaptive_samples {
+                    Some(adaptive) => self.scheduler.schedule(
+                        adaptive,
+                        config.samples_count,
+                        self.pipeline.render_time(),
+                    ),
+                    None => config.dispatches_per_frame.max(1),
+                }
+            } else {
+                0
+            };
+        // Every dispatch accumulates a frame
+        self.frame_index += dispatches as u64;
+
+        // Follows the updates of the main side, traced first so both are
+        // submitted before the front waits for them
+        let compare = match (&mut self.compare, &config.compare) {
+            (Some(pipeline), Some(compare)) => Some(pipeline.present(
+                bundle,
+                compare,
+                &config,
+                (config_data.is_some(), scene_data.is_some(), &edited),
+                size,
+                dispatches,
+                invalidate,
+                reproject,
+            )?),
+            _ => None,
+        };
 
-        self.pipeline.present(
+        let slot = self.pipeline.present(
             bundle,
             config_data,
-            objects_data,
+            scene_data,
+            edited_objects,
             push_constants,
+            dispatches,
             invalidate,
-        )
+            reproject,
+            pick,
+        )?;
+        Ok(TracerSlot { compare, ..slot })
+    }
+
+    /// Dispatches accumulated into the current image
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Continues the accumulation from RGBA32F pixels of the given size,
+    /// taken after `frame_index` dispatches. Applied with the next frame
+    /// once the assets are loaded, replacing its invalidation.
+    pub fn restore_accumulation(
+        &mut self,
+        size: glam::UVec2,
+        pixels: Vec<u8>,
+        frame_index: u64,
+    ) -> TracerResult<()> {
+        if pixels.len() != (size.x * size.y) as usize * 16 {
+            return Err(TracerError::FrameSize);
+        }
+        self.restore = Some(RestoredAccumulation {
+            size,
+            pixels,
+            frame_index,
+        });
+        Ok(())
+    }
+
+    pub fn set_async_assets(&mut self, async_assets: bool) {
+        self.async_assets = async_assets;
+    }
+
+    pub fn set_region(&mut self, region: Option<FrameRegion>) {
+        if self.region != region {
+            self.region = region;
+            self.invalidate_history = true;
+        }
+    }
+
+    pub fn release(&mut self, index: usize, point: SyncPoint) {
+        if let Some(compare) = &mut self.compare {
+            compare.release(point);
+        }
+        self.pipeline.release(index, point);
     }
 
     pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if let Some(compare) = &mut self.compare {
+            compare.destroy(bundle);
+        }
         self.pipeline.destroy(bundle);
     }
 
-    pub unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> anyhow::Result<()> {
-        self.pipeline.resize(bundle, size)
+    pub unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> TracerResult<()> {
+        if self.viewport != size {
+            self.viewport = size;
+            // With a fixed render size the images and their samples stay
+            if Self::internal_size(self.image_size(), self.resolution_scale) != self.size {
+                self.resize_pipeline(bundle)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub unsafe fn snapshot(
+        &mut self,
+        bundle: Bundle,
+    ) -> TracerResult<Option<(glam::UVec2, Vec<u8>)>> {
+        self.pipeline.snapshot(bundle)
+    }
+
+    pub unsafe fn accumulation(
+        &mut self,
+        bundle: Bundle,
+    ) -> TracerResult<Option<(glam::UVec2, Vec<u8>)>> {
+        self.pipeline.accumulation(bundle)
     }
 
     pub fn get_profile(&self) -> TracerProfile {
-        self.pipeline.get_profile()
+        let config = self.config.0.borrow();
+        TracerProfile {
+            adaptive_samples: config
+                .adaptive_samples
+                .as_ref()
+                .map(|_| self.scheduler.samples(config.samples_count)),
+            loading: self
+                .environment_load
+                .iter()
+                .map(|load| load.id().to_string())
+                .collect(),
+            ..self.pipeline.get_profile()
+        }
+    }
+
+    pub fn record_render_times(&mut self) {
+        self.pipeline.record_render_times();
+    }
+
+    pub fn take_render_times(&mut self) -> Vec<f32> {
+        self.pipeline.take_render_times()
     }
 }
 
 impl TracerConfigInner {
-    fn as_objects(&self) -> SSBOObjectsData {
-        let mut objects = [SSBOObjectData::default(); MAX_OBJECTS];
-        for (i, object) in self.objects.iter().enumerate() {
-            if i >= MAX_OBJECTS {
-                break;
+    fn as_scene(&self) -> SceneData {
+        let textures = self.texture_assets();
+        SceneData {
+            objects: self.as_objects(&textures),
+            instances: self.as_instances(),
+            lights: self.as_lights(),
+            materials: self.as_materials(),
+            volumes: self.as_volumes(),
+            textures,
+        }
+    }
+
+    // Every texture asset used by the objects, each once
+    fn texture_assets(&self) -> Vec<String> {
+        let mut assets: Vec<String> = vec![];
+        for asset in self
+            .objects
+            .iter()
+            .filter_map(Object::textures)
+            .flat_map(ObjectTextures::slots)
+            .flatten()
+        {
+            if !assets.iter().any(|known| known == asset) {
+                assets.push(asset.to_string());
             }
-            match object {
-                crate::config::Object::Sphere {
-                    center,
-                    radius,
-                    material,
-                } => {
-                    objects[i] = SSBOObjectData::new_sphere(*center, *radius, material);
-                }
+        }
+        assets
+    }
+
+    fn as_objects(&self, textures: &[String]) -> SSBOObjectsData {
+        self.visible_objects()
+            .into_iter()
+            .map(|index| self.as_object(index, textures))
+            .collect()
+    }
+
+    /// Edited objects with their slots, the hidden ones have none.
+    /// Textures of an edited object stay the same, the pipeline keeps
+    /// their slots from the last scene upload.
+    fn as_edited_objects(&self, edited: &[usize]) -> Vec<(usize, SSBOObjectData)> {
+        let visible = self.visible_objects();
+        edited
+            .iter()
+            .filter_map(|index| {
+                let slot = visible.iter().position(|visible| visible == index)?;
+                Some((slot, self.as_object(*index, &[])))
+            })
+            .collect()
+    }
+
+    /// The shader only knows the slots of the uploaded objects, the picked
+    /// ones are turned back into indices in the objects
+    fn with_object_indices(&self, mut picked: PickResult) -> PickResult {
+        let visible = self.visible_objects();
+        picked.object = picked.object.and_then(|slot| visible.get(slot).copied());
+        for vertex in picked
+            .path
+            .iter_mut()
+            .filter(|vertex| vertex.distance.is_some())
+        {
+            if let Some(index) = visible.get(vertex.object as usize) {
+                vertex.object = *index as u32;
             }
         }
+        picked
+    }
 
-        objects
+    fn as_object(&self, index: usize, textures: &[String]) -> SSBOObjectData {
+        match &self.objects[index] {
+            Object::Sphere {
+                center,
+                radius,
+                material,
+                textures: object_textures,
+                ..
+            } => SSBOObjectData::new_sphere(
+                *center,
+                *radius,
+                // Validated on load, fall back to the first one anyway
+                self.material_index(material).unwrap_or(0),
+                self.is_instanced(index),
+                object_textures.slots().map(|slot| {
+                    slot.and_then(|asset| textures.iter().position(|known| known == asset))
+                        .map_or(NO_TEXTURE, |position| position as u32)
+                }),
+            ),
+            Object::Sdf {
+                center,
+                shape,
+                material,
+                ..
+            } => SSBOObjectData::new_sdf(
+                *center,
+                shape,
+                self.material_index(material).unwrap_or(0),
+                self.is_instanced(index),
+            ),
+        }
+    }
+
+    fn as_instances(&self) -> SSBOInstancesData {
+        let visible = self.visible_objects();
+        self.instance_transforms()
+            .into_iter()
+            .filter_map(|(object, transform)| {
+                let slot = visible.iter().position(|index| *index == object)?;
+                Some(SSBOInstanceData::new(slot as u32, transform))
+            })
+            .collect()
+    }
+
+    fn as_volumes(&self) -> SSBOVolumesData {
+        self.volumes.iter().map(SSBOVolumeData::new).collect()
+    }
+
+    fn as_materials(&self) -> SSBOMaterialsData {
+        self.materials.values().map(SSBOMaterialData::new).collect()
+    }
+
+    fn as_lights(&self) -> SSBOLightsData {
+        self.lights
+            .iter()
+            .map(|light| match light {
+                Light::Point {
+                    position,
+                    color,
+                    intensity,
+                } => SSBOLightData::new_point(*position, *color, *intensity),
+                Light::Directional {
+                    direction,
+                    color,
+                    intensity,
+                } => SSBOLightData::new_directional(*direction, *color, *intensity),
+                Light::Area {
+                    corner,
+                    edge_u,
+                    edge_v,
+                    color,
+                    intensity,
+                } => SSBOLightData::new_area(*corner, *edge_u, *edge_v, *color, *intensity),
+            })
+            .collect()
     }
 
     fn as_config(&self) -> SSBOConfigData {
         SSBOConfigData {
             camera_transform: self.camera.as_transform().to_cols_array_2d(),
-            camera_fov: self.camera.fov,
-            objects_count: self.objects.len() as u32,
+            prev_camera_transform: Default::default(),
+            camera_fov: self.camera.vertical_fov(),
+            objects_count: self.visible_objects().len() as u32,
             samples_count: self.samples_count,
             max_bounces: self.max_bounces,
             sky_color_top: *self.sky_color_top.extend(0.0).as_ref(),
             sky_color_bottom: *self.sky_color_bottom.extend(0.0).as_ref(),
             ground_color: *self.ground_color.extend(0.0).as_ref(),
+            blue_noise: self.blue_noise as u32,
+            blue_noise_texture: 0,
+            camera_aperture: self.camera.aperture,
+            camera_focus_distance: self.camera.focus_distance,
+            lights_count: self.lights.len() as u32,
+            next_event_estimation: self.next_event_estimation as u32,
+            instances_count: self.as_instances().len() as u32,
+            volumes_count: self.volumes.len() as u32,
+            volumes_majorant: self
+                .volumes
+                .iter()
+                .map(|volume| volume.medium.extinction().max_element())
+                .sum(),
+            environment: 0,
+            environment_texture: 0,
+            environment_width: 0,
+            environment_height: 0,
+            environment_intensity: self
+                .environment
+                .as_ref()
+                .map_or(0.0, |environment| environment.intensity),
+            temporal_accumulation: self.temporal_accumulation as u32,
+            jitter_strength: self.jitter_strength,
+            converged_samples: self.converged_samples.unwrap_or(0),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::queue::test_family;
+
+    fn all() -> vk::QueueFlags {
+        vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER
+    }
+
+    // Graphics and compute family, compute-only family, transfer-only family
+    fn discrete() -> [vk::QueueFamilyProperties; 3] {
+        [
+            test_family(all(), 16),
+            test_family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 8),
+            test_family(vk::QueueFlags::TRANSFER, 2),
+        ]
+    }
+
+    #[test]
+    fn single_family_serves_everything() {
+        let indices = BackQueueFamilyIndices::select(&[test_family(all(), 1)], false).unwrap();
+        assert_eq!(indices.graphics_family, 0);
+        assert_eq!(indices.compute_family, 0);
+        assert_eq!(indices.transfer_family, 0);
+        assert!(!indices.background_compute);
+        assert!(!indices.has_dedicated_transfer());
+    }
+
+    #[test]
+    fn discrete_prefers_async_compute_and_dedicated_transfer() {
+        let indices = BackQueueFamilyIndices::select(&discrete(), false).unwrap();
+        assert_eq!(indices.graphics_family, 0);
+        assert_eq!(indices.compute_family, 1);
+        assert_eq!(indices.transfer_family, 2);
+        assert!(indices.has_dedicated_transfer());
+    }
+
+    #[test]
+    fn compute_needs_timestamps() {
+        let mut properties = discrete();
+        properties[1].timestamp_valid_bits = 0;
+        let indices = BackQueueFamilyIndices::select(&properties, false).unwrap();
+        assert_eq!(indices.compute_family, 0);
+
+        properties[0].timestamp_valid_bits = 0;
+        let result = BackQueueFamilyIndices::select(&properties, false);
+        assert!(matches!(result, Err(TracerError::NoQueueFamily("compute"))));
+    }
+
+    #[test]
+    fn missing_graphics_family_fails() {
+        let properties = [test_family(vk::QueueFlags::COMPUTE, 4)];
+        let result = BackQueueFamilyIndices::select(&properties, false);
+        assert!(matches!(
+            result,
+            Err(TracerError::NoQueueFamily("graphics"))
+        ));
+    }
+
+    #[test]
+    fn low_latency_needs_second_compute_queue() {
+        let indices = BackQueueFamilyIndices::select(&[test_family(all(), 1)], true).unwrap();
+        assert!(!indices.background_compute);
+
+        let indices = BackQueueFamilyIndices::select(&[test_family(all(), 2)], true).unwrap();
+        assert!(indices.background_compute);
+    }
+}
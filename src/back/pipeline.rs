@@ -1,120 +1,398 @@
 use crate::assets::AssetManager;
+use crate::back::bindless::{
+    BindlessTable, ENVIRONMENT_BINDING, INSTANCES_BINDING, INVALID_PIXELS_BINDING, LIGHTS_BINDING,
+    MATERIALS_BINDING, PICK_BINDING, TILE_QUEUE_BINDING, VOLUMES_BINDING,
+};
+use crate::back::environment::EnvironmentMap;
+use crate::back::frame_data::FrameData;
+use crate::back::history::TemporalHistory;
+use crate::back::post::PostStack;
 use crate::back::push_constants::PushConstantsData;
-use crate::back::ssbo::config::{SSBOConfig, SSBOConfigData};
-use crate::back::ssbo::objects::{SSBOObjects, SSBOObjectsData};
-use crate::back::{BackQueues, TracerSlot, TracerSlotImage};
-use crate::common::command_buffer::CommandBuffer;
+use crate::back::ssbo::config::SSBOConfigData;
+use crate::back::ssbo::environment::SSBOEnvironment;
+use crate::back::ssbo::instances::{SSBOInstances, SSBOInstancesData};
+use crate::back::ssbo::invalid_pixels::{SSBOInvalidPixels, SSBOInvalidPixelsData, NO_PIXEL};
+use crate::back::ssbo::lights::{SSBOLights, SSBOLightsData};
+use crate::back::ssbo::materials::{SSBOMaterials, SSBOMaterialsData};
+use crate::back::ssbo::objects::{SSBOObjectData, SSBOObjectsData, NO_TEXTURE};
+use crate::back::ssbo::pick::{SSBOPick, SSBOPickData};
+use crate::back::ssbo::volumes::{SSBOVolumes, SSBOVolumesData};
+use crate::back::ssbo::{SSBOUploadQueue, SSBO};
+use crate::back::tiles::TileQueue;
+use crate::back::variants::{PipelineVariants, SceneFeatures};
+use crate::back::{BackQueues, TracerSlot, TracerSlotImage, OUTPUT_BINDING};
+use crate::common::command_buffer::{CommandBuffer, OneTimeSubmit, UploadBatch};
+use crate::common::debug_label::DebugLabels;
+use crate::common::descriptor::DescriptorAllocator;
+use crate::common::frame_graph::{Pass, PassSubmit, SyncPoint, Timeline};
+use crate::common::gpu_timer::GpuTimer;
+use crate::common::image_pool::{ImagePool, PooledMemory};
+use crate::common::queue::QueueFamily;
 use crate::common::shader::Shader;
+use crate::common::texture::Texture;
+use crate::common::watchdog::Watchdog;
+use crate::config::{ImportedTexture, PostPass, EXTERNAL_TEXTURE_PREFIX};
+use crate::error::{Context, TracerResult};
 use crate::fps::Fps;
 use crate::tracer::{Bundle, TracerProfile};
-use anyhow::Context;
 use ash::vk;
-use glam::FloatExt;
-use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::vulkan::{AllocationCreateDesc, AllocationScheme};
 use log::{debug, warn};
-
-const COMPUTE_ASSET: &str = "shaders/shader.comp.spv";
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+const COMPUTE_SHADER: &str = "trace";
+const INVALID_PIXELS_SHADER: &str = "invalid_pixels";
+const CLASSIFY_TILES_SHADER: &str = "classify_tiles";
+const BLUE_NOISE_ASSET: &str = "textures/blue_noise.png";
+// Frames in flight. The config and objects are per frame (see `FrameData`),
+// so raising it does not race the host writes against a running dispatch
 const MAX_DEPTH: usize = 1;
+const COMPUTE_SCOPE: &str = "compute";
+const POST_SCOPE: &str = "post";
+const GPU_SCOPES: [&str; 2] = [COMPUTE_SCOPE, POST_SCOPE];
+const INITIAL_INSTANCES_CAPACITY: usize = 64;
+const INITIAL_LIGHTS_CAPACITY: usize = 8;
+const INITIAL_MATERIALS_CAPACITY: usize = 16;
+const INITIAL_VOLUMES_CAPACITY: usize = 4;
+// Fraction of the reserved device memory left unused above which a resize
+// returns the pooled image memory to the allocator
+const DEFRAGMENT_THRESHOLD: f32 = 0.5;
+
+/// Scene buffers, uploaded together whenever the scene changes
+pub(crate) struct SceneData {
+    pub objects: SSBOObjectsData,
+    pub instances: SSBOInstancesData,
+    pub lights: SSBOLightsData,
+    pub materials: SSBOMaterialsData,
+    pub volumes: SSBOVolumesData,
+    // Assets referenced by the texture slots of the objects
+    pub textures: Vec<String>,
+}
+
+/// Texture of another process and its index in the bindless table
+struct ImportedSlot {
+    texture: Texture,
+    index: u32,
+}
 
 pub(crate) struct TracerPipeline {
     queues: BackQueues,
     destroyed: bool,
     fps: Fps,
     profile: TracerProfile,
+    // Every measured render time, only kept once requested
+    render_time_samples: Option<Vec<f32>>,
 
     // Output images
-    descriptor_set_layout_0: vk::DescriptorSetLayout,
-    descriptor_pool_0: vk::DescriptorPool,
-    descriptor_sets_0: Vec<vk::DescriptorSet>, // Size = MAX_DEPTH
+    descriptors_0: DescriptorAllocator, // sets size = MAX_DEPTH
     images_custom_usage: vk::ImageUsageFlags,
 
-    // Parameters SSBO
-    descriptor_set_layout_1: vk::DescriptorSetLayout,
-    descriptor_pool_1: vk::DescriptorPool,
-    descriptor_set_1: vk::DescriptorSet,
-
-    query_pool: vk::QueryPool,
-    timestamp_period: f32,
-
-    config_ssbo: SSBOConfig,
-    objects_ssbo: SSBOObjects,
+    // Scene data: parameters, objects, instances, lights, materials, volumes
+    // and textures
+    bindless: BindlessTable,
+    blue_noise: Texture,
+    blue_noise_index: u32,
+    // Texture and its index in the bindless table, None without environment
+    environment: Option<(Texture, u32)>,
+    // Object textures by asset, loaded while the scene uses them
+    textures: BTreeMap<String, (Texture, u32)>,
+    // Textures of other processes by name, kept until replaced
+    imported: BTreeMap<String, ImportedSlot>,
+    asset_manager: AssetManager,
+
+    gpu_timer: GpuTimer,
+
+    // Config and objects, a copy per frame in flight
+    frame_data: FrameData,
+    instances_ssbo: SSBOInstances,
+    lights_ssbo: SSBOLights,
+    materials_ssbo: SSBOMaterials,
+    volumes_ssbo: SSBOVolumes,
+    environment_ssbo: SSBOEnvironment,
+    pick_ssbo: SSBOPick,
+    invalid_pixels_ssbo: SSBOInvalidPixels,
+    tile_queue: TileQueue,
 
     pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline,
+    // Tracing pipelines by scene features and the features of the scene
+    variants: PipelineVariants,
+    features: SceneFeatures,
+    // Scans the traced image for NaN and infinite pixels, same layout
+    invalid_pixels_pipeline: vk::Pipeline,
+    // Queues the tiles not converged yet, same layout
+    classify_tiles_pipeline: vk::Pipeline,
 
     command_pool: vk::CommandPool,
     command_buffers: Vec<CommandBuffer>, // size = MAX_DEPTH
-
-    should_invalidate: Vec<bool>,               // size = MAX_DEPTH
-    images: Vec<vk::Image>,                     // size = MAX_DEPTH
-    image_views: Vec<vk::ImageView>,            // size = MAX_DEPTH
-    image_samplers: Vec<vk::Sampler>,           // size = MAX_DEPTH
-    image_allocations: Vec<Option<Allocation>>, // size = MAX_DEPTH
+    transfer_command_pool: vk::CommandPool,
+    uploads: SSBOUploadQueue,
+
+    should_invalidate: Vec<bool>,            // size = MAX_DEPTH
+    images: Vec<vk::Image>,                  // size = MAX_DEPTH
+    image_views: Vec<vk::ImageView>,         // size = MAX_DEPTH
+    image_samplers: Vec<vk::Sampler>,        // size = MAX_DEPTH
+    image_memory: Vec<Option<PooledMemory>>, // size = MAX_DEPTH
+    // Memory of the images released on resize, reused by the next ones
+    image_pool: ImagePool,
     image_bytesize: usize,
-
-    fences: Vec<vk::Fence>, // size = MAX_DEPTH
+    history: TemporalHistory,
+    // Post-processing of the traced images, presented in their place
+    post: PostStack,
+
+    // Camera of the last uploaded config, the one the history was traced with
+    camera_transform: [[f32; 4]; 4],
+    // Updates received while the slot was busy, applied with the next dispatch
+    pending_config: Option<SSBOConfigData>,
+    pending_scene: Option<SceneData>,
+    // Edited objects by index, uploaded without the rest of the scene
+    pending_objects: Vec<(usize, SSBOObjectData)>,
+    pending_invalidate: bool,
+    pending_reproject: bool,
+    pending_pick: Option<glam::UVec2>,
+    // The post passes changed, run again with the next dispatch
+    pending_post: bool,
+    // Submission writing into the pick buffer, if not read back yet
+    pick_submitted: Option<u64>,
+    check_invalid_pixels: bool,
+    // Submission scanning for invalid pixels and the width of the scanned
+    // image, if not read back yet
+    invalid_pixels_submitted: Option<(u64, u32)>,
+
+    timeline: Timeline,
+    // Queue of the last submission, the frames are ordered by the queue
+    // only as long as they stay on the same one
+    last_queue: vk::Queue,
+    // None if disabled in the config
+    watchdog: Option<Watchdog>,
+    submitted: Vec<u64>,              // size = MAX_DEPTH
+    released: Vec<Option<SyncPoint>>, // size = MAX_DEPTH
 
     current_frame: usize,
     last_finished_frame: Option<usize>,
     viewport: glam::UVec2,
 
     compute_shader: Shader,
+    invalid_pixels_shader: Shader,
+    classify_tiles_shader: Shader,
 }
 
 impl TracerPipeline {
+    #[tracing::instrument(name = "TracerPipeline::new", skip_all)]
     pub unsafe fn new(
         bundle: Bundle,
         asset_manager: AssetManager,
         viewport: glam::UVec2,
         queues: BackQueues,
         images_custom_usage: vk::ImageUsageFlags,
-    ) -> anyhow::Result<Self> {
+        watchdog_timeout: f32,
+        labels: bool,
+    ) -> TracerResult<Self> {
         let (command_pool, command_buffers) = Self::create_command_buffers(bundle, &queues)
             .context("Failed to create command buffers")?;
-
-        let (image_bytesize, images, image_views, image_samplers, image_allocations) =
-            Self::create_images(bundle, &queues, command_pool, viewport, images_custom_usage)
-                .context("Failed to create images")?;
+        let transfer_command_pool = Self::create_transfer_command_pool(bundle, &queues)
+            .context("Failed to create transfer command pool")?;
+        debug!(
+            "Uploading staged buffers on a {} queue",
+            if queues.indices.has_dedicated_transfer() {
+                "dedicated transfer"
+            } else {
+                "compute"
+            }
+        );
+        let uploads = SSBOUploadQueue::new(bundle, transfer_command_pool, queues.transfer_queue)
+            .context("Failed to create upload timeline")?;
+
+        // Layout transitions and texture uploads of all the images below go
+        // into a single submission
+        let mut batch = UploadBatch::begin(bundle, command_pool)
+            .context("Failed to begin initialization batch")?;
+        let mut image_pool = ImagePool::default();
+        let (image_bytesize, images, image_views, image_samplers, image_memory) =
+            Self::create_images(
+                bundle,
+                &queues,
+                &batch,
+                &mut image_pool,
+                viewport,
+                images_custom_usage,
+            )
+            .context("Failed to create images")?;
+        let history = TemporalHistory::new(bundle, &batch, &mut image_pool, viewport)
+            .context("Failed to create temporal history")?;
+        let post = PostStack::new(
+            bundle,
+            asset_manager.clone(),
+            &queues,
+            MAX_DEPTH,
+            images_custom_usage,
+        )
+        .context("Failed to create post stack")?;
 
         debug!("Creating SSBOs");
-        let config_ssbo = SSBOConfig::new(bundle, Some("Config SSBO Buffer"))
-            .context("Failed to create config SSBO")?;
-        let objects_ssbo = SSBOObjects::new(bundle, Some("Objects SSBO Buffer"))
-            .context("Failed to create objects SSBO")?;
-
-        let (descriptor_set_layout_0, descriptor_pool_0, descriptor_sets_0) =
-            Self::create_descriptor_set_0(bundle, &image_views)
-                .context("Failed to create descriptor set 0 layout")?;
-        let (descriptor_set_layout_1, descriptor_pool_1, descriptor_set_1) =
-            Self::create_descriptor_set_1(bundle, &config_ssbo, &objects_ssbo)
-                .context("Failed to create descriptor set 1 layout")?;
+        let frame_data = FrameData::new(bundle, MAX_DEPTH, &Self::ssbo_queue_families(&queues))
+            .context("Failed to create frame data")?;
+        let instances_ssbo = SSBOInstances::new_array(
+            bundle,
+            INITIAL_INSTANCES_CAPACITY,
+            &Self::ssbo_queue_families(&queues),
+            Some("Instances SSBO Buffer"),
+        )
+        .context("Failed to create instances SSBO")?;
+        let lights_ssbo = SSBOLights::new_array(
+            bundle,
+            INITIAL_LIGHTS_CAPACITY,
+            &Self::ssbo_queue_families(&queues),
+            Some("Lights SSBO Buffer"),
+        )
+        .context("Failed to create lights SSBO")?;
+        let materials_ssbo = SSBOMaterials::new_array(
+            bundle,
+            INITIAL_MATERIALS_CAPACITY,
+            &Self::ssbo_queue_families(&queues),
+            Some("Materials SSBO Buffer"),
+        )
+        .context("Failed to create materials SSBO")?;
+        let volumes_ssbo = SSBOVolumes::new_array(
+            bundle,
+            INITIAL_VOLUMES_CAPACITY,
+            &Self::ssbo_queue_families(&queues),
+            Some("Volumes SSBO Buffer"),
+        )
+        .context("Failed to create volumes SSBO")?;
+        // Grows to the size of the sampling tables once an environment is set
+        let environment_ssbo = SSBOEnvironment::new_array(
+            bundle,
+            1,
+            &Self::ssbo_queue_families(&queues),
+            Some("Environment SSBO Buffer"),
+        )
+        .context("Failed to create environment SSBO")?;
+        let pick_ssbo = SSBOPick::new_readback(bundle, Some("Pick SSBO Buffer"))
+            .context("Failed to create pick SSBO")?;
+        let invalid_pixels_ssbo =
+            SSBOInvalidPixels::new_readback(bundle, Some("Invalid Pixels SSBO Buffer"))
+                .context("Failed to create invalid pixels SSBO")?;
+        let tile_queue = TileQueue::new(bundle, viewport).context("Failed to create tile queue")?;
+
+        let descriptors_0 = DescriptorAllocator::new(
+            bundle,
+            &TracerSlot::descriptor_set_layout_bindings(),
+            &[],
+            MAX_DEPTH,
+        )
+        .context("Failed to create descriptor set 0")?;
+        Self::write_descriptor_sets_0(bundle, descriptors_0.sets(), &image_views, &history);
+        let bindless = BindlessTable::new(bundle).context("Failed to create bindless table")?;
+        bindless.write_buffer(bundle, INSTANCES_BINDING, instances_ssbo.buffer);
+        bindless.write_buffer(bundle, LIGHTS_BINDING, lights_ssbo.buffer);
+        bindless.write_buffer(bundle, MATERIALS_BINDING, materials_ssbo.buffer);
+        bindless.write_buffer(bundle, VOLUMES_BINDING, volumes_ssbo.buffer);
+        bindless.write_buffer(bundle, ENVIRONMENT_BINDING, environment_ssbo.buffer);
+        bindless.write_buffer(bundle, PICK_BINDING, pick_ssbo.buffer);
+        bindless.write_buffer(bundle, INVALID_PIXELS_BINDING, invalid_pixels_ssbo.buffer);
+        bindless.write_buffer(bundle, TILE_QUEUE_BINDING, tile_queue.buffer);
+
+        debug!("Loading blue noise texture");
+        let blue_noise = asset_manager
+            .load_asset(BLUE_NOISE_ASSET)
+            .context("Failed to load blue noise asset")?;
+        // Tiled over the screen and fetched per texel, so no filtering
+        let blue_noise_sampler = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT);
+        let blue_noise = Texture::new_from_image(
+            bundle,
+            &mut batch,
+            blue_noise.get_image()?,
+            &blue_noise_sampler,
+            "Blue Noise Texture",
+        )
+        .context("Failed to create blue noise texture")?;
+        batch
+            .submit_and_wait(bundle, queues.compute_queue)
+            .context("Failed to initialize images")?;
+        let blue_noise_index = bindless
+            .add_texture(
+                bundle,
+                blue_noise.image_view,
+                blue_noise.sampler,
+                blue_noise.layout,
+            )
+            .context("Failed to register blue noise texture")?;
 
         debug!("Creating compute shader");
         let compute_shader = asset_manager
-            .load_asset(COMPUTE_ASSET)
+            .load_shader(COMPUTE_SHADER)
             .context("Failed to load compute shader asset")?;
         let compute_shader = Shader::new_from_spirv(bundle, compute_shader.get_spirv()?)
             .context("Failed to create compute shader")?;
 
-        let stage = vk::PipelineShaderStageCreateInfo::default()
-            .stage(vk::ShaderStageFlags::COMPUTE)
-            .module(compute_shader.module)
-            .name(c"main");
-
         debug!("Creating pipeline");
-        let (pipeline_layout, pipeline) = Self::create_pipeline(
+        let pipeline_layout = Self::create_pipeline_layout(
+            bundle,
+            descriptors_0.layout,
+            bindless.layout,
+            frame_data.layout(),
+        )
+        .context("Failed to create pipeline layout")?;
+        let variants = PipelineVariants::new(bundle, compute_shader.module, pipeline_layout)
+            .context("Failed to create pipeline")?;
+
+        debug!("Creating invalid pixels pipeline");
+        let invalid_pixels_shader = asset_manager
+            .load_shader(INVALID_PIXELS_SHADER)
+            .context("Failed to load invalid pixels shader asset")?;
+        let invalid_pixels_shader =
+            Shader::new_from_spirv(bundle, invalid_pixels_shader.get_spirv()?)
+                .context("Failed to create invalid pixels shader")?;
+        let invalid_pixels_pipeline = Self::create_compute_pipeline(
+            bundle,
+            pipeline_layout,
+            &vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(invalid_pixels_shader.module)
+                .name(c"main"),
+        )
+        .context("Failed to create invalid pixels pipeline")?;
+
+        debug!("Creating classify tiles pipeline");
+        let classify_tiles_shader = asset_manager
+            .load_shader(CLASSIFY_TILES_SHADER)
+            .context("Failed to load classify tiles shader asset")?;
+        let classify_tiles_shader =
+            Shader::new_from_spirv(bundle, classify_tiles_shader.get_spirv()?)
+                .context("Failed to create classify tiles shader")?;
+        let classify_tiles_pipeline = Self::create_compute_pipeline(
             bundle,
-            descriptor_set_layout_0,
-            descriptor_set_layout_1,
-            &stage,
+            pipeline_layout,
+            &vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(classify_tiles_shader.module)
+                .name(c"main"),
         )
-        .context("Failed to create pipeline")?;
+        .context("Failed to create classify tiles pipeline")?;
 
         debug!("Creating sync objects");
-        let fences = Self::create_sync_objects(bundle).context("Failed to create fences")?;
+        let timeline =
+            Timeline::new(bundle, Pass::Compute).context("Failed to create compute timeline")?;
+
+        debug!("Creating GPU timer");
+        let gpu_timer = GpuTimer::new(
+            bundle,
+            &GPU_SCOPES,
+            MAX_DEPTH,
+            DebugLabels::new(bundle, labels),
+        )
+        .context("Failed to create GPU timer")?;
 
-        debug!("Creating query pool");
-        let (query_pool, timestamp_period) = Self::create_query_pool(bundle)?;
+        let watchdog = (watchdog_timeout > 0.0)
+            .then(|| Watchdog::new(bundle, Duration::from_secs_f32(watchdog_timeout)));
 
         Ok(Self {
             queues,
@@ -122,89 +400,106 @@ impl TracerPipeline {
             fps: Fps::new(),
 
             profile: TracerProfile::default(),
+            render_time_samples: None,
 
-            descriptor_set_layout_0,
-            descriptor_pool_0,
-            descriptor_sets_0,
+            descriptors_0,
 
             images_custom_usage,
-            descriptor_set_layout_1,
-            descriptor_pool_1,
-            descriptor_set_1,
-
-            query_pool,
-            timestamp_period,
-            config_ssbo,
-            objects_ssbo,
+            bindless,
+            blue_noise,
+            blue_noise_index,
+            environment: None,
+            textures: BTreeMap::new(),
+            imported: BTreeMap::new(),
+            asset_manager,
+
+            gpu_timer,
+            frame_data,
+            instances_ssbo,
+            lights_ssbo,
+            materials_ssbo,
+            volumes_ssbo,
+            environment_ssbo,
+            pick_ssbo,
+            invalid_pixels_ssbo,
+            tile_queue,
             pipeline_layout,
-            pipeline,
+            variants,
+            features: SceneFeatures::ALL,
+            invalid_pixels_pipeline,
+            classify_tiles_pipeline,
             command_pool,
             command_buffers,
+            transfer_command_pool,
+            uploads,
             should_invalidate: vec![true; MAX_DEPTH],
             images,
             image_views,
             image_samplers,
-            image_allocations: image_allocations.into_iter().map(Some).collect(),
+            image_memory: image_memory.into_iter().map(Some).collect(),
+            image_pool,
             image_bytesize,
-            fences,
+            history,
+            post,
+            camera_transform: Default::default(),
+            pending_config: None,
+            pending_scene: None,
+            pending_objects: vec![],
+            pending_invalidate: false,
+            pending_reproject: false,
+            pending_pick: None,
+            pending_post: false,
+            pick_submitted: None,
+            check_invalid_pixels: false,
+            invalid_pixels_submitted: None,
+            timeline,
+            last_queue: vk::Queue::null(),
+            watchdog,
+            submitted: vec![0; MAX_DEPTH],
+            released: vec![None; MAX_DEPTH],
             current_frame: 0,
             last_finished_frame: None,
             viewport,
             compute_shader,
+            invalid_pixels_shader,
+            classify_tiles_shader,
         })
     }
 
-    unsafe fn create_query_pool(bundle: Bundle) -> anyhow::Result<(vk::QueryPool, f32)> {
-        let query_pool_info = vk::QueryPoolCreateInfo::default()
-            .query_type(vk::QueryType::TIMESTAMP)
-            .query_count(2);
-        let query_pool = bundle.device.create_query_pool(&query_pool_info, None)?;
-
-        bundle.device.reset_query_pool(query_pool, 0, 2);
-
-        let props = bundle
-            .instance
-            .get_physical_device_properties(bundle.physical_device);
-        let timestamp_period = props.limits.timestamp_period;
-
-        Ok((query_pool, timestamp_period))
-    }
-
-    unsafe fn create_sync_objects(bundle: Bundle) -> anyhow::Result<Vec<vk::Fence>> {
-        let mut fences = Vec::with_capacity(MAX_DEPTH);
-        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-        for _ in 0..MAX_DEPTH {
-            let fence = bundle.device.create_fence(&fence_info, None)?;
-            fences.push(fence);
-        }
-
-        Ok(fences)
-    }
-
+    /// The transitions to the general layout are recorded into the batch
     unsafe fn create_images(
         bundle: Bundle,
         queues: &BackQueues,
-        command_pool: vk::CommandPool,
+        batch: &UploadBatch,
+        pool: &mut ImagePool,
         viewport: glam::UVec2,
         images_custom_usage: vk::ImageUsageFlags,
-    ) -> anyhow::Result<(
+    ) -> TracerResult<(
         usize,
         Vec<vk::Image>,
         Vec<vk::ImageView>,
         Vec<vk::Sampler>,
-        Vec<Allocation>,
+        Vec<PooledMemory>,
     )> {
         let mut images = Vec::with_capacity(MAX_DEPTH);
         let mut image_views = Vec::with_capacity(MAX_DEPTH);
         let mut image_samplers = Vec::with_capacity(MAX_DEPTH);
-        let mut image_allocations = Vec::with_capacity(MAX_DEPTH);
+        let mut image_memory = Vec::with_capacity(MAX_DEPTH);
+        let mut barriers = Vec::with_capacity(MAX_DEPTH);
         let mut image_bytesize = 0;
 
+        let queue_family_indices = QueueFamily::unique_indices(&[
+            queues.indices.graphics_family,
+            queues.indices.compute_family,
+            queues.indices.transfer_family,
+        ]);
+        let sharing_mode = if queue_family_indices.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+
         for depth in 0..MAX_DEPTH {
-            let queue_family_indices = [
-                queues.indices.graphics_family,
-                queues.indices.compute_family,
-            ];
             let create_image_info = vk::ImageCreateInfo::default()
                 .image_type(vk::ImageType::TYPE_2D)
                 .format(vk::Format::R32G32B32A32_SFLOAT)
@@ -217,27 +512,28 @@ impl TracerPipeline {
                 .array_layers(1)
                 .samples(vk::SampleCountFlags::TYPE_1)
                 .tiling(vk::ImageTiling::OPTIMAL)
-                .usage(vk::ImageUsageFlags::STORAGE | images_custom_usage)
-                .sharing_mode(vk::SharingMode::CONCURRENT)
+                // Transfer source for copying into the temporal history,
+                // destination of a restored accumulation
+                .usage(
+                    vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::TRANSFER_SRC
+                        | vk::ImageUsageFlags::TRANSFER_DST
+                        | images_custom_usage,
+                )
+                .sharing_mode(sharing_mode)
                 .queue_family_indices(&queue_family_indices)
                 .initial_layout(vk::ImageLayout::UNDEFINED);
             let image = bundle.device.create_image(&create_image_info, None)?;
 
             let mem_requirements = bundle.device.get_image_memory_requirements(image);
             image_bytesize = mem_requirements.size as usize;
-            let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
-                name: format!("Tracer Pipeline Image Allocation {}", depth).as_str(),
-                requirements: mem_requirements,
-                location: gpu_allocator::MemoryLocation::GpuOnly,
-                linear: false,
-                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-            })?;
-
-            bundle
-                .device
-                .bind_image_memory(image, allocation.memory(), allocation.offset())?;
+            let memory = pool.bind(
+                bundle,
+                image,
+                &format!("Tracer Pipeline Image Allocation {}", depth),
+            )?;
             images.push(image);
-            image_allocations.push(allocation);
+            image_memory.push(memory);
 
             let image_view_info = vk::ImageViewCreateInfo::default()
                 .image(image)
@@ -255,8 +551,6 @@ impl TracerPipeline {
             image_views.push(image_view);
 
             // Transition undefined memory layout to the general
-            let mut command_buffer = CommandBuffer::new_from_pool(bundle, command_pool)?;
-            command_buffer.begin(bundle)?;
             let barrier = vk::ImageMemoryBarrier::default()
                 .old_layout(vk::ImageLayout::UNDEFINED)
                 .new_layout(vk::ImageLayout::GENERAL)
@@ -273,22 +567,7 @@ impl TracerPipeline {
                 )
                 .src_access_mask(vk::AccessFlags::empty())
                 .dst_access_mask(vk::AccessFlags::SHADER_WRITE);
-            bundle.device.cmd_pipeline_barrier(
-                command_buffer.as_inner(),
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::COMPUTE_SHADER,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &[barrier],
-            );
-            command_buffer.end(bundle)?;
-            let submit_info = command_buffer.as_submit_info();
-            bundle
-                .device
-                .queue_submit(queues.compute_queue, &[submit_info], vk::Fence::null())?;
-            bundle.device.queue_wait_idle(queues.compute_queue)?;
-            command_buffer.destroy(bundle, command_pool);
+            barriers.push(barrier);
 
             let sampler_info = vk::SamplerCreateInfo::default()
                 .mag_filter(vk::Filter::LINEAR)
@@ -303,20 +582,48 @@ impl TracerPipeline {
             let sampler = bundle.device.create_sampler(&sampler_info, None)?;
             image_samplers.push(sampler);
         }
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
 
         Ok((
             image_bytesize,
             images,
             image_views,
             image_samplers,
-            image_allocations,
+            image_memory,
         ))
     }
 
+    /// Hands the memory of the images back to the pool
+    unsafe fn destroy_images(
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        images: &[vk::Image],
+        image_views: &[vk::ImageView],
+        image_samplers: &[vk::Sampler],
+        image_memory: &mut [Option<PooledMemory>],
+    ) {
+        for (i, image) in images.iter().enumerate() {
+            bundle.device.destroy_image_view(image_views[i], None);
+            bundle.device.destroy_sampler(image_samplers[i], None);
+            bundle.device.destroy_image(*image, None);
+            if let Some(memory) = image_memory[i].take() {
+                pool.release(memory);
+            }
+        }
+    }
+
     unsafe fn create_command_buffers(
         bundle: Bundle,
         queues: &BackQueues,
-    ) -> anyhow::Result<(vk::CommandPool, Vec<CommandBuffer>)> {
+    ) -> TracerResult<(vk::CommandPool, Vec<CommandBuffer>)> {
         let command_pool_info = vk::CommandPoolCreateInfo::default()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(queues.indices.compute_family);
@@ -326,50 +633,40 @@ impl TracerPipeline {
 
         let command_buffer = (0..MAX_DEPTH)
             .map(|_| CommandBuffer::new_from_pool(bundle, command_pool))
-            .collect::<anyhow::Result<Vec<CommandBuffer>>>()?;
+            .collect::<TracerResult<Vec<CommandBuffer>>>()?;
 
         Ok((command_pool, command_buffer))
     }
 
-    unsafe fn create_descriptor_set_0(
+    unsafe fn create_transfer_command_pool(
         bundle: Bundle,
-        image_views: &[vk::ImageView],
-    ) -> anyhow::Result<(
-        vk::DescriptorSetLayout,
-        vk::DescriptorPool,
-        Vec<vk::DescriptorSet>,
-    )> {
-        let bindings = [
-            // (set = 0, binding = 0, rgba8) uniform writeonly image2D output_image;
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(0)
-                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT),
-        ];
-
-        let descriptor_layout_info =
-            vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
-        let descriptor_set_layout = bundle
-            .device
-            .create_descriptor_set_layout(&descriptor_layout_info, None)?;
-
-        let descriptor_pool_sizes = [vk::DescriptorPoolSize::default()
-            .ty(vk::DescriptorType::STORAGE_IMAGE)
-            .descriptor_count(MAX_DEPTH as u32)];
-        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&descriptor_pool_sizes)
-            .max_sets(MAX_DEPTH as u32);
-        let descriptor_pool = bundle
+        queues: &BackQueues,
+    ) -> TracerResult<vk::CommandPool> {
+        // The upload command buffers are recorded again once completed
+        let command_pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            )
+            .queue_family_index(queues.indices.transfer_family);
+        Ok(bundle
             .device
-            .create_descriptor_pool(&descriptor_pool_info, None)?;
+            .create_command_pool(&command_pool_info, None)?)
+    }
 
-        let layout_handles = vec![descriptor_set_layout; MAX_DEPTH];
-        let alloc_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&layout_handles);
-        let descriptor_sets = bundle.device.allocate_descriptor_sets(&alloc_info)?;
+    fn ssbo_queue_families(queues: &BackQueues) -> [u32; 2] {
+        [
+            queues.indices.compute_family,
+            queues.indices.transfer_family,
+        ]
+    }
 
+    unsafe fn write_descriptor_sets_0(
+        bundle: Bundle,
+        descriptor_sets: &[vk::DescriptorSet],
+        image_views: &[vk::ImageView],
+        history: &TemporalHistory,
+    ) {
         for (i, descriptor_set) in descriptor_sets.iter().enumerate() {
             let out_image_info = vk::DescriptorImageInfo::default()
                 .image_view(image_views[i])
@@ -377,167 +674,557 @@ impl TracerPipeline {
 
             let writes = [vk::WriteDescriptorSet::default()
                 .dst_set(*descriptor_set)
-                .dst_binding(0)
+                .dst_binding(OUTPUT_BINDING)
                 .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
                 .image_info(std::slice::from_ref(&out_image_info))];
             bundle.device.update_descriptor_sets(&writes, &[]);
+            history.write_descriptor_set(bundle, *descriptor_set);
+        }
+    }
+
+    /// Writes the elements into the buffer selected by `ssbo`, growing it to
+    /// the next power of two if they do not fit. The buffers are shared by
+    /// the slots, so no submitted frame may still read them. `ssbo` borrows
+    /// the upload queue along with the buffer, both are fields of `self`.
+    unsafe fn update_array<T: Debug>(
+        &mut self,
+        bundle: Bundle,
+        binding: u32,
+        name: &str,
+        ssbo: fn(&mut Self) -> (&mut SSBO<T>, &mut SSBOUploadQueue),
+        data: &[T],
+    ) -> TracerResult<()> {
+        self.wait_submitted(bundle, self.timeline.last().value)?;
+        if data.len() > ssbo(self).0.capacity {
+            let capacity = data.len().next_power_of_two();
+            debug!(
+                "Growing {} from {} to {} elements",
+                name,
+                ssbo(self).0.capacity,
+                capacity
+            );
+
+            let grown = SSBO::new_array(
+                bundle,
+                capacity,
+                &Self::ssbo_queue_families(&self.queues),
+                Some(name),
+            )
+            .with_context(|| format!("Failed to create {}", name))?;
+            let mut old = std::mem::replace(ssbo(self).0, grown);
+            old.destroy(bundle);
+
+            let buffer = ssbo(self).0.buffer;
+            self.bindless.write_buffer(bundle, binding, buffer);
         }
 
-        Ok((descriptor_set_layout, descriptor_pool, descriptor_sets))
+        let (ssbo, uploads) = ssbo(self);
+        ssbo.update_slice(bundle, uploads, data)
     }
 
-    unsafe fn create_descriptor_set_1(
+    /// Loads the textures the scene needs and releases the ones it does not.
+    /// Returns the bindless index of every asset, `NO_TEXTURE` if it failed.
+    unsafe fn update_textures(
+        &mut self,
         bundle: Bundle,
-        config_ssbo: &SSBOConfig,
-        objects_ssbo: &SSBOObjects,
-    ) -> anyhow::Result<(
-        vk::DescriptorSetLayout,
-        vk::DescriptorPool,
-        vk::DescriptorSet,
-    )> {
-        let bindings = [
-            // (set = 1, binding = 0) buffer config
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(0)
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::COMPUTE),
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(1)
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::COMPUTE),
-        ];
-
-        let descriptor_layout_info =
-            vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
-        let descriptor_set_layout = bundle
-            .device
-            .create_descriptor_set_layout(&descriptor_layout_info, None)?;
-
-        let descriptor_pool_sizes = [vk::DescriptorPoolSize::default()
-            .ty(vk::DescriptorType::STORAGE_BUFFER)
-            .descriptor_count(2)];
-        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&descriptor_pool_sizes)
-            .max_sets(2);
-        let descriptor_pool = bundle
-            .device
-            .create_descriptor_pool(&descriptor_pool_info, None)?;
-
-        let layout_handles = vec![descriptor_set_layout; 2];
-        let alloc_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&layout_handles);
-        let descriptor_sets = bundle.device.allocate_descriptor_sets(&alloc_info)?;
-        let descriptor_set = descriptor_sets[0];
-
-        let config_buffer_info = vk::DescriptorBufferInfo::default()
-            .buffer(config_ssbo.buffer)
-            .offset(0)
-            .range(vk::WHOLE_SIZE);
-        let objects_buffer_info = vk::DescriptorBufferInfo::default()
-            .buffer(objects_ssbo.buffer)
-            .offset(0)
-            .range(vk::WHOLE_SIZE);
-        let writes = [
-            vk::WriteDescriptorSet::default()
-                .dst_set(descriptor_set)
-                .dst_binding(0)
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                .buffer_info(std::slice::from_ref(&config_buffer_info)),
-            vk::WriteDescriptorSet::default()
-                .dst_set(descriptor_set)
-                .dst_binding(1)
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                .buffer_info(std::slice::from_ref(&objects_buffer_info)),
-        ];
-        bundle.device.update_descriptor_sets(&writes, &[]);
-
-        Ok((descriptor_set_layout, descriptor_pool, descriptor_set))
-    }
-
-    unsafe fn create_pipeline(
+        assets: &[String],
+    ) -> TracerResult<Vec<u32>> {
+        let unused = self
+            .textures
+            .keys()
+            .filter(|asset| !assets.contains(*asset))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unused.is_empty() {
+            // Make sure no submitted frame still samples them
+            self.wait_submitted(bundle, self.timeline.last().value)?;
+            for asset in unused {
+                debug!("Releasing texture {}", asset);
+                if let Some((mut texture, index)) = self.textures.remove(&asset) {
+                    self.bindless.remove_texture(index);
+                    texture.destroy(bundle);
+                }
+            }
+        }
+
+        // Uploads of all the new textures go into a single submission
+        let mut batch = UploadBatch::begin(bundle, self.command_pool)?;
+        let mut loaded = vec![];
+        for asset in assets {
+            // Imported textures are never loaded, they are there or not
+            if asset.starts_with(EXTERNAL_TEXTURE_PREFIX) {
+                continue;
+            }
+            if !self.textures.contains_key(asset) && !loaded.iter().any(|(a, _)| a == asset) {
+                // A missing texture should not take the whole scene down
+                match self.load_texture(bundle, &mut batch, asset) {
+                    Ok(texture) => loaded.push((asset.clone(), texture)),
+                    Err(e) => warn!("Failed to load texture {}: {}", asset, e),
+                }
+            }
+        }
+        if loaded.is_empty() {
+            batch.discard(bundle);
+        } else if let Err(e) = batch.submit_and_wait(bundle, self.queues.compute_queue) {
+            for (_, mut texture) in loaded {
+                texture.destroy(bundle);
+            }
+            return Err(e);
+        }
+
+        // Registered once uploaded, so a texture failing to register can
+        // be destroyed right away
+        for (asset, mut texture) in loaded {
+            match self.bindless.add_texture(
+                bundle,
+                texture.image_view,
+                texture.sampler,
+                texture.layout,
+            ) {
+                Ok(index) => {
+                    self.textures.insert(asset, (texture, index));
+                }
+                Err(e) => {
+                    warn!("Failed to register texture {}: {}", asset, e);
+                    texture.destroy(bundle);
+                }
+            }
+        }
+
+        Ok(assets
+            .iter()
+            .map(|asset| match asset.strip_prefix(EXTERNAL_TEXTURE_PREFIX) {
+                Some(name) => self
+                    .imported
+                    .get(name)
+                    .map_or(NO_TEXTURE, |slot| slot.index),
+                None => self
+                    .textures
+                    .get(asset)
+                    .map_or(NO_TEXTURE, |(_, index)| *index),
+            })
+            .collect())
+    }
+
+    /// Imports the texture of another process, replacing the one of the
+    /// same name. The objects pick it up with the next scene upload.
+    #[cfg(unix)]
+    pub unsafe fn import_texture(
+        &mut self,
+        bundle: Bundle,
+        imported: &ImportedTexture,
+    ) -> TracerResult<()> {
+        if let Some(mut slot) = self.imported.remove(&imported.name) {
+            // Make sure no submitted frame still samples it
+            self.wait_submitted(bundle, self.timeline.last().value)?;
+            self.bindless.remove_texture(slot.index);
+            slot.texture.destroy(bundle);
+        }
+
+        let mut batch = UploadBatch::begin(bundle, self.command_pool)?;
+        let mut texture = match Texture::import_fd(
+            bundle,
+            &mut batch,
+            self.queues.indices.compute_family,
+            imported.fd,
+            imported.dimensions,
+            &Self::object_sampler(),
+            &imported.name,
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                batch.discard(bundle);
+                return Err(e);
+            }
+        };
+        if let Err(e) = batch.submit_and_wait(bundle, self.queues.compute_queue) {
+            texture.destroy(bundle);
+            return Err(e);
+        }
+        let index = match self.bindless.add_texture(
+            bundle,
+            texture.image_view,
+            texture.sampler,
+            texture.layout,
+        ) {
+            Ok(index) => index,
+            Err(e) => {
+                texture.destroy(bundle);
+                return Err(e);
+            }
+        };
+        self.imported
+            .insert(imported.name.clone(), ImportedSlot { texture, index });
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub unsafe fn import_texture(
+        &mut self,
+        _bundle: Bundle,
+        _imported: &ImportedTexture,
+    ) -> TracerResult<()> {
+        Err(crate::error::TracerError::Unsupported(
+            "texture import without POSIX file descriptors".to_string(),
+        ))
+    }
+
+    // Wraps around the sphere horizontally, clamped at the poles
+    fn object_sampler() -> vk::SamplerCreateInfo<'static> {
+        vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+    }
+
+    unsafe fn load_texture(
+        &self,
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        asset: &str,
+    ) -> TracerResult<Texture> {
+        let image = self.asset_manager.load_asset(asset)?;
+        Texture::new_from_image(
+            bundle,
+            batch,
+            image.get_image()?,
+            &Self::object_sampler(),
+            asset,
+        )
+    }
+
+    unsafe fn update_scene(&mut self, bundle: Bundle, mut scene: SceneData) -> TracerResult<()> {
+        let textures = self
+            .update_textures(bundle, &scene.textures)
+            .context("Failed to update textures")?;
+        for object in &mut scene.objects {
+            for slot in object
+                .textures
+                .iter_mut()
+                .filter(|slot| **slot != NO_TEXTURE)
+            {
+                *slot = textures[*slot as usize];
+            }
+        }
+        self.set_features(
+            bundle,
+            SceneFeatures::new(&scene, self.environment.is_some()),
+        );
+        self.frame_data.set_objects(scene.objects);
+
+        self.update_array(
+            bundle,
+            INSTANCES_BINDING,
+            "Instances SSBO Buffer",
+            |pipeline| (&mut pipeline.instances_ssbo, &mut pipeline.uploads),
+            &scene.instances,
+        )?;
+        self.update_array(
+            bundle,
+            LIGHTS_BINDING,
+            "Lights SSBO Buffer",
+            |pipeline| (&mut pipeline.lights_ssbo, &mut pipeline.uploads),
+            &scene.lights,
+        )?;
+        self.update_array(
+            bundle,
+            MATERIALS_BINDING,
+            "Materials SSBO Buffer",
+            |pipeline| (&mut pipeline.materials_ssbo, &mut pipeline.uploads),
+            &scene.materials,
+        )?;
+        self.update_array(
+            bundle,
+            VOLUMES_BINDING,
+            "Volumes SSBO Buffer",
+            |pipeline| (&mut pipeline.volumes_ssbo, &mut pipeline.uploads),
+            &scene.volumes,
+        )
+    }
+
+    /// Replaces the environment map and its sampling tables. The config has
+    /// to be uploaded again afterwards, it references the map.
+    pub unsafe fn set_environment(
+        &mut self,
+        bundle: Bundle,
+        map: Option<EnvironmentMap>,
+    ) -> TracerResult<()> {
+        // Make sure no submitted frame still samples the old map
+        self.wait_submitted(bundle, self.timeline.last().value)?;
+        if let Some((mut texture, index)) = self.environment.take() {
+            self.bindless.remove_texture(index);
+            texture.destroy(bundle);
+        }
+
+        self.set_features(
+            bundle,
+            SceneFeatures {
+                environment: map.is_some(),
+                ..self.features
+            },
+        );
+        let Some(map) = map else {
+            return Ok(());
+        };
+        // Wraps around horizontally, clamped at the poles
+        let sampler = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let mut batch = UploadBatch::begin(bundle, self.command_pool)?;
+        let mut texture = match Texture::new_from_hdr_image(
+            bundle,
+            &mut batch,
+            &map.image,
+            &sampler,
+            "Environment Texture",
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                batch.discard(bundle);
+                return Err(e).context("Failed to create environment texture");
+            }
+        };
+        if let Err(e) = batch.submit_and_wait(bundle, self.queues.compute_queue) {
+            texture.destroy(bundle);
+            return Err(e).context("Failed to upload environment texture");
+        }
+        let registered =
+            self.bindless
+                .add_texture(bundle, texture.image_view, texture.sampler, texture.layout);
+        let index = match registered {
+            Ok(index) => index,
+            Err(e) => {
+                texture.destroy(bundle);
+                return Err(e).context("Failed to register environment texture");
+            }
+        };
+        self.environment = Some((texture, index));
+
+        self.update_array(
+            bundle,
+            ENVIRONMENT_BINDING,
+            "Environment SSBO Buffer",
+            |pipeline| (&mut pipeline.environment_ssbo, &mut pipeline.uploads),
+            &map.cdf,
+        )
+    }
+
+    /// Switches to the pipeline variant of the features, created here and
+    /// not by the next dispatch. Keeps the variant with every feature if
+    /// that fails, it renders the scene as well, only slower.
+    unsafe fn set_features(&mut self, bundle: Bundle, features: SceneFeatures) {
+        if let Err(e) = self.variants.warm_up(bundle, features) {
+            warn!("Failed to create pipeline variant {:?}: {}", features, e);
+        }
+        self.features = features;
+    }
+
+    unsafe fn create_pipeline_layout(
         bundle: Bundle,
         descriptor_set_layout_0: vk::DescriptorSetLayout,
-        descriptor_set_layout_1: vk::DescriptorSetLayout,
-        shader_stage: &vk::PipelineShaderStageCreateInfo,
-    ) -> anyhow::Result<(vk::PipelineLayout, vk::Pipeline)> {
+        bindless_layout: vk::DescriptorSetLayout,
+        frame_data_layout: vk::DescriptorSetLayout,
+    ) -> TracerResult<vk::PipelineLayout> {
+        PushConstantsData::validate(bundle)?;
         let ranges = [PushConstantsData::get_range()];
-        let layouts = [descriptor_set_layout_0, descriptor_set_layout_1];
+        let layouts = [descriptor_set_layout_0, bindless_layout, frame_data_layout];
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(&layouts)
             .push_constant_ranges(&ranges);
-        let pipline_layout = bundle
+        Ok(bundle
             .device
-            .create_pipeline_layout(&pipeline_layout_info, None)?;
+            .create_pipeline_layout(&pipeline_layout_info, None)?)
+    }
 
+    unsafe fn create_compute_pipeline(
+        bundle: Bundle,
+        pipeline_layout: vk::PipelineLayout,
+        shader_stage: &vk::PipelineShaderStageCreateInfo,
+    ) -> TracerResult<vk::Pipeline> {
         let pipeline_info = vk::ComputePipelineCreateInfo::default()
             .stage(*shader_stage)
-            .layout(pipline_layout);
-        let pipeline = bundle
+            .layout(pipeline_layout);
+        Ok(bundle
             .device
             .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
             .map_err(|(_, e)| e)?
-            .remove(0);
-        Ok((pipline_layout, pipeline))
+            .remove(0))
     }
 
-    unsafe fn record_command_buffer(
+    /// Resets the invalid pixels buffer and counts the invalid pixels of
+    /// the image into it. The image writes must be visible to the shader.
+    unsafe fn record_invalid_pixels_scan(
         &self,
         bundle: Bundle,
         command_buffer: &CommandBuffer,
-        descriptor_set_0: vk::DescriptorSet,
-        descriptor_set_1: vk::DescriptorSet,
-        image: vk::Image,
-        need_timestamp: bool,
         extent: vk::Extent2D,
-        push_constants_data: PushConstantsData,
-    ) -> anyhow::Result<()> {
-        command_buffer.reset(bundle)?;
-        command_buffer.begin(bundle)?;
-
-        if need_timestamp {
-            bundle
-                .device
-                .cmd_reset_query_pool(command_buffer.as_inner(), self.query_pool, 0, 2);
-            bundle.device.cmd_write_timestamp(
-                command_buffer.as_inner(),
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                self.query_pool,
-                0,
-            );
-        }
+    ) {
+        let buffer = self.invalid_pixels_ssbo.buffer;
+        let first_pixel = std::mem::offset_of!(SSBOInvalidPixelsData, first_pixel);
+        bundle.device.cmd_fill_buffer(
+            command_buffer.as_inner(),
+            buffer,
+            0,
+            first_pixel as vk::DeviceSize,
+            0,
+        );
+        bundle.device.cmd_fill_buffer(
+            command_buffer.as_inner(),
+            buffer,
+            first_pixel as vk::DeviceSize,
+            size_of::<u32>() as vk::DeviceSize,
+            NO_PIXEL,
+        );
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
 
+        // The layouts match, the bound descriptor sets stay valid
         bundle.device.cmd_bind_pipeline(
             command_buffer.as_inner(),
             vk::PipelineBindPoint::COMPUTE,
-            self.pipeline,
+            self.invalid_pixels_pipeline,
         );
-        bundle.device.cmd_bind_descriptor_sets(
+        bundle.device.cmd_dispatch(
             command_buffer.as_inner(),
-            vk::PipelineBindPoint::COMPUTE,
-            self.pipeline_layout,
-            0,
-            &[descriptor_set_0, descriptor_set_1],
+            extent.width.div_ceil(16),
+            extent.height.div_ceil(16),
+            1,
+        );
+
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
             &[],
         );
+    }
+
+    unsafe fn record_push_constants(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        push_constants_data: &PushConstantsData,
+    ) {
         bundle.device.cmd_push_constants(
             command_buffer.as_inner(),
             self.pipeline_layout,
             vk::ShaderStageFlags::COMPUTE,
             0,
             std::slice::from_raw_parts(
-                (&push_constants_data as *const PushConstantsData) as *const u8,
+                (push_constants_data as *const PushConstantsData) as *const u8,
                 size_of::<PushConstantsData>(),
             ),
         );
-        bundle.device.cmd_dispatch(
+    }
+
+    unsafe fn record_command_buffer(
+        &mut self,
+        bundle: Bundle,
+        index: usize,
+        command_buffer: &CommandBuffer,
+        descriptor_set_0: vk::DescriptorSet,
+        descriptor_set_1: vk::DescriptorSet,
+        descriptor_set_2: vk::DescriptorSet,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        push_constants_data: PushConstantsData,
+        dispatches: u32,
+        scan_invalid_pixels: bool,
+    ) -> TracerResult<()> {
+        command_buffer.reset(bundle)?;
+        command_buffer.begin(bundle)?;
+
+        self.gpu_timer
+            .reset(bundle, command_buffer, index)
+            .context("Failed to get query pool results")?;
+        self.gpu_timer.begin(bundle, command_buffer, COMPUTE_SCOPE);
+
+        if push_constants_data.reproject == 1 {
+            if let Some(previous) = self.last_finished_frame {
+                self.history
+                    .record_copy(bundle, command_buffer, self.images[previous], extent);
+            }
+        }
+
+        bundle.device.cmd_bind_descriptor_sets(
             command_buffer.as_inner(),
-            extent.width.div_ceil(16),
-            extent.height.div_ceil(16),
-            1,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            0,
+            &[descriptor_set_0, descriptor_set_1, descriptor_set_2],
+            &[],
+        );
+        // Only the tiles not converged yet are traced, all the dispatches
+        // of the frame trace the same ones
+        let tiled = self.frame_data.config().converged_samples != 0;
+        if tiled {
+            self.record_push_constants(bundle, command_buffer, &push_constants_data);
+            self.tile_queue.record_classify(
+                bundle,
+                command_buffer,
+                self.classify_tiles_pipeline,
+                extent,
+            );
+        }
+        bundle.device.cmd_bind_pipeline(
+            command_buffer.as_inner(),
+            vk::PipelineBindPoint::COMPUTE,
+            self.variants.get(self.features),
         );
+        let samples_count = self.frame_data.config().samples_count;
+        for i in 0..dispatches {
+            // The following dispatches accumulate onto the first one
+            let push_constants_data = if i == 0 {
+                push_constants_data
+            } else {
+                // Each dispatch reads the accumulation of the previous one
+                let barrier = vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+                bundle.device.cmd_pipeline_barrier(
+                    command_buffer.as_inner(),
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[barrier],
+                    &[],
+                    &[],
+                );
+                push_constants_data.advanced(i, samples_count)
+            };
+            self.record_push_constants(bundle, command_buffer, &push_constants_data);
+            if tiled {
+                self.tile_queue.record_dispatch(bundle, command_buffer);
+            } else {
+                let (tiles_x, tiles_y) = TileQueue::grid(extent);
+                bundle
+                    .device
+                    .cmd_dispatch(command_buffer.as_inner(), tiles_x, tiles_y, 1);
+            }
+        }
 
         let barrier = vk::ImageMemoryBarrier::default()
             .src_access_mask(vk::AccessFlags::SHADER_WRITE)
@@ -566,15 +1253,44 @@ impl TracerPipeline {
             &[barrier],
         );
 
-        if need_timestamp {
-            bundle.device.cmd_write_timestamp(
+        if scan_invalid_pixels {
+            self.record_invalid_pixels_scan(bundle, command_buffer, extent);
+        }
+
+        if push_constants_data.pick_x >= 0 {
+            self.history.record_object_id_copy(
+                bundle,
+                command_buffer,
+                glam::UVec2::new(
+                    push_constants_data.pick_x as u32,
+                    push_constants_data.pick_y as u32,
+                ),
+                self.pick_ssbo.buffer,
+                std::mem::offset_of!(SSBOPickData, object),
+            );
+
+            // Make the picked pixel visible to the host read back
+            let barrier = vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            bundle.device.cmd_pipeline_barrier(
                 command_buffer.as_inner(),
-                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                self.query_pool,
-                1,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
             );
         }
 
+        self.gpu_timer.end(bundle, command_buffer, COMPUTE_SCOPE);
+
+        // Reads the traced image, made visible by the barrier above
+        self.gpu_timer.begin(bundle, command_buffer, POST_SCOPE);
+        self.post.record(bundle, command_buffer, index, extent);
+        self.gpu_timer.end(bundle, command_buffer, POST_SCOPE);
+
         command_buffer.end(bundle)?;
 
         Ok(())
@@ -583,101 +1299,215 @@ impl TracerPipeline {
     unsafe fn enqueue_new_frame(
         &mut self,
         bundle: Bundle,
-        need_timestamp: bool,
         index: usize,
         mut push_constants_data: PushConstantsData,
-    ) -> anyhow::Result<()> {
-        bundle.device.reset_fences(&[self.fences[index]])?;
-
+        dispatches: u32,
+        interactive: bool,
+    ) -> TracerResult<()> {
         let buffer_ptr: *mut CommandBuffer = &mut self.command_buffers[index];
         push_constants_data.invalidate = self.should_invalidate[index] as u32;
+        // Nothing to reproject from before the first frame
+        push_constants_data.reproject = (self.pending_reproject
+            && !self.should_invalidate[index]
+            && self.last_finished_frame.is_some()) as u32;
+        let pick = self.pending_pick.take();
+        if let Some(pick) = pick {
+            push_constants_data.pick_x = pick.x as i32;
+            push_constants_data.pick_y = pick.y as i32;
+        }
+        // The previous dispatch of the slot has completed, its copy of
+        // the config and objects can be brought up to date
+        let frame_data_set = self
+            .frame_data
+            .prepare(bundle, &mut self.uploads, index)
+            .context("Failed to update frame data")?;
+        // The buffer is shared by the slots, scanned again once read back
+        let scan_invalid_pixels =
+            self.check_invalid_pixels && self.invalid_pixels_submitted.is_none();
         self.record_command_buffer(
             bundle,
+            index,
             &*buffer_ptr,
-            self.descriptor_sets_0[index],
-            self.descriptor_set_1,
+            self.descriptors_0.sets()[index],
+            self.bindless.set,
+            frame_data_set,
             self.images[index],
-            need_timestamp,
             vk::Extent2D {
                 width: self.viewport.x,
                 height: self.viewport.y,
             },
             push_constants_data,
+            dispatches,
+            scan_invalid_pixels,
         )?;
 
-        // Submit
-        let command_buffers = vec![self.command_buffers[index].as_inner()];
-
-        let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
-        bundle.device.queue_submit(
-            self.queues.compute_queue,
-            &[submit_info],
-            self.fences[index],
-        )?;
+        // Frames following an update go to the high priority queue, the
+        // accumulation ones to the background queue if there is one
+        let queue = if interactive {
+            self.queues.compute_queue
+        } else {
+            self.queues.background_compute_queue
+        };
+
+        // Submit. The image may not be overwritten until the consumer
+        // of the previous frame in this slot is done with it
+        let previous = self.timeline.last();
+        let point = self.timeline.advance();
+        let mut submit = PassSubmit::new()
+            .command_buffer(&self.command_buffers[index])
+            .signal(point);
+        if let Some(released) = self.released[index].take() {
+            submit = submit.after(released, Pass::Compute.wait_stage());
+        }
+        if queue != self.last_queue {
+            // Other queue, keep the accumulation and the timeline in order
+            submit = submit.after(previous, Pass::Compute.wait_stage());
+        }
+        // Reads the buffers uploaded since the last dispatch
+        submit = submit.after(self.uploads.last(), Pass::Compute.wait_stage());
+        submit.submit(bundle, queue)?;
+        self.last_queue = queue;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.submitted(
+                point,
+                format!(
+                    "slot {}, viewport {:?}, interactive {}, {:?}",
+                    index, self.viewport, interactive, push_constants_data
+                ),
+            );
+        }
+        self.submitted[index] = point.value;
+        if pick.is_some() {
+            self.pick_submitted = Some(point.value);
+        }
+        if scan_invalid_pixels {
+            self.invalid_pixels_submitted = Some((point.value, self.viewport.x));
+        }
 
         Ok(())
     }
 
-    unsafe fn fetch_render_time(&mut self, bundle: Bundle) -> anyhow::Result<Option<f32>> {
-        let mut timestamps = vec![0u64; 2];
-
-        match bundle.device.get_query_pool_results(
-            self.query_pool,
-            0,
-            &mut timestamps,
-            vk::QueryResultFlags::TYPE_64,
-        ) {
-            Ok(()) => {
-                let delta = timestamps[1] - timestamps[0];
-                let render_time_ms = (delta as f64 * self.timestamp_period as f64) / 1_000_000.0;
-                Ok(Some(render_time_ms as f32))
-            }
-            Err(vk::Result::NOT_READY) => Ok(None),
-            Err(e) => Err(anyhow::anyhow!("Failed to get query pool results: {:?}", e)),
-        }
-    }
-
+    #[tracing::instrument(name = "TracerPipeline::present", skip_all)]
     pub unsafe fn present(
         &mut self,
         bundle: Bundle,
         config_data: Option<SSBOConfigData>,
-        objects_data: Option<SSBOObjectsData>,
+        scene_data: Option<SceneData>,
+        edited_objects: Vec<(usize, SSBOObjectData)>,
         push_constants_data: PushConstantsData,
+        dispatches: u32,
         invalidate: bool,
-    ) -> anyhow::Result<TracerSlot> {
+        reproject: bool,
+        pick: Option<glam::UVec2>,
+    ) -> TracerResult<TracerSlot> {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.check()?;
+            self.profile.gpu_stall = watchdog.stall();
+        }
+
+        // Keep the updates until the next dispatch, the slot may be busy
+        if config_data.is_some() {
+            self.pending_config = config_data;
+        }
+        if scene_data.is_some() {
+            self.pending_scene = scene_data;
+            // Included in the scene
+            self.pending_objects.clear();
+        }
+        self.pending_objects.extend(edited_objects);
+        self.pending_invalidate |= invalidate;
+        self.pending_reproject |= reproject;
+        if pick.is_some() {
+            self.pending_pick = pick;
+        }
+
+        let interactive = self.pending_config.is_some()
+            || self.pending_scene.is_some()
+            || !self.pending_objects.is_empty()
+            || self.pending_invalidate
+            || self.pending_reproject;
+        // No dispatch is skipped while there are updates the slot was too
+        // busy for, or before there is any frame to present. The post
+        // passes only run after a dispatch, changing them traces a frame.
+        let dispatches = if interactive
+            || self.pending_pick.is_some()
+            || self.pending_post
+            || self.last_finished_frame.is_none()
+        {
+            dispatches.max(1)
+        } else {
+            dispatches
+        };
+
         let current_frame = self.current_frame;
-        let status = bundle.device.get_fence_status(self.fences[current_frame])?;
+        let status = dispatches > 0
+            && self
+                .timeline
+                .is_reached(bundle, self.submitted[current_frame])?;
         if status {
-            if invalidate {
+            if std::mem::take(&mut self.pending_invalidate) {
                 // Mark all frames as invalidated
                 self.should_invalidate = vec![true; MAX_DEPTH];
             }
 
-            let mut need_timestamp = self.last_finished_frame.is_none();
-            if let Some(ms) = self.fetch_render_time(bundle)? {
-                self.profile.render_time = self.profile.render_time.lerp(ms, 0.01);
-                need_timestamp = true;
+            let measured = self
+                .gpu_timer
+                .fetch(bundle)
+                .context("Failed to get query pool results")?;
+            if let Some(samples) = &mut self.render_time_samples {
+                samples.extend(
+                    measured
+                        .iter()
+                        .filter(|(name, _)| *name == COMPUTE_SCOPE)
+                        .map(|(_, ms)| *ms),
+                );
             }
-
-            // Update config SSBO if needed
-            if let Some(config_data) = config_data {
-                self.config_ssbo.update(config_data);
+            self.profile.render_time = self.gpu_timer.time(COMPUTE_SCOPE);
+            self.profile.gpu_times = self.gpu_timer.times();
+            self.read_invalid_pixels(bundle)?;
+
+            // Uploaded into the frames as they are dispatched
+            if let Some(mut config_data) = self.pending_config.take() {
+                config_data.blue_noise_texture = self.blue_noise_index;
+                if let Some((texture, index)) = &self.environment {
+                    config_data.environment = 1;
+                    config_data.environment_texture = *index;
+                    config_data.environment_width = texture.dimensions.x;
+                    config_data.environment_height = texture.dimensions.y;
+                }
+                config_data.prev_camera_transform = self.camera_transform;
+                self.camera_transform = config_data.camera_transform;
+                self.frame_data.set_config(config_data);
             }
-            if let Some(objects_data) = objects_data {
-                self.objects_ssbo.update(objects_data);
+            if let Some(scene_data) = self.pending_scene.take() {
+                self.update_scene(bundle, scene_data)
+                    .context("Failed to update scene SSBOs")?;
             }
-
-            self.enqueue_new_frame(bundle, need_timestamp, current_frame, push_constants_data)?;
-
-            // If it's the first frame, we need to wait for the first frame
-            // to finish rendering before we can present it.
-            if self.last_finished_frame.is_none() {
-                debug!("Waiting for first frame to finish rendering");
-                bundle
-                    .device
-                    .wait_for_fences(&[self.fences[current_frame]], true, u64::MAX)?;
+            let edited_objects = std::mem::take(&mut self.pending_objects);
+            if !edited_objects.is_empty() {
+                let objects = edited_objects.iter().map(|(_, object)| object);
+                if !self.features.sdf && SceneFeatures::has_sdf(objects) {
+                    self.set_features(
+                        bundle,
+                        SceneFeatures {
+                            sdf: true,
+                            ..self.features
+                        },
+                    );
+                }
+                self.frame_data.edit_objects(edited_objects);
             }
 
+            self.enqueue_new_frame(
+                bundle,
+                current_frame,
+                push_constants_data,
+                dispatches,
+                interactive,
+            )?;
+            self.pending_reproject = false;
+            self.pending_post = false;
+
             self.profile.fps = self.fps.update();
 
             self.should_invalidate[current_frame] = false;
@@ -685,96 +1515,454 @@ impl TracerPipeline {
             self.current_frame = (self.current_frame + 1) % MAX_DEPTH;
         }
 
-        // Return last processed frame
+        // Return the last submitted frame, which may still be traced. The
+        // consumers wait for its point on the GPU, not here.
         if let Some(idx) = self.last_finished_frame {
+            let (image, descriptor_set) = self.presented_image(idx);
             Ok(TracerSlot {
-                image: TracerSlotImage {
-                    image: self.images[idx],
-                    image_view: self.image_views[idx],
-                    sampler: self.image_samplers[idx],
-                    dimensions: self.viewport,
-                    byte_size: self.image_bytesize,
-                    layout: vk::ImageLayout::GENERAL,
-                    format: vk::Format::R8G8B8A8_UNORM,
-                },
-                descriptor_set: self.descriptor_sets_0[idx],
+                image,
+                descriptor_set,
                 index: idx,
+                ready: self.timeline.point(self.submitted[idx]),
+                compare: None,
             })
         } else {
             unreachable!("TracerPipeline::present called before first frame was rendered")
         }
     }
 
-    pub unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> anyhow::Result<()> {
+    /// Post-processed image of the slot, the traced one without post passes
+    fn presented_image(&self, idx: usize) -> (TracerSlotImage, vk::DescriptorSet) {
+        let image = TracerSlotImage {
+            image: self.images[idx],
+            image_view: self.image_views[idx],
+            sampler: self.image_samplers[idx],
+            dimensions: self.viewport,
+            byte_size: self.image_bytesize,
+            layout: vk::ImageLayout::GENERAL,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+        };
+        match self.post.output(idx) {
+            Some(output) => (
+                TracerSlotImage {
+                    image: output.image,
+                    image_view: output.image_view,
+                    sampler: self.post.sampler(),
+                    byte_size: self.post.image_bytesize(),
+                    ..image
+                },
+                output.descriptor_set,
+            ),
+            None => (image, self.descriptors_0.sets()[idx]),
+        }
+    }
+
+    /// Blocks until the submission has completed, unless the watchdog
+    /// finds the GPU hung first
+    unsafe fn wait_submitted(&self, bundle: Bundle, value: u64) -> TracerResult<()> {
+        match &self.watchdog {
+            Some(watchdog) => watchdog.wait(bundle, self.timeline.point(value)),
+            None => self.timeline.wait(bundle, value),
+        }
+    }
+
+    /// Returns the pick buffer once the dispatch writing it has completed
+    pub unsafe fn take_picked(&mut self, bundle: Bundle) -> TracerResult<Option<SSBOPickData>> {
+        match self.pick_submitted {
+            Some(value) if self.timeline.is_reached(bundle, value)? => {
+                self.pick_submitted = None;
+                Ok(Some(self.pick_ssbo.read()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Scans every traced frame for NaN and infinite pixels, see `TracerProfile`
+    /// Runs the post passes over the next traced frames
+    pub unsafe fn set_post(&mut self, bundle: Bundle, passes: &[PostPass]) -> TracerResult<()> {
+        if self
+            .post
+            .set_passes(
+                bundle,
+                &mut self.image_pool,
+                passes,
+                &self.image_views,
+                self.viewport,
+            )
+            .context("Failed to set post passes")?
+        {
+            self.pending_post = true;
+        }
+        Ok(())
+    }
+
+    pub fn set_check_invalid_pixels(&mut self, check: bool) {
+        self.check_invalid_pixels = check;
+        if !check {
+            self.profile.invalid_pixels = None;
+        }
+    }
+
+    /// Reports the scan once the dispatch writing it has completed
+    unsafe fn read_invalid_pixels(&mut self, bundle: Bundle) -> TracerResult<()> {
+        if let Some((value, width)) = self.invalid_pixels_submitted {
+            if self.timeline.is_reached(bundle, value)? {
+                self.invalid_pixels_submitted = None;
+                if self.check_invalid_pixels {
+                    self.profile.invalid_pixels = self.invalid_pixels_ssbo.read().as_result(width);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the last finished frame to the host, blocking until it is done,
+    /// post-processed if there are post passes. Returns the dimensions and
+    /// the raw RGBA32F pixels.
+    pub unsafe fn snapshot(
+        &mut self,
+        bundle: Bundle,
+    ) -> TracerResult<Option<(glam::UVec2, Vec<u8>)>> {
+        let Some(idx) = self.last_finished_frame else {
+            return Ok(None);
+        };
+        self.wait_submitted(bundle, self.submitted[idx])?;
+        let image = self.presented_image(idx).0.image;
+        self.read_image(bundle, image).map(Some)
+    }
+
+    /// Accumulated samples of the last finished frame, before the post
+    /// passes. Alpha holds the frames accumulated in the pixel.
+    pub unsafe fn accumulation(
+        &mut self,
+        bundle: Bundle,
+    ) -> TracerResult<Option<(glam::UVec2, Vec<u8>)>> {
+        let Some(idx) = self.last_finished_frame else {
+            return Ok(None);
+        };
+        self.wait_submitted(bundle, self.submitted[idx])?;
+        self.read_image(bundle, self.images[idx]).map(Some)
+    }
+
+    /// Replaces the accumulated samples with RGBA32F pixels of the current
+    /// size, the next dispatch adds onto them instead of starting over
+    pub unsafe fn restore_accumulation(
+        &mut self,
+        bundle: Bundle,
+        pixels: &[u8],
+    ) -> TracerResult<()> {
+        debug!("Restoring accumulation of {:?}", self.viewport);
+        bundle.device.device_wait_idle()?;
+
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(pixels.len() as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let requirements = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = match bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Restore Buffer",
+            requirements,
+            location: gpu_allocator::MemoryLocation::CpuToGpu,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        }) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                bundle.device.destroy_buffer(buffer, None);
+                return Err(e.into());
+            }
+        };
+        let mut batch = match UploadBatch::begin(bundle, self.command_pool) {
+            Ok(batch) => batch,
+            Err(e) => {
+                bundle.allocator().free(allocation)?;
+                bundle.device.destroy_buffer(buffer, None);
+                return Err(e);
+            }
+        };
+        let (memory, offset) = (allocation.memory(), allocation.offset());
+        let mapped = allocation
+            .mapped_ptr()
+            .expect("CpuToGpu allocation must be mappable");
+        (mapped.as_ptr() as *mut u8).copy_from_nonoverlapping(pixels.as_ptr(), pixels.len());
+        batch.keep_staging(buffer, allocation);
+        if let Err(e) = bundle.device.bind_buffer_memory(buffer, memory, offset) {
+            batch.discard(bundle);
+            return Err(e.into());
+        }
+
+        // The images stay in the general layout the dispatches use
+        let before = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[before],
+            &[],
+            &[],
+        );
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: self.viewport.x,
+                height: self.viewport.y,
+                depth: 1,
+            });
+        for image in &self.images {
+            bundle.device.cmd_copy_buffer_to_image(
+                batch.as_inner(),
+                buffer,
+                *image,
+                vk::ImageLayout::GENERAL,
+                &[region],
+            );
+        }
+        let after = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[after],
+            &[],
+            &[],
+        );
+        batch.submit_and_wait(bundle, self.queues.compute_queue)?;
+
+        // Nothing left to throw the restored samples away
+        self.should_invalidate = vec![false; MAX_DEPTH];
+        self.pending_invalidate = false;
+        self.pending_reproject = false;
+        Ok(())
+    }
+
+    // Blocks until the image is copied to the host, the image must not be
+    // written meanwhile
+    unsafe fn read_image(
+        &mut self,
+        bundle: Bundle,
+        image: vk::Image,
+    ) -> TracerResult<(glam::UVec2, Vec<u8>)> {
+        debug!("Taking snapshot of {:?}", self.viewport);
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(self.image_bytesize as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let requirements = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Snapshot Buffer",
+            requirements,
+            location: gpu_allocator::MemoryLocation::GpuToCpu,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+        bundle
+            .device
+            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
+
+        let submit = OneTimeSubmit::begin(bundle, self.command_pool)?;
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: self.viewport.x,
+                height: self.viewport.y,
+                depth: 1,
+            });
+        bundle.device.cmd_copy_image_to_buffer(
+            submit.as_inner(),
+            image,
+            vk::ImageLayout::GENERAL,
+            buffer,
+            &[region],
+        );
+        // The next dispatch into the slot is not submitted until this returns,
+        // so waiting for the copy is enough to not race with it
+        submit.submit_and_wait(bundle, self.queues.compute_queue)?;
+
+        let pixels = allocation
+            .mapped_slice()
+            .expect("GpuToCpu allocation must be mappable")
+            [..(self.viewport.x * self.viewport.y) as usize * 16]
+            .to_vec();
+        bundle.allocator().free(allocation)?;
+        bundle.device.destroy_buffer(buffer, None);
+
+        Ok((self.viewport, pixels))
+    }
+
+    /// Makes the next dispatch into the slot wait for the given point,
+    /// i.e. until the front-end stops reading the slot image
+    pub fn release(&mut self, index: usize, point: SyncPoint) {
+        self.released[index] = Some(point);
+    }
+
+    #[tracing::instrument(name = "TracerPipeline::resize", skip_all)]
+    pub unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> TracerResult<()> {
         if self.viewport != size {
             debug!(
                 "Resizing TracerPipeline from {:?} to {:?}",
                 self.viewport, size
             );
-            self.viewport = size;
 
             bundle.device.device_wait_idle()?;
+            self.recreate_images(bundle, size)?;
+            self.trim_image_pool(bundle);
+        }
 
-            // Destroy existing images
-            for (i, image) in self.images.iter().enumerate() {
-                if let Some(allocation) = self.image_allocations[i].take() {
-                    bundle
-                        .allocator()
-                        .free(allocation)
-                        .expect("Failed to free image allocation");
-                }
-                bundle.device.destroy_image_view(self.image_views[i], None);
-                bundle.device.destroy_sampler(self.image_samplers[i], None);
-                bundle.device.destroy_image(*image, None);
-            }
+        Ok(())
+    }
 
-            // Destroy descriptor sets
-            bundle
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout_0, None);
-            bundle
-                .device
-                .destroy_descriptor_pool(self.descriptor_pool_0, None);
+    /// Recreates the images in freshly allocated memory, so they fill the
+    /// gaps left in the allocator blocks, and frees the pooled memory.
+    /// gpu_allocator does not move allocations, emptied blocks are released
+    /// once their last allocation is freed. The new images hold no samples,
+    /// the caller invalidates the accumulation.
+    pub unsafe fn defragment(&mut self, bundle: Bundle) -> TracerResult<()> {
+        debug!("Defragmenting TracerPipeline images");
+        bundle.device.device_wait_idle()?;
+        self.image_pool.trim(bundle);
+        self.recreate_images(bundle, self.viewport)?;
+        self.image_pool.trim(bundle);
+        Ok(())
+    }
 
-            // Create new images
-            let (image_bytesize, images, image_views, image_samplers, image_allocations) =
-                Self::create_images(
+    /// Returns the pooled memory to the allocator once too much of the
+    /// reserved device memory is unused, e.g. after many different sizes
+    unsafe fn trim_image_pool(&mut self, bundle: Bundle) {
+        let report = bundle.allocator().generate_report();
+        let unused = report
+            .total_capacity_bytes
+            .saturating_sub(report.total_allocated_bytes)
+            + self.image_pool.pooled_bytes();
+        if unused as f32 > report.total_capacity_bytes as f32 * DEFRAGMENT_THRESHOLD {
+            self.image_pool.trim(bundle);
+        }
+    }
+
+    unsafe fn recreate_images(&mut self, bundle: Bundle, size: glam::UVec2) -> TracerResult<()> {
+        // Create new images first, so that on failure the pipeline
+        // is left with the old ones intact
+        let mut tile_queue = TileQueue::new(bundle, size).context("Failed to create tile queue")?;
+        let batch = match UploadBatch::begin(bundle, self.command_pool) {
+            Ok(batch) => batch,
+            Err(e) => {
+                tile_queue.destroy(bundle);
+                return Err(e).context("Failed to begin initialization batch");
+            }
+        };
+        let (image_bytesize, images, image_views, image_samplers, image_memory) =
+            match Self::create_images(
+                bundle,
+                &self.queues,
+                &batch,
+                &mut self.image_pool,
+                size,
+                self.images_custom_usage,
+            ) {
+                Ok(images) => images,
+                Err(e) => {
+                    batch.discard(bundle);
+                    tile_queue.destroy(bundle);
+                    return Err(e).context("Failed to create images");
+                }
+            };
+        let history = match TemporalHistory::new(bundle, &batch, &mut self.image_pool, size) {
+            Ok(history) => history,
+            Err(e) => {
+                batch.discard(bundle);
+                tile_queue.destroy(bundle);
+                let mut image_memory: Vec<_> = image_memory.into_iter().map(Some).collect();
+                Self::destroy_images(
                     bundle,
-                    &self.queues,
-                    self.command_pool,
-                    self.viewport,
-                    self.images_custom_usage,
-                )
-                .context("Failed to create images")?;
+                    &mut self.image_pool,
+                    &images,
+                    &image_views,
+                    &image_samplers,
+                    &mut image_memory,
+                );
+                return Err(e).context("Failed to create temporal history");
+            }
+        };
+        batch
+            .submit_and_wait(bundle, self.queues.compute_queue)
+            .context("Failed to initialize images")?;
+        self.viewport = size;
+        let mut old_history = std::mem::replace(&mut self.history, history);
+
+        let old_images = std::mem::replace(&mut self.images, images);
+        let old_image_views = std::mem::replace(&mut self.image_views, image_views);
+        let old_image_samplers = std::mem::replace(&mut self.image_samplers, image_samplers);
+        let mut old_image_memory = std::mem::replace(
+            &mut self.image_memory,
+            image_memory.into_iter().map(Some).collect(),
+        );
+        self.image_bytesize = image_bytesize;
 
-            self.images = images;
-            self.image_views = image_views;
-            self.image_samplers = image_samplers;
-            self.image_allocations = image_allocations.into_iter().map(Some).collect();
-            self.image_bytesize = image_bytesize;
+        // Point the existing descriptor sets to the new images
+        Self::write_descriptor_sets_0(
+            bundle,
+            self.descriptors_0.sets(),
+            &self.image_views,
+            &self.history,
+        );
 
-            // Create new descriptor sets
-            let (descriptor_set_layout_0, descriptor_pool_0, descriptor_sets_0) =
-                Self::create_descriptor_set_0(bundle, &self.image_views)
-                    .context("Failed to create descriptor set layout")?;
-            self.descriptor_set_layout_0 = descriptor_set_layout_0;
-            self.descriptor_pool_0 = descriptor_pool_0;
-            self.descriptor_sets_0 = descriptor_sets_0;
-        }
+        // Destroy old images
+        Self::destroy_images(
+            bundle,
+            &mut self.image_pool,
+            &old_images,
+            &old_image_views,
+            &old_image_samplers,
+            &mut old_image_memory,
+        );
+        old_history.destroy(bundle, &mut self.image_pool);
+        self.post
+            .resize(bundle, &mut self.image_pool, &self.image_views, size)
+            .context("Failed to resize post images")?;
+
+        self.bindless
+            .write_buffer(bundle, TILE_QUEUE_BINDING, tile_queue.buffer);
+        let mut old_tile_queue = std::mem::replace(&mut self.tile_queue, tile_queue);
+        old_tile_queue.destroy(bundle);
 
         Ok(())
     }
 
     pub unsafe fn destroy(&mut self, bundle: Bundle) {
         if !self.destroyed {
-            debug!("Waiting for device to be idle before destroying runtime");
-            bundle.device.device_wait_idle().unwrap();
+            if let Some(watchdog) = &mut self.watchdog {
+                debug!("Stopping GPU watchdog");
+                watchdog.stop();
+            }
 
-            debug!("Destroying fences");
-            for fence in &self.fences {
-                bundle.device.destroy_fence(*fence, None);
+            debug!("Waiting for device to be idle before destroying runtime");
+            // The device may be lost already, destroy everything anyway
+            if let Err(e) = bundle.device.device_wait_idle() {
+                warn!("Failed to wait for device idle: {}", e);
             }
 
+            debug!("Destroying timeline");
+            self.timeline.destroy(bundle);
+
             debug!("Destroying command pool");
             for cmd_buf in &mut self.command_buffers {
                 cmd_buf.destroy(bundle, self.command_pool);
@@ -782,7 +1970,13 @@ impl TracerPipeline {
             bundle.device.destroy_command_pool(self.command_pool, None);
 
             debug!("Destroying pipeline");
-            bundle.device.destroy_pipeline(self.pipeline, None);
+            self.variants.destroy(bundle);
+            bundle
+                .device
+                .destroy_pipeline(self.invalid_pixels_pipeline, None);
+            bundle
+                .device
+                .destroy_pipeline(self.classify_tiles_pipeline, None);
 
             debug!("Destroying pipeline layout");
             bundle
@@ -791,40 +1985,56 @@ impl TracerPipeline {
 
             debug!("Destroying compute shader");
             self.compute_shader.destroy(bundle);
+            self.invalid_pixels_shader.destroy(bundle);
+            self.classify_tiles_shader.destroy(bundle);
 
             debug!("Destroying images");
-            for (i, image) in self.images.iter().enumerate() {
-                if let Some(allocation) = self.image_allocations[i].take() {
-                    bundle
-                        .allocator()
-                        .free(allocation)
-                        .expect("Failed to free image allocation");
-                }
-                bundle.device.destroy_image_view(self.image_views[i], None);
-                bundle.device.destroy_sampler(self.image_samplers[i], None);
-                bundle.device.destroy_image(*image, None);
-            }
+            Self::destroy_images(
+                bundle,
+                &mut self.image_pool,
+                &self.images,
+                &self.image_views,
+                &self.image_samplers,
+                &mut self.image_memory,
+            );
+            self.history.destroy(bundle, &mut self.image_pool);
+            self.post.destroy(bundle, &mut self.image_pool);
+            self.image_pool.destroy(bundle);
 
             debug!("Destroying SSBO");
-            self.config_ssbo.destroy(bundle);
-            self.objects_ssbo.destroy(bundle);
-
-            debug!("Destroying descriptor set layout");
-            bundle
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout_0, None);
-            bundle
-                .device
-                .destroy_descriptor_pool(self.descriptor_pool_0, None);
-            bundle
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout_1, None);
+            self.frame_data.destroy(bundle);
+            self.instances_ssbo.destroy(bundle);
+            self.lights_ssbo.destroy(bundle);
+            self.materials_ssbo.destroy(bundle);
+            self.volumes_ssbo.destroy(bundle);
+            self.environment_ssbo.destroy(bundle);
+            self.pick_ssbo.destroy(bundle);
+            self.invalid_pixels_ssbo.destroy(bundle);
+            self.tile_queue.destroy(bundle);
+            // The staged SSBOs wait for their uploads while destroyed
+            self.uploads.destroy(bundle);
             bundle
                 .device
-                .destroy_descriptor_pool(self.descriptor_pool_1, None);
+                .destroy_command_pool(self.transfer_command_pool, None);
+
+            debug!("Destroying descriptor set layout");
+            self.descriptors_0.destroy(bundle);
+            self.bindless.destroy(bundle);
+
+            debug!("Destroying textures");
+            self.blue_noise.destroy(bundle);
+            if let Some((texture, _)) = &mut self.environment {
+                texture.destroy(bundle);
+            }
+            for (texture, _) in self.textures.values_mut() {
+                texture.destroy(bundle);
+            }
+            for slot in self.imported.values_mut() {
+                slot.texture.destroy(bundle);
+            }
 
-            debug!("Destroying query pool");
-            bundle.device.destroy_query_pool(self.query_pool, None);
+            debug!("Destroying GPU timer");
+            self.gpu_timer.destroy(bundle);
 
             self.destroyed = true;
         } else {
@@ -832,8 +2042,56 @@ impl TracerPipeline {
         }
     }
 
+    /// Smoothed GPU time of the tracing dispatches, 0 until measured
+    pub fn render_time(&self) -> f32 {
+        self.profile.render_time
+    }
+
     pub fn get_profile(&self) -> TracerProfile {
-        self.profile.clone()
+        let environment = self
+            .environment
+            .as_ref()
+            .map_or(0, |(texture, _)| texture.allocated_bytes());
+        let textures = self
+            .textures
+            .values()
+            .map(|(texture, _)| texture.allocated_bytes())
+            .chain(
+                self.imported
+                    .values()
+                    .map(|slot| slot.texture.allocated_bytes()),
+            )
+            .sum::<u64>();
+        TracerProfile {
+            pooled_image_bytes: self.image_pool.pooled_bytes(),
+            scene_bytes: vec![
+                ("Config and objects", self.frame_data.allocated_bytes()),
+                ("Instances", self.instances_ssbo.allocated_bytes()),
+                ("Lights", self.lights_ssbo.allocated_bytes()),
+                ("Materials", self.materials_ssbo.allocated_bytes()),
+                ("Volumes", self.volumes_ssbo.allocated_bytes()),
+                (
+                    "Environment",
+                    self.environment_ssbo.allocated_bytes() + environment,
+                ),
+                ("Object textures", textures),
+                ("Blue noise", self.blue_noise.allocated_bytes()),
+            ],
+            ..self.profile.clone()
+        }
+    }
+
+    /// Starts keeping every measured render time, not just the average
+    pub fn record_render_times(&mut self) {
+        self.render_time_samples.get_or_insert_with(Vec::new);
+    }
+
+    /// Render times in milliseconds measured since the last call
+    pub fn take_render_times(&mut self) -> Vec<f32> {
+        self.render_time_samples
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
     }
 }
 
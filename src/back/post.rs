@@ -0,0 +1,865 @@
+use crate::assets::AssetManager;
+use crate::back::exposure::ExposureBuffer;
+use crate::back::interface::HISTOGRAM_BINS;
+use crate::back::{BackQueues, TracerSlot, OUTPUT_BINDING};
+use crate::common::command_buffer::CommandBuffer;
+use crate::common::descriptor::DescriptorAllocator;
+use crate::common::image_pool::{ImagePool, PooledMemory};
+use crate::common::queue::QueueFamily;
+use crate::common::shader::Shader;
+use crate::config::PostPass;
+use crate::error::{Context, TracerResult};
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+use std::collections::BTreeMap;
+
+const SOURCE_BINDING: u32 = 0;
+const TARGET_BINDING: u32 = 1;
+const BLOOM_BINDING: u32 = 2;
+const EXPOSURE_BINDING: u32 = 3;
+// Local size of the post shaders, see post.glsl
+const WORKGROUP_SIZE: u32 = 16;
+// The exposure is adapted by a single workgroup, a thread per bin
+const _: () = assert!(WORKGROUP_SIZE * WORKGROUP_SIZE == HISTOGRAM_BINS);
+const TONEMAP_SHADER: &str = "post_tonemap";
+const EXPOSURE_HISTOGRAM_SHADER: &str = "post_exposure_histogram";
+const EXPOSURE_ADAPT_SHADER: &str = "post_exposure_adapt";
+const BLOOM_THRESHOLD_SHADER: &str = "post_bloom_threshold";
+const BLOOM_BLUR_SHADER: &str = "post_bloom_blur";
+const BLOOM_COMPOSITE_SHADER: &str = "post_bloom_composite";
+
+/// Images of a slot the passes read and write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PostTarget {
+    Traced,
+    // Ping-pong pair the passes write their results into
+    First,
+    Second,
+    // Half resolution bright parts of the image and their blur, the
+    // composite of the bloom reads `Bright`
+    Bright,
+    Blurred,
+}
+
+// Source and target of the descriptor sets of a slot, the bloom image is
+// bound to all of them
+const SETS: [(PostTarget, PostTarget); 8] = [
+    (PostTarget::Traced, PostTarget::First),
+    (PostTarget::First, PostTarget::Second),
+    (PostTarget::Second, PostTarget::First),
+    (PostTarget::Traced, PostTarget::Bright),
+    (PostTarget::First, PostTarget::Bright),
+    (PostTarget::Second, PostTarget::Bright),
+    (PostTarget::Bright, PostTarget::Blurred),
+    (PostTarget::Blurred, PostTarget::Bright),
+];
+
+/// Settings of a pass, pushed before its dispatch, see post.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct PostPushConstants {
+    params: [f32; 4],
+}
+
+impl PostPushConstants {
+    fn new(x: f32, y: f32, z: f32) -> Self {
+        Self {
+            params: [x, y, z, 0.0],
+        }
+    }
+}
+
+impl PostPass {
+    /// Shaders of the dispatches of the pass
+    fn shaders(&self) -> &'static [&'static str] {
+        match self {
+            PostPass::Tonemap {
+                auto_exposure: Some(_),
+                ..
+            } => &[
+                EXPOSURE_HISTOGRAM_SHADER,
+                EXPOSURE_ADAPT_SHADER,
+                TONEMAP_SHADER,
+            ],
+            PostPass::Tonemap { .. } => &[TONEMAP_SHADER],
+            PostPass::Bloom { .. } => &[
+                BLOOM_THRESHOLD_SHADER,
+                BLOOM_BLUR_SHADER,
+                BLOOM_COMPOSITE_SHADER,
+            ],
+            PostPass::Vignette { .. } => &["post_vignette"],
+            PostPass::Fxaa => &["post_fxaa"],
+        }
+    }
+
+    fn has_auto_exposure(&self) -> bool {
+        matches!(
+            self,
+            PostPass::Tonemap {
+                auto_exposure: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Settings of the single dispatch of the simple passes
+    fn push_constants(&self) -> PostPushConstants {
+        match self {
+            PostPass::Tonemap {
+                exposure,
+                auto_exposure,
+            } => PostPushConstants::new(
+                *exposure,
+                if auto_exposure.is_some() { 1.0 } else { 0.0 },
+                0.0,
+            ),
+            PostPass::Vignette { strength } => PostPushConstants::new(*strength, 0.0, 0.0),
+            PostPass::Bloom { .. } | PostPass::Fxaa => PostPushConstants::default(),
+        }
+    }
+}
+
+struct PostImage {
+    image: vk::Image,
+    view: vk::ImageView,
+    memory: Option<PooledMemory>,
+}
+
+/// Image written by the last pass of a slot, presented in place of the
+/// traced one
+pub(crate) struct PostOutput {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+/// Post-processing passes of the config (see `PostPass`), run over the
+/// traced image at the end of every traced frame. Every pass is a compute
+/// shader reading the image of the previous pass and writing the next
+/// one, the passes ping-pong between two images per slot. The traced
+/// image keeps the accumulation and is not written.
+pub(crate) struct PostStack {
+    // Passes of the config and the ones of them that run, without those
+    // whose pipeline failed to be created
+    requested: Vec<PostPass>,
+    passes: Vec<PostPass>,
+    asset_manager: AssetManager,
+    // Pipelines by shader, created once a pass needs them
+    pipelines: BTreeMap<&'static str, (Shader, vk::Pipeline)>,
+    pipeline_layout: vk::PipelineLayout,
+    descriptors: DescriptorAllocator, // sets size = depth * SETS.len()
+    // Slot descriptor sets of the post images, two per slot. Only the
+    // output binding is written, the presentation reads nothing else.
+    slot_descriptors: DescriptorAllocator,
+    sampler: vk::Sampler,
+    exposure: ExposureBuffer,
+
+    // First and second per slot, None while there are no passes
+    images: Option<Vec<[PostImage; 2]>>,
+    // Bright and blurred per slot, None while there is no bloom
+    bloom_images: Option<Vec<[PostImage; 2]>>,
+    image_bytesize: usize,
+    // Post image the last pass wrote per slot, None if the slot was
+    // traced without passes
+    outputs: Vec<Option<usize>>,
+    depth: usize,
+    queue_family_indices: Vec<u32>,
+    images_custom_usage: vk::ImageUsageFlags,
+    destroyed: bool,
+}
+
+impl PostStack {
+    pub unsafe fn new(
+        bundle: Bundle,
+        asset_manager: AssetManager,
+        queues: &BackQueues,
+        depth: usize,
+        images_custom_usage: vk::ImageUsageFlags,
+    ) -> TracerResult<Self> {
+        let bindings = [
+            (SOURCE_BINDING, vk::DescriptorType::STORAGE_IMAGE),
+            (TARGET_BINDING, vk::DescriptorType::STORAGE_IMAGE),
+            (BLOOM_BINDING, vk::DescriptorType::STORAGE_IMAGE),
+            (EXPOSURE_BINDING, vk::DescriptorType::STORAGE_BUFFER),
+        ]
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let descriptors = DescriptorAllocator::new(bundle, &bindings, &[], depth * SETS.len())
+            .context("Failed to create post descriptor sets")?;
+        let slot_descriptors = DescriptorAllocator::new(
+            bundle,
+            &TracerSlot::descriptor_set_layout_bindings(),
+            &[],
+            depth * 2,
+        )
+        .context("Failed to create post slot descriptor sets")?;
+
+        let ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<PostPushConstants>() as u32)];
+        let layouts = [descriptors.layout];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&layouts)
+            .push_constant_ranges(&ranges);
+        let pipeline_layout = bundle
+            .device
+            .create_pipeline_layout(&pipeline_layout_info, None)?;
+
+        // Same filtering as the traced images
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = bundle.device.create_sampler(&sampler_info, None)?;
+
+        // Bound to every set, read by the tonemapping with or without the
+        // auto exposure
+        let exposure = ExposureBuffer::new(bundle).context("Failed to create exposure buffer")?;
+        let buffer_info = vk::DescriptorBufferInfo::default()
+            .buffer(exposure.buffer)
+            .offset(0)
+            .range(ExposureBuffer::size());
+        let writes: Vec<_> = descriptors
+            .sets()
+            .iter()
+            .map(|set| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(EXPOSURE_BINDING)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(std::slice::from_ref(&buffer_info))
+            })
+            .collect();
+        bundle.device.update_descriptor_sets(&writes, &[]);
+
+        Ok(Self {
+            requested: vec![],
+            passes: vec![],
+            asset_manager,
+            pipelines: BTreeMap::new(),
+            pipeline_layout,
+            descriptors,
+            slot_descriptors,
+            sampler,
+            exposure,
+            images: None,
+            bloom_images: None,
+            image_bytesize: 0,
+            outputs: vec![None; depth],
+            depth,
+            // Read by the front-ends on the graphics queue, like the
+            // traced images
+            queue_family_indices: QueueFamily::unique_indices(&[
+                queues.indices.graphics_family,
+                queues.indices.compute_family,
+                queues.indices.transfer_family,
+            ]),
+            images_custom_usage,
+            destroyed: false,
+        })
+    }
+
+    /// Replaces the passes, creating the pipelines and images they need.
+    /// Returns whether they changed, the new ones run with the next traced
+    /// frame. Nothing in use is destroyed, so the GPU is not waited for.
+    /// A pass whose shader fails to load is skipped with a warning.
+    pub unsafe fn set_passes(
+        &mut self,
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        passes: &[PostPass],
+        traced_views: &[vk::ImageView],
+        size: glam::UVec2,
+    ) -> TracerResult<bool> {
+        if self.requested == passes {
+            return Ok(false);
+        }
+
+        let mut runnable = Vec::with_capacity(passes.len());
+        'passes: for pass in passes {
+            for name in pass.shaders() {
+                if !self.pipelines.contains_key(name) {
+                    debug!("Creating post pipeline {}", name);
+                    match self.create_pipeline(bundle, name) {
+                        Ok(pipeline) => {
+                            self.pipelines.insert(name, pipeline);
+                        }
+                        Err(e) => {
+                            warn!("Skipping post pass {}: {}", pass.name(), e);
+                            continue 'passes;
+                        }
+                    }
+                }
+            }
+            runnable.push(pass.clone());
+        }
+        let bloom = runnable
+            .iter()
+            .any(|pass| matches!(pass, PostPass::Bloom { .. }));
+        if (!runnable.is_empty() && self.images.is_none()) || (bloom && self.bloom_images.is_none())
+        {
+            self.create_images(bundle, pool, traced_views, size, bloom)?;
+        }
+
+        // Enabling the auto exposure starts from the exposure of the image
+        // instead of the one left over from before
+        if !self.passes.iter().any(PostPass::has_auto_exposure)
+            && runnable.iter().any(PostPass::has_auto_exposure)
+        {
+            self.exposure.reset();
+        }
+
+        self.requested = passes.to_vec();
+        self.passes = runnable;
+        Ok(true)
+    }
+
+    unsafe fn create_pipeline(
+        &self,
+        bundle: Bundle,
+        name: &str,
+    ) -> TracerResult<(Shader, vk::Pipeline)> {
+        let shader = self.asset_manager.load_shader(name)?;
+        let mut shader = Shader::new_from_spirv(bundle, shader.get_spirv()?)?;
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader.module)
+            .name(c"main");
+        let pipeline_info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(self.pipeline_layout);
+        match bundle.device.create_compute_pipelines(
+            vk::PipelineCache::null(),
+            &[pipeline_info],
+            None,
+        ) {
+            Ok(mut pipelines) => Ok((shader, pipelines.remove(0))),
+            Err((_, e)) => {
+                shader.destroy(bundle);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Creates the missing images, with the bloom ones if asked to
+    unsafe fn create_images(
+        &mut self,
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        traced_views: &[vk::ImageView],
+        size: glam::UVec2,
+        bloom: bool,
+    ) -> TracerResult<()> {
+        if self.images.is_none() {
+            debug!("Creating post images of {}x{}", size.x, size.y);
+            let images = self
+                .create_pairs(bundle, pool, size, "Post")
+                .context("Failed to create post images")?;
+            self.image_bytesize = bundle
+                .device
+                .get_image_memory_requirements(images[0][0].image)
+                .size as usize;
+            self.images = Some(images);
+            self.outputs = vec![None; self.depth];
+        }
+        if bloom && self.bloom_images.is_none() {
+            let size = Self::bloom_size(size);
+            debug!("Creating bloom images of {}x{}", size.x, size.y);
+            let images = self
+                .create_pairs(bundle, pool, size, "Bloom")
+                .context("Failed to create bloom images")?;
+            self.bloom_images = Some(images);
+        }
+
+        self.write_descriptor_sets(bundle, traced_views);
+        Ok(())
+    }
+
+    fn bloom_size(size: glam::UVec2) -> glam::UVec2 {
+        (size + glam::UVec2::ONE) / 2
+    }
+
+    unsafe fn create_pairs(
+        &self,
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        size: glam::UVec2,
+        name: &str,
+    ) -> TracerResult<Vec<[PostImage; 2]>> {
+        let sharing_mode = if self.queue_family_indices.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+
+        let mut images: Vec<PostImage> = Vec::with_capacity(self.depth * 2);
+        for index in 0..self.depth * 2 {
+            let name = format!("{} Image Allocation {}", name, index);
+            match self.create_image(bundle, pool, sharing_mode, size, &name) {
+                Ok(image) => images.push(image),
+                Err(e) => {
+                    for image in &mut images {
+                        Self::destroy_image(bundle, pool, image);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let mut images = images.into_iter();
+        Ok((0..self.depth)
+            .map(|_| [images.next().unwrap(), images.next().unwrap()])
+            .collect())
+    }
+
+    unsafe fn create_image(
+        &self,
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        sharing_mode: vk::SharingMode,
+        size: glam::UVec2,
+        name: &str,
+    ) -> TracerResult<PostImage> {
+        let create_image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .extent(vk::Extent3D {
+                width: size.x,
+                height: size.y,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            // Transfer source for the headless readback
+            .usage(
+                vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | self.images_custom_usage,
+            )
+            .sharing_mode(sharing_mode)
+            .queue_family_indices(&self.queue_family_indices)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = bundle.device.create_image(&create_image_info, None)?;
+        let memory = match pool.bind(bundle, image, name) {
+            Ok(memory) => memory,
+            Err(e) => {
+                bundle.device.destroy_image(image, None);
+                return Err(e);
+            }
+        };
+        let mut post_image = PostImage {
+            image,
+            view: vk::ImageView::null(),
+            memory: Some(memory),
+        };
+
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .subresource_range(Self::subresource_range());
+        match bundle.device.create_image_view(&image_view_info, None) {
+            Ok(view) => {
+                post_image.view = view;
+                Ok(post_image)
+            }
+            Err(e) => {
+                Self::destroy_image(bundle, pool, &mut post_image);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn subresource_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+
+    fn view(
+        &self,
+        traced_views: &[vk::ImageView],
+        slot: usize,
+        target: PostTarget,
+    ) -> Option<vk::ImageView> {
+        let view = |images: &Option<Vec<[PostImage; 2]>>, index: usize| {
+            images.as_ref().map(|images| images[slot][index].view)
+        };
+        match target {
+            PostTarget::Traced => Some(traced_views[slot]),
+            PostTarget::First => view(&self.images, 0),
+            PostTarget::Second => view(&self.images, 1),
+            PostTarget::Bright => view(&self.bloom_images, 0),
+            PostTarget::Blurred => view(&self.bloom_images, 1),
+        }
+    }
+
+    /// Points the sets to the existing images, the sets of the missing
+    /// ones are left as they are and not bound
+    unsafe fn write_descriptor_sets(&self, bundle: Bundle, traced_views: &[vk::ImageView]) {
+        let image_info = |view: vk::ImageView| {
+            vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::GENERAL)
+        };
+        let write = |set: vk::DescriptorSet, binding: u32, view: vk::ImageView| {
+            let info = image_info(view);
+            let writes = [vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(std::slice::from_ref(&info))];
+            bundle.device.update_descriptor_sets(&writes, &[]);
+        };
+
+        for slot in 0..self.depth {
+            let sets = &self.descriptors.sets()[slot * SETS.len()..][..SETS.len()];
+            let bloom = self.view(traced_views, slot, PostTarget::Bright);
+            for (set, (source, target)) in sets.iter().zip(SETS) {
+                let source = self.view(traced_views, slot, source);
+                let target = self.view(traced_views, slot, target);
+                if let (Some(source), Some(target)) = (source, target) {
+                    write(*set, SOURCE_BINDING, source);
+                    write(*set, TARGET_BINDING, target);
+                    if let Some(bloom) = bloom {
+                        write(*set, BLOOM_BINDING, bloom);
+                    }
+                }
+            }
+
+            for (index, target) in [PostTarget::First, PostTarget::Second]
+                .into_iter()
+                .enumerate()
+            {
+                if let Some(view) = self.view(traced_views, slot, target) {
+                    write(
+                        self.slot_descriptors.sets()[slot * 2 + index],
+                        OUTPUT_BINDING,
+                        view,
+                    );
+                }
+            }
+        }
+    }
+
+    fn set(&self, slot: usize, source: PostTarget, target: PostTarget) -> vk::DescriptorSet {
+        let index = SETS
+            .iter()
+            .position(|pair| *pair == (source, target))
+            .unwrap_or_else(|| panic!("No post descriptor set from {:?} to {:?}", source, target));
+        self.descriptors.sets()[slot * SETS.len() + index]
+    }
+
+    unsafe fn destroy_image(bundle: Bundle, pool: &mut ImagePool, image: &mut PostImage) {
+        if image.view != vk::ImageView::null() {
+            bundle.device.destroy_image_view(image.view, None);
+        }
+        bundle.device.destroy_image(image.image, None);
+        if let Some(memory) = image.memory.take() {
+            pool.release(memory);
+        }
+    }
+
+    unsafe fn destroy_images(&mut self, bundle: Bundle, pool: &mut ImagePool) {
+        for mut images in [self.images.take(), self.bloom_images.take()]
+            .into_iter()
+            .flatten()
+        {
+            for image in images.iter_mut().flatten() {
+                Self::destroy_image(bundle, pool, image);
+            }
+        }
+        self.outputs = vec![None; self.depth];
+    }
+
+    /// Recreates the images for the new traced ones. The GPU must be done
+    /// with the old images.
+    pub unsafe fn resize(
+        &mut self,
+        bundle: Bundle,
+        pool: &mut ImagePool,
+        traced_views: &[vk::ImageView],
+        size: glam::UVec2,
+    ) -> TracerResult<()> {
+        self.destroy_images(bundle, pool);
+        if !self.passes.is_empty() {
+            let bloom = self
+                .passes
+                .iter()
+                .any(|pass| matches!(pass, PostPass::Bloom { .. }));
+            self.create_images(bundle, pool, traced_views, size, bloom)?;
+        }
+        Ok(())
+    }
+
+    /// Records the passes over the traced image of the slot. The tracing
+    /// writes must be visible to compute shader reads.
+    pub unsafe fn record(
+        &mut self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        slot: usize,
+        extent: vk::Extent2D,
+    ) {
+        self.outputs[slot] = None;
+        let Some(images) = &self.images else {
+            return;
+        };
+        if self.passes.is_empty() {
+            return;
+        }
+
+        // Every pass writes every pixel, the previous contents are never
+        // read and are discarded by the transition
+        let barriers: Vec<_> = images[slot]
+            .iter()
+            .chain(self.bloom_images.iter().flat_map(|images| &images[slot]))
+            .map(|image| {
+                vk::ImageMemoryBarrier::default()
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::GENERAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image.image)
+                    .subresource_range(Self::subresource_range())
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+            })
+            .collect();
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+
+        let elapsed = if self.passes.iter().any(PostPass::has_auto_exposure) {
+            self.exposure.record_begin(bundle, command_buffer)
+        } else {
+            0.0
+        };
+
+        let size = glam::UVec2::new(extent.width, extent.height);
+        let bloom_size = Self::bloom_size(size);
+        // The first pass reads the traced image, the following ones
+        // alternate between the post images
+        let mut current = PostTarget::Traced;
+        for pass in &self.passes {
+            let target = if current == PostTarget::First {
+                PostTarget::Second
+            } else {
+                PostTarget::First
+            };
+            match pass {
+                PostPass::Bloom {
+                    threshold,
+                    intensity,
+                    radius,
+                } => {
+                    // In pixels of the half resolution images
+                    let radius = radius * 0.5;
+                    let steps = [
+                        (
+                            BLOOM_THRESHOLD_SHADER,
+                            (current, PostTarget::Bright),
+                            PostPushConstants::new(*threshold, 0.0, 0.0),
+                            bloom_size,
+                        ),
+                        (
+                            BLOOM_BLUR_SHADER,
+                            (PostTarget::Bright, PostTarget::Blurred),
+                            PostPushConstants::new(1.0, 0.0, radius),
+                            bloom_size,
+                        ),
+                        (
+                            BLOOM_BLUR_SHADER,
+                            (PostTarget::Blurred, PostTarget::Bright),
+                            PostPushConstants::new(0.0, 1.0, radius),
+                            bloom_size,
+                        ),
+                        (
+                            BLOOM_COMPOSITE_SHADER,
+                            (current, target),
+                            PostPushConstants::new(*intensity, 0.0, 0.0),
+                            size,
+                        ),
+                    ];
+                    for (shader, (source, target), push_constants, size) in steps {
+                        self.record_dispatch(
+                            bundle,
+                            command_buffer,
+                            shader,
+                            self.set(slot, source, target),
+                            push_constants,
+                            size,
+                        );
+                    }
+                }
+                PostPass::Tonemap {
+                    auto_exposure: Some(auto_exposure),
+                    ..
+                } => {
+                    // Moved this far towards the exposure of the frame
+                    let adaptation = 1.0 - (-elapsed * auto_exposure.speed).exp();
+                    let steps = [
+                        (
+                            EXPOSURE_HISTOGRAM_SHADER,
+                            PostPushConstants::default(),
+                            size,
+                        ),
+                        (
+                            EXPOSURE_ADAPT_SHADER,
+                            PostPushConstants::new(
+                                adaptation,
+                                auto_exposure.min_exposure,
+                                auto_exposure.max_exposure,
+                            ),
+                            glam::UVec2::splat(WORKGROUP_SIZE),
+                        ),
+                        (TONEMAP_SHADER, pass.push_constants(), size),
+                    ];
+                    for (shader, push_constants, size) in steps {
+                        self.record_dispatch(
+                            bundle,
+                            command_buffer,
+                            shader,
+                            self.set(slot, current, target),
+                            push_constants,
+                            size,
+                        );
+                    }
+                }
+                _ => self.record_dispatch(
+                    bundle,
+                    command_buffer,
+                    pass.shaders()[0],
+                    self.set(slot, current, target),
+                    pass.push_constants(),
+                    size,
+                ),
+            }
+            current = target;
+        }
+        self.outputs[slot] = Some(if current == PostTarget::First { 0 } else { 1 });
+    }
+
+    /// Dispatches the shader over the image size and makes its writes
+    /// visible to the next dispatch
+    unsafe fn record_dispatch(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        shader: &str,
+        set: vk::DescriptorSet,
+        push_constants: PostPushConstants,
+        size: glam::UVec2,
+    ) {
+        let (_, pipeline) = &self.pipelines[shader];
+        bundle.device.cmd_bind_pipeline(
+            command_buffer.as_inner(),
+            vk::PipelineBindPoint::COMPUTE,
+            *pipeline,
+        );
+        bundle.device.cmd_bind_descriptor_sets(
+            command_buffer.as_inner(),
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        bundle.device.cmd_push_constants(
+            command_buffer.as_inner(),
+            self.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            std::slice::from_raw_parts(
+                (&push_constants as *const PostPushConstants) as *const u8,
+                size_of::<PostPushConstants>(),
+            ),
+        );
+        bundle.device.cmd_dispatch(
+            command_buffer.as_inner(),
+            size.x.div_ceil(WORKGROUP_SIZE),
+            size.y.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+    }
+
+    /// Image to present for the slot, None if it was traced without passes
+    pub fn output(&self, slot: usize) -> Option<PostOutput> {
+        let index = self.outputs[slot]?;
+        let image = &self.images.as_ref()?[slot][index];
+        Some(PostOutput {
+            image: image.image,
+            image_view: image.view,
+            descriptor_set: self.slot_descriptors.sets()[slot * 2 + index],
+        })
+    }
+
+    pub fn sampler(&self) -> vk::Sampler {
+        self.sampler
+    }
+
+    pub fn image_bytesize(&self) -> usize {
+        self.image_bytesize
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle, pool: &mut ImagePool) {
+        if !self.destroyed {
+            self.destroy_images(bundle, pool);
+            for (_, (mut shader, pipeline)) in std::mem::take(&mut self.pipelines) {
+                bundle.device.destroy_pipeline(pipeline, None);
+                shader.destroy(bundle);
+            }
+            bundle
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            bundle.device.destroy_sampler(self.sampler, None);
+            self.exposure.destroy(bundle);
+            self.descriptors.destroy(bundle);
+            self.slot_descriptors.destroy(bundle);
+            self.destroyed = true;
+        } else {
+            warn!("PostStack already destroyed");
+        }
+    }
+}
+
+impl Drop for PostStack {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked PostStack");
+        }
+    }
+}
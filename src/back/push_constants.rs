@@ -1,18 +1,25 @@
+use crate::error::{TracerError, TracerResult};
+use crate::tracer::Bundle;
 use ash::vk;
 
-#[derive(Clone, Copy, Debug)]
-#[repr(C)]
-#[repr(align(128))]
-pub struct PushConstantsData {
-    pub frame_index: u32,
-    pub invalidate: u32,
-}
+pub use crate::back::interface::PushConstantsData;
 
 impl Default for PushConstantsData {
     fn default() -> Self {
         Self {
             frame_index: 0,
+            accumulated_samples: 0,
+            seed: 0,
             invalidate: 0,
+            reproject: 0,
+            pick_x: -1,
+            pick_y: -1,
+            region_x: 0,
+            region_y: 0,
+            image_width: 0,
+            image_height: 0,
+            frame_width: 0,
+            frame_height: 0,
         }
     }
 }
@@ -26,10 +33,74 @@ impl PushConstantsData {
         }
     }
 
-    pub fn new(frame_index: u32) -> Self {
+    /// Fails if the device cannot hold the push constants
+    pub unsafe fn validate(bundle: Bundle) -> TracerResult<()> {
+        let limit = bundle
+            .instance
+            .get_physical_device_properties(bundle.physical_device)
+            .limits
+            .max_push_constants_size;
+        let size = Self::get_range().size;
+        if size > limit {
+            return Err(TracerError::Unsupported(format!(
+                "push constants size, {} bytes are needed but the device holds {}",
+                size, limit
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Push constants of the frame accumulating `samples_count` samples
+    /// into an image of the size
+    pub fn new(frame_index: u32, samples_count: u32, size: glam::UVec2) -> Self {
         Self {
             frame_index,
+            accumulated_samples: frame_index.wrapping_mul(samples_count),
+            image_width: size.x,
+            image_height: size.y,
+            frame_width: size.x,
+            frame_height: size.y,
+            ..Default::default()
+        }
+        .reseed()
+    }
+
+    pub fn with_region(self, offset: glam::UVec2, frame_size: glam::UVec2) -> Self {
+        Self {
+            region_x: offset.x as i32,
+            region_y: offset.y as i32,
+            frame_width: frame_size.x,
+            frame_height: frame_size.y,
+            ..self
+        }
+        .reseed()
+    }
+
+    /// Push constants of the frame `frames` frames later. Updates are only
+    /// applied by the first frame, the later ones keep accumulating.
+    pub fn advanced(self, frames: u32, samples_count: u32) -> Self {
+        Self {
+            frame_index: self.frame_index.wrapping_add(frames),
+            accumulated_samples: self
+                .accumulated_samples
+                .wrapping_add(frames.wrapping_mul(samples_count)),
             invalidate: 0,
+            reproject: 0,
+            pick_x: -1,
+            pick_y: -1,
+            ..self
+        }
+        .reseed()
+    }
+
+    // Every frame gets its own seed, spread over the pixels of the frame
+    fn reseed(self) -> Self {
+        Self {
+            seed: self
+                .frame_index
+                .wrapping_mul(self.frame_width.wrapping_add(self.frame_height)),
+            ..self
         }
     }
 }
@@ -0,0 +1,59 @@
+use crate::config::AdaptiveSamples;
+
+// Traced frames between two adjustments. The render time is smoothed,
+// it needs a few frames to reflect the last adjustment.
+const ADJUST_INTERVAL: u32 = 8;
+
+/// Picks the dispatches per traced frame from the measured render time,
+/// so a traced frame takes about `target_ms` of GPU time. Every dispatch
+/// adds `samples_count` samples, changing their number keeps the
+/// accumulated image valid.
+pub struct SampleScheduler {
+    dispatches: u32,
+    frames_since_adjust: u32,
+}
+
+impl Default for SampleScheduler {
+    fn default() -> Self {
+        Self {
+            dispatches: 1,
+            frames_since_adjust: 0,
+        }
+    }
+}
+
+impl SampleScheduler {
+    /// Dispatches of the frame about to be traced. `render_time` is the
+    /// GPU time of the frames traced so far, 0 until one has been measured.
+    pub fn schedule(
+        &mut self,
+        adaptive: &AdaptiveSamples,
+        samples_count: u32,
+        render_time: f32,
+    ) -> u32 {
+        let samples_count = samples_count.max(1);
+        let min = adaptive.min_samples.div_ceil(samples_count).max(1);
+        let max = (adaptive.max_samples / samples_count).max(min);
+
+        self.frames_since_adjust += 1;
+        if render_time > 0.0 && self.frames_since_adjust >= ADJUST_INTERVAL {
+            self.frames_since_adjust = 0;
+            let per_dispatch = render_time / self.dispatches as f32;
+            let ideal = (adaptive.target_ms / per_dispatch).floor() as u32;
+            // Halfway there, the time per dispatch is only an estimate
+            self.dispatches = if ideal > self.dispatches {
+                self.dispatches + (ideal - self.dispatches).div_ceil(2)
+            } else {
+                self.dispatches - (self.dispatches - ideal).div_ceil(2)
+            };
+        }
+
+        self.dispatches = self.dispatches.clamp(min, max);
+        self.dispatches
+    }
+
+    /// Samples per traced frame of the last scheduled frame
+    pub fn samples(&self, samples_count: u32) -> u32 {
+        self.dispatches * samples_count.max(1)
+    }
+}
@@ -1,17 +1,15 @@
 use crate::back::ssbo::SSBO;
 
-#[derive(Default, Clone, Debug)]
-#[repr(C)]
-#[repr(align(128))]
-pub struct SSBOConfigData {
-    pub camera_transform: [[f32; 4]; 4],
-    pub camera_fov: f32,
-    pub objects_count: u32,
-    pub samples_count: u32,
-    pub max_bounces: u32,
-    pub sky_color_top: [f32; 4],
-    pub sky_color_bottom: [f32; 4],
-    pub ground_color: [f32; 4],
+pub use crate::back::interface::SSBOConfigData;
+
+impl SSBOConfigData {
+    /// Whether the camera transform is the only difference to the other config
+    pub fn is_camera_moved(&self, other: &Self) -> bool {
+        let mut moved = self.clone();
+        moved.camera_transform = other.camera_transform;
+        moved.prev_camera_transform = other.prev_camera_transform;
+        moved == *other && self.camera_transform != other.camera_transform
+    }
 }
 
 pub type SSBOConfig = SSBO<SSBOConfigData>;
@@ -0,0 +1,4 @@
+use crate::back::ssbo::SSBO;
+
+// Sampling tables of the environment map, see `EnvironmentMap::cdf`
+pub type SSBOEnvironment = SSBO<f32>;
@@ -0,0 +1,18 @@
+use crate::back::interface::Padding;
+use crate::back::ssbo::SSBO;
+use glam::Mat4;
+
+pub use crate::back::interface::SSBOInstanceData;
+
+impl SSBOInstanceData {
+    pub(crate) fn new(object: u32, transform: Mat4) -> Self {
+        Self {
+            object_index: object,
+            _pad: Padding::default(),
+            world_to_object: transform.inverse().to_cols_array_2d(),
+        }
+    }
+}
+
+pub type SSBOInstancesData = Vec<SSBOInstanceData>;
+pub type SSBOInstances = SSBO<SSBOInstanceData>;
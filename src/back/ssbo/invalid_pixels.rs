@@ -0,0 +1,18 @@
+use crate::back::ssbo::SSBO;
+use crate::tracer::InvalidPixels;
+use glam::UVec2;
+
+pub use crate::back::interface::{SSBOInvalidPixelsData, NO_PIXEL};
+
+impl SSBOInvalidPixelsData {
+    /// None if the scanned image of the width had no invalid pixels
+    pub fn as_result(&self, width: u32) -> Option<InvalidPixels> {
+        (self.first_pixel != NO_PIXEL).then(|| InvalidPixels {
+            nan_count: self.nan_count,
+            inf_count: self.inf_count,
+            first: UVec2::new(self.first_pixel % width, self.first_pixel / width),
+        })
+    }
+}
+
+pub type SSBOInvalidPixels = SSBO<SSBOInvalidPixelsData>;
@@ -0,0 +1,49 @@
+use crate::back::interface::{Padding, LIGHT_TYPE_AREA, LIGHT_TYPE_DIRECTIONAL, LIGHT_TYPE_POINT};
+use crate::back::ssbo::SSBO;
+use glam::Vec3;
+
+pub use crate::back::interface::SSBOLightData;
+
+impl SSBOLightData {
+    fn new(light_type: u32, color: Vec3, intensity: f32, data: [Vec3; 3]) -> Self {
+        Self {
+            light_type,
+            _pad: Padding::default(),
+            color: *color.extend(intensity).as_ref(),
+            data1: *data[0].extend(0.0).as_ref(),
+            data2: *data[1].extend(0.0).as_ref(),
+            data3: *data[2].extend(0.0).as_ref(),
+        }
+    }
+
+    pub(crate) fn new_point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self::new(
+            LIGHT_TYPE_POINT,
+            color,
+            intensity,
+            [position, Vec3::ZERO, Vec3::ZERO],
+        )
+    }
+
+    pub(crate) fn new_directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self::new(
+            LIGHT_TYPE_DIRECTIONAL,
+            color,
+            intensity,
+            [direction.normalize_or_zero(), Vec3::ZERO, Vec3::ZERO],
+        )
+    }
+
+    pub(crate) fn new_area(
+        corner: Vec3,
+        edge_u: Vec3,
+        edge_v: Vec3,
+        color: Vec3,
+        intensity: f32,
+    ) -> Self {
+        Self::new(LIGHT_TYPE_AREA, color, intensity, [corner, edge_u, edge_v])
+    }
+}
+
+pub type SSBOLightsData = Vec<SSBOLightData>;
+pub type SSBOLights = SSBO<SSBOLightData>;
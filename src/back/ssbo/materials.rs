@@ -0,0 +1,19 @@
+use crate::back::ssbo::SSBO;
+use crate::config::Material;
+
+pub use crate::back::interface::SSBOMaterialData;
+
+impl SSBOMaterialData {
+    pub(crate) fn new(material: &Material) -> Self {
+        Self {
+            albedo: *material.albedo.extend(0.0).as_ref(),
+            emission: *material
+                .emission_color
+                .extend(material.emission_strength)
+                .as_ref(),
+        }
+    }
+}
+
+pub type SSBOMaterialsData = Vec<SSBOMaterialData>;
+pub type SSBOMaterials = SSBO<SSBOMaterialData>;
@@ -1,16 +1,128 @@
+use crate::common::command_buffer::CommandBuffer;
+use crate::common::frame_graph::{Pass, PassSubmit, SyncPoint, Timeline};
+use crate::common::queue::QueueFamily;
+use crate::error::TracerResult;
 use crate::tracer::Bundle;
 use ash::vk;
 use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
 use gpu_allocator::MemoryLocation;
-use log::debug;
+use log::{debug, warn};
 use std::fmt::Debug;
 
 pub mod config;
+pub mod environment;
+pub mod instances;
+pub mod invalid_pixels;
+pub mod lights;
+pub mod materials;
 pub mod objects;
+pub mod pick;
+pub mod volumes;
+
+// Buffers of at least this size are kept in device-local memory and
+// written through a staging buffer instead of a host-visible mapping
+const STAGING_THRESHOLD: usize = 64 * 1024;
+
+/// Queue the staged SSBO uploads are submitted to. Every upload signals
+/// the next value of its timeline, the dispatches reading the buffers wait
+/// for `last` instead of the host waiting for each copy.
+pub struct SSBOUploadQueue {
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    timeline: Timeline,
+    // Command buffers of the uploads and the values they signal, reused
+    // once reached
+    command_buffers: Vec<(CommandBuffer, u64)>,
+}
+
+impl SSBOUploadQueue {
+    /// The pool has to allow resetting single command buffers
+    pub unsafe fn new(
+        bundle: Bundle,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> TracerResult<Self> {
+        Ok(Self {
+            command_pool,
+            queue,
+            timeline: Timeline::new(bundle, Pass::Upload)?,
+            command_buffers: vec![],
+        })
+    }
+
+    /// Reached once every upload submitted so far has completed
+    pub fn last(&self) -> SyncPoint {
+        self.timeline.last()
+    }
+
+    /// Command buffer of a completed upload, a new one if all are pending
+    unsafe fn acquire(&mut self, bundle: Bundle) -> TracerResult<usize> {
+        for (i, (_, value)) in self.command_buffers.iter().enumerate() {
+            if self.timeline.is_reached(bundle, *value)? {
+                return Ok(i);
+            }
+        }
+        let command_buffer = CommandBuffer::new_from_pool(bundle, self.command_pool)?;
+        self.command_buffers.push((command_buffer, 0));
+        Ok(self.command_buffers.len() - 1)
+    }
+
+    unsafe fn copy(
+        &mut self,
+        bundle: Bundle,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        offset: usize,
+        size: usize,
+    ) -> TracerResult<SyncPoint> {
+        let index = self.acquire(bundle)?;
+        let (command_buffer, value) = &mut self.command_buffers[index];
+        command_buffer.begin(bundle)?;
+        let copy_region = vk::BufferCopy::default()
+            .src_offset(offset as vk::DeviceSize)
+            .dst_offset(offset as vk::DeviceSize)
+            .size(size as vk::DeviceSize);
+        bundle
+            .device
+            .cmd_copy_buffer(command_buffer.as_inner(), src, dst, &[copy_region]);
+        command_buffer.end(bundle)?;
+
+        // The semaphore makes the copy visible to the waiting dispatch, on
+        // this queue or on another family
+        let point = self.timeline.advance();
+        PassSubmit::new()
+            .command_buffer(command_buffer)
+            .signal(point)
+            .submit(bundle, self.queue)?;
+        *value = point.value;
+        Ok(point)
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if let Err(e) = self.last().wait(bundle) {
+            warn!("Failed to wait for the SSBO uploads: {}", e);
+        }
+        for (command_buffer, _) in &mut self.command_buffers {
+            command_buffer.destroy(bundle, self.command_pool);
+        }
+        self.timeline.destroy(bundle);
+    }
+}
+
+struct StagingBuffer {
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    // Last upload reading the staging buffer
+    pending: Option<SyncPoint>,
+}
+
 pub struct SSBO<T> {
     pub buffer: vk::Buffer,
     pub allocation: Option<Allocation>,
     pub destroyed: bool,
+    // Number of T elements the buffer can hold
+    pub capacity: usize,
+    staging: Option<StagingBuffer>,
 
     _marker: std::marker::PhantomData<T>,
 }
@@ -19,18 +131,122 @@ impl<T> SSBO<T>
 where
     T: Debug,
 {
-    pub unsafe fn new(bundle: Bundle, option: Option<&str>) -> anyhow::Result<Self> {
+    pub unsafe fn new(bundle: Bundle, option: Option<&str>) -> TracerResult<Self> {
+        Self::new_array(bundle, 1, &[], option)
+    }
+
+    /// Creates a buffer for `capacity` elements. Staged buffers are shared
+    /// concurrently between the given queue families.
+    pub unsafe fn new_array(
+        bundle: Bundle,
+        capacity: usize,
+        queue_family_indices: &[u32],
+        option: Option<&str>,
+    ) -> TracerResult<Self> {
+        // Zero-sized buffers are not allowed, so always keep at least one element
+        let capacity = capacity.max(1);
+        let size = size_of::<T>() * capacity;
+        let staged = size >= STAGING_THRESHOLD;
+        let name = option.as_deref().unwrap_or("SSBO Buffer");
+
+        let (buffer, allocation) = if staged {
+            debug!("Creating staged SSBO {} of {} bytes", name, size);
+            Self::create_buffer(
+                bundle,
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                MemoryLocation::GpuOnly,
+                queue_family_indices,
+                name,
+            )?
+        } else {
+            Self::create_buffer(
+                bundle,
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu,
+                &[],
+                name,
+            )?
+        };
+
+        let staging = if staged {
+            let (buffer, allocation) = Self::create_buffer(
+                bundle,
+                size,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                MemoryLocation::CpuToGpu,
+                &[],
+                "SSBO Staging Buffer",
+            )?;
+            Some(StagingBuffer {
+                buffer,
+                allocation: Some(allocation),
+                pending: None,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            buffer,
+            allocation: Some(allocation),
+            destroyed: false,
+            capacity,
+            staging,
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    /// Creates a host-visible buffer the shader and transfers write into and the host reads back
+    pub unsafe fn new_readback(bundle: Bundle, option: Option<&str>) -> TracerResult<Self> {
+        let size = size_of::<T>();
+        let name = option.as_deref().unwrap_or("SSBO Readback Buffer");
+        let (buffer, allocation) = Self::create_buffer(
+            bundle,
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            &[],
+            name,
+        )?;
+
+        Ok(Self {
+            buffer,
+            allocation: Some(allocation),
+            destroyed: false,
+            capacity: 1,
+            staging: None,
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    unsafe fn create_buffer(
+        bundle: Bundle,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+        queue_family_indices: &[u32],
+        name: &str,
+    ) -> TracerResult<(vk::Buffer, Allocation)> {
+        let queue_family_indices = QueueFamily::unique_indices(queue_family_indices);
         let buffer_create_info = vk::BufferCreateInfo::default()
-            .size(size_of::<T>() as vk::DeviceSize)
-            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            .size(size as vk::DeviceSize)
+            .usage(usage);
+        let buffer_create_info = if queue_family_indices.len() > 1 {
+            buffer_create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_family_indices)
+        } else {
+            buffer_create_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
         let buffer = bundle.device.create_buffer(&buffer_create_info, None)?;
         let reqs = bundle.device.get_buffer_memory_requirements(buffer);
 
         let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
-            name: option.as_deref().unwrap_or("SSBO Buffer"),
+            name,
             requirements: reqs,
-            location: MemoryLocation::CpuToGpu,
+            location,
             linear: true,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         })?;
@@ -38,18 +254,37 @@ where
             .device
             .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
 
-        Ok(Self {
-            buffer,
-            allocation: Some(allocation),
-            destroyed: false,
-            _marker: std::marker::PhantomData,
-        })
+        Ok((buffer, allocation))
+    }
+
+    /// Memory of the buffer itself, the staging copy is not counted
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocation
+            .as_ref()
+            .map_or(0, |allocation| allocation.size())
     }
 
     pub unsafe fn destroy(&mut self, bundle: Bundle) {
         if !self.destroyed {
+            if let Some(mut staging) = self.staging.take() {
+                // The upload writes into the buffer as well
+                if let Some(pending) = staging.pending {
+                    if let Err(e) = pending.wait(bundle) {
+                        warn!("Failed to wait for the SSBO upload: {}", e);
+                    }
+                }
+                if let Some(allocation) = staging.allocation.take() {
+                    if let Err(e) = bundle.allocator().free(allocation) {
+                        warn!("Failed to free SSBO staging memory: {}", e);
+                    }
+                }
+                bundle.device.destroy_buffer(staging.buffer, None);
+            }
+
             if let Some(allocation) = self.allocation.take() {
-                bundle.allocator().free(allocation).unwrap();
+                if let Err(e) = bundle.allocator().free(allocation) {
+                    warn!("Failed to free SSBO memory: {}", e);
+                }
             }
             bundle.device.destroy_buffer(self.buffer, None);
 
@@ -57,17 +292,98 @@ where
         }
     }
 
-    pub unsafe fn update(&mut self, data: T) {
+    pub unsafe fn update(
+        &mut self,
+        bundle: Bundle,
+        upload_queue: &mut SSBOUploadQueue,
+        data: T,
+    ) -> TracerResult<()> {
         debug!("Updating SSBO: {:?}", data);
+        self.write(bundle, upload_queue, 0, std::slice::from_ref(&data))
+    }
+
+    pub unsafe fn update_slice(
+        &mut self,
+        bundle: Bundle,
+        upload_queue: &mut SSBOUploadQueue,
+        data: &[T],
+    ) -> TracerResult<()> {
+        debug!("Updating SSBO with {} elements", data.len());
+        self.write(bundle, upload_queue, 0, data)
+    }
+
+    /// Overwrites the elements starting at `offset`, leaving the rest as is
+    pub unsafe fn update_range(
+        &mut self,
+        bundle: Bundle,
+        upload_queue: &mut SSBOUploadQueue,
+        offset: usize,
+        data: &[T],
+    ) -> TracerResult<()> {
+        debug!("Updating {} SSBO elements at {}", data.len(), offset);
+        self.write(bundle, upload_queue, offset, data)
+    }
+
+    /// Reads back the first element. The caller makes sure that the shader
+    /// writes are complete and made available to the host.
+    pub unsafe fn read(&self) -> T
+    where
+        T: Clone,
+    {
+        assert!(self.staging.is_none(), "Staged SSBO cannot be read back");
         let mapped = self.allocation.as_ref().unwrap().mapped_ptr().unwrap();
-        let dst = mapped.as_ptr() as *mut T;
-        dst.copy_from_nonoverlapping(&data, 1);
+        (*(mapped.as_ptr() as *const T)).clone()
+    }
+
+    unsafe fn write(
+        &mut self,
+        bundle: Bundle,
+        upload_queue: &mut SSBOUploadQueue,
+        offset: usize,
+        data: &[T],
+    ) -> TracerResult<()> {
+        assert!(
+            offset + data.len() <= self.capacity,
+            "SSBO capacity exceeded: {} > {}",
+            offset + data.len(),
+            self.capacity
+        );
+
+        // The previous upload may still be reading the staging buffer
+        if let Some(pending) = self.staging.as_ref().and_then(|staging| staging.pending) {
+            pending.wait(bundle)?;
+        }
+
+        let target = match &self.staging {
+            Some(staging) => staging.allocation.as_ref().unwrap(),
+            None => self.allocation.as_ref().unwrap(),
+        };
+        let mapped = target.mapped_ptr().unwrap();
+        let dst = (mapped.as_ptr() as *mut T).add(offset);
+        dst.copy_from_nonoverlapping(data.as_ptr(), data.len());
+
+        if let Some(staging) = &mut self.staging {
+            if !data.is_empty() {
+                staging.pending = Some(upload_queue.copy(
+                    bundle,
+                    staging.buffer,
+                    self.buffer,
+                    offset * size_of::<T>(),
+                    size_of_val(data),
+                )?);
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl<T> Drop for SSBO<T> {
     fn drop(&mut self) {
-        if !self.destroyed {
+        // Panicking again while unwinding would abort the teardown
+        if !self.destroyed && std::thread::panicking() {
+            warn!("Leaked SSBO");
+        } else if !self.destroyed {
             panic!("SSBO must be destroyed before being dropped");
         }
     }
@@ -1,41 +1,63 @@
+use crate::back::interface::{
+    OBJECT_TYPE_SDF, OBJECT_TYPE_SPHERE, SDF_SHAPE_GYROID, SDF_SHAPE_MANDELBULB, SDF_SHAPE_TORUS,
+};
 use crate::back::ssbo::SSBO;
-use crate::config::Material;
+use crate::config::SdfShape;
 use glam::Vec3;
 
-const OBJECT_TYPE_SPHERE: u32 = 1;
+pub use crate::back::interface::{SSBOObjectData, NO_TEXTURE};
 
-pub const MAX_OBJECTS: usize = 128;
+impl SSBOObjectData {
+    pub(crate) fn new_sphere(
+        center: Vec3,
+        radius: f32,
+        material: u32,
+        instanced: bool,
+        textures: [u32; 3],
+    ) -> Self {
+        Self {
+            object_type: OBJECT_TYPE_SPHERE,
+            material_index: material,
+            instanced: instanced as u32,
+            sdf_shape: 0,
+            data1: [center[0], center[1], center[2], 0.0],
+            data2: [radius, 0.0, 0.0, 0.0],
+            textures: [textures[0], textures[1], textures[2], NO_TEXTURE],
+        }
+    }
 
-#[derive(Default, Clone, Debug)]
-#[repr(C)]
-#[repr(align(16))]
-#[derive(Copy)]
-pub struct SSBOObjectData {
-    pub object_type: [u32; 4],
-    pub albedo: [f32; 4],
-    pub emission_color: [f32; 4],
-    pub material_properties: [f32; 4],
-    pub data2: [f32; 4],
-    pub data3: [f32; 4],
-}
+    pub(crate) fn new_sdf(center: Vec3, shape: &SdfShape, material: u32, instanced: bool) -> Self {
+        let (shape_type, parameters) = match shape {
+            SdfShape::Torus {
+                major_radius,
+                minor_radius,
+            } => (SDF_SHAPE_TORUS, [*major_radius, *minor_radius, 0.0, 0.0]),
+            SdfShape::Gyroid {
+                scale,
+                thickness,
+                radius,
+            } => (SDF_SHAPE_GYROID, [*scale, *thickness, *radius, 0.0]),
+            SdfShape::Mandelbulb {
+                power,
+                iterations,
+                radius,
+            } => (
+                SDF_SHAPE_MANDELBULB,
+                [*power, *iterations as f32, *radius, 0.0],
+            ),
+        };
 
-impl SSBOObjectData {
-    pub(crate) fn new_sphere(center: Vec3, radius: f32, material: &Material) -> Self {
         Self {
-            object_type: [OBJECT_TYPE_SPHERE, 0, 0, 0],
-            albedo: [material.albedo.x, material.albedo.y, material.albedo.z, 0.0],
-            emission_color: [
-                material.emission_color.x,
-                material.emission_color.y,
-                material.emission_color.z,
-                0.0,
-            ],
-            material_properties: [material.emission_strength, 0.0, 0.0, 0.0],
-            data2: [center[0], center[1], center[2], 0.0],
-            data3: [radius, 0.0, 0.0, 0.0],
+            object_type: OBJECT_TYPE_SDF,
+            material_index: material,
+            instanced: instanced as u32,
+            sdf_shape: shape_type,
+            data1: [center[0], center[1], center[2], shape.bounding_radius()],
+            data2: parameters,
+            textures: [NO_TEXTURE; 4],
         }
     }
 }
 
-pub type SSBOObjectsData = [SSBOObjectData; MAX_OBJECTS];
-pub type SSBOObjects = SSBO<SSBOObjectsData>;
+pub type SSBOObjectsData = Vec<SSBOObjectData>;
+pub type SSBOObjects = SSBO<SSBOObjectData>;
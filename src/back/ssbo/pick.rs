@@ -0,0 +1,42 @@
+use crate::back::ssbo::SSBO;
+use crate::config::{PathVertex, PickResult};
+use glam::{UVec2, Vec3, Vec4Swizzles};
+
+pub use crate::back::interface::{SSBOPathVertexData, SSBOPickData, MAX_PATH_VERTICES, NO_OBJECT};
+
+impl SSBOPickData {
+    pub fn as_result(&self) -> PickResult {
+        let count = (self.vertex_count as usize).min(MAX_PATH_VERTICES);
+        PickResult {
+            pixel: UVec2::from(self.pixel),
+            depth: (self.depth >= 0.0).then_some(self.depth),
+            object: (self.object != NO_OBJECT).then_some(self.object as usize),
+            path: self.vertices[..count]
+                .iter()
+                .map(SSBOPathVertexData::as_vertex)
+                .collect(),
+        }
+    }
+}
+
+impl SSBOPathVertexData {
+    fn as_vertex(&self) -> PathVertex {
+        let vec3 = |v: [f32; 4]| -> Vec3 { glam::Vec4::from(v).xyz() };
+        PathVertex {
+            origin: vec3(self.origin),
+            direction: vec3(self.direction),
+            distance: (self.point[3] >= 0.0).then_some(self.point[3]),
+            point: vec3(self.point),
+            normal: vec3(self.normal),
+            front_face: self.normal[3] > 0.5,
+            object: self.info[0],
+            albedo: vec3(self.albedo),
+            cosine: self.albedo[3],
+            emission: vec3(self.emission),
+            throughput: vec3(self.throughput),
+            radiance: vec3(self.radiance),
+        }
+    }
+}
+
+pub type SSBOPick = SSBO<SSBOPickData>;
@@ -0,0 +1,19 @@
+use crate::back::ssbo::SSBO;
+use crate::config::Volume;
+
+pub use crate::back::interface::SSBOVolumeData;
+
+impl SSBOVolumeData {
+    pub(crate) fn new(volume: &Volume) -> Self {
+        let medium = &volume.medium;
+        Self {
+            box_min: *volume.min.extend(medium.anisotropy).as_ref(),
+            box_max: *volume.max.extend(0.0).as_ref(),
+            extinction: *medium.extinction().extend(0.0).as_ref(),
+            scattering: *medium.scattering.extend(0.0).as_ref(),
+        }
+    }
+}
+
+pub type SSBOVolumesData = Vec<SSBOVolumeData>;
+pub type SSBOVolumes = SSBO<SSBOVolumeData>;
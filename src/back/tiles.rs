@@ -0,0 +1,172 @@
+use crate::back::interface::{SSBOTileQueueData, TILE_SIZE};
+use crate::common::command_buffer::CommandBuffer;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::{debug, warn};
+
+/// GPU-driven queue of the tiles still to be traced. The classification
+/// pass (classify_tiles.comp) appends every tile with a pixel short of
+/// `converged_samples` to the buffer and counts them into the arguments
+/// of the indirect dispatch tracing them, a workgroup per tile. Converged
+/// tiles are never dispatched. Sized for the image, recreated with it.
+/// Reset by every traced frame, the frames in flight share it.
+pub struct TileQueue {
+    pub buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    destroyed: bool,
+}
+
+impl TileQueue {
+    /// Tiles along each side of the image
+    pub fn grid(extent: vk::Extent2D) -> (u32, u32) {
+        (
+            extent.width.div_ceil(TILE_SIZE),
+            extent.height.div_ceil(TILE_SIZE),
+        )
+    }
+
+    pub unsafe fn new(bundle: Bundle, size: glam::UVec2) -> TracerResult<Self> {
+        let (tiles_x, tiles_y) = Self::grid(vk::Extent2D {
+            width: size.x,
+            height: size.y,
+        });
+        let size = size_of::<SSBOTileQueueData>() + (tiles_x * tiles_y) as usize * size_of::<u32>();
+        debug!("Creating tile queue of {}x{} tiles", tiles_x, tiles_y);
+
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size as vk::DeviceSize)
+            .usage(
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::INDIRECT_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let requirements = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = match bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Tile Queue Buffer",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        }) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                bundle.device.destroy_buffer(buffer, None);
+                return Err(e.into());
+            }
+        };
+        let mut queue = Self {
+            buffer,
+            allocation: Some(allocation),
+            destroyed: false,
+        };
+        let allocation = queue.allocation.as_ref().unwrap();
+        if let Err(e) =
+            bundle
+                .device
+                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        {
+            queue.destroy(bundle);
+            return Err(e.into());
+        }
+        Ok(queue)
+    }
+
+    /// Empties the queue and classifies the tiles of the image into it.
+    /// The classification pipeline shares the layout of the tracing one,
+    /// the descriptor sets and push constants of the frame must be bound.
+    pub unsafe fn record_classify(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        pipeline: vk::Pipeline,
+        extent: vk::Extent2D,
+    ) {
+        // The previous frame may still be reading the queue
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+        let empty = SSBOTileQueueData {
+            groups_x: 0,
+            groups_y: 1,
+            groups_z: 1,
+        };
+        bundle.device.cmd_update_buffer(
+            command_buffer.as_inner(),
+            self.buffer,
+            0,
+            std::slice::from_raw_parts(
+                (&empty as *const SSBOTileQueueData) as *const u8,
+                size_of::<SSBOTileQueueData>(),
+            ),
+        );
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+
+        let (tiles_x, tiles_y) = Self::grid(extent);
+        bundle.device.cmd_bind_pipeline(
+            command_buffer.as_inner(),
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline,
+        );
+        bundle
+            .device
+            .cmd_dispatch(command_buffer.as_inner(), tiles_x, tiles_y, 1);
+
+        // Read as the dispatch arguments and by the tracing shader
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ);
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+    }
+
+    /// Traces the queued tiles with the bound pipeline
+    pub unsafe fn record_dispatch(&self, bundle: Bundle, command_buffer: &CommandBuffer) {
+        bundle
+            .device
+            .cmd_dispatch_indirect(command_buffer.as_inner(), self.buffer, 0);
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            if let Some(allocation) = self.allocation.take() {
+                if let Err(e) = bundle.allocator().free(allocation) {
+                    warn!("Failed to free tile queue memory: {}", e);
+                }
+            }
+            bundle.device.destroy_buffer(self.buffer, None);
+            self.destroyed = true;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::back::interface::OBJECT_TYPE_SDF;
+use crate::back::pipeline::SceneData;
+use crate::back::ssbo::objects::SSBOObjectData;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use log::debug;
+use std::collections::BTreeMap;
+
+/// Optional parts of the compute shader, compiled out of the pipeline of
+/// a scene not using them. Maps to the specialization constants of
+/// shader.comp, in the order of their ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct SceneFeatures {
+    pub volumes: bool,
+    pub sdf: bool,
+    pub environment: bool,
+}
+
+impl SceneFeatures {
+    /// Renders every scene, used until the scene is known
+    pub const ALL: Self = Self {
+        volumes: true,
+        sdf: true,
+        environment: true,
+    };
+
+    pub fn new(scene: &SceneData, environment: bool) -> Self {
+        Self {
+            volumes: !scene.volumes.is_empty(),
+            sdf: Self::has_sdf(scene.objects.iter()),
+            environment,
+        }
+    }
+
+    pub fn has_sdf<'a>(mut objects: impl Iterator<Item = &'a SSBOObjectData>) -> bool {
+        objects.any(|object| object.object_type == OBJECT_TYPE_SDF)
+    }
+
+    fn constants(&self) -> [vk::Bool32; 3] {
+        [
+            self.volumes as vk::Bool32,
+            self.sdf as vk::Bool32,
+            self.environment as vk::Bool32,
+        ]
+    }
+}
+
+/// Compute pipelines of the tracing shader, one per set of scene features.
+/// A variant is created once and kept until the pipeline is destroyed,
+/// there are only a few of them. They share a pipeline cache, so the
+/// driver does not compile the common parts again.
+pub(crate) struct PipelineVariants {
+    module: vk::ShaderModule,
+    layout: vk::PipelineLayout,
+    cache: vk::PipelineCache,
+    pipelines: BTreeMap<SceneFeatures, vk::Pipeline>,
+}
+
+impl PipelineVariants {
+    pub unsafe fn new(
+        bundle: Bundle,
+        module: vk::ShaderModule,
+        layout: vk::PipelineLayout,
+    ) -> TracerResult<Self> {
+        let cache = bundle
+            .device
+            .create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)?;
+        let mut variants = Self {
+            module,
+            layout,
+            cache,
+            pipelines: BTreeMap::new(),
+        };
+        // The fallback of the variants not created yet
+        if let Err(e) = variants.warm_up(bundle, SceneFeatures::ALL) {
+            variants.destroy(bundle);
+            return Err(e);
+        }
+        Ok(variants)
+    }
+
+    /// Creates the variant ahead of its first dispatch, so a scene load
+    /// and not the first frame of the scene waits for the driver
+    pub unsafe fn warm_up(&mut self, bundle: Bundle, features: SceneFeatures) -> TracerResult<()> {
+        if self.pipelines.contains_key(&features) {
+            return Ok(());
+        }
+
+        debug!("Creating pipeline variant {:?}", features);
+        let constants = features.constants();
+        let entries: Vec<_> = (0..constants.len())
+            .map(|i| vk::SpecializationMapEntry {
+                constant_id: i as u32,
+                offset: (i * size_of::<vk::Bool32>()) as u32,
+                size: size_of::<vk::Bool32>(),
+            })
+            .collect();
+        let specialization = vk::SpecializationInfo::default()
+            .map_entries(&entries)
+            .data(std::slice::from_raw_parts(
+                constants.as_ptr() as *const u8,
+                size_of_val(&constants),
+            ));
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(self.module)
+            .name(c"main")
+            .specialization_info(&specialization);
+        let pipeline_info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(self.layout);
+        let pipeline = bundle
+            .device
+            .create_compute_pipelines(self.cache, &[pipeline_info], None)
+            .map_err(|(_, e)| e)?
+            .remove(0);
+        self.pipelines.insert(features, pipeline);
+        Ok(())
+    }
+
+    /// Variant of the features, the one with every feature if it was not
+    /// created
+    pub fn get(&self, features: SceneFeatures) -> vk::Pipeline {
+        self.pipelines
+            .get(&features)
+            .or_else(|| self.pipelines.get(&SceneFeatures::ALL))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        for (_, pipeline) in std::mem::take(&mut self.pipelines) {
+            bundle.device.destroy_pipeline(pipeline, None);
+        }
+        bundle.device.destroy_pipeline_cache(self.cache, None);
+        self.cache = vk::PipelineCache::null();
+    }
+}
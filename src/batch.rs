@@ -0,0 +1,158 @@
+use crate::assets::AssetManager;
+use crate::config::TracerConfig;
+use crate::front::headless::{headless_tracer, TracerHeadlessFront, TracerHeadlessOutput};
+use crate::tracer::Tracer;
+use anyhow::Context;
+use build_info::BuildInfo;
+use glam::UVec2;
+use image::{ImageBuffer, RgbImage};
+use log::{error, info};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// List of renders, read from a JSON file:
+///
+/// ```json
+/// {
+///   "jobs": [
+///     { "scene": "scenes/fog.json", "output": "out/fog.png", "resolution": [1920, 1080], "samples": 1024 }
+///   ]
+/// }
+/// ```
+///
+/// Relative paths are resolved against the directory of the manifest.
+#[derive(Debug, Deserialize)]
+pub struct BatchManifest {
+    pub jobs: Vec<BatchJob>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchJob {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+    // Viewport given on the command line if not set
+    pub resolution: Option<UVec2>,
+    // Samples per pixel, accumulated over frames of `samples_count` samples
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+}
+
+fn default_samples() -> u32 {
+    1
+}
+
+impl BatchManifest {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read batch manifest {}", path.display()))?;
+        let mut manifest: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid batch manifest {}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for job in &mut manifest.jobs {
+            job.scene = base.join(&job.scene);
+            job.output = base.join(&job.output);
+        }
+        Ok(manifest)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub rendered: Vec<(PathBuf, Duration)>,
+    pub failed: Vec<PathBuf>,
+}
+
+/// Renders the jobs of the manifest one after another. The tracer is created
+/// once, every job swaps the scene in and resizes it. A failed job is logged
+/// and skipped, so one broken scene does not stop the whole queue.
+pub unsafe fn run_batch(
+    asset_manager: AssetManager,
+    viewport: UVec2,
+    bi: BuildInfo,
+    manifest: &Path,
+) -> anyhow::Result<BatchSummary> {
+    let manifest = BatchManifest::load(manifest)?;
+    anyhow::ensure!(!manifest.jobs.is_empty(), "Batch manifest has no jobs");
+
+    let config = TracerConfig::default();
+    let mut tracer = headless_tracer(config.clone(), asset_manager, viewport, bi, |_| {})?;
+
+    let mut summary = BatchSummary::default();
+    let total = manifest.jobs.len();
+    let start = Instant::now();
+    for (index, job) in manifest.jobs.iter().enumerate() {
+        info!(
+            "[{}/{}] Rendering {} to {}",
+            index + 1,
+            total,
+            job.scene.display(),
+            job.output.display()
+        );
+
+        let job_start = Instant::now();
+        match render_job(&mut tracer, &config, job, viewport) {
+            Ok(()) => {
+                let elapsed = job_start.elapsed();
+                info!(
+                    "[{}/{}] Done in {:.2} s",
+                    index + 1,
+                    total,
+                    elapsed.as_secs_f32()
+                );
+                summary.rendered.push((job.output.clone(), elapsed));
+            }
+            Err(e) => {
+                error!("[{}/{}] Failed: {:#}", index + 1, total, e);
+                summary.failed.push(job.scene.clone());
+            }
+        }
+    }
+
+    info!(
+        "Batch finished in {:.2} s: {} rendered, {} failed",
+        start.elapsed().as_secs_f32(),
+        summary.rendered.len(),
+        summary.failed.len()
+    );
+    Ok(summary)
+}
+
+unsafe fn render_job(
+    tracer: &mut Tracer<TracerHeadlessFront>,
+    config: &TracerConfig,
+    job: &BatchJob,
+    viewport: UVec2,
+) -> anyhow::Result<()> {
+    let scene = TracerConfig::load(&job.scene)?;
+    let inner = scene.0.borrow().clone();
+    let frames = job.samples.div_ceil(inner.samples_count.max(1)).max(1);
+    config.replace(inner);
+    tracer.resize(job.resolution.unwrap_or(viewport))?;
+
+    for _ in 0..frames {
+        tracer.trace(None)?;
+        // Every trace has to dispatch, otherwise the frames are skipped
+        // while the slot is busy and fewer samples are accumulated
+        tracer.flush()?;
+    }
+
+    let TracerHeadlessOutput {
+        width,
+        height,
+        rgb888,
+    } = tracer
+        .snapshot()?
+        .ok_or_else(|| anyhow::anyhow!("No frame has been traced"))?;
+    let image: RgbImage = ImageBuffer::from_raw(width, height, rgb888)
+        .ok_or_else(|| anyhow::anyhow!("Frame data does not match its dimensions"))?;
+
+    if let Some(parent) = job.output.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    image
+        .save(&job.output)
+        .with_context(|| format!("Failed to save {}", job.output.display()))
+}
@@ -0,0 +1,130 @@
+use crate::assets::AssetManager;
+use crate::config::TracerConfig;
+use crate::front::headless::headless_tracer;
+use crate::tracer::DeviceInfo;
+use anyhow::Context;
+use build_info::{BuildInfo, VersionControl};
+use glam::UVec2;
+use log::info;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+// Frames traced before the measurement starts, while the caches and
+// clocks settle down
+const WARMUP_FRAMES: usize = 16;
+
+#[derive(Debug, Serialize)]
+pub struct RenderTimeStats {
+    pub min: f32,
+    pub avg: f32,
+    pub median: f32,
+    pub p95: f32,
+    pub max: f32,
+}
+
+impl RenderTimeStats {
+    fn new(samples: &[f32]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let percentile = |p: f32| {
+            let rank = ((sorted.len() as f32 * p).ceil() as usize).max(1);
+            sorted[rank.min(sorted.len()) - 1]
+        };
+
+        Self {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchmarkReport {
+    pub version: String,
+    pub commit: Option<String>,
+    pub profile: String,
+    pub device: DeviceInfo,
+    pub resolution: UVec2,
+    pub frames: usize,
+    pub wall_time_s: f32,
+    // GPU time of the tracing dispatch, in milliseconds
+    pub render_time_ms: RenderTimeStats,
+    // Config the scene was rendered with, for reproducing the run
+    pub config: TracerConfig,
+}
+
+/// Traces the built-in default scene headlessly for `frames` frames and
+/// writes the render time statistics to `report` as JSON.
+/// The sampling depends only on the frame index, so runs are reproducible.
+pub unsafe fn run_benchmark(
+    asset_manager: AssetManager,
+    viewport: UVec2,
+    bi: BuildInfo,
+    frames: usize,
+    report: &Path,
+) -> anyhow::Result<BenchmarkReport> {
+    anyhow::ensure!(frames > 0, "Benchmark needs at least one frame");
+
+    let config = TracerConfig::default();
+    let mut tracer = headless_tracer(config.clone(), asset_manager, viewport, bi.clone(), |_| {})?;
+    let device = tracer.get_device_info();
+    info!(
+        "Benchmarking {} frames at {}x{} on {}",
+        frames, viewport.x, viewport.y, device.name
+    );
+
+    tracer.record_render_times();
+    let mut warmup = WARMUP_FRAMES;
+    let mut samples = vec![];
+    let mut start = Instant::now();
+    while samples.len() < frames {
+        tracer.trace(None)?;
+        for ms in tracer.take_render_times() {
+            if warmup > 0 {
+                warmup -= 1;
+                start = Instant::now();
+            } else {
+                samples.push(ms);
+            }
+        }
+    }
+    tracer.flush()?;
+    let wall_time = start.elapsed().as_secs_f32();
+    samples.truncate(frames);
+
+    let commit = if let Some(VersionControl::Git(git)) = &bi.version_control {
+        Some(format!(
+            "{}{}",
+            git.commit_id,
+            if git.dirty { "-dirty" } else { "" }
+        ))
+    } else {
+        None
+    };
+    let result = BenchmarkReport {
+        version: bi.crate_info.version.to_string(),
+        commit,
+        profile: bi.profile.clone(),
+        device,
+        resolution: viewport,
+        frames,
+        wall_time_s: wall_time,
+        render_time_ms: RenderTimeStats::new(&samples),
+        config,
+    };
+    info!(
+        "Render time: min {:.3} ms, avg {:.3} ms, p95 {:.3} ms",
+        result.render_time_ms.min, result.render_time_ms.avg, result.render_time_ms.p95
+    );
+
+    let content = serde_json::to_string_pretty(&result)?;
+    std::fs::write(report, content)
+        .with_context(|| format!("Failed to write benchmark report {}", report.display()))?;
+    info!("Benchmark report written to {}", report.display());
+
+    Ok(result)
+}
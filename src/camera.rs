@@ -0,0 +1,48 @@
+use glam::{UVec2, Vec2};
+
+/// Sensor of a full frame camera in millimeters
+pub const FULL_FRAME_SENSOR: Vec2 = Vec2::new(36.0, 24.0);
+
+/// Focal lengths in millimeters offered as lens presets
+pub const LENS_PRESETS: [f32; 3] = [24.0, 50.0, 85.0];
+
+/// Vertical field of view in radians of a lens with the focal length
+/// projecting onto a sensor of the height, both in millimeters
+pub fn fov_from_focal_length(focal_length: f32, sensor_height: f32) -> f32 {
+    2.0 * (sensor_height / (2.0 * focal_length.max(f32::EPSILON))).atan()
+}
+
+/// Inverse of `fov_from_focal_length`
+pub fn focal_length_from_fov(fov: f32, sensor_height: f32) -> f32 {
+    sensor_height / (2.0 * (fov * 0.5).tan().max(f32::EPSILON))
+}
+
+/// Largest size of the aspect ratio fitting into the outer size.
+/// The rest of the outer size is left for the black bars.
+pub fn fit_aspect(outer: UVec2, aspect_ratio: Option<f32>) -> UVec2 {
+    let Some(aspect_ratio) = aspect_ratio.filter(|aspect| *aspect > 0.0) else {
+        return outer;
+    };
+
+    let outer_f = outer.as_vec2();
+    let size = if outer_f.x / outer_f.y.max(1.0) > aspect_ratio {
+        // Pillarbox, the outer size is wider
+        Vec2::new(outer_f.y * aspect_ratio, outer_f.y)
+    } else {
+        // Letterbox, the outer size is taller
+        Vec2::new(outer_f.x, outer_f.x / aspect_ratio)
+    };
+    size.round()
+        .as_uvec2()
+        .clamp(UVec2::ONE, outer.max(UVec2::ONE))
+}
+
+/// Offset and size of the centered rectangle with the inner aspect ratio
+/// inside the outer size
+pub fn letterbox(outer: UVec2, inner: UVec2) -> (UVec2, UVec2) {
+    let aspect_ratio = inner.x as f32 / inner.y.max(1) as f32;
+    let size = fit_aspect(outer, Some(aspect_ratio));
+    // Rounding of a scaled down inner size must not leave a one pixel bar
+    let size = UVec2::select(outer.saturating_sub(size).cmple(UVec2::ONE), outer, size);
+    (outer.saturating_sub(size) / 2, size)
+}
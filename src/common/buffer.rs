@@ -1,4 +1,5 @@
-use crate::common::command_buffer::CommandBuffer;
+use crate::common::command_buffer::OneTimeSubmit;
+use crate::error::TracerResult;
 use crate::tracer::Bundle;
 use ash::vk;
 use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
@@ -11,7 +12,7 @@ pub unsafe fn create_device_local_buffer_with_data<T: Copy>(
     usage: vk::BufferUsageFlags,
     data: &[T],
     name: &'static str,
-) -> anyhow::Result<(vk::Buffer, Allocation)> {
+) -> TracerResult<(vk::Buffer, Allocation)> {
     let buffer_size = size_of_val(data) as vk::DeviceSize;
     let buffer_info = vk::BufferCreateInfo::default()
         .size(buffer_size)
@@ -58,28 +59,14 @@ pub unsafe fn create_device_local_buffer_with_data<T: Copy>(
         // allocator.flush(&staging_alloc, 0, buffer_size)?;
     }
 
-    let mut command_buffer = CommandBuffer::new_from_pool(bundle, command_pool)?;
-
-    command_buffer.begin(bundle)?;
+    let submit = OneTimeSubmit::begin(bundle, command_pool)?;
 
     let copy_region = vk::BufferCopy::default().size(buffer_size);
-    bundle.device.cmd_copy_buffer(
-        command_buffer.as_inner(),
-        staging_buffer,
-        buffer,
-        &[copy_region],
-    );
-
-    command_buffer.end(bundle)?;
-
-    let submit_info = command_buffer.as_submit_info();
-
     bundle
         .device
-        .queue_submit(queue, &[submit_info], vk::Fence::null())?;
-    bundle.device.queue_wait_idle(queue)?;
+        .cmd_copy_buffer(submit.as_inner(), staging_buffer, buffer, &[copy_region]);
 
-    command_buffer.destroy(bundle, command_pool);
+    submit.submit_and_wait(bundle, queue)?;
 
     bundle.allocator().free(staging_alloc)?;
     bundle.device.destroy_buffer(staging_buffer, None);
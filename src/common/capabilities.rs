@@ -3,9 +3,18 @@
 pub struct InstanceCapabilities {
     pub debug_utils_ext: bool,
     pub validation_layer: bool,
+    pub portability_enumeration: bool,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Default)]
 pub struct DeviceCapabilities {
     pub host_image_copy: bool,
+    pub portability_subset: bool,
+    pub dynamic_rendering: bool,
+    // VK_KHR_present_id and VK_KHR_present_wait, see `PresentWait`
+    pub present_wait: bool,
+    // VK_KHR_external_memory_fd, textures can be imported from other
+    // processes, see `Texture::import_fd`
+    pub external_memory_fd: bool,
 }
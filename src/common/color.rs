@@ -0,0 +1,91 @@
+use ash::vk;
+
+// Color contract of the tracer:
+//
+// - The traced image and every post pass hold linear color, tonemapped into
+//   [0, 1] if the post passes include the tonemapping, unbounded otherwise.
+// - Textures are stored as sRGB and decoded in the shaders when sampled.
+// - Encoding to sRGB happens exactly once, where the image becomes 8 bit:
+//   * windowed, by the hardware on store into a *_SRGB swapchain, by the
+//     presentation fragment shader into a *_UNORM one;
+//   * headless and snapshots, on the readback by `linear_to_srgb8`.
+//
+// The shader side of the conversion mirrors `linear_to_srgb`, the two are
+// kept in sync by hand.
+
+/// Whether stores into images of the format are sRGB encoded by the hardware
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+    )
+}
+
+/// sRGB transfer function (IEC 61966-2-1), clamped to [0, 1]
+pub fn linear_to_srgb(value: f32) -> f32 {
+    // NaN of a broken sample maps to black, as the hardware conversion does
+    let value = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    };
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn linear_to_srgb8(value: f32) -> u8 {
+    (linear_to_srgb(value) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_range_bounds() {
+        assert_eq!(linear_to_srgb8(0.0), 0);
+        assert_eq!(linear_to_srgb8(1.0), 255);
+    }
+
+    #[test]
+    fn clamps_out_of_range() {
+        assert_eq!(linear_to_srgb8(-1.0), 0);
+        assert_eq!(linear_to_srgb8(16.0), 255);
+        assert_eq!(linear_to_srgb8(f32::NAN), 0);
+        assert_eq!(linear_to_srgb8(f32::INFINITY), 255);
+    }
+
+    #[test]
+    fn matches_reference_values() {
+        // Middle gray and the end of the linear segment
+        assert_eq!(linear_to_srgb8(0.18), 118);
+        assert_eq!(linear_to_srgb8(0.5), 188);
+        assert!((linear_to_srgb(0.003_130_8) - 0.040_45).abs() < 1e-4);
+    }
+
+    #[test]
+    fn is_continuous_and_monotonic() {
+        let encoded: Vec<f32> = (0..=1000)
+            .map(|i| linear_to_srgb(i as f32 / 1000.0))
+            .collect();
+        assert!(encoded.windows(2).all(|pair| pair[1] > pair[0]));
+        let below = linear_to_srgb(0.003_130_8);
+        let above = linear_to_srgb(0.003_130_9);
+        assert!((above - below).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hardware_encoded_swapchain_formats() {
+        assert!(is_srgb_format(vk::Format::B8G8R8A8_SRGB));
+        assert!(is_srgb_format(vk::Format::R8G8B8A8_SRGB));
+        assert!(!is_srgb_format(vk::Format::B8G8R8A8_UNORM));
+        assert!(!is_srgb_format(vk::Format::R8G8B8A8_UNORM));
+    }
+}
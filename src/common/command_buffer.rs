@@ -1,5 +1,7 @@
+use crate::error::TracerResult;
 use crate::tracer::Bundle;
 use ash::vk;
+use gpu_allocator::vulkan::Allocation;
 use log::warn;
 
 pub struct CommandBuffer {
@@ -11,7 +13,7 @@ impl CommandBuffer {
     pub unsafe fn new_from_pool(
         bundle: Bundle,
         command_pool: vk::CommandPool,
-    ) -> anyhow::Result<Self> {
+    ) -> TracerResult<Self> {
         let cmd_alloc_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(command_pool)
             .level(vk::CommandBufferLevel::PRIMARY)
@@ -24,7 +26,7 @@ impl CommandBuffer {
         })
     }
 
-    pub unsafe fn begin(&self, bundle: Bundle) -> anyhow::Result<()> {
+    pub unsafe fn begin(&self, bundle: Bundle) -> TracerResult<()> {
         let begin_info = vk::CommandBufferBeginInfo::default()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         bundle
@@ -33,7 +35,7 @@ impl CommandBuffer {
         Ok(())
     }
 
-    pub unsafe fn end(&self, bundle: Bundle) -> anyhow::Result<()> {
+    pub unsafe fn end(&self, bundle: Bundle) -> TracerResult<()> {
         bundle.device.end_command_buffer(self.command_buffer)?;
         Ok(())
     }
@@ -49,7 +51,7 @@ impl CommandBuffer {
         }
     }
 
-    pub unsafe fn reset(&self, bundle: Bundle) -> anyhow::Result<()> {
+    pub unsafe fn reset(&self, bundle: Bundle) -> TracerResult<()> {
         bundle
             .device
             .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())?;
@@ -72,3 +74,105 @@ impl Drop for CommandBuffer {
         }
     }
 }
+
+/// Command buffer recorded once and submitted right away. The submission
+/// is waited for with its own fence, other work on the queue is not.
+pub struct OneTimeSubmit {
+    command_buffer: CommandBuffer,
+    command_pool: vk::CommandPool,
+}
+
+impl OneTimeSubmit {
+    pub unsafe fn begin(bundle: Bundle, command_pool: vk::CommandPool) -> TracerResult<Self> {
+        let mut command_buffer = CommandBuffer::new_from_pool(bundle, command_pool)?;
+        if let Err(e) = command_buffer.begin(bundle) {
+            command_buffer.destroy(bundle, command_pool);
+            return Err(e);
+        }
+
+        Ok(Self {
+            command_buffer,
+            command_pool,
+        })
+    }
+
+    pub fn as_inner(&self) -> vk::CommandBuffer {
+        self.command_buffer.as_inner()
+    }
+
+    /// Submits the recorded commands and blocks until they complete
+    pub unsafe fn submit_and_wait(mut self, bundle: Bundle, queue: vk::Queue) -> TracerResult<()> {
+        let result = self.submit(bundle, queue);
+        self.command_buffer.destroy(bundle, self.command_pool);
+        result
+    }
+
+    unsafe fn submit(&self, bundle: Bundle, queue: vk::Queue) -> TracerResult<()> {
+        self.command_buffer.end(bundle)?;
+        let fence = bundle
+            .device
+            .create_fence(&vk::FenceCreateInfo::default(), None)?;
+        let submit_info = self.command_buffer.as_submit_info();
+        let result = bundle
+            .device
+            .queue_submit(queue, &[submit_info], fence)
+            .and_then(|_| bundle.device.wait_for_fences(&[fence], true, u64::MAX));
+        bundle.device.destroy_fence(fence, None);
+        Ok(result?)
+    }
+
+    /// Drops the recorded commands without submitting them
+    pub unsafe fn discard(mut self, bundle: Bundle) {
+        self.command_buffer.destroy(bundle, self.command_pool);
+    }
+}
+
+/// Records the initialization of several resources (layout transitions,
+/// staging copies) into a single submission. Staging buffers are kept
+/// until the submission completes.
+pub struct UploadBatch {
+    submit: OneTimeSubmit,
+    staging: Vec<(vk::Buffer, Allocation)>,
+}
+
+impl UploadBatch {
+    pub unsafe fn begin(bundle: Bundle, command_pool: vk::CommandPool) -> TracerResult<Self> {
+        Ok(Self {
+            submit: OneTimeSubmit::begin(bundle, command_pool)?,
+            staging: vec![],
+        })
+    }
+
+    pub fn as_inner(&self) -> vk::CommandBuffer {
+        self.submit.as_inner()
+    }
+
+    /// Takes over the staging buffer, it's destroyed once the batch is done
+    pub fn keep_staging(&mut self, buffer: vk::Buffer, allocation: Allocation) {
+        self.staging.push((buffer, allocation));
+    }
+
+    pub unsafe fn submit_and_wait(mut self, bundle: Bundle, queue: vk::Queue) -> TracerResult<()> {
+        let staging = std::mem::take(&mut self.staging);
+        let result = self.submit.submit_and_wait(bundle, queue);
+        Self::free_staging(bundle, staging);
+        result
+    }
+
+    /// Drops the recorded commands, e.g. when a resource of the batch
+    /// failed to be created and the others are destroyed
+    pub unsafe fn discard(mut self, bundle: Bundle) {
+        let staging = std::mem::take(&mut self.staging);
+        self.submit.discard(bundle);
+        Self::free_staging(bundle, staging);
+    }
+
+    unsafe fn free_staging(bundle: Bundle, staging: Vec<(vk::Buffer, Allocation)>) {
+        for (buffer, allocation) in staging {
+            if let Err(e) = bundle.allocator().free(allocation) {
+                warn!("Failed to free staging memory: {}", e);
+            }
+            bundle.device.destroy_buffer(buffer, None);
+        }
+    }
+}
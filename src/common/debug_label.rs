@@ -0,0 +1,50 @@
+use crate::common::command_buffer::CommandBuffer;
+use crate::tracer::Bundle;
+use ash::vk;
+use std::ffi::CString;
+
+/// Names the passes of the command buffers and the frames of a queue with
+/// VK_EXT_debug_utils, so GPU profilers and frame debuggers show them.
+/// Does nothing unless enabled and the extension is available.
+#[derive(Clone)]
+pub struct DebugLabels {
+    loader: Option<ash::ext::debug_utils::Device>,
+}
+
+impl DebugLabels {
+    pub unsafe fn new(bundle: Bundle, enabled: bool) -> Self {
+        let available = bundle.instance_capabilities.debug_utils_ext;
+        Self {
+            loader: (enabled && available)
+                .then(|| ash::ext::debug_utils::Device::new(bundle.instance, bundle.device)),
+        }
+    }
+
+    pub unsafe fn begin(&self, command_buffer: &CommandBuffer, name: &str) {
+        if let Some(loader) = &self.loader {
+            let name = Self::name(name);
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+            loader.cmd_begin_debug_utils_label(command_buffer.as_inner(), &label);
+        }
+    }
+
+    /// Closes the last label begun in the command buffer
+    pub unsafe fn end(&self, command_buffer: &CommandBuffer) {
+        if let Some(loader) = &self.loader {
+            loader.cmd_end_debug_utils_label(command_buffer.as_inner());
+        }
+    }
+
+    /// Marks a point between the submissions of the queue
+    pub unsafe fn insert(&self, queue: vk::Queue, name: &str) {
+        if let Some(loader) = &self.loader {
+            let name = Self::name(name);
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+            loader.queue_insert_debug_utils_label(queue, &label);
+        }
+    }
+
+    fn name(name: &str) -> CString {
+        CString::new(name.replace('\0', "")).unwrap()
+    }
+}
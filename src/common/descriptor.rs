@@ -0,0 +1,154 @@
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+
+/// Descriptor set layout together with the pool its sets are allocated from.
+/// The pool is sized for exactly `count` sets of the layout, so the sets live
+/// as long as the allocator and are updated in place instead of reallocated.
+/// With `count` of 0 only the layout is created (e.g. for pipelines binding
+/// sets owned by someone else).
+pub struct DescriptorAllocator {
+    pub layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    sets: Vec<vk::DescriptorSet>,
+    destroyed: bool,
+}
+
+impl DescriptorAllocator {
+    /// `binding_flags` is either empty or has an entry per binding. A
+    /// variable-sized binding must be the last one, its sets are allocated
+    /// with the full `descriptor_count`.
+    pub unsafe fn new(
+        bundle: Bundle,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        count: usize,
+    ) -> TracerResult<Self> {
+        let update_after_bind = binding_flags
+            .iter()
+            .any(|flags| flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND));
+        let variable_count = match (bindings.last(), binding_flags.last()) {
+            (Some(binding), Some(flags))
+                if flags.contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT) =>
+            {
+                Some(binding.descriptor_count)
+            }
+            _ => None,
+        };
+
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(binding_flags);
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+        let layout_info = if update_after_bind {
+            layout_info.flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+        } else {
+            layout_info
+        };
+        let layout_info = if binding_flags.is_empty() {
+            layout_info
+        } else {
+            layout_info.push_next(&mut binding_flags_info)
+        };
+        let layout = bundle
+            .device
+            .create_descriptor_set_layout(&layout_info, None)?;
+
+        let mut allocator = Self {
+            layout,
+            pool: vk::DescriptorPool::null(),
+            sets: vec![],
+            destroyed: false,
+        };
+        if count > 0 {
+            allocator.allocate(bundle, bindings, update_after_bind, variable_count, count)?;
+        }
+
+        Ok(allocator)
+    }
+
+    unsafe fn allocate(
+        &mut self,
+        bundle: Bundle,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        update_after_bind: bool,
+        variable_count: Option<u32>,
+        count: usize,
+    ) -> TracerResult<()> {
+        debug!(
+            "Allocating {} descriptor sets of {} bindings",
+            count,
+            bindings.len()
+        );
+
+        let mut pool_sizes: Vec<vk::DescriptorPoolSize> = vec![];
+        for binding in bindings {
+            let descriptor_count = binding.descriptor_count * count as u32;
+            match pool_sizes
+                .iter_mut()
+                .find(|size| size.ty == binding.descriptor_type)
+            {
+                Some(size) => size.descriptor_count += descriptor_count,
+                None => pool_sizes.push(
+                    vk::DescriptorPoolSize::default()
+                        .ty(binding.descriptor_type)
+                        .descriptor_count(descriptor_count),
+                ),
+            }
+        }
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(count as u32);
+        let pool_info = if update_after_bind {
+            pool_info.flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+        } else {
+            pool_info
+        };
+        self.pool = bundle.device.create_descriptor_pool(&pool_info, None)?;
+
+        let layouts = vec![self.layout; count];
+        let counts = vec![variable_count.unwrap_or(0); count];
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+                .descriptor_counts(&counts);
+        let alloc_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.pool)
+            .set_layouts(&layouts);
+        let alloc_info = if variable_count.is_some() {
+            alloc_info.push_next(&mut variable_count_info)
+        } else {
+            alloc_info
+        };
+        self.sets = bundle.device.allocate_descriptor_sets(&alloc_info)?;
+
+        Ok(())
+    }
+
+    pub fn sets(&self) -> &[vk::DescriptorSet] {
+        &self.sets
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            // Sets are freed together with the pool
+            if self.pool != vk::DescriptorPool::null() {
+                bundle.device.destroy_descriptor_pool(self.pool, None);
+            }
+            bundle
+                .device
+                .destroy_descriptor_set_layout(self.layout, None);
+            self.sets.clear();
+            self.destroyed = true;
+        } else {
+            warn!("DescriptorAllocator already destroyed");
+        }
+    }
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked DescriptorAllocator");
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::common::command_buffer::CommandBuffer;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use log::{debug, warn};
+use std::time::Duration;
+
+/// Passes producing a single frame, in the order they are submitted.
+/// Every pass consumes the output of the preceding one:
+///
+/// Compute -> [Denoise] -> UI -> Present
+///
+/// In the headless mode the frame is copied to the host instead:
+///
+/// Compute -> [Denoise] -> Readback
+///
+/// Each pass owns a timeline semaphore, the value of which is the number
+/// of submissions of the pass completed so far. Staged buffer uploads run
+/// on a timeline of their own, the compute pass waits for it.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Upload,
+    Compute,
+    Denoise,
+    UI,
+    Present,
+    Readback,
+}
+
+impl Pass {
+    /// Stage at which the pass starts reading the output of the previous one
+    pub fn wait_stage(self) -> vk::PipelineStageFlags {
+        match self {
+            Pass::Compute | Pass::Denoise => vk::PipelineStageFlags::COMPUTE_SHADER,
+            Pass::UI => vk::PipelineStageFlags::FRAGMENT_SHADER,
+            Pass::Present => vk::PipelineStageFlags::ALL_COMMANDS,
+            Pass::Upload | Pass::Readback => vk::PipelineStageFlags::TRANSFER,
+        }
+    }
+}
+
+/// Value on the timeline of a pass. Reached once the submission
+/// signalling it has completed.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncPoint {
+    pub pass: Pass,
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+}
+
+impl SyncPoint {
+    pub unsafe fn wait(&self, bundle: Bundle) -> TracerResult<()> {
+        let semaphores = [self.semaphore];
+        let values = [self.value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        bundle.device.wait_semaphores(&wait_info, u64::MAX)?;
+        Ok(())
+    }
+
+    /// Same as `wait`, returns false if the point is not reached in time
+    pub unsafe fn wait_timeout(&self, bundle: Bundle, timeout: Duration) -> TracerResult<bool> {
+        let semaphores = [self.semaphore];
+        let values = [self.value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        match bundle
+            .device
+            .wait_semaphores(&wait_info, timeout.as_nanos() as u64)
+        {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+pub struct Timeline {
+    pass: Pass,
+    semaphore: vk::Semaphore,
+    // Last value handed out for signalling
+    value: u64,
+    destroyed: bool,
+}
+
+impl Timeline {
+    pub unsafe fn new(bundle: Bundle, pass: Pass) -> TracerResult<Self> {
+        debug!("Creating {:?} timeline", pass);
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        let semaphore = bundle.device.create_semaphore(&semaphore_info, None)?;
+
+        Ok(Self {
+            pass,
+            semaphore,
+            value: 0,
+            destroyed: false,
+        })
+    }
+
+    /// Point of the given submission. Value 0 is always reached.
+    pub fn point(&self, value: u64) -> SyncPoint {
+        SyncPoint {
+            pass: self.pass,
+            semaphore: self.semaphore,
+            value,
+        }
+    }
+
+    /// Point of the last submission
+    pub fn last(&self) -> SyncPoint {
+        self.point(self.value)
+    }
+
+    /// Reserves the point to be signalled by the next submission
+    pub fn advance(&mut self) -> SyncPoint {
+        self.value += 1;
+        self.last()
+    }
+
+    pub unsafe fn is_reached(&self, bundle: Bundle, value: u64) -> TracerResult<bool> {
+        let current = bundle.device.get_semaphore_counter_value(self.semaphore)?;
+        Ok(current >= value)
+    }
+
+    pub unsafe fn wait(&self, bundle: Bundle, value: u64) -> TracerResult<()> {
+        self.point(value).wait(bundle)
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            bundle.device.destroy_semaphore(self.semaphore, None);
+            self.destroyed = true;
+        } else {
+            warn!("{:?} timeline already destroyed", self.pass);
+        }
+    }
+}
+
+impl Drop for Timeline {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked {:?} timeline", self.pass);
+        }
+    }
+}
+
+/// Queue submission of a single pass together with its dependencies.
+/// Binary semaphores are still required for the swapchain acquire and
+/// present, these are mixed in with a dummy value.
+#[derive(Default)]
+pub struct PassSubmit {
+    wait_semaphores: Vec<vk::Semaphore>,
+    wait_values: Vec<u64>,
+    wait_stages: Vec<vk::PipelineStageFlags>,
+    signal_semaphores: Vec<vk::Semaphore>,
+    signal_values: Vec<u64>,
+    command_buffers: Vec<vk::CommandBuffer>,
+}
+
+impl PassSubmit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits for the given point before the `stage` of this submission
+    pub fn after(mut self, point: SyncPoint, stage: vk::PipelineStageFlags) -> Self {
+        if point.value > 0 {
+            self.wait_semaphores.push(point.semaphore);
+            self.wait_values.push(point.value);
+            self.wait_stages.push(stage);
+        }
+        self
+    }
+
+    pub fn after_binary(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) -> Self {
+        self.wait_semaphores.push(semaphore);
+        self.wait_values.push(0);
+        self.wait_stages.push(stage);
+        self
+    }
+
+    pub fn signal(mut self, point: SyncPoint) -> Self {
+        self.signal_semaphores.push(point.semaphore);
+        self.signal_values.push(point.value);
+        self
+    }
+
+    pub fn signal_binary(mut self, semaphore: vk::Semaphore) -> Self {
+        self.signal_semaphores.push(semaphore);
+        self.signal_values.push(0);
+        self
+    }
+
+    pub fn command_buffer(mut self, command_buffer: &CommandBuffer) -> Self {
+        self.command_buffers.push(command_buffer.as_inner());
+        self
+    }
+
+    pub unsafe fn submit(self, bundle: Bundle, queue: vk::Queue) -> TracerResult<()> {
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(&self.wait_values)
+            .signal_semaphore_values(&self.signal_values);
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&self.wait_semaphores)
+            .wait_dst_stage_mask(&self.wait_stages)
+            .signal_semaphores(&self.signal_semaphores)
+            .command_buffers(&self.command_buffers)
+            .push_next(&mut timeline_info);
+        bundle
+            .device
+            .queue_submit(queue, &[submit_info], vk::Fence::null())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use crate::common::command_buffer::CommandBuffer;
+use crate::common::debug_label::DebugLabels;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use glam::FloatExt;
+use log::warn;
+
+#[derive(Clone, Copy, Default)]
+struct Queries {
+    // Reset in the command buffer being recorded and can be written
+    armed: bool,
+    // Written, the results are not read back yet
+    pending: bool,
+}
+
+/// GPU timestamp queries around named scopes, two queries per scope and
+/// frame in flight. Every frame measures into its own queries, which are
+/// read back before the frame is recorded again, so no frame is skipped
+/// while the results of another one are not available yet. The scopes
+/// are also labelled for GPU profilers if the labels are enabled.
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    // Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    names: &'static [&'static str],
+    // Smoothed time in milliseconds per scope, None until the first measurement
+    times: Vec<Option<f32>>,
+    // Per frame, then per scope
+    queries: Vec<Queries>,
+    // Frame being recorded
+    frame: usize,
+    // Read back but not yet returned by `fetch`
+    measured: Vec<(&'static str, f32)>,
+    labels: DebugLabels,
+    destroyed: bool,
+}
+
+impl GpuTimer {
+    pub unsafe fn new(
+        bundle: Bundle,
+        names: &'static [&'static str],
+        frames: usize,
+        labels: DebugLabels,
+    ) -> TracerResult<Self> {
+        let query_pool_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count((frames * names.len()) as u32 * 2);
+        let query_pool = bundle.device.create_query_pool(&query_pool_info, None)?;
+
+        let props = bundle
+            .instance
+            .get_physical_device_properties(bundle.physical_device);
+
+        Ok(Self {
+            query_pool,
+            timestamp_period: props.limits.timestamp_period,
+            names,
+            times: vec![None; names.len()],
+            queries: vec![Queries::default(); frames * names.len()],
+            frame: 0,
+            measured: vec![],
+            labels,
+            destroyed: false,
+        })
+    }
+
+    fn index(&self, name: &str) -> usize {
+        self.names
+            .iter()
+            .position(|scope| *scope == name)
+            .unwrap_or_else(|| panic!("Unknown GPU timer scope {}", name))
+    }
+
+    // Index of the queries of the scope in the frame being recorded
+    fn slot(&self, name: &str) -> usize {
+        self.frame * self.names.len() + self.index(name)
+    }
+
+    /// Resets the queries of the frame about to be recorded. The previous
+    /// submission of the frame must have completed, its results are read
+    /// back first. Must be recorded outside of render passes, before any
+    /// `begin`.
+    pub unsafe fn reset(
+        &mut self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        frame: usize,
+    ) -> TracerResult<()> {
+        self.frame = frame;
+        for i in 0..self.names.len() {
+            let slot = frame * self.names.len() + i;
+            if self.queries[slot].pending {
+                self.read(bundle, slot)?;
+            }
+            // Still not available if the frame was not submitted after all
+            let queries = &mut self.queries[slot];
+            queries.armed = !queries.pending;
+            if queries.armed {
+                bundle.device.cmd_reset_query_pool(
+                    command_buffer.as_inner(),
+                    self.query_pool,
+                    slot as u32 * 2,
+                    2,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub unsafe fn begin(&self, bundle: Bundle, command_buffer: &CommandBuffer, name: &str) {
+        self.labels.begin(command_buffer, name);
+        let slot = self.slot(name);
+        if self.queries[slot].armed {
+            bundle.device.cmd_write_timestamp(
+                command_buffer.as_inner(),
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.query_pool,
+                slot as u32 * 2,
+            );
+        }
+    }
+
+    pub unsafe fn end(&mut self, bundle: Bundle, command_buffer: &CommandBuffer, name: &str) {
+        self.labels.end(command_buffer);
+        let slot = self.slot(name);
+        let queries = &mut self.queries[slot];
+        if queries.armed {
+            bundle.device.cmd_write_timestamp(
+                command_buffer.as_inner(),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pool,
+                slot as u32 * 2 + 1,
+            );
+            queries.armed = false;
+            queries.pending = true;
+        }
+    }
+
+    // Reads back the queries without waiting, they stay pending if the
+    // GPU is not done with them
+    unsafe fn read(&mut self, bundle: Bundle, slot: usize) -> TracerResult<()> {
+        let mut timestamps = [0u64; 2];
+        match bundle.device.get_query_pool_results(
+            self.query_pool,
+            slot as u32 * 2,
+            &mut timestamps,
+            vk::QueryResultFlags::TYPE_64,
+        ) {
+            Ok(()) => {
+                let delta = timestamps[1].saturating_sub(timestamps[0]);
+                let ms = ((delta as f64 * self.timestamp_period as f64) / 1_000_000.0) as f32;
+                let scope = slot % self.names.len();
+                let time = &mut self.times[scope];
+                *time = Some(time.map_or(ms, |time| time.lerp(ms, 0.01)));
+                self.queries[slot].pending = false;
+                self.measured.push((self.names[scope], ms));
+                Ok(())
+            }
+            Err(vk::Result::NOT_READY) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads back the frames the GPU is done with.
+    /// Returns the new measurements in milliseconds.
+    pub unsafe fn fetch(&mut self, bundle: Bundle) -> TracerResult<Vec<(&'static str, f32)>> {
+        for slot in 0..self.queries.len() {
+            if self.queries[slot].pending {
+                self.read(bundle, slot)?;
+            }
+        }
+
+        Ok(std::mem::take(&mut self.measured))
+    }
+
+    /// Smoothed time of the scope in milliseconds
+    pub fn time(&self, name: &str) -> f32 {
+        self.times[self.index(name)].unwrap_or_default()
+    }
+
+    /// Smoothed times of every measured scope, in milliseconds
+    pub fn times(&self) -> Vec<(&'static str, f32)> {
+        self.names
+            .iter()
+            .zip(&self.times)
+            .filter_map(|(name, time)| time.map(|time| (*name, time)))
+            .collect()
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            bundle.device.destroy_query_pool(self.query_pool, None);
+            self.destroyed = true;
+        } else {
+            warn!("GpuTimer already destroyed");
+        }
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked GpuTimer");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use log::{debug, info, warn};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Requests are small JSON documents, anything larger is rejected
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+// A client that stops sending its request is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections served at once, each has a thread. Others are turned away.
+const MAX_CLIENTS: usize = 16;
+
+/// Bare minimum of HTTP/1.1 for the embedded servers: a single request
+/// per connection, the connection is closed after the response.
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn read(stream: &TcpStream) -> anyhow::Result<Self> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        let mut parts = request.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let path = parts.next().unwrap_or("").to_string();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse()?;
+                }
+            }
+        }
+        if content_length > MAX_BODY_SIZE {
+            anyhow::bail!("Request body of {} bytes is too large", content_length);
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        Ok(Self { method, path, body })
+    }
+}
+
+pub fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+// Counts a client served until dropped, also when its handler panics
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Accepts connections on a background thread and serves each of them on a
+/// thread of its own with `handler`, up to `MAX_CLIENTS` at once
+pub struct HttpServer {
+    name: String,
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl HttpServer {
+    pub fn bind<H>(address: impl ToSocketAddrs, name: &str, handler: H) -> std::io::Result<Self>
+    where
+        H: Fn(&mut TcpStream, HttpRequest) -> anyhow::Result<()> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        info!("Serving {} on http://{}/", name, address);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let stopped = stopped.clone();
+            let name = name.to_string();
+            let handler = Arc::new(handler);
+            std::thread::Builder::new()
+                .name(format!("{}-accept", name))
+                .spawn(move || Self::accept(listener, name, stopped, handler))?
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            address,
+            stopped,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting new connections. The ones being served are left
+    /// to finish on their own.
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.accept_thread.take() {
+            debug!("Stopping {} server", self.name);
+            self.stopped.store(true, Ordering::Relaxed);
+
+            // Wake up the blocking accept
+            let mut wake = self.address;
+            if wake.ip().is_unspecified() {
+                wake.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+            }
+            let _ = TcpStream::connect(wake);
+            let _ = thread.join();
+        }
+    }
+
+    fn accept<H>(listener: TcpListener, name: String, stopped: Arc<AtomicBool>, handler: Arc<H>)
+    where
+        H: Fn(&mut TcpStream, HttpRequest) -> anyhow::Result<()> + Send + Sync + 'static,
+    {
+        let clients = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept {} client: {}", name, e);
+                    continue;
+                }
+            };
+            if clients.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+                clients.fetch_sub(1, Ordering::Relaxed);
+                warn!("Too many {} clients, rejecting a connection", name);
+                // Best effort, the accept loop may not block on the client
+                let _ = stream.set_write_timeout(Some(READ_TIMEOUT));
+                let _ = respond(&mut stream, "503 Service Unavailable", "text/plain", b"");
+                continue;
+            }
+            let slot = ClientSlot(clients.clone());
+            let handler = handler.clone();
+            let client_name = name.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("{}-client", name))
+                .spawn(move || {
+                    let _slot = slot;
+                    let peer = stream.peer_addr().ok();
+                    let result = HttpRequest::read(&stream).and_then(|request| {
+                        debug!(
+                            "{} request: {} {}",
+                            client_name, request.method, request.path
+                        );
+                        handler(&mut stream, request)
+                    });
+                    if let Err(e) = result {
+                        debug!("{} client {:?} disconnected: {}", client_name, peer, e);
+                    }
+                });
+            if let Err(e) = spawned {
+                warn!("Failed to spawn {} client thread: {}", name, e);
+            }
+        }
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
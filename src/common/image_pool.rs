@@ -0,0 +1,127 @@
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::{debug, warn};
+
+// A pooled allocation is only reused for an image needing at least this
+// fraction of it, so shrinking does not pin large allocations
+const MIN_REUSE_FILL: f32 = 0.5;
+
+/// GPU-only memory bound to an image, handed back to the pool on release
+pub struct PooledMemory {
+    allocation: Allocation,
+    // Memory types the allocation was made for
+    memory_type_bits: u32,
+}
+
+/// Memory of released GPU-only images kept for the next ones. Resizing
+/// recreates all the viewport-sized images, reusing their memory keeps
+/// the allocator from fragmenting into blocks of stale sizes. The pool
+/// is emptied by `trim`, letting the allocator free the emptied blocks.
+#[derive(Default)]
+pub struct ImagePool {
+    free: Vec<PooledMemory>,
+    destroyed: bool,
+}
+
+impl ImagePool {
+    /// Binds memory to the image, reusing the smallest pooled allocation
+    /// it fits into or allocating a new one
+    pub unsafe fn bind(
+        &mut self,
+        bundle: Bundle,
+        image: vk::Image,
+        name: &str,
+    ) -> TracerResult<PooledMemory> {
+        let requirements = bundle.device.get_image_memory_requirements(image);
+        let reusable = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, memory)| {
+                let size = memory.allocation.size();
+                memory.memory_type_bits == requirements.memory_type_bits
+                    && size >= requirements.size
+                    && requirements.size as f32 >= size as f32 * MIN_REUSE_FILL
+                    && memory.allocation.offset() % requirements.alignment == 0
+            })
+            .min_by_key(|(_, memory)| memory.allocation.size())
+            .map(|(index, _)| index);
+
+        let memory = match reusable {
+            Some(index) => {
+                debug!("Reusing pooled image memory for {}", name);
+                self.free.swap_remove(index)
+            }
+            None => PooledMemory {
+                allocation: bundle.allocator().allocate(&AllocationCreateDesc {
+                    name,
+                    requirements,
+                    location: MemoryLocation::GpuOnly,
+                    linear: false,
+                    allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+                })?,
+                memory_type_bits: requirements.memory_type_bits,
+            },
+        };
+        if let Err(e) = bundle.device.bind_image_memory(
+            image,
+            memory.allocation.memory(),
+            memory.allocation.offset(),
+        ) {
+            self.free.push(memory);
+            return Err(e.into());
+        }
+
+        Ok(memory)
+    }
+
+    /// Keeps the memory for the next image. The image bound to it must be
+    /// destroyed and no longer in use by the device.
+    pub fn release(&mut self, memory: PooledMemory) {
+        self.free.push(memory);
+    }
+
+    /// Bytes held by the pool and not bound to any image
+    pub fn pooled_bytes(&self) -> u64 {
+        self.free
+            .iter()
+            .map(|memory| memory.allocation.size())
+            .sum()
+    }
+
+    /// Returns all the pooled memory to the allocator
+    pub unsafe fn trim(&mut self, bundle: Bundle) {
+        if !self.free.is_empty() {
+            debug!(
+                "Trimming image pool of {} allocations, {} bytes",
+                self.free.len(),
+                self.pooled_bytes()
+            );
+        }
+        for memory in self.free.drain(..) {
+            if let Err(e) = bundle.allocator().free(memory.allocation) {
+                warn!("Failed to free pooled image memory: {}", e);
+            }
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            self.trim(bundle);
+            self.destroyed = true;
+        } else {
+            warn!("ImagePool already destroyed");
+        }
+    }
+}
+
+impl Drop for ImagePool {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked ImagePool");
+        }
+    }
+}
@@ -1,5 +1,17 @@
 pub mod buffer;
 pub mod capabilities;
+pub mod color;
 pub mod command_buffer;
+pub mod debug_label;
+pub mod descriptor;
+pub mod frame_graph;
+pub mod gpu_timer;
+pub mod http;
+pub mod image_pool;
+pub mod interrupt;
+pub mod panic;
+pub mod portability;
 pub mod queue;
 pub mod shader;
+pub mod texture;
+pub mod watchdog;
@@ -0,0 +1,31 @@
+use log::error;
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Routes panics through the logger as well, so they end up in the log
+/// file next to the teardown messages that follow them
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("{}", info);
+        default_hook(info);
+    }));
+}
+
+/// Runs `f`, turning a panic into an error. Everything `f` owns is dropped
+/// while unwinding, so the tracer still waits for the device and destroys
+/// its objects in order instead of leaking them all at exit.
+pub fn catch_panic<R>(f: impl FnOnce() -> anyhow::Result<R>) -> anyhow::Result<R> {
+    catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(anyhow::anyhow!("Panicked: {}", panic_message(&*payload))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -0,0 +1,52 @@
+use crate::common::capabilities::{DeviceCapabilities, InstanceCapabilities};
+use crate::error::TracerResult;
+use ash::vk;
+use log::info;
+use std::ffi::c_char;
+
+/// Handles Vulkan portability implementations (e.g. MoltenVK on macOS).
+/// Such implementations are hidden from the device enumeration unless
+/// the instance opts in, and require the portability subset extension
+/// to be enabled on the logical device.
+pub struct Portability;
+
+impl Portability {
+    pub unsafe fn get_required_instance_extensions(
+        available: &[String],
+        capabilities: &mut InstanceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        let mut required = vec![];
+        let extension = ash::khr::portability_enumeration::NAME.to_str()?;
+        if available.contains(&extension.to_string()) {
+            info!("Portability enumeration extension required");
+            required.push(ash::khr::portability_enumeration::NAME.as_ptr());
+            capabilities.portability_enumeration = true;
+        }
+
+        Ok(required)
+    }
+
+    pub unsafe fn get_required_device_extensions(
+        available: &[String],
+        capabilities: &mut DeviceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        let mut required = vec![];
+        // The spec requires this extension to be enabled whenever it is exposed
+        let extension = ash::khr::portability_subset::NAME.to_str()?;
+        if available.contains(&extension.to_string()) {
+            info!("Portability subset extension required");
+            required.push(ash::khr::portability_subset::NAME.as_ptr());
+            capabilities.portability_subset = true;
+        }
+
+        Ok(required)
+    }
+
+    pub fn instance_create_flags(capabilities: &InstanceCapabilities) -> vk::InstanceCreateFlags {
+        if capabilities.portability_enumeration {
+            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+        } else {
+            vk::InstanceCreateFlags::empty()
+        }
+    }
+}
@@ -1,24 +1,188 @@
-#[derive(Debug)]
+use crate::error::{TracerError, TracerResult};
+use ash::vk;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct QueueFamily {
     pub index: u32,
     pub priorities: Vec<f32>,
 }
 
 impl QueueFamily {
-    pub(crate) fn merge_queues(a: &mut Vec<QueueFamily>) {
-        // If the queue families are the same, merge them
-        let mut i = 0;
-        while i < a.len() {
-            let mut j = i + 1;
-            while j < a.len() {
-                if a[i].index == a[j].index {
-                    // TODO: Merge priorities
-                    a.remove(j);
-                } else {
-                    j += 1;
+    /// Merges the requests for the same family, a family may be created only
+    /// once. Every queue gets the highest priority it was requested with,
+    /// clamped to [0, 1]. Families are sorted by index and checked against
+    /// the number of queues they have.
+    pub(crate) fn merge_queues(
+        requested: Vec<QueueFamily>,
+        properties: &[vk::QueueFamilyProperties],
+    ) -> TracerResult<Vec<QueueFamily>> {
+        let mut merged: Vec<QueueFamily> = vec![];
+        for family in requested {
+            let priorities = family.priorities.iter().map(|p| p.clamp(0.0, 1.0));
+            match merged.iter_mut().find(|other| other.index == family.index) {
+                Some(existing) => {
+                    for (k, priority) in priorities.enumerate() {
+                        match existing.priorities.get_mut(k) {
+                            Some(current) => *current = current.max(priority),
+                            None => existing.priorities.push(priority),
+                        }
+                    }
                 }
+                None => merged.push(QueueFamily {
+                    index: family.index,
+                    priorities: priorities.collect(),
+                }),
             }
-            i += 1;
         }
+        merged.sort_by_key(|family| family.index);
+
+        for family in &merged {
+            let available = properties
+                .get(family.index as usize)
+                .map_or(0, |properties| properties.queue_count);
+            if family.priorities.len() > available as usize {
+                return Err(TracerError::Unsupported(format!(
+                    "{} queues of family {}, it has {}",
+                    family.priorities.len(),
+                    family.index,
+                    available
+                )));
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Finds a queue family that supports transfers but neither graphics nor compute.
+    /// Such families usually map to the dedicated DMA engines of discrete GPUs.
+    pub(crate) fn find_dedicated_transfer(properties: &[vk::QueueFamilyProperties]) -> Option<u32> {
+        properties
+            .iter()
+            .position(|family| {
+                family.queue_count > 0
+                    && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                    && !family
+                        .queue_flags
+                        .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|i| i as u32)
+    }
+
+    /// Deduplicates family indices to be used with `vk::SharingMode::CONCURRENT`
+    pub(crate) fn unique_indices(indices: &[u32]) -> Vec<u32> {
+        let mut unique = indices.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+        unique
+    }
+}
+
+/// Family with timestamps, shared by the queue selection tests
+#[cfg(test)]
+pub(crate) fn test_family(flags: vk::QueueFlags, queue_count: u32) -> vk::QueueFamilyProperties {
+    vk::QueueFamilyProperties {
+        queue_flags: flags,
+        queue_count,
+        timestamp_valid_bits: 64,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(index: u32, priorities: &[f32]) -> QueueFamily {
+        QueueFamily {
+            index,
+            priorities: priorities.to_vec(),
+        }
+    }
+
+    #[test]
+    fn merges_shared_family_into_one() {
+        let properties = [test_family(
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER,
+            1,
+        )];
+        // Graphics, compute and transfer of the back-end, graphics of the front
+        let requested = vec![
+            request(0, &[1.0]),
+            request(0, &[1.0]),
+            request(0, &[1.0]),
+            request(0, &[1.0]),
+        ];
+
+        let merged = QueueFamily::merge_queues(requested, &properties).unwrap();
+        assert_eq!(merged, vec![request(0, &[1.0])]);
+    }
+
+    #[test]
+    fn keeps_highest_priority_of_every_queue() {
+        let properties = [test_family(vk::QueueFlags::COMPUTE, 4)];
+        let requested = vec![
+            request(0, &[0.5]),
+            request(0, &[1.0, 0.25]),
+            request(0, &[0.75, 0.5, 0.1]),
+        ];
+
+        let merged = QueueFamily::merge_queues(requested, &properties).unwrap();
+        assert_eq!(merged, vec![request(0, &[1.0, 0.5, 0.1])]);
+    }
+
+    #[test]
+    fn clamps_priorities() {
+        let properties = [test_family(vk::QueueFlags::COMPUTE, 2)];
+        let merged =
+            QueueFamily::merge_queues(vec![request(0, &[2.0, -1.0])], &properties).unwrap();
+        assert_eq!(merged, vec![request(0, &[1.0, 0.0])]);
+    }
+
+    #[test]
+    fn sorts_separate_families() {
+        let properties = [
+            test_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE, 1),
+            test_family(vk::QueueFlags::COMPUTE, 2),
+            test_family(vk::QueueFlags::TRANSFER, 1),
+        ];
+        let requested = vec![
+            request(2, &[1.0]),
+            request(0, &[1.0]),
+            request(1, &[1.0, 0.5]),
+            request(0, &[1.0]),
+        ];
+
+        let merged = QueueFamily::merge_queues(requested, &properties).unwrap();
+        assert_eq!(
+            merged,
+            vec![
+                request(0, &[1.0]),
+                request(1, &[1.0, 0.5]),
+                request(2, &[1.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_more_queues_than_the_family_has() {
+        let properties = [test_family(vk::QueueFlags::COMPUTE, 1)];
+        let requested = vec![request(0, &[1.0]), request(0, &[1.0, 0.5])];
+        assert!(QueueFamily::merge_queues(requested, &properties).is_err());
+        assert!(QueueFamily::merge_queues(vec![request(1, &[1.0])], &properties).is_err());
+    }
+
+    #[test]
+    fn finds_dedicated_transfer_family() {
+        let properties = [
+            test_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::TRANSFER, 1),
+            test_family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 1),
+            test_family(vk::QueueFlags::TRANSFER, 1),
+        ];
+        assert_eq!(QueueFamily::find_dedicated_transfer(&properties), Some(2));
+        assert_eq!(QueueFamily::find_dedicated_transfer(&properties[..2]), None);
+    }
+
+    #[test]
+    fn unique_indices_are_sorted() {
+        assert_eq!(QueueFamily::unique_indices(&[2, 0, 2, 1, 0]), vec![0, 1, 2]);
     }
 }
@@ -1,3 +1,4 @@
+use crate::error::{TracerError, TracerResult};
 use crate::tracer::Bundle;
 use ash::vk;
 use log::warn;
@@ -8,7 +9,7 @@ pub struct Shader {
 }
 
 impl Shader {
-    pub unsafe fn new_from_spirv(bundle: Bundle, source: &[u8]) -> anyhow::Result<Shader> {
+    pub unsafe fn new_from_spirv(bundle: Bundle, source: &[u8]) -> TracerResult<Shader> {
         // Make sure that source is padded to 4 bytes
         assert_eq!(source.len() % 4, 0);
         let create_info = vk::ShaderModuleCreateInfo::default().code(std::slice::from_raw_parts(
@@ -16,7 +17,10 @@ impl Shader {
             source.len() / 4,
         ));
 
-        let module = bundle.device.create_shader_module(&create_info, None)?;
+        let module = bundle
+            .device
+            .create_shader_module(&create_info, None)
+            .map_err(TracerError::ShaderCompile)?;
         Ok(Shader {
             module,
             destroyed: false,
@@ -0,0 +1,448 @@
+use crate::common::command_buffer::UploadBatch;
+#[cfg(unix)]
+use crate::error::TracerError;
+use crate::error::TracerResult;
+use crate::tracer::Bundle;
+use ash::vk;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::{debug, warn};
+
+/// Immutable sampled RGBA8 or RGBA32F texture. The data is uploaded once on
+/// creation, afterwards the image stays in the shader read-only layout.
+/// The upload is recorded into the batch, the texture may not be sampled
+/// before the batch is submitted.
+pub struct Texture {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub dimensions: glam::UVec2,
+    // Layout the image is sampled in
+    pub layout: vk::ImageLayout,
+    allocation: Option<Allocation>,
+    // Memory imported from another process and its size, outside of the allocator
+    imported: Option<(vk::DeviceMemory, u64)>,
+    destroyed: bool,
+}
+
+impl Texture {
+    pub unsafe fn new_from_image(
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        image: &image::RgbaImage,
+        sampler_info: &vk::SamplerCreateInfo,
+        name: &str,
+    ) -> TracerResult<Self> {
+        Self::new_from_data(
+            bundle,
+            batch,
+            glam::UVec2::new(image.width(), image.height()),
+            vk::Format::R8G8B8A8_UNORM,
+            image.as_raw(),
+            sampler_info,
+            name,
+        )
+    }
+
+    /// Same as `new_from_image`, but keeps the full float range
+    pub unsafe fn new_from_hdr_image(
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        image: &image::Rgba32FImage,
+        sampler_info: &vk::SamplerCreateInfo,
+        name: &str,
+    ) -> TracerResult<Self> {
+        let pixels = image.as_raw();
+        let data =
+            std::slice::from_raw_parts(pixels.as_ptr() as *const u8, size_of_val(&pixels[..]));
+        Self::new_from_data(
+            bundle,
+            batch,
+            glam::UVec2::new(image.width(), image.height()),
+            vk::Format::R32G32B32A32_SFLOAT,
+            data,
+            sampler_info,
+            name,
+        )
+    }
+
+    unsafe fn new_from_data(
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        dimensions: glam::UVec2,
+        format: vk::Format,
+        data: &[u8],
+        sampler_info: &vk::SamplerCreateInfo,
+        name: &str,
+    ) -> TracerResult<Self> {
+        debug!(
+            "Creating texture {} of {}x{}",
+            name, dimensions.x, dimensions.y
+        );
+
+        let create_image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: dimensions.x,
+                height: dimensions.y,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let vk_image = bundle.device.create_image(&create_image_info, None)?;
+
+        let requirements = bundle.device.get_image_memory_requirements(vk_image);
+        let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+        bundle
+            .device
+            .bind_image_memory(vk_image, allocation.memory(), allocation.offset())?;
+
+        Self::upload(bundle, batch, vk_image, dimensions, data)?;
+
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .image(vk_image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(Self::subresource_range());
+        let image_view = bundle.device.create_image_view(&image_view_info, None)?;
+        let sampler = bundle.device.create_sampler(sampler_info, None)?;
+
+        Ok(Self {
+            image: vk_image,
+            image_view,
+            sampler,
+            dimensions,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            allocation: Some(allocation),
+            imported: None,
+            destroyed: false,
+        })
+    }
+
+    /// Wraps an RGBA8 image another process allocated and exported as an
+    /// opaque file descriptor (VK_KHR_external_memory_fd). The descriptor is
+    /// owned by the texture from then on, also when the import fails. The
+    /// producer creates the image with the same parameters on the same
+    /// device and leaves it in the general layout. The image is taken over
+    /// once, the producer may not write into it anymore; new contents are
+    /// handed over by importing the texture again.
+    #[cfg(unix)]
+    pub unsafe fn import_fd(
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        queue_family: u32,
+        fd: std::os::fd::RawFd,
+        dimensions: glam::UVec2,
+        sampler_info: &vk::SamplerCreateInfo,
+        name: &str,
+    ) -> TracerResult<Self> {
+        debug!(
+            "Importing texture {} of {}x{} from fd {}",
+            name, dimensions.x, dimensions.y, fd
+        );
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let (vk_image, memory, size) = match Self::import_memory(bundle, fd, dimensions, format) {
+            Ok(imported) => imported,
+            Err(e) => {
+                // Vulkan only takes the descriptor over on success
+                libc::close(fd);
+                return Err(e);
+            }
+        };
+
+        let mut texture = Self {
+            image: vk_image,
+            image_view: vk::ImageView::null(),
+            sampler: vk::Sampler::null(),
+            dimensions,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            allocation: None,
+            imported: Some((memory, size)),
+            destroyed: false,
+        };
+        if let Err(e) = texture.finish_import(bundle, batch, queue_family, format, sampler_info) {
+            texture.destroy(bundle);
+            return Err(e);
+        }
+        Ok(texture)
+    }
+
+    #[cfg(unix)]
+    unsafe fn import_memory(
+        bundle: Bundle,
+        fd: std::os::fd::RawFd,
+        dimensions: glam::UVec2,
+        format: vk::Format,
+    ) -> TracerResult<(vk::Image, vk::DeviceMemory, u64)> {
+        if !bundle.device_capabilities.external_memory_fd {
+            return Err(TracerError::Unsupported(
+                "external memory, VK_KHR_external_memory_fd is not available".to_string(),
+            ));
+        }
+
+        let handle_type = vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD;
+        let mut external_info =
+            vk::ExternalMemoryImageCreateInfo::default().handle_types(handle_type);
+        let create_image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: dimensions.x,
+                height: dimensions.y,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .push_next(&mut external_info);
+        let vk_image = bundle.device.create_image(&create_image_info, None)?;
+
+        let requirements = bundle.device.get_image_memory_requirements(vk_image);
+        let Some(memory_type) = Self::find_memory_type(
+            bundle,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ) else {
+            bundle.device.destroy_image(vk_image, None);
+            return Err(TracerError::Unsupported(format!(
+                "memory types {:#b} of the imported image",
+                requirements.memory_type_bits
+            )));
+        };
+        let mut import_info = vk::ImportMemoryFdInfoKHR::default()
+            .handle_type(handle_type)
+            .fd(fd);
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().image(vk_image);
+        let allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type)
+            .push_next(&mut import_info)
+            .push_next(&mut dedicated_info);
+        match bundle.device.allocate_memory(&allocate_info, None) {
+            Ok(memory) => Ok((vk_image, memory, requirements.size)),
+            Err(e) => {
+                bundle.device.destroy_image(vk_image, None);
+                Err(e.into())
+            }
+        }
+    }
+
+    // Binds the memory, creates the view and the sampler and records the
+    // acquisition of the image from the producer, into the sampled layout
+    #[cfg(unix)]
+    unsafe fn finish_import(
+        &mut self,
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        queue_family: u32,
+        format: vk::Format,
+        sampler_info: &vk::SamplerCreateInfo,
+    ) -> TracerResult<()> {
+        let (memory, _) = self.imported.expect("Texture must be imported");
+        bundle.device.bind_image_memory(self.image, memory, 0)?;
+
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .image(self.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(Self::subresource_range());
+        self.image_view = bundle.device.create_image_view(&image_view_info, None)?;
+        self.sampler = bundle.device.create_sampler(sampler_info, None)?;
+
+        let acquire = vk::ImageMemoryBarrier::default()
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(self.layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_EXTERNAL)
+            .dst_queue_family_index(queue_family)
+            .image(self.image)
+            .subresource_range(Self::subresource_range())
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[acquire],
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    unsafe fn find_memory_type(
+        bundle: Bundle,
+        type_bits: u32,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Option<u32> {
+        let properties = bundle
+            .instance
+            .get_physical_device_memory_properties(bundle.physical_device);
+        (0..properties.memory_type_count).find(|&index| {
+            type_bits & (1 << index) != 0
+                && properties.memory_types[index as usize]
+                    .property_flags
+                    .contains(flags)
+        })
+    }
+
+    fn subresource_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+
+    // Records the copy of the pixels through a staging buffer and the
+    // transition of the image to the shader read-only layout
+    unsafe fn upload(
+        bundle: Bundle,
+        batch: &mut UploadBatch,
+        vk_image: vk::Image,
+        dimensions: glam::UVec2,
+        data: &[u8],
+    ) -> TracerResult<()> {
+        let staging_info = vk::BufferCreateInfo::default()
+            .size(data.len() as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let staging_buffer = bundle.device.create_buffer(&staging_info, None)?;
+        let staging_reqs = bundle.device.get_buffer_memory_requirements(staging_buffer);
+        let staging_alloc = bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Texture staging buffer",
+            requirements: staging_reqs,
+            location: MemoryLocation::CpuToGpu,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+        bundle.device.bind_buffer_memory(
+            staging_buffer,
+            staging_alloc.memory(),
+            staging_alloc.offset(),
+        )?;
+
+        let mapped = staging_alloc
+            .mapped_ptr()
+            .expect("CpuToGpu allocation must be mappable");
+        let dst = mapped.as_ptr() as *mut u8;
+        dst.copy_from_nonoverlapping(data.as_ptr(), data.len());
+
+        let to_transfer = vk::ImageMemoryBarrier::default()
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(vk_image)
+            .subresource_range(Self::subresource_range())
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_transfer],
+        );
+
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: dimensions.x,
+                height: dimensions.y,
+                depth: 1,
+            });
+        bundle.device.cmd_copy_buffer_to_image(
+            batch.as_inner(),
+            staging_buffer,
+            vk_image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+
+        let to_shader = vk::ImageMemoryBarrier::default()
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(vk_image)
+            .subresource_range(Self::subresource_range())
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        bundle.device.cmd_pipeline_barrier(
+            batch.as_inner(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_shader],
+        );
+
+        batch.keep_staging(staging_buffer, staging_alloc);
+
+        Ok(())
+    }
+
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocation
+            .as_ref()
+            .map_or(0, |allocation| allocation.size())
+            + self.imported.map_or(0, |(_, size)| size)
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            if let Some(allocation) = self.allocation.take() {
+                bundle
+                    .allocator()
+                    .free(allocation)
+                    .expect("Failed to free texture allocation");
+            }
+            if let Some((memory, _)) = self.imported.take() {
+                bundle.device.free_memory(memory, None);
+            }
+            bundle.device.destroy_sampler(self.sampler, None);
+            bundle.device.destroy_image_view(self.image_view, None);
+            bundle.device.destroy_image(self.image, None);
+            self.destroyed = true;
+        } else {
+            warn!("Texture already destroyed");
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked texture");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::common::frame_graph::SyncPoint;
+use crate::error::{TracerError, TracerResult};
+use crate::tracer::Bundle;
+use ash::{vk, Device};
+use log::{debug, error, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// How often the watchdog looks at the last submission
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Submission that has missed the watchdog timeout
+#[derive(Clone, Debug)]
+pub struct GpuStall {
+    pub value: u64,
+    // Time the submission took, or has been pending for if it never completed
+    pub elapsed: Duration,
+    // Completed within the second timeout, the GPU was only slow
+    pub recovered: bool,
+    // Pipeline state of the submission, see `Watchdog::submitted`
+    pub state: String,
+}
+
+struct Submission {
+    point: SyncPoint,
+    submitted: Instant,
+    state: String,
+}
+
+#[derive(Default)]
+struct Watched {
+    last: Option<Submission>,
+    stall: Option<GpuStall>,
+}
+
+/// Thread keeping an eye on the submissions of a timeline. A submission not
+/// completed within the timeout is logged together with the pipeline state
+/// it was recorded with, instead of leaving the application silently frozen.
+pub struct Watchdog {
+    watched: Arc<Mutex<Watched>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn new(bundle: Bundle, timeout: Duration) -> Self {
+        let watched = Arc::new(Mutex::new(Watched::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let device = bundle.device.clone();
+        let thread = {
+            let watched = watched.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("gpu-watchdog".to_string())
+                .spawn(move || Self::run(device, watched, stop, timeout))
+                .map_err(|e| warn!("Failed to start GPU watchdog: {}", e))
+                .ok()
+        };
+
+        Self {
+            watched,
+            stop,
+            thread,
+        }
+    }
+
+    fn run(device: Device, watched: Arc<Mutex<Watched>>, stop: Arc<AtomicBool>, timeout: Duration) {
+        debug!("GPU watchdog started, timeout {:?}", timeout);
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let (point, submitted, state) = {
+                let watched = watched.lock().unwrap_or_else(PoisonError::into_inner);
+                let reported = watched.stall.as_ref().map(|stall| stall.value);
+                match &watched.last {
+                    // Every submission is reported once
+                    Some(last) if Some(last.point.value) != reported => {
+                        (last.point, last.submitted, last.state.clone())
+                    }
+                    _ => continue,
+                }
+            };
+
+            let counter = match unsafe { device.get_semaphore_counter_value(point.semaphore) } {
+                Ok(counter) => counter,
+                Err(e) => {
+                    error!(
+                        "GPU watchdog failed to query {:?} timeline: {}",
+                        point.pass, e
+                    );
+                    0
+                }
+            };
+            if counter >= point.value || submitted.elapsed() < timeout {
+                continue;
+            }
+
+            error!(
+                "{:?} submission {} has not completed in {:?}, last pipeline state: {}",
+                point.pass,
+                point.value,
+                submitted.elapsed(),
+                state
+            );
+            // vkDeviceWaitIdle has no timeout and needs every queue to be
+            // externally synchronized, so wait for the submission instead
+            let semaphores = [point.semaphore];
+            let values = [point.value];
+            let wait_info = vk::SemaphoreWaitInfo::default()
+                .semaphores(&semaphores)
+                .values(&values);
+            let recovered =
+                unsafe { device.wait_semaphores(&wait_info, timeout.as_nanos() as u64) }.is_ok();
+            if recovered {
+                warn!(
+                    "{:?} submission {} completed after {:?}",
+                    point.pass,
+                    point.value,
+                    submitted.elapsed()
+                );
+            } else {
+                error!("GPU does not respond, giving up on it");
+            }
+
+            watched.lock().unwrap_or_else(PoisonError::into_inner).stall = Some(GpuStall {
+                value: point.value,
+                elapsed: submitted.elapsed(),
+                recovered,
+                state,
+            });
+        }
+        debug!("GPU watchdog stopped");
+    }
+
+    /// Starts watching the submission signalling the point. `state` is
+    /// logged if it does not complete in time.
+    pub fn submitted(&self, point: SyncPoint, state: String) {
+        self.watched
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .last = Some(Submission {
+            point,
+            submitted: Instant::now(),
+            state,
+        });
+    }
+
+    /// Last submission that has missed the timeout, if any
+    pub fn stall(&self) -> Option<GpuStall> {
+        self.watched
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stall
+            .clone()
+    }
+
+    /// Fails if a submission has never completed
+    pub fn check(&self) -> TracerResult<()> {
+        match self.stall() {
+            Some(stall) if !stall.recovered => Err(TracerError::DeviceHung(stall.value)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Blocks until the point is reached, giving up once the GPU is hung
+    pub unsafe fn wait(&self, bundle: Bundle, point: SyncPoint) -> TracerResult<()> {
+        while !point.wait_timeout(bundle, POLL_INTERVAL)? {
+            self.check()?;
+        }
+        Ok(())
+    }
+
+    /// Has to be called before the device is destroyed
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            warn!("GPU watchdog not stopped");
+            self.stop();
+        }
+    }
+}
@@ -1,14 +1,35 @@
-use glam::{Mat4, Vec3};
+use crate::camera;
+use anyhow::Context;
+use glam::{EulerRot, FloatExt, Mat4, Quat, UVec2, Vec2, Vec3};
 use serde::{Deserialize, Serialize, Serializer};
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::rc::Rc;
+use std::str::FromStr;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// Upper bound of `frames_in_flight`, more only adds latency
+pub const MAX_FRAMES_IN_FLIGHT: u32 = 4;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 #[repr(C)]
 pub struct Camera {
     pub position: Vec3,
     pub direction: Vec3,
+    // Vertical, in radians. Ignored if the focal length is set
     pub fov: f32,
+    // Thin lens diameter, 0 for a pinhole camera without depth of field
+    pub aperture: f32,
+    // Distance to the plane in focus along the view direction
+    pub focus_distance: f32,
+    // In millimeters, derives the field of view from the sensor height
+    pub focal_length: Option<f32>,
+    // Width and height in millimeters
+    pub sensor_size: Vec2,
+    // Width over height of the rendered image, the window aspect if unset.
+    // The image is letterboxed inside the window.
+    pub aspect_ratio: Option<f32>,
 }
 
 impl Default for Camera {
@@ -17,11 +38,43 @@ impl Default for Camera {
             position: Vec3::ZERO,
             direction: Vec3::new(0.0, 0.0, -1.0),
             fov: std::f32::consts::FRAC_PI_2,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            focal_length: None,
+            sensor_size: camera::FULL_FRAME_SENSOR,
+            aspect_ratio: None,
         }
     }
 }
 
 impl Camera {
+    /// Vertical field of view in radians
+    pub fn vertical_fov(&self) -> f32 {
+        match self.focal_length {
+            Some(focal_length) => camera::fov_from_focal_length(focal_length, self.sensor_size.y),
+            None => self.fov,
+        }
+    }
+
+    /// Focal length in millimeters, derived from the field of view if not set
+    pub fn effective_focal_length(&self) -> f32 {
+        self.focal_length
+            .unwrap_or_else(|| camera::focal_length_from_fov(self.fov, self.sensor_size.y))
+    }
+
+    // The lens and the sensor are not splined, the focal length is
+    // interpolated only between keyframes both having it set
+    fn lens_lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            focal_length: self
+                .focal_length
+                .zip(other.focal_length)
+                .map(|(a, b)| a.lerp(b, t))
+                .or(self.focal_length),
+            ..self.clone()
+        }
+    }
+
     pub fn as_transform(&self) -> Mat4 {
         let forward = self.direction.normalize();
         let right = forward.cross(Vec3::Y).normalize();
@@ -36,109 +89,1003 @@ impl Camera {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Material {
     pub albedo: Vec3,
     pub emission_color: Vec3,
     pub emission_strength: f32,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Object {
     Sphere {
         center: Vec3,
         radius: f32,
-        material: Material,
+        // Name in the materials table
+        material: String,
+        // Name of the scene graph node the object moves with
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        node: Option<String>,
+        #[serde(default, skip_serializing_if = "ObjectTextures::is_empty")]
+        textures: ObjectTextures,
+        // Left out of the upload, see `TracerConfigInner::visible_objects`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        hidden: bool,
+        // Once any object is soloed, only the soloed ones are uploaded
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        solo: bool,
+    },
+    // Procedural surface, sphere traced in the shader
+    Sdf {
+        center: Vec3,
+        shape: SdfShape,
+        material: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        node: Option<String>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        hidden: bool,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        solo: bool,
     },
 }
 
 impl Object {
-    pub fn as_material_mut(&mut self) -> &mut Material {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Object::Sphere { .. } => "Sphere",
+            Object::Sdf { .. } => "SDF",
+        }
+    }
+
+    pub fn material(&self) -> &str {
+        match self {
+            Object::Sphere { material, .. } | Object::Sdf { material, .. } => material,
+        }
+    }
+
+    pub fn node(&self) -> Option<&str> {
+        match self {
+            Object::Sphere { node, .. } | Object::Sdf { node, .. } => node.as_deref(),
+        }
+    }
+
+    pub fn textures(&self) -> Option<&ObjectTextures> {
+        match self {
+            Object::Sphere { textures, .. } => Some(textures),
+            Object::Sdf { .. } => None,
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        match self {
+            Object::Sphere { hidden, .. } | Object::Sdf { hidden, .. } => *hidden,
+        }
+    }
+
+    pub fn set_hidden(&mut self, value: bool) {
+        match self {
+            Object::Sphere { hidden, .. } | Object::Sdf { hidden, .. } => *hidden = value,
+        }
+    }
+
+    pub fn is_solo(&self) -> bool {
+        match self {
+            Object::Sphere { solo, .. } | Object::Sdf { solo, .. } => *solo,
+        }
+    }
+
+    pub fn set_solo(&mut self, value: bool) {
+        match self {
+            Object::Sphere { solo, .. } | Object::Sdf { solo, .. } => *solo = value,
+        }
+    }
+
+    /// Center and radius of a sphere around the object, relative to its node
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        match self {
+            Object::Sphere { center, radius, .. } => (*center, *radius),
+            Object::Sdf { center, shape, .. } => (*center, shape.bounding_radius()),
+        }
+    }
+}
+
+/// Image assets mapped onto an object through its UV parametrization.
+/// Albedo is in sRGB and multiplies the material albedo, the normal map is
+/// in the tangent space and roughness is read from the red channel.
+/// `external:<name>` stands for a texture imported from another process,
+/// see `ImportedTexture`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObjectTextures {
+    pub albedo: Option<String>,
+    pub normal: Option<String>,
+    pub roughness: Option<String>,
+}
+
+impl ObjectTextures {
+    pub fn is_empty(&self) -> bool {
+        self.slots().iter().all(Option::is_none)
+    }
+
+    /// Albedo, normal and roughness, in the order the shader expects them
+    pub fn slots(&self) -> [Option<&str>; 3] {
+        [
+            self.albedo.as_deref(),
+            self.normal.as_deref(),
+            self.roughness.as_deref(),
+        ]
+    }
+}
+
+/// Prefix of the texture assets referring to imported textures
+pub const EXTERNAL_TEXTURE_PREFIX: &str = "external:";
+
+/// RGBA8 image allocated by another process, handed over as an opaque
+/// file descriptor the tracer inherited, e.g. `albedo=3:512x512`.
+/// The objects use it as the `external:<name>` texture asset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportedTexture {
+    pub name: String,
+    pub fd: i32,
+    pub dimensions: UVec2,
+}
+
+impl FromStr for ImportedTexture {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, rest) = s.split_once('=').context("Expected NAME=FD:WIDTHxHEIGHT")?;
+        let mut parts = rest.split(':');
+        let fd = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .context("Invalid file descriptor")?;
+        let (width, height) = parts
+            .next()
+            .and_then(|size| size.split_once('x'))
+            .context("Expected the size as WIDTHxHEIGHT")?;
+        let dimensions = UVec2::new(
+            width.parse().context("Invalid width")?,
+            height.parse().context("Invalid height")?,
+        );
+        if let Some(rest) = parts.next() {
+            anyhow::bail!("Unexpected imported texture suffix: {}", rest);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            fd,
+            dimensions,
+        })
+    }
+}
+
+/// Built-in signed distance functions, centered at the object center
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SdfShape {
+    // Lies in the XZ plane
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    // Triply periodic surface clipped by a sphere
+    Gyroid {
+        // Number of periods per unit
+        scale: f32,
+        thickness: f32,
+        radius: f32,
+    },
+    Mandelbulb {
+        power: f32,
+        iterations: u32,
+        // The bulb roughly fits a sphere of 1.2 radius at 1.0
+        radius: f32,
+    },
+}
+
+impl SdfShape {
+    /// Sphere around the center the surface fits in, the tracing is limited to it
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            SdfShape::Torus {
+                major_radius,
+                minor_radius,
+            } => major_radius + minor_radius,
+            SdfShape::Gyroid { radius, .. } => *radius,
+            SdfShape::Mandelbulb { radius, .. } => radius * 1.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: Vec3,
+    // Euler angles in radians, applied in XYZ order
+    pub rotation: Vec3,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn as_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            self.scale,
+            Quat::from_euler(
+                EulerRot::XYZ,
+                self.rotation.x,
+                self.rotation.y,
+                self.rotation.z,
+            ),
+            self.translation,
+        )
+    }
+}
+
+/// Copies of an object placed with their own transforms. The object is
+/// uploaded once, an instanced object is only rendered through its instances.
+/// Transforms are relative to the node of the object, if any.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Instance {
+    // Index in the objects
+    pub object: usize,
+    pub transforms: Vec<Transform>,
+}
+
+/// Node of the transform hierarchy. The transform is relative to the parent,
+/// so moving a node moves its whole subtree.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Node {
+    pub parent: Option<String>,
+    pub transform: Transform,
+}
+
+/// Homogeneous participating medium, e.g. fog or smoke
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Medium {
+    // Per unit of distance
+    pub absorption: Vec3,
+    pub scattering: Vec3,
+    // Henyey-Greenstein asymmetry in (-1, 1), positive scatters forward
+    pub anisotropy: f32,
+}
+
+impl Default for Medium {
+    fn default() -> Self {
+        Self {
+            absorption: Vec3::splat(0.01),
+            scattering: Vec3::splat(0.1),
+            anisotropy: 0.0,
+        }
+    }
+}
+
+impl Medium {
+    pub fn extinction(&self) -> Vec3 {
+        self.absorption + self.scattering
+    }
+}
+
+/// Axis aligned region filled with a medium. Overlapping volumes add up.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Volume {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub medium: Medium,
+}
+
+/// Equirectangular HDR image surrounding the scene, lights everything the
+/// rays escape to. Replaces the sky, which is not traced otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Environment {
+    // Asset id of a Radiance .hdr image, e.g. `environments/studio.hdr`
+    pub asset: String,
+    #[serde(default = "default_environment_intensity")]
+    pub intensity: f32,
+}
+
+fn default_environment_intensity() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Passes through the keyframes with a continuous velocity
+    CatmullRom,
+}
+
+/// Camera pose at a point of the animation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keyframe {
+    // In seconds from the start of the animation
+    pub time: f32,
+    pub camera: Camera,
+}
+
+/// Placement of an object at a point of the animation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectKeyframe {
+    pub time: f32,
+    pub center: Vec3,
+    // Spheres only, the radius is kept if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+}
+
+/// Keyframes of a single object. The moved object is uploaded alone.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectTrack {
+    // Index in the objects
+    pub object: usize,
+    // Sorted by time
+    pub keyframes: Vec<ObjectKeyframe>,
+}
+
+impl ObjectTrack {
+    pub fn sample(&self, time: f32) -> Option<ObjectKeyframe> {
+        let (a, b, t) = bracket(&self.keyframes, time, |keyframe| keyframe.time)?;
+        Some(ObjectKeyframe {
+            time,
+            center: a.center.lerp(b.center, t),
+            radius: a
+                .radius
+                .zip(b.radius)
+                .map(|(a, b)| a.lerp(b, t))
+                .or(a.radius),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialKeyframe {
+    pub time: f32,
+    pub material: Material,
+}
+
+/// Keyframes of a material, shared by every object referencing it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialTrack {
+    // Name in the materials table
+    pub material: String,
+    // Sorted by time
+    pub keyframes: Vec<MaterialKeyframe>,
+}
+
+impl MaterialTrack {
+    pub fn sample(&self, time: f32) -> Option<Material> {
+        let (a, b, t) = bracket(&self.keyframes, time, |keyframe| keyframe.time)?;
+        let (a, b) = (&a.material, &b.material);
+        Some(Material {
+            albedo: a.albedo.lerp(b.albedo, t),
+            emission_color: a.emission_color.lerp(b.emission_color, t),
+            emission_strength: a.emission_strength.lerp(b.emission_strength, t),
+        })
+    }
+}
+
+// Keyframes around the time and the position between them. Outside of
+// the keyframes the first or the last one is held.
+fn bracket<K>(keyframes: &[K], time: f32, key: impl Fn(&K) -> f32) -> Option<(&K, &K, f32)> {
+    let next = keyframes.partition_point(|keyframe| key(keyframe) <= time);
+    if next == 0 || next == keyframes.len() {
+        return keyframes
+            .get(next.saturating_sub(1))
+            .map(|keyframe| (keyframe, keyframe, 0.0));
+    }
+    let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+    let t = (time - key(a)) / (key(b) - key(a)).max(f32::EPSILON);
+    Some((a, b, t))
+}
+
+/// Camera path through the keyframes, along with the tracks of the objects
+/// and materials. Played in the windowed mode, rendered frame by frame with
+/// `--sequence-fps` in the headless mode.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Animation {
+    // Of the camera, the tracks are interpolated linearly
+    pub interpolation: Interpolation,
+    // Sorted by time
+    pub keyframes: Vec<Keyframe>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<ObjectTrack>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<MaterialTrack>,
+}
+
+impl Animation {
+    /// Time of the last keyframe of the camera or of any track
+    pub fn duration(&self) -> f32 {
+        let objects = self
+            .objects
+            .iter()
+            .filter_map(|track| track.keyframes.last().map(|keyframe| keyframe.time));
+        let materials = self
+            .materials
+            .iter()
+            .filter_map(|track| track.keyframes.last().map(|keyframe| keyframe.time));
+        self.keyframes
+            .last()
+            .map(|keyframe| keyframe.time)
+            .into_iter()
+            .chain(objects)
+            .chain(materials)
+            .fold(0.0, f32::max)
+    }
+
+    /// Nothing is animated, neither the camera nor any track
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+            && self.objects.iter().all(|track| track.keyframes.is_empty())
+            && self
+                .materials
+                .iter()
+                .all(|track| track.keyframes.is_empty())
+    }
+
+    /// Adds the keyframe in time order, replacing the one at the same time
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|other| other.time.total_cmp(&keyframe.time))
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    /// Camera at the time, clamped to the animation range.
+    /// None if there are no keyframes.
+    pub fn sample(&self, time: f32) -> Option<Camera> {
+        let keyframes = &self.keyframes;
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 || next == keyframes.len() {
+            return keyframes
+                .get(next.saturating_sub(1))
+                .map(|keyframe| keyframe.camera.clone());
+        }
+
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time).max(f32::EPSILON);
+        let camera = match self.interpolation {
+            Interpolation::Linear => Camera {
+                position: a.camera.position.lerp(b.camera.position, t),
+                direction: a.camera.direction.lerp(b.camera.direction, t),
+                fov: a.camera.fov.lerp(b.camera.fov, t),
+                aperture: a.camera.aperture.lerp(b.camera.aperture, t),
+                focus_distance: a.camera.focus_distance.lerp(b.camera.focus_distance, t),
+                ..a.camera.lens_lerp(&b.camera, t)
+            },
+            Interpolation::CatmullRom => {
+                // The end keyframes stand in for the missing neighbours
+                let p0 = &keyframes[next.saturating_sub(2)].camera;
+                let p3 = &keyframes[(next + 1).min(keyframes.len() - 1)].camera;
+                let (p1, p2) = (&a.camera, &b.camera);
+                Camera {
+                    position: catmull_rom(p0.position, p1.position, p2.position, p3.position, t),
+                    direction: catmull_rom(
+                        p0.direction,
+                        p1.direction,
+                        p2.direction,
+                        p3.direction,
+                        t,
+                    ),
+                    fov: catmull_rom(p0.fov, p1.fov, p2.fov, p3.fov, t),
+                    aperture: catmull_rom(p0.aperture, p1.aperture, p2.aperture, p3.aperture, t)
+                        .max(0.0),
+                    focus_distance: catmull_rom(
+                        p0.focus_distance,
+                        p1.focus_distance,
+                        p2.focus_distance,
+                        p3.focus_distance,
+                        t,
+                    ),
+                    ..p1.lens_lerp(p2, t)
+                }
+            }
+        };
+
+        Some(Camera {
+            direction: camera.direction.normalize_or(a.camera.direction),
+            ..camera
+        })
+    }
+}
+
+// Uniform Catmull-Rom spline between p1 and p2
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>,
+{
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+/// Light sampled directly with next event estimation. Lights are not
+/// geometry, the rays do not hit them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Light {
+    // Radiant intensity, falls off with the squared distance
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    // Irradiance from infinitely far away, e.g. the sun
+    Directional {
+        // Direction the light travels in
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    // Two-sided parallelogram spanned by the edges, intensity is its radiance
+    Area {
+        corner: Vec3,
+        edge_u: Vec3,
+        edge_v: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+}
+
+impl Light {
+    pub fn name(&self) -> &'static str {
         match self {
-            Object::Sphere { material, .. } => material,
+            Light::Point { .. } => "Point",
+            Light::Directional { .. } => "Directional",
+            Light::Area { .. } => "Area",
+        }
+    }
+
+    pub fn as_color_mut(&mut self) -> (&mut Vec3, &mut f32) {
+        match self {
+            Light::Point {
+                color, intensity, ..
+            }
+            | Light::Directional {
+                color, intensity, ..
+            }
+            | Light::Area {
+                color, intensity, ..
+            } => (color, intensity),
         }
     }
 }
 
+/// GPU time budget of a traced frame, the samples traced per frame are
+/// adjusted to fit it
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveSamples {
+    // In milliseconds
+    pub target_ms: f32,
+    // Bounds of the samples per traced frame, rounded to whole dispatches
+    // of `samples_count` samples
+    pub min_samples: u32,
+    pub max_samples: u32,
+}
+
+impl Default for AdaptiveSamples {
+    fn default() -> Self {
+        Self {
+            target_ms: 16.0,
+            min_samples: 1,
+            max_samples: 64,
+        }
+    }
+}
+
+/// Second configuration traced next to the main one, the windowed mode
+/// shows them side by side split at `divider`. Only the settings apply,
+/// both sides trace the same scene from the same camera.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompareConfig {
+    // `key=value` overrides of the main config, see `apply_override`
+    pub overrides: Vec<String>,
+    // Position of the split, 0 shows only the compared side and 1 only
+    // the main one
+    pub divider: f32,
+}
+
+impl Default for CompareConfig {
+    fn default() -> Self {
+        Self {
+            overrides: vec!["samples_count=8".to_string()],
+            divider: 0.5,
+        }
+    }
+}
+
+impl CompareConfig {
+    /// The main config with the overrides applied
+    pub fn apply(&self, config: &TracerConfigInner) -> anyhow::Result<TracerConfigInner> {
+        let compared = TracerConfig(Rc::new(RefCell::new(config.clone())));
+        // Blank lines are left while editing
+        for assignment in self.overrides.iter().filter(|o| !o.trim().is_empty()) {
+            compared.apply_override(assignment)?;
+        }
+        let compared = compared.0.borrow().clone();
+        Ok(compared)
+    }
+}
+
+/// Named quality level, overlaid on the loaded config. Sets the samples,
+/// the bounces, the resolution scale and the noise handling, the scene
+/// and the rest of the settings are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityPreset {
+    // Fast and noisy, for setting up the scene
+    Draft,
+    Medium,
+    // Converges slowly, for the finished images
+    Final,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 3] = [
+        QualityPreset::Draft,
+        QualityPreset::Medium,
+        QualityPreset::Final,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityPreset::Draft => "Draft",
+            QualityPreset::Medium => "Medium",
+            QualityPreset::Final => "Final",
+        }
+    }
+
+    // Samples count, max bounces, resolution scale, temporal reprojection
+    // and converged samples. The draft keeps the samples while moving and
+    // hides the noise sooner, the final image is accumulated from exact
+    // samples only.
+    fn settings(&self) -> (u32, u32, f32, bool, Option<u32>) {
+        match self {
+            QualityPreset::Draft => (1, 2, 0.5, true, None),
+            QualityPreset::Medium => (4, 5, 1.0, true, Some(256)),
+            QualityPreset::Final => (16, 12, 1.0, false, Some(4096)),
+        }
+    }
+
+    pub fn apply(&self, config: &mut TracerConfigInner) {
+        (
+            config.samples_count,
+            config.max_bounces,
+            config.resolution_scale,
+            config.temporal_reprojection,
+            config.converged_samples,
+        ) = self.settings();
+        config.updated = true;
+    }
+
+    /// Preset the config is at, None if its settings were changed since
+    pub fn matching(config: &TracerConfigInner) -> Option<Self> {
+        let current = (
+            config.samples_count,
+            config.max_bounces,
+            config.resolution_scale,
+            config.temporal_reprojection,
+            config.converged_samples,
+        );
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.settings() == current)
+    }
+}
+
+impl FromStr for QualityPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow::anyhow!("Unknown quality preset: {}", s))
+    }
+}
+
+/// Exposure of the tonemapping following the brightness of the image.
+/// A histogram of the luminance is built on the GPU every frame, the
+/// exposure moves towards the one bringing its average to middle grey.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposure {
+    // Adaptation rate per second, higher follows the changes faster and
+    // 0 keeps the first exposure
+    pub speed: f32,
+    // Range in stops the adapted exposure is clamped to
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            speed: 1.5,
+            min_exposure: -8.0,
+            max_exposure: 8.0,
+        }
+    }
+}
+
+/// Post-processing pass run over the traced image before it is presented,
+/// see `post`. The accumulated samples are left untouched, so changing
+/// the passes does not restart the accumulation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PostPass {
+    // Maps the HDR image into [0, 1] with the ACES filmic curve, after
+    // scaling it by 2^exposure. With the auto exposure the exposure is a
+    // compensation added to the adapted one.
+    Tonemap {
+        exposure: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        auto_exposure: Option<AutoExposure>,
+    },
+    // Glow around the parts of the image brighter than the threshold,
+    // blurred over about `radius` pixels and added with the intensity.
+    // Works on the HDR image, so it goes before the tonemapping.
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    // Darkens the image towards the corners, 0 leaves it as is and 1
+    // turns the corners black
+    Vignette {
+        strength: f32,
+    },
+    // Fast approximate antialiasing of the edges, meant to run on the
+    // tonemapped image
+    Fxaa,
+}
+
+impl PostPass {
+    /// Whether a bloom runs after the tonemapping, on the clamped image
+    /// with nothing above the threshold left
+    pub fn is_bloom_after_tonemap(passes: &[PostPass]) -> bool {
+        let tonemap = passes
+            .iter()
+            .position(|pass| matches!(pass, PostPass::Tonemap { .. }));
+        let bloom = passes
+            .iter()
+            .rposition(|pass| matches!(pass, PostPass::Bloom { .. }));
+        matches!((tonemap, bloom), (Some(tonemap), Some(bloom)) if bloom > tonemap)
+    }
+
+    /// One of every pass with the default settings
+    pub const ALL: [PostPass; 4] = [
+        PostPass::Tonemap {
+            exposure: 0.0,
+            auto_exposure: None,
+        },
+        PostPass::Bloom {
+            threshold: 1.0,
+            intensity: 0.1,
+            radius: 16.0,
+        },
+        PostPass::Vignette { strength: 0.3 },
+        PostPass::Fxaa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostPass::Tonemap { .. } => "Tonemap",
+            PostPass::Bloom { .. } => "Bloom",
+            PostPass::Vignette { .. } => "Vignette",
+            PostPass::Fxaa => "FXAA",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 #[allow(dead_code)]
 pub struct TracerConfigInner {
     pub camera: Camera,
+    // Shared by the objects referencing them by name
+    pub materials: BTreeMap<String, Material>,
     pub objects: Vec<Object>,
+    pub instances: Vec<Instance>,
+    // Transform hierarchy, flattened into instance transforms on upload
+    pub nodes: BTreeMap<String, Node>,
+    pub lights: Vec<Light>,
+    pub volumes: Vec<Volume>,
+    pub environment: Option<Environment>,
+    pub animation: Animation,
+    // Sample the lights directly at every bounce. Without it the lights
+    // do not contribute, only the emissive objects do.
+    pub next_event_estimation: bool,
     pub samples_count: u32,
     pub max_bounces: u32,
     pub sky_color_top: Vec3,
     pub sky_color_bottom: Vec3,
     pub ground_color: Vec3,
-    
+    // Internal resolution of the tracer relative to the viewport.
+    // The traced image is upscaled to the viewport on presentation.
+    pub resolution_scale: f32,
+    // Size of the traced image regardless of the window, which shows it
+    // letterboxed. Resizing the window then keeps the accumulated samples.
+    // None follows the window.
+    pub render_size: Option<UVec2>,
+    // Drive pixel jitter and first bounce sampling with a tiled blue noise
+    // texture instead of white noise. Looks better at low sample counts.
+    pub blue_noise: bool,
+    // Keep the accumulated samples when the camera moves by reprojecting
+    // them into the new view, instead of restarting the accumulation
+    pub temporal_reprojection: bool,
+    // Add every traced frame to the accumulated image. Without it every
+    // frame is shown on its own, noisy but reacting to changes at once.
+    pub temporal_accumulation: bool,
+    // Subpixel jitter of the camera rays, in pixels. Antialiases the
+    // accumulated image, 0 traces through the pixel centers.
+    pub jitter_strength: f32,
+    // Trace the accumulation frames on a second, lower priority compute
+    // queue, keeping the high priority one free for the frames following
+    // an update. Read once on device creation, needs a family with more
+    // than one queue.
+    pub low_latency: bool,
+    // Seconds a compute submission may take before the watchdog reports
+    // it, 0 disables the watchdog. Read once on device creation.
+    pub watchdog_timeout: f32,
+    // Frames the CPU records ahead of the GPU. 1 has the lowest latency,
+    // 3 the most throughput. Read once on window creation.
+    pub frames_in_flight: u32,
+    // Images of the swapchain, clamped to what the surface supports. None
+    // uses one more than the minimum. Read once on window creation.
+    pub swapchain_images: Option<u32>,
+    // Dispatches accumulated into every traced frame, each adding
+    // `samples_count` samples. Fast scenes converge quicker.
+    pub dispatches_per_frame: u32,
+    // Presented frames per traced frame while accumulating. Slow scenes
+    // leave the GPU to the UI in between and stay interactive. Updates
+    // are traced right away regardless.
+    pub frames_per_dispatch: u32,
+    // Adjust the dispatches per frame to the measured render time instead
+    // of using `dispatches_per_frame`, None keeps them fixed
+    pub adaptive_samples: Option<AdaptiveSamples>,
+    // Stop tracing the tiles of the image whose pixels all accumulated
+    // this many samples, the converged tiles then cost nothing. None
+    // traces every tile.
+    pub converged_samples: Option<u32>,
+    // Debug pass counting the NaN and infinite pixels of every traced
+    // frame, reported in the tracer profile
+    pub check_invalid_pixels: bool,
+    // Trace a second accumulation with other settings for an A/B view,
+    // None traces only the main one
+    pub compare: Option<CompareConfig>,
+    // Path of a Rhai script manipulating the scene every frame, relative
+    // to the working directory, see `SceneScript`
+    pub script: Option<String>,
+    // Post-processing passes run in order over the traced image, e.g.
+    // `[{"Tonemap": {"exposure": 0.5}}, "Fxaa"]`. Empty presents the
+    // traced image as is.
+    pub post: Vec<PostPass>,
+
+    // Runtime flags, not part of the config file
+    #[serde(skip)]
     pub updated: bool,
+    #[serde(skip)]
     pub objects_updated: bool,
+    // Objects edited in place (moved, resized or given another material),
+    // uploaded alone instead of the whole scene. Adding, removing or
+    // retexturing objects needs `objects_updated`, which covers these too.
+    #[serde(skip)]
+    pub objects_edited: Vec<usize>,
+    // Position in the viewport to pick, normalized to [0..1]
+    #[serde(skip)]
+    pub pick_request: Option<Vec2>,
+    #[serde(skip)]
+    pub picked: Option<PickResult>,
+    // Recreate the images in compacted memory with the next frame
+    #[serde(skip)]
+    pub defragment_request: bool,
+    // Textures from other processes to import with the next frame
+    #[serde(skip)]
+    pub import_requests: Vec<ImportedTexture>,
+    // Label the GPU passes for profilers, see `pin_for_profiling`. Read
+    // once on device creation.
+    #[serde(skip)]
+    pub profiler_friendly: bool,
+}
+
+/// Surface under the pixel requested with `pick_request`
+#[derive(Clone, Debug)]
+pub struct PickResult {
+    pub pixel: UVec2,
+    // Distance along the camera view direction, None if nothing was hit
+    pub depth: Option<f32>,
+    // Index in the objects, None if nothing was hit
+    pub object: Option<usize>,
+    // Path traced by the first sample of the pixel
+    pub path: Vec<PathVertex>,
+}
+
+/// Single bounce of a traced path
+#[derive(Clone, Debug)]
+pub struct PathVertex {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    // Ray distance to the hit, None if the ray escaped the scene
+    pub distance: Option<f32>,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    pub object: u32,
+    pub albedo: Vec3,
+    // Cosine term the throughput was scaled by
+    pub cosine: f32,
+    pub emission: Vec3,
+    // Path throughput and gathered radiance after the bounce
+    pub throughput: Vec3,
+    pub radiance: Vec3,
+}
+
+fn default_materials() -> BTreeMap<String, Material> {
+    let material = |name: &str, albedo: Vec3, emission_strength: f32| {
+        let material = Material {
+            albedo,
+            emission_color: Vec3::ONE * emission_strength.min(1.0),
+            emission_strength,
+        };
+        (name.to_string(), material)
+    };
+
+    BTreeMap::from([
+        material("light", Vec3::ZERO, 5.0),
+        material("ground", Vec3::new(0.2, 0.4, 0.4), 0.0),
+        material("blue", Vec3::new(0.1, 0.2, 0.5), 0.0),
+        material("white", Vec3::new(0.8, 0.8, 0.8), 0.0),
+        material("gold", Vec3::new(0.8, 0.6, 0.2), 0.0),
+    ])
 }
 
 #[allow(dead_code)]
 fn scene_simple() -> Vec<Object> {
+    let sphere = |center: Vec3, radius: f32, material: &str| Object::Sphere {
+        center,
+        radius,
+        material: material.to_string(),
+        node: None,
+        textures: ObjectTextures::default(),
+        hidden: false,
+        solo: false,
+    };
+
     vec![
-        Object::Sphere {
-            center: Vec3::new(-8.0, 4.5, 9.0),
-            radius: 1.0,
-            material: Material {
-                albedo: Vec3::new(0.0, 0.0, 0.0),
-                emission_color: Vec3::new(1.0, 1.0, 1.0),
-                emission_strength: 5.00,
-            },
-        },
-        Object::Sphere {
-            center: Vec3::new(16.0, 4.5, -9.0),
-            radius: 1.0,
-            material: Material {
-                albedo: Vec3::new(0.0, 0.0, 0.0),
-                emission_color: Vec3::new(1.0, 1.0, 1.0),
-                emission_strength: 5.00,
-            },
-        },
-        Object::Sphere {
-            center: Vec3::new(0.0, -100.5, -1.0),
-            radius: 100.0,
-            material: Material {
-                albedo: Vec3::new(0.2, 0.4, 0.4),
-                emission_color: Vec3::new(0.0, 0.0, 0.0),
-                emission_strength: 0.00,
-            },
-        },
-        Object::Sphere {
-            center: Vec3::new(0.0, 0.0, -1.2),
-            radius: 0.5,
-            material: Material {
-                albedo: Vec3::new(0.1, 0.2, 0.5),
-                emission_color: Vec3::new(0.0, 0.0, 0.0),
-                emission_strength: 0.00,
-            },
-        },
-        Object::Sphere {
-            center: Vec3::new(-1.0, 0.0, -1.0),
-            radius: 0.5,
-            material: Material {
-                albedo: Vec3::new(0.8, 0.8, 0.8),
-                emission_color: Vec3::new(0.0, 0.0, 0.0),
-                emission_strength: 0.00,
-            },
-        },
-        Object::Sphere {
-            center: Vec3::new(1.0, 0.0, -1.0),
-            radius: 0.5,
-            material: Material {
-                albedo: Vec3::new(0.8, 0.6, 0.2),
-                emission_color: Vec3::new(0.0, 0.0, 0.0),
-                emission_strength: 0.00,
-            },
-        },
+        sphere(Vec3::new(-8.0, 4.5, 9.0), 1.0, "light"),
+        sphere(Vec3::new(16.0, 4.5, -9.0), 1.0, "light"),
+        sphere(Vec3::new(0.0, -100.5, -1.0), 100.0, "ground"),
+        sphere(Vec3::new(0.0, 0.0, -1.2), 0.5, "blue"),
+        sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5, "white"),
+        sphere(Vec3::new(1.0, 0.0, -1.0), 0.5, "gold"),
     ]
 }
 
 #[allow(dead_code)]
 fn scene_array() -> Vec<Object> {
     let mut objects = Vec::new();
-    const ALBEDO: Vec3 = Vec3::new(0.1, 0.2, 0.5);
     const RADIUS: f32 = 0.5;
     const OFFSET: f32 = RADIUS * 2.3;
     const SIDE: usize = 8;
@@ -152,11 +1099,11 @@ fn scene_array() -> Vec<Object> {
                     0.0,
                 ),
                 radius: RADIUS,
-                material: Material {
-                    albedo: ALBEDO,
-                    emission_color: Vec3::new(0.0, 0.0, 0.0),
-                    emission_strength: 0.00,
-                },
+                material: "blue".to_string(),
+                node: None,
+                textures: ObjectTextures::default(),
+                hidden: false,
+                solo: false,
             })
         }
     }
@@ -168,19 +1115,346 @@ impl Default for TracerConfigInner {
     fn default() -> Self {
         Self {
             camera: Camera::default(),
+            materials: default_materials(),
             objects: scene_simple(),
             // objects: scene_array(),
+            instances: vec![],
+            nodes: BTreeMap::new(),
+            lights: vec![],
+            volumes: vec![],
+            environment: None,
+            animation: Animation::default(),
+            next_event_estimation: true,
             samples_count: 1,
             max_bounces: 5,
             sky_color_top: Vec3::new(1.0, 1.0, 1.0),
             sky_color_bottom: Vec3::new(0.5, 0.7, 1.0),
             ground_color: Vec3::new(0.8, 0.8, 0.0),
+            resolution_scale: 1.0,
+            render_size: None,
+            blue_noise: true,
+            temporal_reprojection: true,
+            temporal_accumulation: true,
+            jitter_strength: 1.0,
+            low_latency: false,
+            watchdog_timeout: 10.0,
+            frames_in_flight: 2,
+            swapchain_images: None,
+            dispatches_per_frame: 1,
+            frames_per_dispatch: 1,
+            adaptive_samples: None,
+            converged_samples: None,
+            check_invalid_pixels: false,
+            compare: None,
+            script: None,
+            post: vec![],
             updated: true,
             objects_updated: true,
+            objects_edited: vec![],
+            pick_request: None,
+            picked: None,
+            defragment_request: false,
+            import_requests: vec![],
+            profiler_friendly: false,
         }
     }
 }
 
+impl TracerConfigInner {
+    /// Poses the scene at the time of the animation and flags the changes
+    /// for upload. Moved objects are uploaded alone, a changed material
+    /// uploads the scene. Returns whether the camera was set.
+    pub fn animate(&mut self, time: f32) -> bool {
+        let camera = self.animation.sample(time);
+        let objects: Vec<_> = self
+            .animation
+            .objects
+            .iter()
+            .filter_map(|track| Some((track.object, track.sample(time)?)))
+            .collect();
+        let materials: Vec<_> = self
+            .animation
+            .materials
+            .iter()
+            .filter_map(|track| Some((track.material.clone(), track.sample(time)?)))
+            .collect();
+
+        for (index, keyframe) in objects {
+            let Some(object) = self.objects.get_mut(index) else {
+                continue;
+            };
+            let moved = match object {
+                Object::Sphere { center, radius, .. } => {
+                    let new_radius = keyframe.radius.unwrap_or(*radius);
+                    let moved = *center != keyframe.center || *radius != new_radius;
+                    *center = keyframe.center;
+                    *radius = new_radius;
+                    moved
+                }
+                Object::Sdf { center, .. } => {
+                    let moved = *center != keyframe.center;
+                    *center = keyframe.center;
+                    moved
+                }
+            };
+            if moved {
+                self.objects_edited.push(index);
+            }
+        }
+        for (name, material) in materials {
+            if let Some(current) = self.materials.get_mut(&name) {
+                if *current != material {
+                    *current = material;
+                    self.objects_updated = true;
+                    // The config holds the number of lights
+                    self.updated = true;
+                }
+            }
+        }
+
+        let Some(camera) = camera else {
+            return false;
+        };
+        self.camera = camera;
+        self.updated = true;
+        true
+    }
+
+    /// Turns off everything adapting the work to the measurements or the
+    /// image, so every presented frame traces the same dispatches and GPU
+    /// profiler captures can be compared
+    pub fn pin_for_profiling(&mut self) {
+        self.profiler_friendly = true;
+        self.frames_per_dispatch = 1;
+        self.adaptive_samples = None;
+        self.converged_samples = None;
+        self.check_invalid_pixels = false;
+        self.compare = None;
+        self.low_latency = false;
+    }
+
+    /// Checks that every object references an existing material and node,
+    /// every instance an existing object and that the hierarchy is a tree
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (index, object) in self.objects.iter().enumerate() {
+            if !self.materials.contains_key(object.material()) {
+                anyhow::bail!(
+                    "Object {} references unknown material {}",
+                    index,
+                    object.material()
+                );
+            }
+            if let Some(node) = object.node().filter(|node| !self.nodes.contains_key(*node)) {
+                anyhow::bail!("Object {} references unknown node {}", index, node);
+            }
+        }
+        for name in self.nodes.keys() {
+            self.node_to_world(name)?;
+        }
+        for (index, volume) in self.volumes.iter().enumerate() {
+            let medium = &volume.medium;
+            anyhow::ensure!(
+                medium.absorption.min_element() >= 0.0 && medium.scattering.min_element() >= 0.0,
+                "Volume {} has negative coefficients",
+                index
+            );
+            anyhow::ensure!(
+                medium.anisotropy.abs() < 1.0,
+                "Volume {} anisotropy must be in (-1, 1)",
+                index
+            );
+        }
+        anyhow::ensure!(
+            self.animation
+                .keyframes
+                .windows(2)
+                .all(|pair| pair[0].time < pair[1].time),
+            "Animation keyframes must be sorted by time"
+        );
+        for track in &self.animation.objects {
+            anyhow::ensure!(
+                track.object < self.objects.len(),
+                "Animation track references unknown object {}",
+                track.object
+            );
+            anyhow::ensure!(
+                track
+                    .keyframes
+                    .windows(2)
+                    .all(|pair| pair[0].time < pair[1].time),
+                "Keyframes of object {} must be sorted by time",
+                track.object
+            );
+        }
+        for track in &self.animation.materials {
+            anyhow::ensure!(
+                self.materials.contains_key(&track.material),
+                "Animation track references unknown material {}",
+                track.material
+            );
+            anyhow::ensure!(
+                track
+                    .keyframes
+                    .windows(2)
+                    .all(|pair| pair[0].time < pair[1].time),
+                "Keyframes of material {} must be sorted by time",
+                track.material
+            );
+        }
+        for pass in &self.post {
+            match pass {
+                PostPass::Vignette { strength } => anyhow::ensure!(
+                    (0.0..=1.0).contains(strength),
+                    "Vignette strength must be in [0, 1]"
+                ),
+                PostPass::Bloom {
+                    threshold,
+                    intensity,
+                    radius,
+                } => anyhow::ensure!(
+                    *threshold >= 0.0 && *intensity >= 0.0 && *radius >= 0.0,
+                    "Bloom threshold, intensity and radius must not be negative"
+                ),
+                PostPass::Tonemap {
+                    auto_exposure: Some(auto_exposure),
+                    ..
+                } => anyhow::ensure!(
+                    auto_exposure.speed >= 0.0
+                        && auto_exposure.min_exposure <= auto_exposure.max_exposure,
+                    "Auto exposure speed must not be negative and its range not empty"
+                ),
+                PostPass::Tonemap { .. } | PostPass::Fxaa => {}
+            }
+        }
+        anyhow::ensure!(
+            (1..=MAX_FRAMES_IN_FLIGHT).contains(&self.frames_in_flight),
+            "Frames in flight must be in [1, {}]",
+            MAX_FRAMES_IN_FLIGHT
+        );
+        for (index, instance) in self.instances.iter().enumerate() {
+            if instance.object >= self.objects.len() {
+                anyhow::bail!(
+                    "Instance {} references unknown object {}",
+                    index,
+                    instance.object
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// World transform of the node, composed of the transforms of its ancestors
+    pub fn node_to_world(&self, name: &str) -> anyhow::Result<Mat4> {
+        let mut transform = Mat4::IDENTITY;
+        let mut current = Some(name);
+        let mut depth = 0;
+        while let Some(name) = current {
+            let node = self
+                .nodes
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown node {}", name))?;
+            depth += 1;
+            anyhow::ensure!(
+                depth <= self.nodes.len(),
+                "Node hierarchy has a cycle through {}",
+                name
+            );
+
+            transform = node.transform.as_matrix() * transform;
+            current = node.parent.as_deref();
+        }
+        Ok(transform)
+    }
+
+    /// Bounding sphere of the object in the world, placed by its node
+    pub fn object_bounds(&self, index: usize) -> (Vec3, f32) {
+        let object = &self.objects[index];
+        let (center, radius) = object.bounding_sphere();
+        let transform = object
+            .node()
+            .and_then(|node| self.node_to_world(node).ok())
+            .unwrap_or(Mat4::IDENTITY);
+        Self::place_sphere(&transform, center, radius)
+    }
+
+    /// Bounding sphere moved by the transform. The radius grows with the
+    /// largest scale, so the sphere still bounds an unevenly scaled object.
+    pub fn place_sphere(transform: &Mat4, center: Vec3, radius: f32) -> (Vec3, f32) {
+        let (scale, _, _) = transform.to_scale_rotation_translation();
+        (
+            transform.transform_point3(center),
+            radius * scale.abs().max_element(),
+        )
+    }
+
+    /// Flattens the instances and the objects attached to nodes into
+    /// world transforms, as pairs of the object index and its transform
+    pub fn instance_transforms(&self) -> Vec<(usize, Mat4)> {
+        let object_to_world = |object: usize| {
+            self.objects[object]
+                .node()
+                .and_then(|node| self.node_to_world(node).ok())
+                .unwrap_or(Mat4::IDENTITY)
+        };
+
+        let instances = self.instances.iter().flat_map(|instance| {
+            let parent = object_to_world(instance.object);
+            instance
+                .transforms
+                .iter()
+                .map(move |transform| (instance.object, parent * transform.as_matrix()))
+        });
+        let attached = self
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(index, object)| {
+                object.node().is_some()
+                    && !self
+                        .instances
+                        .iter()
+                        .any(|instance| instance.object == *index)
+            })
+            .map(|(index, _)| (index, object_to_world(index)));
+
+        instances.chain(attached).collect()
+    }
+
+    /// Whether the object is rendered only through instance transforms
+    /// Indices of the objects uploaded to the GPU, in the order of their
+    /// slots. The hidden objects are left out, or all but the soloed ones
+    /// if any is soloed, so the shader never sees them.
+    pub fn visible_objects(&self) -> Vec<usize> {
+        let soloing = self.objects.iter().any(Object::is_solo);
+        (0..self.objects.len())
+            .filter(|index| {
+                let object = &self.objects[*index];
+                if soloing {
+                    object.is_solo()
+                } else {
+                    !object.is_hidden()
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_instanced(&self, object: usize) -> bool {
+        self.objects[object].node().is_some()
+            || self
+                .instances
+                .iter()
+                .any(|instance| instance.object == object)
+    }
+
+    /// Index of the material in the materials SSBO
+    pub fn material_index(&self, name: &str) -> Option<u32> {
+        self.materials
+            .keys()
+            .position(|key| key == name)
+            .map(|index| index as u32)
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct TracerConfig(pub Rc<RefCell<TracerConfigInner>>);
@@ -216,4 +1490,115 @@ impl Clone for TracerConfig {
     }
 }
 
-impl TracerConfig {}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("json") => Ok(Self::Json),
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml") | Some("yml") => Ok(Self::Yaml),
+            _ => anyhow::bail!(
+                "Unknown config format of {}, expected .json, .toml or .yaml",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl TracerConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let format = ConfigFormat::from_path(path)?;
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let config: TracerConfig = match format {
+            ConfigFormat::Json => serde_json::from_str(&content)?,
+            ConfigFormat::Toml => toml::from_str(&content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(&content)?,
+        };
+        config
+            .0
+            .borrow()
+            .validate()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Hash of the serialized config, tells which config an output was
+    /// traced with. FNV-1a, so unlike `DefaultHasher` it is the same
+    /// across builds.
+    pub fn content_hash(&self) -> anyhow::Result<u64> {
+        let content = serde_json::to_vec(self)?;
+        Ok(content.iter().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        }))
+    }
+
+    pub fn dump(&self, path: &Path) -> anyhow::Result<()> {
+        let content = match ConfigFormat::from_path(path)? {
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(self)?,
+        };
+
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write config file {}", path.display()))
+    }
+
+    /// Applies a `key=value` override, where key is a dot-separated path
+    /// (e.g. `camera.fov` or `objects.0.Sphere.radius`) and value is parsed
+    /// as JSON, falling back to a plain string.
+    pub fn apply_override(&self, assignment: &str) -> anyhow::Result<()> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Override {} is not in key=value form", assignment))?;
+        let value = serde_json::from_str(value.trim())
+            .unwrap_or_else(|_| serde_json::Value::String(value.trim().to_string()));
+        self.set_value(key, value)
+    }
+
+    /// Replaces the value at the dot-separated `key`, see `apply_override`
+    pub fn set_value(&self, key: &str, value: serde_json::Value) -> anyhow::Result<()> {
+        let mut root = serde_json::to_value(&*self.0.borrow())?;
+        let mut node = &mut root;
+        for part in key.trim().split('.') {
+            node = match node {
+                serde_json::Value::Object(map) => map.get_mut(part),
+                serde_json::Value::Array(array) => part
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| array.get_mut(index)),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow::anyhow!("Unknown config key {}", key))?;
+        }
+        *node = value;
+
+        let inner: TracerConfigInner = serde_json::from_value(root)
+            .with_context(|| format!("Invalid value for config key {}", key))?;
+        inner.validate()?;
+        self.replace(inner);
+        Ok(())
+    }
+
+    /// Swaps in new settings, keeping the runtime state and marking
+    /// everything as updated so the tracer picks the change up
+    pub fn replace(&self, mut inner: TracerConfigInner) {
+        let mut current = self.0.borrow_mut();
+        inner.updated = true;
+        inner.objects_updated = true;
+        inner.pick_request = current.pick_request.take();
+        inner.picked = current.picked.take();
+        *current = inner;
+    }
+}
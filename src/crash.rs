@@ -0,0 +1,198 @@
+use crate::config::TracerConfigInner;
+use crate::get_build_info;
+use crate::settings::config_dir;
+use crate::tracer::{DeviceInfo, TracerProfile};
+use ash::vk;
+use build_info::VersionControl;
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+// Last log lines kept for the report
+const LOG_TAIL: usize = 2000;
+// The profile is copied at most this often, it changes little between frames
+const PROFILE_INTERVAL: Duration = Duration::from_secs(1);
+// Under the config directory, or the current one if there is none
+const REPORTS_DIR: &str = "crash-reports";
+
+struct ReportedDevice {
+    info: DeviceInfo,
+    properties: vk::PhysicalDeviceProperties,
+    extensions: Vec<String>,
+}
+
+/// What the report is made of, collected while running since little of it
+/// can be queried once the error happened
+struct ReportState {
+    log: VecDeque<String>,
+    // Serialized as started, after the overrides
+    config: Option<String>,
+    instance_extensions: Vec<String>,
+    instance_layers: Vec<String>,
+    // Every device created, more than one when splitting the frame
+    devices: Vec<ReportedDevice>,
+    profile: Option<(Instant, TracerProfile)>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static STATE: Mutex<ReportState> = Mutex::new(ReportState {
+    log: VecDeque::new(),
+    config: None,
+    instance_extensions: vec![],
+    instance_layers: vec![],
+    devices: vec![],
+    profile: None,
+});
+
+// A panic while holding the lock must not take the report down with it
+fn state() -> MutexGuard<'static, ReportState> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Output of the logger, keeps the formatted line. Nothing called here
+/// may log, the state is locked.
+pub fn push_log_line(record: &log::Record) {
+    let mut state = state();
+    if state.log.len() == LOG_TAIL {
+        state.log.pop_front();
+    }
+    state.log.push_back(record.args().to_string());
+}
+
+pub fn record_config(config: &TracerConfigInner) {
+    let config = serde_json::to_string_pretty(config)
+        .unwrap_or_else(|e| format!("Failed to serialize the config: {}", e));
+    state().config = Some(config);
+}
+
+/// Names of the extensions or layers passed to Vulkan
+pub unsafe fn names(pointers: &[*const c_char]) -> Vec<String> {
+    pointers
+        .iter()
+        .map(|name| CStr::from_ptr(*name).to_string_lossy().into_owned())
+        .collect()
+}
+
+pub fn record_instance(extensions: Vec<String>, layers: Vec<String>) {
+    let mut state = state();
+    state.instance_extensions = extensions;
+    state.instance_layers = layers;
+}
+
+pub fn record_device(
+    info: DeviceInfo,
+    properties: vk::PhysicalDeviceProperties,
+    extensions: Vec<String>,
+) {
+    state().devices.push(ReportedDevice {
+        info,
+        properties,
+        extensions,
+    });
+}
+
+/// Keeps the profile of the last traced frames, the callback is only
+/// called once the previous copy is old enough
+pub fn record_profile(profile: impl FnOnce() -> TracerProfile) {
+    let due = state()
+        .profile
+        .as_ref()
+        .is_none_or(|(recorded, _)| recorded.elapsed() >= PROFILE_INTERVAL);
+    if due {
+        // Outside of the lock, reading the profile may log
+        let profile = profile();
+        state().profile = Some((Instant::now(), profile));
+    }
+}
+
+fn build_report() -> String {
+    let bi = get_build_info();
+    let mut text = format!(
+        "Version: {}\nFeatures: {:?}\nProfile: {}\nTarget: {}\nCompiler: {}\n",
+        bi.crate_info.version, bi.crate_info.enabled_features, bi.profile, bi.target, bi.compiler
+    );
+    if let Some(VersionControl::Git(git)) = &bi.version_control {
+        text += &format!("Commit: {} (dirty: {})\n", git.commit_id, git.dirty);
+    }
+    text
+}
+
+fn device_report(state: &ReportState) -> String {
+    let mut text = format!(
+        "Enabled instance extensions: {:?}\nEnabled instance layers: {:?}\n",
+        state.instance_extensions, state.instance_layers
+    );
+    if state.devices.is_empty() {
+        text += "\nNo device was created\n";
+    }
+    for (index, device) in state.devices.iter().enumerate() {
+        text += &format!(
+            "\nDevice {}: {:#?}\nEnabled device extensions: {:?}\nProperties: {:#?}\n",
+            index, device.info, device.extensions, device.properties
+        );
+    }
+    text
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Writes the report of the fatal error unless disabled and prints where
+/// to. Printed even if the error came before the logging was set up.
+pub fn report(error: &anyhow::Error) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    match write_report(error) {
+        Ok(path) => eprintln!(
+            "Wrote diagnostics report to {}, please attach it when reporting the issue",
+            path.display()
+        ),
+        Err(e) => eprintln!("Failed to write diagnostics report: {}", e),
+    }
+}
+
+/// Writes the diagnostics of the fatal error into a new directory named
+/// after the current time and returns its path. The directory holds the
+/// error with the build, the tail of the log, the effective config, the
+/// devices with the enabled extensions and layers, and the last profile.
+fn write_report(error: &anyhow::Error) -> std::io::Result<PathBuf> {
+    let time = chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let dir = config_dir()
+        .unwrap_or_default()
+        .join(REPORTS_DIR)
+        .join(time);
+    std::fs::create_dir_all(&dir)?;
+
+    let files = {
+        let state = state();
+        let log: Vec<_> = state.log.iter().map(String::as_str).collect();
+        [
+            ("error.txt", format!("{:?}\n\n{}", error, build_report())),
+            ("log.txt", log.join("\n")),
+            (
+                "config.json",
+                state
+                    .config
+                    .clone()
+                    .unwrap_or_else(|| "No config was loaded".to_string()),
+            ),
+            ("device.txt", device_report(&state)),
+            (
+                "profile.txt",
+                match &state.profile {
+                    Some((_, profile)) => format!("{:#?}", profile),
+                    None => "No frame was traced".to_string(),
+                },
+            ),
+        ]
+    };
+    for (name, contents) in files {
+        std::fs::write(dir.join(name), contents)?;
+    }
+    Ok(dir)
+}
@@ -0,0 +1,200 @@
+use crate::config::{Object, TracerConfigInner};
+use crate::generate::{generate_scene, Random};
+use glam::Vec3;
+use log::info;
+use std::time::Instant;
+
+// Number of spheres of the demo scene and the seed of their arrangement
+pub const DEMO_SPHERES: usize = 120;
+pub const DEMO_SEED: u64 = 7;
+
+const GRAVITY: Vec3 = Vec3::new(0.0, -9.81, 0.0);
+// Fraction of the speed kept by a bounce, 1 would bounce forever
+const RESTITUTION: f32 = 0.8;
+// Simulated in fixed steps, so the motion does not depend on the frame rate
+const STEP: f32 = 1.0 / 120.0;
+// Longest frame simulated, e.g. after the window was dragged
+const MAX_FRAME: f32 = 0.1;
+// Bodies slower than this on the ground or on another sphere come to rest
+// and are no longer uploaded, so the image converges once all settled.
+// Above the speed gained from falling for a single step.
+const REST_SPEED: f32 = 0.2;
+// Larger spheres, e.g. the ground, stay in place
+const MAX_BODY_RADIUS: f32 = 10.0;
+// Half of the side of the square the spheres are kept in
+const ARENA: f32 = 12.0;
+// Heights the spheres of the demo scene are dropped from
+const DROP_HEIGHT: (f32, f32) = (3.0, 12.0);
+
+/// The generated cover scene with its spheres lifted into the air, see
+/// `generate_scene`. Played with `FallingSpheres`.
+pub fn demo_scene(spheres: usize, seed: u64) -> TracerConfigInner {
+    let mut config = generate_scene(spheres, seed);
+    let mut random = Random(seed);
+    for object in &mut config.objects {
+        if let Object::Sphere { center, radius, .. } = object {
+            if *radius <= MAX_BODY_RADIUS {
+                center.y += random.range(DROP_HEIGHT.0, DROP_HEIGHT.1);
+            }
+        }
+    }
+    config
+}
+
+struct Body {
+    // Index in the objects
+    object: usize,
+    velocity: Vec3,
+    resting: bool,
+}
+
+/// Falling spheres demo. Every sphere of the config small enough falls
+/// onto the ground at y = 0 and bounces off it and off the other spheres,
+/// simulated on the CPU every frame. The moved spheres are uploaded alone,
+/// which stress-tests the in-place object updates.
+pub struct FallingSpheres {
+    bodies: Vec<Body>,
+    last: Option<Instant>,
+    // Frame time not simulated yet, less than a step
+    remainder: f32,
+}
+
+impl FallingSpheres {
+    pub fn new(config: &TracerConfigInner) -> Self {
+        let bodies: Vec<_> = config
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| match object {
+                Object::Sphere { radius, node, .. } => node.is_none() && *radius <= MAX_BODY_RADIUS,
+                Object::Sdf { .. } => false,
+            })
+            .map(|(object, _)| Body {
+                object,
+                velocity: Vec3::ZERO,
+                resting: false,
+            })
+            .collect();
+        info!("Simulating {} falling spheres", bodies.len());
+        Self {
+            bodies,
+            last: None,
+            remainder: 0.0,
+        }
+    }
+
+    /// Advances the simulation by the time since the last call and flags
+    /// the moved spheres for upload
+    pub fn update(&mut self, config: &mut TracerConfigInner) {
+        let now = Instant::now();
+        let elapsed = self
+            .last
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_FRAME));
+        self.last = Some(now);
+
+        self.remainder += elapsed;
+        let mut moved = vec![false; self.bodies.len()];
+        while self.remainder >= STEP {
+            self.remainder -= STEP;
+            self.step(config, &mut moved);
+        }
+        for (body, moved) in self.bodies.iter().zip(moved) {
+            if moved {
+                config.objects_edited.push(body.object);
+            }
+        }
+    }
+
+    fn step(&mut self, config: &mut TracerConfigInner, moved: &mut [bool]) {
+        // Positions and radii, written back once the step is done
+        let mut spheres: Vec<(Vec3, f32)> = self
+            .bodies
+            .iter()
+            .map(|body| match &config.objects[body.object] {
+                Object::Sphere { center, radius, .. } => (*center, *radius),
+                Object::Sdf { .. } => unreachable!("Only spheres are simulated"),
+            })
+            .collect();
+
+        // On the ground or on another sphere
+        let mut supported = vec![false; self.bodies.len()];
+        for ((body, (center, radius)), supported) in
+            self.bodies.iter_mut().zip(&mut spheres).zip(&mut supported)
+        {
+            if body.resting {
+                continue;
+            }
+            body.velocity += GRAVITY * STEP;
+            *center += body.velocity * STEP;
+
+            if center.y < *radius {
+                center.y = *radius;
+                body.velocity.y = -body.velocity.y * RESTITUTION;
+                body.velocity.x *= RESTITUTION;
+                body.velocity.z *= RESTITUTION;
+                *supported = true;
+            }
+            for axis in [0, 2] {
+                let limit = ARENA - *radius;
+                if center[axis].abs() > limit {
+                    center[axis] = center[axis].clamp(-limit, limit);
+                    body.velocity[axis] = -body.velocity[axis] * RESTITUTION;
+                }
+            }
+        }
+
+        // Elastic collisions, the mass grows with the volume
+        for i in 0..self.bodies.len() {
+            for j in i + 1..self.bodies.len() {
+                let ((a, ra), (b, rb)) = (spheres[i], spheres[j]);
+                let offset = b - a;
+                let distance = offset.length();
+                if distance >= ra + rb || distance <= f32::EPSILON {
+                    continue;
+                }
+                let normal = offset / distance;
+                let (ma, mb) = (ra.powi(3), rb.powi(3));
+                let (wa, wb) = (mb / (ma + mb), ma / (ma + mb));
+
+                // Pushed apart, the lighter sphere moves more
+                let overlap = ra + rb - distance;
+                spheres[i].0 -= normal * overlap * wa;
+                spheres[j].0 += normal * overlap * wb;
+
+                let approach = (self.bodies[i].velocity - self.bodies[j].velocity).dot(normal);
+                if approach > 0.0 {
+                    let impulse = (1.0 + RESTITUTION) * approach;
+                    self.bodies[i].velocity -= normal * impulse * wa;
+                    self.bodies[j].velocity += normal * impulse * wb;
+                }
+                // Resting spheres are woken by a hit, not by a touch
+                if approach > REST_SPEED {
+                    self.bodies[i].resting = false;
+                    self.bodies[j].resting = false;
+                }
+                // The upper sphere lies on the lower one
+                if normal.y > 0.5 {
+                    supported[j] = true;
+                } else if normal.y < -0.5 {
+                    supported[i] = true;
+                }
+            }
+        }
+
+        for (body, supported) in self.bodies.iter_mut().zip(supported) {
+            if supported && body.velocity.length() < REST_SPEED {
+                body.resting = true;
+                body.velocity = Vec3::ZERO;
+            }
+        }
+
+        for ((body, (position, _)), moved) in self.bodies.iter().zip(spheres).zip(moved) {
+            if let Object::Sphere { center, .. } = &mut config.objects[body.object] {
+                if *center != position {
+                    *center = position;
+                    *moved = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::back::Back;
+use crate::common::capabilities::DeviceCapabilities;
+use crate::error::{Context, TracerResult};
+use crate::front::headless::TracerHeadlessFront;
+use crate::tracer::{DeviceInfo, Tracer};
+use ash::{vk, Entry, Instance};
+use build_info::BuildInfo;
+use std::ffi::CStr;
+use std::fmt::Write;
+
+// Formats of the tracer images, written from the compute shader
+const STORAGE_FORMATS: [(&str, vk::Format); 3] = [
+    ("color", vk::Format::R32G32B32A32_SFLOAT),
+    ("depth", vk::Format::R32_SFLOAT),
+    ("object id", vk::Format::R32_UINT),
+];
+
+/// Creates just the Vulkan instance and prints what every physical device
+/// offers to pathrs, including why a device would not be picked
+pub unsafe fn print_device_info(bi: BuildInfo) -> TracerResult<()> {
+    let entry = Entry::load()?;
+    let (instance, _) = Tracer::<TracerHeadlessFront>::new_instance(&entry, bi)?;
+    let report = device_report(&entry, &instance);
+    instance.destroy_instance(None);
+
+    print!("{}", report?);
+    Ok(())
+}
+
+unsafe fn device_report(entry: &Entry, instance: &Instance) -> TracerResult<String> {
+    let devices = instance
+        .enumerate_physical_devices()
+        .context("Failed to enumerate physical devices")?;
+
+    let mut report = String::new();
+    writeln!(report, "{} physical device(s)", devices.len()).unwrap();
+    for (index, device) in devices.into_iter().enumerate() {
+        writeln!(report).unwrap();
+        write_device(&mut report, entry, instance, index, device)?;
+    }
+
+    Ok(report)
+}
+
+unsafe fn write_device(
+    report: &mut String,
+    entry: &Entry,
+    instance: &Instance,
+    index: usize,
+    device: vk::PhysicalDevice,
+) -> TracerResult<()> {
+    let info = DeviceInfo::query(instance, device);
+    let properties = instance.get_physical_device_properties(device);
+    let limits = &properties.limits;
+    writeln!(report, "Device {}: {}", index, info.name).unwrap();
+    writeln!(report, "  Type: {:?}", properties.device_type).unwrap();
+    writeln!(
+        report,
+        "  Vendor: {:#06x}, device: {:#06x}",
+        info.vendor_id, info.device_id
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "  Driver: {} ({}), API {}",
+        info.driver_name, info.driver_info, info.api_version
+    )
+    .unwrap();
+
+    let extensions: Vec<String> = instance
+        .enumerate_device_extension_properties(device)
+        .context("Failed to enumerate device extension properties")?
+        .iter()
+        .map(|ext| {
+            CStr::from_ptr(ext.extension_name.as_ptr())
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let missing: Vec<String> =
+        Back::get_required_device_extensions(&extensions, &mut DeviceCapabilities::default())?
+            .into_iter()
+            .map(|name| CStr::from_ptr(name).to_string_lossy().into_owned())
+            .filter(|name| !extensions.contains(name))
+            .collect();
+    let queues = Back::find_queue_families(entry, instance, device, false);
+    match (&queues, missing.is_empty()) {
+        (Ok(_), true) => writeln!(report, "  Suitable: yes").unwrap(),
+        (Ok(_), false) => {
+            writeln!(report, "  Suitable: no, missing {}", missing.join(", ")).unwrap()
+        }
+        (Err(e), _) => writeln!(report, "  Suitable: no, {}", e).unwrap(),
+    }
+
+    writeln!(report, "  Queue families:").unwrap();
+    let families = instance.get_physical_device_queue_family_properties(device);
+    for (i, family) in families.iter().enumerate() {
+        writeln!(
+            report,
+            "    {}: {:?}, {} queue(s), {} timestamp bits",
+            i, family.queue_flags, family.queue_count, family.timestamp_valid_bits
+        )
+        .unwrap();
+    }
+
+    let mut features = vk::PhysicalDeviceVulkan12Features::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut features);
+    instance.get_physical_device_features2(device, &mut features2);
+    writeln!(report, "  Features:").unwrap();
+    for (name, supported) in [
+        ("bufferDeviceAddress", features.buffer_device_address),
+        ("hostQueryReset", features.host_query_reset),
+        ("timelineSemaphore", features.timeline_semaphore),
+        ("runtimeDescriptorArray", features.runtime_descriptor_array),
+        (
+            "shaderSampledImageArrayNonUniformIndexing",
+            features.shader_sampled_image_array_non_uniform_indexing,
+        ),
+        (
+            "descriptorBindingPartiallyBound",
+            features.descriptor_binding_partially_bound,
+        ),
+        (
+            "descriptorBindingVariableDescriptorCount",
+            features.descriptor_binding_variable_descriptor_count,
+        ),
+    ] {
+        writeln!(report, "    {}: {}", name, supported == vk::TRUE).unwrap();
+    }
+
+    writeln!(report, "  Limits:").unwrap();
+    writeln!(
+        report,
+        "    maxComputeWorkGroupSize: {:?}",
+        limits.max_compute_work_group_size
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxComputeWorkGroupInvocations: {}",
+        limits.max_compute_work_group_invocations
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxComputeWorkGroupCount: {:?}",
+        limits.max_compute_work_group_count
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxImageDimension2D: {}",
+        limits.max_image_dimension2_d
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxPushConstantsSize: {}",
+        limits.max_push_constants_size
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxStorageBufferRange: {}",
+        limits.max_storage_buffer_range
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    maxPerStageDescriptorSamplers: {}",
+        limits.max_per_stage_descriptor_samplers
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "    timestampComputeAndGraphics: {}, timestampPeriod: {} ns",
+        limits.timestamp_compute_and_graphics == vk::TRUE,
+        limits.timestamp_period
+    )
+    .unwrap();
+
+    writeln!(report, "  Storage image formats:").unwrap();
+    for (name, format) in STORAGE_FORMATS {
+        let features = instance
+            .get_physical_device_format_properties(device, format)
+            .optimal_tiling_features;
+        writeln!(
+            report,
+            "    {:?} ({}): {}",
+            format,
+            name,
+            features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+        )
+        .unwrap();
+    }
+
+    let memory = instance.get_physical_device_memory_properties(device);
+    writeln!(report, "  Memory heaps:").unwrap();
+    for (i, heap) in memory.memory_heaps_as_slice().iter().enumerate() {
+        writeln!(
+            report,
+            "    {}: {} MiB, {:?}",
+            i,
+            heap.size / (1024 * 1024),
+            heap.flags
+        )
+        .unwrap();
+    }
+
+    let mut sorted = extensions;
+    sorted.sort();
+    writeln!(report, "  Extensions ({}):", sorted.len()).unwrap();
+    for extension in sorted {
+        writeln!(report, "    {}", extension).unwrap();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use ash::vk;
+
+pub type TracerResult<T> = Result<T, TracerError>;
+
+/// Failures of the tracer core. Only the binary turns them into `anyhow`
+/// errors, so everything in between can match on what actually went wrong,
+/// see `root_cause`.
+#[derive(Debug, thiserror::Error)]
+pub enum TracerError {
+    #[error("Failed to load the Vulkan library")]
+    Loading(#[from] ash::LoadingError),
+    #[error("Failed to create Vulkan instance")]
+    InstanceCreation(#[source] vk::Result),
+    #[error("No suitable physical device found")]
+    NoSuitableDevice,
+    #[error("No {0} queue family found")]
+    NoQueueFamily(&'static str),
+    #[error("Swapchain surface is lost")]
+    SwapchainLost,
+    #[error("Failed to create shader module")]
+    ShaderCompile(#[source] vk::Result),
+    #[error("Could not find assets directory in current or parent directories")]
+    NoAssetsDirectory,
+    #[error("Asset not found: {0}")]
+    AssetMissing(String),
+    #[error("Asset {0} is not {1}")]
+    AssetType(String, &'static str),
+    #[error("Unknown shader: {0}")]
+    UnknownShader(String),
+    #[error("Worker loading asset {0} exited")]
+    AssetWorker(String),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+    #[error("Frame data does not match its dimensions")]
+    FrameSize,
+    #[error("GPU stopped responding, submission {0} never completed")]
+    DeviceHung(u64),
+    #[error("Vulkan call failed")]
+    Vulkan(#[from] vk::Result),
+    #[error("GPU memory allocation failed")]
+    Allocation(#[from] gpu_allocator::AllocationError),
+    #[error("UI renderer failed")]
+    UiRenderer(#[from] egui_ash_renderer::RendererError),
+    #[error("Window handle is not available")]
+    WindowHandle(#[from] winit::raw_window_handle::HandleError),
+    #[error("String passed to Vulkan contains a nul byte")]
+    Nul(#[from] std::ffi::NulError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<TracerError>,
+    },
+}
+
+impl TracerError {
+    /// Innermost error, past all the context added on the way up
+    pub fn root_cause(&self) -> &TracerError {
+        match self {
+            TracerError::Context { source, .. } => source.root_cause(),
+            _ => self,
+        }
+    }
+}
+
+/// Same as `anyhow::Context`, wraps the error with a description of what
+/// was being done when it happened
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> TracerResult<T>;
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> TracerResult<T>;
+}
+
+impl<T, E: Into<TracerError>> Context<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> TracerResult<T> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> TracerResult<T> {
+        self.map_err(|e| TracerError::Context {
+            context: context().into(),
+            source: Box::new(e.into()),
+        })
+    }
+}
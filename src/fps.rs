@@ -1,6 +1,8 @@
-use std::time::Instant;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 const FPS_CALCULATE_INTERVAL: u128 = 500; // in milliseconds
+const FRAME_HISTORY: usize = 512; // frames kept for the percentiles
 
 #[derive(Debug, Clone, Copy)]
 pub enum FPSResult {
@@ -23,23 +25,73 @@ impl FPSResult {
     }
 }
 
+/// Frame time statistics over the last `FRAME_HISTORY` frames
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameStats {
+    pub avg_ms: f32,
+    // 99th percentile of the frame time
+    pub p99_ms: f32,
+    // Average FPS of the slowest 1% of the frames
+    pub low_1_fps: f32,
+}
+
+impl FrameStats {
+    fn new(frame_times: &VecDeque<f32>) -> Self {
+        if frame_times.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = frame_times.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f32::total_cmp);
+        let p99_rank = ((sorted.len() as f32 * 0.99).ceil() as usize).clamp(1, sorted.len());
+        let slowest = &sorted[sorted.len() - (sorted.len() / 100).max(1)..];
+        let slowest_avg = slowest.iter().sum::<f32>() / slowest.len() as f32;
+
+        Self {
+            avg_ms: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p99_ms: sorted[p99_rank - 1],
+            low_1_fps: if slowest_avg > 0.0 {
+                1000.0 / slowest_avg
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
 pub struct Fps {
     prev_calculate: Instant,
+    prev_frame: Option<Instant>,
     accumulated: u32,
     fps: f32,
+    // In milliseconds, oldest first
+    frame_times: VecDeque<f32>,
+    stats: FrameStats,
 }
 
 impl Fps {
     pub(crate) fn new() -> Self {
         Self {
             prev_calculate: Instant::now(),
+            prev_frame: None,
             accumulated: 0,
             fps: 0.0,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            stats: FrameStats::default(),
         }
     }
 
     pub(crate) fn update(&mut self) -> FPSResult {
         let now = Instant::now();
+        if let Some(prev_frame) = self.prev_frame {
+            if self.frame_times.len() == FRAME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times
+                .push_back(now.duration_since(prev_frame).as_secs_f32() * 1000.0);
+        }
+        self.prev_frame = Some(now);
+
         let elapsed = now.duration_since(self.prev_calculate).as_millis();
         self.accumulated += 1;
         if elapsed > FPS_CALCULATE_INTERVAL {
@@ -47,9 +99,32 @@ impl Fps {
             self.accumulated = 0;
             self.prev_calculate = now;
             self.fps = fps;
+            self.stats = FrameStats::new(&self.frame_times);
             FPSResult::Updated(fps)
         } else {
             FPSResult::Cached(self.fps)
         }
     }
+
+    /// Statistics as of the last `FPSResult::Updated`
+    pub(crate) fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Sleeps until a frame has taken at least `1 / max_fps` seconds.
+    /// When the GPU finishes early the frames are spread out evenly
+    /// instead of being submitted in bursts. Call right before the frame
+    /// is submitted, `update` marks its end.
+    pub(crate) fn pace(&self, max_fps: f32) {
+        if max_fps <= 0.0 {
+            return;
+        }
+
+        if let Some(prev_frame) = self.prev_frame {
+            let interval = Duration::from_secs_f32(1.0 / max_fps);
+            if let Some(remaining) = interval.checked_sub(prev_frame.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+    }
 }
@@ -0,0 +1,150 @@
+use crate::config::TracerConfig;
+use crate::front::Front;
+use crate::tracer::Tracer;
+use glam::UVec2;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"PATHRSAC";
+const VERSION: u32 = 1;
+
+/// Describes the accumulation of a checkpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    // NumPy type string of a channel, as in `FloatDumpHeader`
+    pub dtype: String,
+    // Dispatches accumulated, each adding `samples_count` samples per pixel
+    pub frame_index: u64,
+    pub samples_count: u32,
+    // Frames of the render traced so far, the resumed render traces the rest
+    pub frames: usize,
+    // Hex of `TracerConfig::content_hash`, only the same config resumes
+    pub config_hash: String,
+}
+
+/// Accumulated samples of a render in progress, so it can be resumed later,
+/// e.g. after a reboot. The file holds a magic, the little-endian length of
+/// the JSON header, the header and the raw RGBA32F pixels, alpha being the
+/// frames accumulated in the pixel.
+pub struct Checkpoint {
+    pub header: CheckpointHeader,
+    pixels: Vec<u8>,
+}
+
+impl Checkpoint {
+    fn dtype() -> &'static str {
+        if cfg!(target_endian = "little") {
+            "<f4"
+        } else {
+            ">f4"
+        }
+    }
+
+    /// None if no frame has been traced yet. `frames` is what the render
+    /// traced so far, including the frames it was resumed after.
+    pub unsafe fn capture<F: Front>(
+        tracer: &mut Tracer<F>,
+        config: &TracerConfig,
+        frames: usize,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some((dimensions, pixels)) = tracer.accumulation_raw()? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            header: CheckpointHeader {
+                version: VERSION,
+                width: dimensions.x,
+                height: dimensions.y,
+                dtype: Self::dtype().to_string(),
+                frame_index: tracer.frame_index(),
+                samples_count: config.0.borrow().samples_count,
+                frames,
+                config_hash: format!("{:016x}", config.content_hash()?),
+            },
+            pixels,
+        }))
+    }
+
+    /// Written next to the path first, a crash while writing leaves the
+    /// previous checkpoint intact
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let header = serde_json::to_vec(&self.header)?;
+        let mut content = Vec::with_capacity(MAGIC.len() + 4 + header.len() + self.pixels.len());
+        content.extend_from_slice(MAGIC);
+        content.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        content.extend_from_slice(&header);
+        content.extend_from_slice(&self.pixels);
+
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, content)?;
+        std::fs::rename(&partial, path)?;
+        info!(
+            "Saved checkpoint of {} frames to {}",
+            self.header.frames,
+            path.display()
+        );
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read(path)?;
+        let rest = content
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| anyhow::anyhow!("Not a checkpoint"))?;
+        let (length, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| anyhow::anyhow!("Truncated checkpoint"))?;
+        let (header, pixels) = rest
+            .split_at_checked(u32::from_le_bytes(*length) as usize)
+            .ok_or_else(|| anyhow::anyhow!("Truncated checkpoint"))?;
+        let header: CheckpointHeader = serde_json::from_slice(header)?;
+        anyhow::ensure!(
+            header.version == VERSION,
+            "Unsupported checkpoint version {}",
+            header.version
+        );
+        anyhow::ensure!(
+            header.dtype == Self::dtype(),
+            "Checkpoint of {} channels, {} expected",
+            header.dtype,
+            Self::dtype()
+        );
+        Ok(Self {
+            pixels: pixels.to_vec(),
+            header,
+        })
+    }
+
+    /// Continues the accumulation with the next traced frame. Returns the
+    /// frames traced before the checkpoint.
+    pub fn restore<F: Front>(
+        self,
+        tracer: &mut Tracer<F>,
+        config: &TracerConfig,
+    ) -> anyhow::Result<usize> {
+        let config_hash = format!("{:016x}", config.content_hash()?);
+        anyhow::ensure!(
+            self.header.config_hash == config_hash,
+            "Checkpoint was traced with another config ({}, now {})",
+            self.header.config_hash,
+            config_hash
+        );
+        // The viewport is not part of the config hash
+        let size = UVec2::new(self.header.width, self.header.height);
+        anyhow::ensure!(
+            size == tracer.traced_size(),
+            "Checkpoint was traced at {}x{}, now {}x{}",
+            size.x,
+            size.y,
+            tracer.traced_size().x,
+            tracer.traced_size().y
+        );
+        let frames = self.header.frames;
+        tracer.restore_accumulation(size, self.pixels, self.header.frame_index)?;
+        Ok(frames)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::config::TracerConfig;
+use crate::front::Front;
+use crate::tracer::Tracer;
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Describes the raw pixels of a float dump, written next to them
+#[derive(Debug, Serialize)]
+pub struct FloatDumpHeader {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u32,
+    // NumPy type string of a channel, e.g. "<f4" for little-endian float32
+    pub dtype: &'static str,
+    pub frame_index: u64,
+    // Hex of `TracerConfig::content_hash`
+    pub config_hash: String,
+}
+
+/// Last traced frame with the exact values, for numerical analysis. The
+/// pixels are written as rows of RGBA float32 without any conversion, so
+/// `numpy.fromfile(path, dtype).reshape(height, width, channels)` loads
+/// them back.
+pub struct FloatDump {
+    pub header: FloatDumpHeader,
+    // Linear R32G32B32A32_SFLOAT pixels
+    pub pixels: Vec<u8>,
+}
+
+impl FloatDump {
+    /// None if no frame has been traced yet
+    pub unsafe fn capture<F: Front>(
+        tracer: &mut Tracer<F>,
+        config: &TracerConfig,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some((dimensions, pixels)) = tracer.snapshot_raw()? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            header: FloatDumpHeader {
+                width: dimensions.x,
+                height: dimensions.y,
+                channels: 4,
+                dtype: if cfg!(target_endian = "little") {
+                    "<f4"
+                } else {
+                    ">f4"
+                },
+                frame_index: tracer.frame_index(),
+                config_hash: format!("{:016x}", config.content_hash()?),
+            },
+            pixels,
+        }))
+    }
+
+    /// `out.bin` gets its header in `out.json`
+    pub fn header_path(path: &Path) -> PathBuf {
+        path.with_extension("json")
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, &self.pixels)?;
+        std::fs::write(
+            Self::header_path(path),
+            serde_json::to_string_pretty(&self.header)?,
+        )?;
+        info!(
+            "Dumped {}x{} float frame {} to {}",
+            self.header.width,
+            self.header.height,
+            self.header.frame_index,
+            path.display()
+        );
+        Ok(())
+    }
+}
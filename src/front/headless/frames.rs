@@ -0,0 +1,91 @@
+use crate::error::TracerResult;
+use crate::front::headless::{TracerHeadlessFront, TracerHeadlessOutput};
+use crate::tracer::Tracer;
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver};
+
+/// Frames of a headless tracer as an iterator, each item is the image after
+/// one more accumulated frame. A frame is only traced once the previous one
+/// was taken, so a slow consumer holds the GPU back instead of piling up
+/// images. Ends after `limit` frames, once `cancelled` returns true or after
+/// the first error. The callback of the front is restored on drop.
+pub struct HeadlessFrames<'a, C: Fn() -> bool> {
+    tracer: &'a mut Tracer<TracerHeadlessFront>,
+    // Replaced by the channel while iterating
+    callback: Option<Box<dyn FnMut(TracerHeadlessOutput) + Send>>,
+    receiver: Receiver<TracerHeadlessOutput>,
+    // Delivered by the front but not yet taken
+    pending: VecDeque<TracerHeadlessOutput>,
+    remaining: usize,
+    cancelled: C,
+    failed: bool,
+}
+
+impl Tracer<TracerHeadlessFront> {
+    /// Iterates over the next `limit` frames, see `HeadlessFrames`
+    pub unsafe fn frames<C: Fn() -> bool>(
+        &mut self,
+        limit: usize,
+        cancelled: C,
+    ) -> HeadlessFrames<'_, C> {
+        let (sender, receiver) = channel();
+        let callback = self.with_front(|_, front| {
+            front.replace_callback(Box::new(move |output| {
+                // The iterator is gone only while the callback is restored
+                let _ = sender.send(output);
+            }))
+        });
+        HeadlessFrames {
+            tracer: self,
+            callback: Some(callback),
+            receiver,
+            pending: VecDeque::new(),
+            remaining: limit,
+            cancelled,
+            failed: false,
+        }
+    }
+}
+
+impl<C: Fn() -> bool> HeadlessFrames<'_, C> {
+    unsafe fn trace(&mut self) -> TracerResult<()> {
+        self.tracer.trace(None)?;
+        // Delivers the frame right away instead of the readback depth later
+        self.tracer.flush()?;
+        self.pending.extend(self.receiver.try_iter());
+        Ok(())
+    }
+}
+
+impl<C: Fn() -> bool> Iterator for HeadlessFrames<'_, C> {
+    type Item = TracerResult<TracerHeadlessOutput>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining == 0 {
+            return None;
+        }
+        // A busy slot skips the frame, trace until one comes out
+        while self.pending.is_empty() {
+            if (self.cancelled)() {
+                return None;
+            }
+            if let Err(e) = unsafe { self.trace() } {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        }
+        self.remaining -= 1;
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+impl<C: Fn() -> bool> Drop for HeadlessFrames<'_, C> {
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            unsafe {
+                self.tracer
+                    .with_front(|_, front| front.replace_callback(callback));
+            }
+        }
+    }
+}
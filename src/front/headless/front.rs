@@ -1,36 +1,83 @@
-use crate::back::TracerSlot;
+use crate::back::{Back, TracerSlot};
 use crate::common::capabilities::DeviceCapabilities;
+use crate::common::color::linear_to_srgb8;
+use crate::common::command_buffer::CommandBuffer;
+use crate::common::frame_graph::{Pass, PassSubmit, SyncPoint, Timeline};
 use crate::common::queue::QueueFamily;
+use crate::error::{Context, TracerError, TracerResult};
 use crate::front::headless::TracerHeadlessOutput;
 use crate::front::{Front, QueueFamilyIndices};
 use crate::tracer::Bundle;
 use ash::{vk, Device, Entry, Instance};
-use log::info;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::{debug, info, warn};
 use std::ffi::{c_char, c_void};
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
-pub struct HeadlessQueueFamilyIndices {}
+pub struct HeadlessQueueFamilyIndices {
+    // Used for image readback when the host image copy is not available
+    pub transfer_family: u32,
+}
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
-pub struct HeadlessQueues {}
+pub struct HeadlessQueues {
+    pub indices: HeadlessQueueFamilyIndices,
+    pub transfer_queue: vk::Queue,
+}
 
 impl QueueFamilyIndices for HeadlessQueueFamilyIndices {
     type Queues = HeadlessQueues;
 
     fn as_families(&self) -> Vec<QueueFamily> {
-        vec![]
+        vec![QueueFamily {
+            index: self.transfer_family,
+            priorities: vec![1.0],
+        }]
     }
 
-    unsafe fn into_queues(self, _device: &Device) -> anyhow::Result<Self::Queues> {
-        Ok(HeadlessQueues {})
+    unsafe fn into_queues(self, device: &Device) -> TracerResult<Self::Queues> {
+        let transfer_queue = device.get_device_queue(self.transfer_family, 0);
+        Ok(HeadlessQueues {
+            indices: self,
+            transfer_queue,
+        })
     }
 }
 
+// Frames copied to the host at once, so that the copy of one frame
+// overlaps the tracing of the following ones
+const READBACK_DEPTH: usize = 3;
+
+struct PendingReadback {
+    // Reached once the copy is complete
+    value: u64,
+    dimensions: glam::UVec2,
+    format: vk::Format,
+}
+
+/// Host-visible buffer the tracer image is copied into
+struct Readback {
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    size: usize,
+    command_buffer: CommandBuffer,
+    pending: Option<PendingReadback>,
+}
+
 #[allow(dead_code)]
 pub struct TracerHeadlessFront {
     callback: Box<dyn FnMut(TracerHeadlessOutput) + Send>,
+    queues: Option<HeadlessQueues>,
+    command_pool: vk::CommandPool,
+    timeline: Option<Timeline>,
+    readbacks: Vec<Readback>, // size = READBACK_DEPTH
+    next_readback: usize,
+    // See `Front::device_index`
+    device_index: usize,
+    destroyed: bool,
 }
 
 impl TracerHeadlessFront {
@@ -40,12 +87,185 @@ impl TracerHeadlessFront {
     {
         Self {
             callback: Box::new(callback),
+            queues: None,
+            command_pool: vk::CommandPool::null(),
+            timeline: None,
+            readbacks: vec![],
+            next_readback: 0,
+            device_index: 0,
+            destroyed: false,
+        }
+    }
+
+    /// Delivers the following frames to `callback`, returns the previous one
+    pub(crate) fn replace_callback(
+        &mut self,
+        callback: Box<dyn FnMut(TracerHeadlessOutput) + Send>,
+    ) -> Box<dyn FnMut(TracerHeadlessOutput) + Send> {
+        std::mem::replace(&mut self.callback, callback)
+    }
+
+    pub(crate) fn with_device_index(mut self, device_index: usize) -> Self {
+        self.device_index = device_index;
+        self
+    }
+
+    unsafe fn create_readback_buffer(
+        bundle: Bundle,
+        size: usize,
+    ) -> TracerResult<(vk::Buffer, Allocation)> {
+        debug!("Creating headless readback buffer of {} bytes", size);
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let reqs = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Headless Readback Buffer",
+            requirements: reqs,
+            location: MemoryLocation::GpuToCpu,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+        bundle
+            .device
+            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
+
+        Ok((buffer, allocation))
+    }
+
+    unsafe fn destroy_readback_buffer(bundle: Bundle, readback: &mut Readback) {
+        if let Some(allocation) = readback.allocation.take() {
+            bundle
+                .allocator()
+                .free(allocation)
+                .expect("Failed to free readback buffer allocation");
+        }
+        if readback.buffer != vk::Buffer::null() {
+            bundle.device.destroy_buffer(readback.buffer, None);
+            readback.buffer = vk::Buffer::null();
+        }
+        readback.size = 0;
+    }
+
+    /// Submits the copy of the slot image into the next readback buffer.
+    /// Returns the point after which the slot image is no longer read.
+    unsafe fn enqueue_readback(
+        &mut self,
+        bundle: Bundle,
+        slot: &TracerSlot,
+    ) -> TracerResult<SyncPoint> {
+        let index = self.next_readback;
+        self.next_readback = (self.next_readback + 1) % READBACK_DEPTH;
+
+        // The oldest frame has to be delivered before its buffer is reused
+        self.deliver(bundle, index)?;
+
+        let queues = self.queues.as_ref().unwrap();
+        let readback = &mut self.readbacks[index];
+        if readback.size < slot.image.byte_size {
+            Self::destroy_readback_buffer(bundle, readback);
+            let (buffer, allocation) = Self::create_readback_buffer(bundle, slot.image.byte_size)?;
+            readback.buffer = buffer;
+            readback.allocation = Some(allocation);
+            readback.size = slot.image.byte_size;
         }
+
+        let command_buffer = &readback.command_buffer;
+        command_buffer.reset(bundle)?;
+        command_buffer.begin(bundle)?;
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: slot.image.dimensions.x,
+                height: slot.image.dimensions.y,
+                depth: 1,
+            });
+        bundle.device.cmd_copy_image_to_buffer(
+            command_buffer.as_inner(),
+            slot.image.image,
+            slot.image.layout,
+            readback.buffer,
+            &[region],
+        );
+        command_buffer.end(bundle)?;
+
+        let point = self.timeline.as_mut().unwrap().advance();
+        PassSubmit::new()
+            .after(slot.ready, Pass::Readback.wait_stage())
+            .command_buffer(command_buffer)
+            .signal(point)
+            .submit(bundle, queues.transfer_queue)?;
+        readback.pending = Some(PendingReadback {
+            value: point.value,
+            dimensions: slot.image.dimensions,
+            format: slot.image.format,
+        });
+
+        Ok(point)
+    }
+
+    /// Waits for the copy into the readback buffer, if any, and hands the frame over
+    unsafe fn deliver(&mut self, bundle: Bundle, index: usize) -> TracerResult<()> {
+        let Some(pending) = self.readbacks[index].pending.take() else {
+            return Ok(());
+        };
+
+        self.timeline
+            .as_ref()
+            .unwrap()
+            .wait(bundle, pending.value)?;
+        let mapped = self.readbacks[index]
+            .allocation
+            .as_ref()
+            .unwrap()
+            .mapped_slice()
+            .expect("GpuToCpu allocation must be mappable");
+        let output = TracerHeadlessOutput::from_memory(pending.dimensions, pending.format, mapped)?;
+        (self.callback)(output);
+
+        Ok(())
     }
 }
 
 impl TracerHeadlessOutput {
-    pub fn from_rgba8888(width: u32, height: u32, rgba8888: Vec<u8>) -> Self {
+    pub fn from_memory(
+        dimensions: glam::UVec2,
+        format: vk::Format,
+        memory: &[u8],
+    ) -> TracerResult<Self> {
+        let pixels = (dimensions.x * dimensions.y) as usize;
+        match format {
+            vk::Format::R8G8B8A8_UNORM => Ok(Self::from_rgba8888(
+                dimensions.x,
+                dimensions.y,
+                &memory[..pixels * 4],
+            )),
+            vk::Format::R32G32B32A32_SFLOAT => Ok(Self::from_rgba32f(
+                dimensions.x,
+                dimensions.y,
+                &memory[..pixels * 16],
+            )),
+            _ => Err(TracerError::Unsupported(format!(
+                "image format {:?}",
+                format
+            ))),
+        }
+    }
+
+    // Already sRGB encoded, taken as is
+    pub fn from_rgba8888(width: u32, height: u32, rgba8888: &[u8]) -> Self {
         Self {
             width,
             height,
@@ -55,11 +275,32 @@ impl TracerHeadlessOutput {
                 .collect(),
         }
     }
+
+    // Linear color, sRGB encoded here, see common::color
+    pub fn from_rgba32f(width: u32, height: u32, rgba32f: &[u8]) -> Self {
+        Self {
+            width,
+            height,
+            rgb888: rgba32f
+                .chunks(16)
+                .flat_map(|pixel| {
+                    pixel[..12].chunks(4).map(|channel| {
+                        let value = f32::from_ne_bytes(channel.try_into().unwrap());
+                        linear_to_srgb8(value)
+                    })
+                })
+                .collect(),
+        }
+    }
 }
 
 impl Front for TracerHeadlessFront {
     type FrontQueueFamilyIndices = HeadlessQueueFamilyIndices;
 
+    fn device_index(&self) -> usize {
+        self.device_index
+    }
+
     unsafe fn get_required_image_usage_flags(
         capabilities: &DeviceCapabilities,
     ) -> vk::ImageUsageFlags {
@@ -74,7 +315,7 @@ impl Front for TracerHeadlessFront {
         &self,
         available: &Vec<String>,
         capabilities: &mut DeviceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let mut required = vec![];
         if available.contains(&ash::ext::host_image_copy::NAME.to_str()?.to_string()) {
             capabilities.host_image_copy = true;
@@ -87,11 +328,15 @@ impl Front for TracerHeadlessFront {
 
     unsafe fn find_queue_families(
         &self,
-        _entry: &Entry,
-        _instance: &Instance,
-        _physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<HeadlessQueueFamilyIndices> {
-        Ok(HeadlessQueueFamilyIndices {})
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> TracerResult<HeadlessQueueFamilyIndices> {
+        // Reuse the back-end transfer family, the tracer images are shared with it
+        let back = Back::find_queue_families(entry, instance, physical_device, false)?;
+        Ok(HeadlessQueueFamilyIndices {
+            transfer_family: back.transfer_family,
+        })
     }
 
     unsafe fn patch_create_device_info(
@@ -101,8 +346,8 @@ impl Front for TracerHeadlessFront {
         _physical_device: vk::PhysicalDevice,
         device_capabilities: &DeviceCapabilities,
         create_info: vk::DeviceCreateInfo,
-        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> anyhow::Result<Device>,
-    ) -> anyhow::Result<Device> {
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
         if device_capabilities.host_image_copy {
             let mut physical_device_host_image_copy_features =
                 vk::PhysicalDeviceHostImageCopyFeaturesEXT::default().host_image_copy(true);
@@ -113,16 +358,77 @@ impl Front for TracerHeadlessFront {
         }
     }
 
+    unsafe fn init(&mut self, bundle: Bundle, queues: HeadlessQueues) -> TracerResult<()> {
+        let command_pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            )
+            .queue_family_index(queues.indices.transfer_family);
+        self.command_pool = bundle
+            .device
+            .create_command_pool(&command_pool_info, None)
+            .context("Failed to create headless command pool")?;
+        self.timeline = Some(
+            Timeline::new(bundle, Pass::Readback).context("Failed to create readback timeline")?,
+        );
+        self.readbacks = (0..READBACK_DEPTH)
+            .map(|_| {
+                Ok(Readback {
+                    buffer: vk::Buffer::null(),
+                    allocation: None,
+                    size: 0,
+                    command_buffer: CommandBuffer::new_from_pool(bundle, self.command_pool)?,
+                    pending: None,
+                })
+            })
+            .collect::<TracerResult<_>>()
+            .context("Failed to create readback command buffers")?;
+        self.queues = Some(queues);
+        Ok(())
+    }
+
+    unsafe fn destroy(&mut self, bundle: Bundle) {
+        if !self.destroyed {
+            let undelivered = self
+                .readbacks
+                .iter()
+                .filter(|readback| readback.pending.is_some())
+                .count();
+            if undelivered > 0 {
+                warn!("Dropping {} undelivered headless frames", undelivered);
+            }
+            bundle.device.device_wait_idle().unwrap();
+
+            debug!("Destroying headless readback buffers");
+            for readback in &mut self.readbacks {
+                Self::destroy_readback_buffer(bundle, readback);
+                readback.command_buffer.destroy(bundle, self.command_pool);
+            }
+            if let Some(timeline) = &mut self.timeline {
+                timeline.destroy(bundle);
+            }
+
+            debug!("Destroying headless command pool");
+            bundle.device.destroy_command_pool(self.command_pool, None);
+            self.destroyed = true;
+        } else {
+            warn!("Front already destroyed");
+        }
+    }
+
     unsafe fn present(
         &mut self,
         bundle: Bundle,
         _w: Option<&winit::window::Window>,
         slot: TracerSlot,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<Option<SyncPoint>> {
         info!("Presenting frame");
 
-        let memory = vec![0u8; slot.image.byte_size];
         if bundle.device_capabilities.host_image_copy {
+            slot.ready.wait(bundle)?;
+
+            let mut memory = vec![0u8; slot.image.byte_size];
             let factory = ash::ext::host_image_copy::Device::new(&bundle.instance, &bundle.device);
             let regions = vk::ImageToMemoryCopyEXT::default()
                 .host_pointer(memory.as_ptr() as *mut c_void)
@@ -144,21 +450,36 @@ impl Front for TracerHeadlessFront {
                 .src_image(slot.image.image)
                 .src_image_layout(slot.image.layout);
             factory.copy_image_to_memory(&copy_image_to_memory_info)?;
+
+            let output = TracerHeadlessOutput::from_memory(
+                slot.image.dimensions,
+                slot.image.format,
+                &memory,
+            )?;
+            (self.callback)(output);
+            Ok(None)
         } else {
-            unimplemented!("Not yet implemented without host image copy extension")
+            let point = self
+                .enqueue_readback(bundle, &slot)
+                .context("Failed to read back tracer image")?;
+            Ok(Some(point))
         }
+    }
 
-        let data = match slot.image.format {
-            vk::Format::R8G8B8A8_UNORM => TracerHeadlessOutput::from_rgba8888(
-                slot.image.dimensions.x,
-                slot.image.dimensions.y,
-                memory,
-            ),
-            _ => panic!("Unsupported image format"),
-        };
-
-        (self.callback)(data);
-
+    unsafe fn flush(&mut self, bundle: Bundle) -> TracerResult<()> {
+        // Oldest first
+        for i in 0..self.readbacks.len() {
+            let index = (self.next_readback + i) % self.readbacks.len();
+            self.deliver(bundle, index)?;
+        }
         Ok(())
     }
 }
+
+impl Drop for TracerHeadlessFront {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("Leaked headless front");
+        }
+    }
+}
@@ -1,11 +1,31 @@
 use crate::assets::AssetManager;
 use crate::config::TracerConfig;
-use crate::front::headless::front::TracerHeadlessFront;
+use crate::error::{TracerError, TracerResult};
+pub use crate::front::headless::checkpoint::Checkpoint;
+pub use crate::front::headless::dump::FloatDump;
+pub(crate) use crate::front::headless::front::{
+    HeadlessQueueFamilyIndices, HeadlessQueues, TracerHeadlessFront,
+};
+pub use crate::front::headless::progress::{HeadlessProgress, RenderProgress, TerminalProgress};
+pub use crate::front::headless::sequence::sequence_frame_path;
+pub use crate::front::headless::split::SplitFrameTracer;
+pub use crate::front::headless::turntable::Turntable;
+pub use crate::front::headless::writer::ImageWriter;
 use crate::tracer::Tracer;
 use build_info::BuildInfo;
 use glam::UVec2;
+use image::{ImageBuffer, ImageFormat, Rgb};
+use std::io::Cursor;
 
+mod checkpoint;
+mod dump;
+mod frames;
 mod front;
+mod progress;
+mod sequence;
+mod split;
+mod turntable;
+mod writer;
 
 pub struct TracerHeadlessOutput {
     pub width: u32,
@@ -13,13 +33,24 @@ pub struct TracerHeadlessOutput {
     pub rgb888: Vec<u8>,
 }
 
+impl TracerHeadlessOutput {
+    pub fn encode_png(self) -> TracerResult<Vec<u8>> {
+        let image: ImageBuffer<Rgb<u8>, _> =
+            ImageBuffer::from_raw(self.width, self.height, self.rgb888)
+                .ok_or(TracerError::FrameSize)?;
+        let mut png = vec![];
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(png)
+    }
+}
+
 pub unsafe fn headless_tracer<C>(
     config: TracerConfig,
     asset_manager: AssetManager,
     viewport: UVec2,
     bi: BuildInfo,
     callback: C,
-) -> anyhow::Result<Tracer<TracerHeadlessFront>>
+) -> TracerResult<Tracer<TracerHeadlessFront>>
 where
     C: FnMut(TracerHeadlessOutput) + Send + 'static,
 {
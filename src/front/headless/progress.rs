@@ -0,0 +1,115 @@
+use crate::error::TracerResult;
+use crate::front::headless::TracerHeadlessFront;
+use crate::tracer::Tracer;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+// Redrawing the bar on every frame is slow for tiny renders
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// State of a headless render, reported after every traced frame
+#[derive(Clone, Debug)]
+pub struct RenderProgress {
+    pub frames: usize,
+    pub total_frames: usize,
+    // Samples per pixel accumulated so far
+    pub samples: u64,
+    pub elapsed: Duration,
+    // Estimated from the average frame time
+    pub eta: Duration,
+}
+
+pub trait HeadlessProgress {
+    fn on_progress(&mut self, progress: &RenderProgress);
+
+    /// Called once the render is done or cancelled
+    fn on_finished(&mut self, _progress: &RenderProgress) {}
+}
+
+impl<F: FnMut(&RenderProgress)> HeadlessProgress for F {
+    fn on_progress(&mut self, progress: &RenderProgress) {
+        self(progress)
+    }
+}
+
+/// Single line progress bar on stderr
+#[derive(Default)]
+pub struct TerminalProgress {
+    last_draw: Option<Instant>,
+}
+
+impl TerminalProgress {
+    fn draw(progress: &RenderProgress) {
+        let filled = BAR_WIDTH * progress.frames / progress.total_frames.max(1);
+        eprint!(
+            "\r[{}{}] {}/{} frames, {} spp, {:.1} s elapsed, ETA {:.1} s ",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            progress.frames,
+            progress.total_frames,
+            progress.samples,
+            progress.elapsed.as_secs_f32(),
+            progress.eta.as_secs_f32()
+        );
+        let _ = std::io::stderr().flush();
+    }
+}
+
+impl HeadlessProgress for TerminalProgress {
+    fn on_progress(&mut self, progress: &RenderProgress) {
+        if self
+            .last_draw
+            .is_some_and(|last_draw| last_draw.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+        Self::draw(progress);
+    }
+
+    fn on_finished(&mut self, progress: &RenderProgress) {
+        Self::draw(progress);
+        eprintln!();
+    }
+}
+
+impl Tracer<TracerHeadlessFront> {
+    /// Accumulates `frames` frames, reporting the progress after each one.
+    /// Stops early once `cancelled` returns true, the image then holds the
+    /// samples accumulated so far. Returns the number of traced frames.
+    pub unsafe fn render(
+        &mut self,
+        frames: usize,
+        samples_per_frame: u32,
+        progress: &mut impl HeadlessProgress,
+        cancelled: impl Fn() -> bool,
+    ) -> TracerResult<usize> {
+        let start = Instant::now();
+        let mut state = RenderProgress {
+            frames: 0,
+            total_frames: frames,
+            samples: 0,
+            elapsed: Duration::ZERO,
+            eta: Duration::ZERO,
+        };
+
+        while state.frames < frames && !cancelled() {
+            self.trace(None)?;
+            // Every trace has to dispatch, otherwise the frames are skipped
+            // while the slot is busy and the counts do not add up
+            self.flush()?;
+
+            state.frames += 1;
+            state.samples += samples_per_frame as u64;
+            state.elapsed = start.elapsed();
+            state.eta = state
+                .elapsed
+                .mul_f64((frames - state.frames) as f64 / state.frames as f64);
+            progress.on_progress(&state);
+        }
+
+        progress.on_finished(&state);
+        Ok(state.frames)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::config::TracerConfig;
+use crate::error::TracerResult;
+use crate::front::headless::{HeadlessProgress, TracerHeadlessFront, TracerHeadlessOutput};
+use crate::script::SceneScript;
+use crate::tracer::Tracer;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+/// `frame.png` becomes `frame_0042.png`
+pub fn sequence_frame_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_{:04}.png", stem, index))
+}
+
+impl Tracer<TracerHeadlessFront> {
+    /// Renders the animation of the config, `fps` images per second
+    /// of the animation, accumulating `frames` frames for each of them.
+    /// `config` must be the config the tracer was created with. Every image
+    /// is passed to `on_image` with its index. Returns the number of images.
+    pub unsafe fn render_sequence(
+        &mut self,
+        config: &TracerConfig,
+        fps: f32,
+        frames: usize,
+        samples_per_frame: u32,
+        progress: &mut impl HeadlessProgress,
+        cancelled: impl Fn() -> bool,
+        mut on_image: impl FnMut(usize, TracerHeadlessOutput) -> TracerResult<()>,
+    ) -> TracerResult<usize> {
+        let animation = config.0.borrow().animation.clone();
+        // Called with the time of every image
+        let mut script = config
+            .0
+            .borrow()
+            .script
+            .as_ref()
+            .map(|path| SceneScript::new(Path::new(path)));
+        if animation.is_empty() {
+            warn!("Animation has no keyframes, nothing to render");
+            return Ok(0);
+        }
+        let count = (animation.duration() * fps).floor() as usize + 1;
+        info!(
+            "Rendering {} images of {:.2}s animation",
+            count,
+            animation.duration()
+        );
+
+        for index in 0..count {
+            let time = index as f32 / fps;
+            config.0.borrow_mut().animate(time);
+            if let Some(script) = &mut script {
+                script.run(&mut config.0.borrow_mut(), time, 1.0 / fps);
+            }
+
+            let traced = self.render(frames, samples_per_frame, &mut *progress, &cancelled)?;
+            if traced < frames {
+                warn!("Interrupted at image {} of {}", index, count);
+                return Ok(index);
+            }
+            if let Some(output) = self.snapshot()? {
+                on_image(index, output)?;
+            }
+        }
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::assets::AssetManager;
+use crate::back::FrameRegion;
+use crate::config::TracerConfig;
+use crate::error::{TracerError, TracerResult};
+use crate::front::headless::{
+    HeadlessProgress, RenderProgress, TracerHeadlessFront, TracerHeadlessOutput,
+};
+use crate::tracer::Tracer;
+use build_info::BuildInfo;
+use glam::UVec2;
+use log::info;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Experimental split-frame rendering over several GPUs. Every GPU gets
+/// its own tracer tracing a horizontal band of the frame, the bands are
+/// read back and stitched together on the CPU. Headless only, the
+/// presentation front does not composite the bands.
+pub struct SplitFrameTracer {
+    // Top to bottom
+    bands: Vec<Tracer<TracerHeadlessFront>>,
+}
+
+impl SplitFrameTracer {
+    pub unsafe fn new(
+        config: &TracerConfig,
+        asset_manager: AssetManager,
+        viewport: UVec2,
+        bi: BuildInfo,
+        devices: usize,
+    ) -> TracerResult<Self> {
+        let devices = devices.clamp(1, viewport.y as usize);
+        let mut bands = vec![];
+        for index in 0..devices {
+            let top = viewport.y * index as u32 / devices as u32;
+            let bottom = viewport.y * (index + 1) as u32 / devices as u32;
+            info!(
+                "Creating tracer for rows {}..{} on device {}",
+                top, bottom, index
+            );
+            // Every back-end clears the update flags of its own config
+            let config = TracerConfig(Rc::new(RefCell::new(config.0.borrow().clone())));
+            let mut tracer = Tracer::<TracerHeadlessFront>::new(
+                config,
+                asset_manager.clone(),
+                UVec2::new(viewport.x, bottom - top),
+                bi.clone(),
+                |_, _| Ok(TracerHeadlessFront::new(|_| {}).with_device_index(index)),
+            )?;
+            tracer.set_region(Some(FrameRegion {
+                offset: UVec2::new(0, top),
+                frame_size: viewport,
+            }));
+            bands.push(tracer);
+        }
+
+        Ok(Self { bands })
+    }
+
+    /// Same as `Tracer::render`, the GPUs trace their bands in parallel
+    pub unsafe fn render(
+        &mut self,
+        frames: usize,
+        samples_per_frame: u32,
+        progress: &mut impl HeadlessProgress,
+        cancelled: impl Fn() -> bool,
+    ) -> TracerResult<usize> {
+        let start = Instant::now();
+        let mut state = RenderProgress {
+            frames: 0,
+            total_frames: frames,
+            samples: 0,
+            elapsed: Duration::ZERO,
+            eta: Duration::ZERO,
+        };
+
+        while state.frames < frames && !cancelled() {
+            // Submit to every device before waiting for any of them
+            for tracer in &mut self.bands {
+                tracer.trace(None)?;
+            }
+            for tracer in &mut self.bands {
+                tracer.flush()?;
+            }
+
+            state.frames += 1;
+            state.samples += samples_per_frame as u64;
+            state.elapsed = start.elapsed();
+            state.eta = state
+                .elapsed
+                .mul_f64((frames - state.frames) as f64 / state.frames as f64);
+            progress.on_progress(&state);
+        }
+
+        progress.on_finished(&state);
+        Ok(state.frames)
+    }
+
+    /// Whole frame stitched from the bands, None until every band is traced
+    pub unsafe fn snapshot(&mut self) -> TracerResult<Option<TracerHeadlessOutput>> {
+        let mut frame: Option<TracerHeadlessOutput> = None;
+        for tracer in &mut self.bands {
+            let Some(band) = tracer.snapshot()? else {
+                return Ok(None);
+            };
+            match &mut frame {
+                Some(frame) if frame.width != band.width => return Err(TracerError::FrameSize),
+                Some(frame) => {
+                    frame.height += band.height;
+                    frame.rgb888.extend_from_slice(&band.rgb888);
+                }
+                None => frame = Some(band),
+            }
+        }
+
+        Ok(frame)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::{Camera, TracerConfig};
+use crate::error::TracerResult;
+use crate::front::headless::{HeadlessProgress, TracerHeadlessFront, TracerHeadlessOutput};
+use crate::tracer::Tracer;
+use glam::{Quat, Vec3};
+use log::{info, warn};
+
+/// Full turn of the camera about the vertical axis through the point in
+/// focus, for showcasing the model in the middle of the view
+#[derive(Clone, Debug)]
+pub struct Turntable {
+    start: Camera,
+    focus: Vec3,
+    count: usize,
+}
+
+impl Turntable {
+    /// Turn lasting `duration` seconds at `fps` images per second. The last
+    /// image stops a step short of the first one, so the sequence loops.
+    pub fn new(start: Camera, duration: f32, fps: f32) -> Self {
+        let focus = start.position + start.direction.normalize() * start.focus_distance;
+        Self {
+            start,
+            focus,
+            count: ((duration * fps).round() as usize).max(1),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Camera of the image with the index
+    pub fn camera(&self, index: usize) -> Camera {
+        let angle = std::f32::consts::TAU * index as f32 / self.count as f32;
+        let rotation = Quat::from_rotation_y(angle);
+        Camera {
+            position: self.focus + rotation * (self.start.position - self.focus),
+            direction: rotation * self.start.direction,
+            ..self.start.clone()
+        }
+    }
+
+    pub fn start(&self) -> &Camera {
+        &self.start
+    }
+}
+
+impl Tracer<TracerHeadlessFront> {
+    /// Renders the turntable, accumulating `frames` frames for each image.
+    /// `config` must be the config the tracer was created with, its camera
+    /// is restored afterwards. Every image is passed to `on_image` with its
+    /// index. Returns the number of images.
+    pub unsafe fn render_turntable(
+        &mut self,
+        config: &TracerConfig,
+        turntable: &Turntable,
+        frames: usize,
+        samples_per_frame: u32,
+        progress: &mut impl HeadlessProgress,
+        cancelled: impl Fn() -> bool,
+        mut on_image: impl FnMut(usize, TracerHeadlessOutput) -> TracerResult<()>,
+    ) -> TracerResult<usize> {
+        let count = turntable.count();
+        info!("Rendering {} turntable images", count);
+
+        let mut rendered = count;
+        for index in 0..count {
+            {
+                let mut cfg = config.0.borrow_mut();
+                cfg.camera = turntable.camera(index);
+                cfg.updated = true;
+            }
+
+            let traced = self.render(frames, samples_per_frame, &mut *progress, &cancelled)?;
+            if traced < frames {
+                warn!("Interrupted at image {} of {}", index, count);
+                rendered = index;
+                break;
+            }
+            if let Some(output) = self.snapshot()? {
+                on_image(index, output)?;
+            }
+        }
+
+        let mut cfg = config.0.borrow_mut();
+        cfg.camera = turntable.start().clone();
+        cfg.updated = true;
+        Ok(rendered)
+    }
+}
@@ -0,0 +1,125 @@
+use crate::error::{TracerError, TracerResult};
+use crate::front::headless::TracerHeadlessOutput;
+use log::{debug, error};
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+
+// Images queued per worker before `write` waits for one
+const QUEUE_DEPTH: usize = 2;
+// Encoding is CPU bound, more workers than this only compete with the tracer
+const MAX_WORKERS: usize = 4;
+
+struct Job {
+    path: PathBuf,
+    output: TracerHeadlessOutput,
+}
+
+/// Threads encoding the images to PNG and writing them to disk, so the
+/// tracer keeps submitting while the previous images are saved. The queue
+/// is bounded, once it is full `write` waits for a worker, keeping the
+/// memory of a long sequence in check.
+pub struct ImageWriter {
+    // None once finished
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    // First failure of a worker, see `write` and `finish`
+    error: Arc<Mutex<Option<TracerError>>>,
+}
+
+impl ImageWriter {
+    /// One worker per spare core, the tracer thread keeps one
+    pub fn new() -> TracerResult<Self> {
+        let cores = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::with_workers((cores.saturating_sub(1)).clamp(1, MAX_WORKERS))
+    }
+
+    fn with_workers(count: usize) -> TracerResult<Self> {
+        debug!("Starting {} image writers", count);
+        let (sender, receiver) = sync_channel(count * QUEUE_DEPTH);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let error = Arc::new(Mutex::new(None));
+        let workers = (0..count)
+            .map(|index| {
+                let receiver = receiver.clone();
+                let error = error.clone();
+                std::thread::Builder::new()
+                    .name(format!("image-writer-{}", index))
+                    .spawn(move || Self::run(&receiver, &error))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            sender: Some(sender),
+            workers,
+            error,
+        })
+    }
+
+    fn run(receiver: &Mutex<Receiver<Job>>, error: &Mutex<Option<TracerError>>) {
+        loop {
+            // The lock is only held while waiting, the others take the
+            // following jobs meanwhile
+            let job = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+            let Ok(Job { path, output }) = job else {
+                return;
+            };
+            let written = output
+                .encode_png()
+                .and_then(|png| Ok(std::fs::write(&path, png)?));
+            if let Err(e) = written {
+                error!("Failed to save {}: {}", path.display(), e);
+                error
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get_or_insert(e);
+            }
+        }
+    }
+
+    /// Queues the image, waits while the queue is full. Fails with the
+    /// error of an earlier image, if any.
+    pub fn write(&self, path: PathBuf, output: TracerHeadlessOutput) -> TracerResult<()> {
+        if let Some(e) = self.take_error() {
+            return Err(e);
+        }
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(Job { path, output }).ok())
+            .ok_or_else(|| std::io::Error::other("Image writers have stopped").into())
+    }
+
+    /// Waits for the queued images to be written
+    pub fn finish(mut self) -> TracerResult<()> {
+        self.join();
+        self.take_error().map_or(Ok(()), Err)
+    }
+
+    fn take_error(&self) -> Option<TracerError> {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    fn join(&mut self) {
+        // Workers return once the queue is drained and the sender gone
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("Image writer panicked");
+            }
+        }
+    }
+}
+
+impl Drop for ImageWriter {
+    fn drop(&mut self) {
+        // The queued images are still written
+        self.join();
+    }
+}
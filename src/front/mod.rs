@@ -1,19 +1,24 @@
 use crate::back::TracerSlot;
 use crate::common::capabilities::{DeviceCapabilities, InstanceCapabilities};
+use crate::common::frame_graph::SyncPoint;
 use crate::common::queue::QueueFamily;
+use crate::error::TracerResult;
 use crate::tracer::Bundle;
 use ash::{vk, Device, Entry, Instance};
 use std::ffi::c_char;
 use std::fmt::Debug;
 
 pub mod headless;
+#[cfg(unix)]
+pub mod share;
+pub mod stream;
 pub mod windowed;
 
 pub trait QueueFamilyIndices {
     type Queues: Debug;
 
     fn as_families(&self) -> Vec<QueueFamily>;
-    unsafe fn into_queues(self, device: &Device) -> anyhow::Result<Self::Queues>;
+    unsafe fn into_queues(self, device: &Device) -> TracerResult<Self::Queues>;
 }
 
 pub trait Front {
@@ -28,14 +33,14 @@ pub trait Front {
     unsafe fn get_required_instance_extensions(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
     unsafe fn get_required_instance_layers(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
@@ -43,7 +48,7 @@ pub trait Front {
         &self,
         _available: &Vec<String>,
         _capabilities: &mut DeviceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
@@ -52,16 +57,22 @@ pub trait Front {
         _entry: &Entry,
         _instance: &Instance,
         _physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<bool> {
+    ) -> TracerResult<bool> {
         Ok(true)
     }
 
+    /// Index among the suitable devices to create the tracer on,
+    /// so the tracers can be spread over several GPUs
+    fn device_index(&self) -> usize {
+        0
+    }
+
     unsafe fn find_queue_families(
         &self,
         _entry: &Entry,
         _instance: &Instance,
         _physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<Self::FrontQueueFamilyIndices>;
+    ) -> TracerResult<Self::FrontQueueFamilyIndices>;
 
     unsafe fn patch_create_device_info(
         &self,
@@ -70,8 +81,8 @@ pub trait Front {
         _physical_device: vk::PhysicalDevice,
         _device_capabilities: &DeviceCapabilities,
         create_info: vk::DeviceCreateInfo,
-        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> anyhow::Result<Device>,
-    ) -> anyhow::Result<Device> {
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
         on_patched(create_info)
     }
 
@@ -79,22 +90,43 @@ pub trait Front {
         &mut self,
         _bundle: Bundle,
         _queues: <<Self as Front>::FrontQueueFamilyIndices as QueueFamilyIndices>::Queues,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         Ok(())
     }
 
     unsafe fn destroy(&mut self, _bundle: Bundle) {}
 
-    unsafe fn resize(&mut self, _bundle: Bundle, _size: glam::UVec2) -> anyhow::Result<()> {
+    unsafe fn resize(&mut self, _bundle: Bundle, _size: glam::UVec2) -> TracerResult<()> {
         Ok(())
     }
 
+    /// Shows or delivers the traced slot. Reading the image must wait for
+    /// `TracerSlot::ready`, see `TracerSlot`.
     unsafe fn present(
         &mut self,
         _bundle: Bundle,
         _w: Option<&winit::window::Window>, // ???
         _tracer_slot: TracerSlot,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<Option<SyncPoint>> {
+        // Point after which the slot image is no longer read,
+        // None if the front is done with it on return
+        Ok(None)
+    }
+
+    unsafe fn flush(&mut self, _bundle: Bundle) -> TracerResult<()> {
+        // Blocks until every presented frame has been delivered
         Ok(())
     }
+
+    /// GPU time of the passes of the front-end in milliseconds,
+    /// see `GpuTimer`
+    fn gpu_times(&self) -> Vec<(&'static str, f32)> {
+        vec![]
+    }
+
+    /// Time from presenting a frame to it showing up on the display in
+    /// milliseconds, None unless the front-end can measure it
+    fn display_latency(&self) -> Option<f32> {
+        None
+    }
 }
@@ -0,0 +1,102 @@
+use crate::back::TracerSlot;
+use crate::common::capabilities::DeviceCapabilities;
+use crate::common::frame_graph::SyncPoint;
+use crate::error::TracerResult;
+use crate::front::headless::{HeadlessQueueFamilyIndices, HeadlessQueues, TracerHeadlessFront};
+use crate::front::share::memory::SharedFrame;
+use crate::front::Front;
+use crate::tracer::Bundle;
+use ash::{vk, Device, Entry, Instance};
+use log::warn;
+use std::ffi::c_char;
+
+/// Headless front copying every delivered frame into shared memory, where
+/// other processes pick it up without going through files or sockets.
+/// The frames are the CPU readbacks of the headless front, the traced image
+/// itself is not exported (no external memory or DMA-BUF), so every frame
+/// costs a download and a copy
+pub struct TracerShareFront {
+    headless: TracerHeadlessFront,
+}
+
+impl TracerShareFront {
+    pub(crate) fn new(name: &str) -> TracerResult<Self> {
+        let mut shared = SharedFrame::create(name)?;
+        let headless = TracerHeadlessFront::new(move |output| {
+            if let Err(e) = shared.publish(&output) {
+                warn!("Failed to publish shared frame: {}", e);
+            }
+        });
+
+        Ok(Self { headless })
+    }
+}
+
+impl Front for TracerShareFront {
+    type FrontQueueFamilyIndices = HeadlessQueueFamilyIndices;
+
+    unsafe fn get_required_image_usage_flags(
+        capabilities: &DeviceCapabilities,
+    ) -> vk::ImageUsageFlags {
+        TracerHeadlessFront::get_required_image_usage_flags(capabilities)
+    }
+
+    unsafe fn get_required_device_extensions(
+        &self,
+        available: &Vec<String>,
+        capabilities: &mut DeviceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        self.headless
+            .get_required_device_extensions(available, capabilities)
+    }
+
+    unsafe fn find_queue_families(
+        &self,
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> TracerResult<HeadlessQueueFamilyIndices> {
+        self.headless
+            .find_queue_families(entry, instance, physical_device)
+    }
+
+    unsafe fn patch_create_device_info(
+        &self,
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        device_capabilities: &DeviceCapabilities,
+        create_info: vk::DeviceCreateInfo,
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
+        self.headless.patch_create_device_info(
+            entry,
+            instance,
+            physical_device,
+            device_capabilities,
+            create_info,
+            on_patched,
+        )
+    }
+
+    unsafe fn init(&mut self, bundle: Bundle, queues: HeadlessQueues) -> TracerResult<()> {
+        self.headless.init(bundle, queues)
+    }
+
+    unsafe fn destroy(&mut self, bundle: Bundle) {
+        self.headless.destroy(bundle);
+    }
+
+    unsafe fn present(
+        &mut self,
+        bundle: Bundle,
+        w: Option<&winit::window::Window>,
+        slot: TracerSlot,
+    ) -> TracerResult<Option<SyncPoint>> {
+        self.headless.present(bundle, w, slot)
+    }
+
+    unsafe fn flush(&mut self, bundle: Bundle) -> TracerResult<()> {
+        self.headless.flush(bundle)
+    }
+}
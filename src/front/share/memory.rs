@@ -0,0 +1,142 @@
+use crate::error::{TracerError, TracerResult};
+use crate::front::headless::TracerHeadlessOutput;
+use log::{debug, warn};
+use std::ffi::CString;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: [u8; 8] = *b"PATHRSFB";
+const VERSION: u32 = 1;
+
+/// Start of the shared memory object, the pixels follow it
+#[repr(C)]
+struct SharedHeader {
+    magic: [u8; 8],
+    version: u32,
+    width: u32,
+    height: u32,
+    // Bytes per row of the tightly packed RGB888 pixels
+    stride: u32,
+    // Odd while a frame is being written, readers retry if it is odd or
+    // changed while they were copying the pixels
+    sequence: AtomicU64,
+}
+
+const HEADER_SIZE: usize = size_of::<SharedHeader>();
+
+/// POSIX shared memory object holding the latest frame, for compositors
+/// and other processes to map. The object is named `/<name>` (on Linux,
+/// `/dev/shm/<name>`) and removed once the frame is dropped. It only grows,
+/// readers map `HEADER_SIZE + stride * height` bytes after checking the header.
+/// It holds a CPU copy of the frame, not a GPU image readers could import.
+pub struct SharedFrame {
+    name: CString,
+    fd: libc::c_int,
+    memory: *mut u8,
+    // Mapped bytes, 0 until the first frame
+    size: usize,
+}
+
+// The mapping is only touched through &mut self
+unsafe impl Send for SharedFrame {}
+
+impl SharedFrame {
+    pub fn create(name: &str) -> TracerResult<Self> {
+        let name = CString::new(format!("/{}", name.trim_start_matches('/')))?;
+        debug!("Creating shared memory object {:?}", name);
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_RDWR,
+                0o600 as libc::mode_t,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            name,
+            fd,
+            memory: std::ptr::null_mut(),
+            size: 0,
+        })
+    }
+
+    unsafe fn grow(&mut self, size: usize) -> TracerResult<()> {
+        debug!("Growing shared frame {:?} to {} bytes", self.name, size);
+        self.unmap();
+        if libc::ftruncate(self.fd, size as libc::off_t) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let memory = libc::mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            self.fd,
+            0,
+        );
+        if memory == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        self.memory = memory as *mut u8;
+        self.size = size;
+        Ok(())
+    }
+
+    unsafe fn unmap(&mut self) {
+        if !self.memory.is_null() {
+            libc::munmap(self.memory as *mut libc::c_void, self.size);
+            self.memory = std::ptr::null_mut();
+            self.size = 0;
+        }
+    }
+
+    /// Replaces the frame, the sequence grows by two with every publish
+    pub fn publish(&mut self, output: &TracerHeadlessOutput) -> TracerResult<()> {
+        let stride = output.width as usize * 3;
+        if output.rgb888.len() != stride * output.height as usize {
+            return Err(TracerError::FrameSize);
+        }
+        let size = HEADER_SIZE + output.rgb888.len();
+        unsafe {
+            if size > self.size {
+                self.grow(size)?;
+            }
+
+            // Growing keeps the contents, the sequence continues
+            let header = &mut *(self.memory as *mut SharedHeader);
+            let sequence = header.sequence.load(Ordering::Relaxed);
+            let sequence = sequence + (sequence & 1);
+            header.sequence.store(sequence + 1, Ordering::Relaxed);
+            fence(Ordering::Release);
+
+            header.magic = MAGIC;
+            header.version = VERSION;
+            header.width = output.width;
+            header.height = output.height;
+            header.stride = stride as u32;
+            self.memory
+                .add(HEADER_SIZE)
+                .copy_from_nonoverlapping(output.rgb888.as_ptr(), output.rgb888.len());
+
+            header.sequence.store(sequence + 2, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SharedFrame {
+    fn drop(&mut self) {
+        unsafe {
+            self.unmap();
+            libc::close(self.fd);
+            if libc::shm_unlink(self.name.as_ptr()) != 0 {
+                warn!(
+                    "Failed to remove shared memory object {:?}: {}",
+                    self.name,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
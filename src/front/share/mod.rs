@@ -0,0 +1,24 @@
+use crate::assets::AssetManager;
+use crate::config::TracerConfig;
+use crate::error::TracerResult;
+use crate::front::share::front::TracerShareFront;
+use crate::tracer::Tracer;
+use build_info::BuildInfo;
+use glam::UVec2;
+
+mod front;
+mod memory;
+
+/// Tracer without a window publishing its output in the shared memory
+/// object `name`, see `SharedFrame` for the layout
+pub unsafe fn share_tracer(
+    config: TracerConfig,
+    asset_manager: AssetManager,
+    viewport: UVec2,
+    bi: BuildInfo,
+    name: &str,
+) -> TracerResult<Tracer<TracerShareFront>> {
+    Tracer::<TracerShareFront>::new(config, asset_manager, viewport, bi, |_, _| {
+        TracerShareFront::new(name)
+    })
+}
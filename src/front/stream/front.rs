@@ -0,0 +1,118 @@
+use crate::back::TracerSlot;
+use crate::common::capabilities::DeviceCapabilities;
+use crate::common::frame_graph::SyncPoint;
+use crate::error::TracerResult;
+use crate::front::headless::{HeadlessQueueFamilyIndices, HeadlessQueues, TracerHeadlessFront};
+use crate::front::stream::server::{StreamHandle, StreamServer};
+use crate::front::Front;
+use crate::tracer::Bundle;
+use ash::{vk, Device, Entry, Instance};
+use log::warn;
+use std::ffi::c_char;
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
+
+/// Headless front publishing the traced frames over HTTP. The readback is
+/// done by the headless front, this one only encodes the delivered frames
+/// and hands them over to the server.
+pub struct TracerStreamFront {
+    headless: TracerHeadlessFront,
+    server: StreamServer,
+}
+
+impl TracerStreamFront {
+    pub(crate) fn new(address: impl ToSocketAddrs, max_fps: f32) -> TracerResult<Self> {
+        let server = StreamServer::bind(address)?;
+        let handle = server.handle();
+        let interval = Duration::from_secs_f32(1.0 / max_fps.max(0.1));
+        let mut last_encoded: Option<Instant> = None;
+        let headless = TracerHeadlessFront::new(move |output| {
+            if !handle.has_clients() {
+                return;
+            }
+            if let Some(last) = last_encoded {
+                if last.elapsed() < interval {
+                    return;
+                }
+            }
+            last_encoded = Some(Instant::now());
+
+            match output.encode_png() {
+                Ok(png) => handle.publish(png),
+                Err(e) => warn!("Failed to encode streamed frame: {}", e),
+            }
+        });
+
+        Ok(Self { headless, server })
+    }
+}
+
+impl Front for TracerStreamFront {
+    type FrontQueueFamilyIndices = HeadlessQueueFamilyIndices;
+
+    unsafe fn get_required_image_usage_flags(
+        capabilities: &DeviceCapabilities,
+    ) -> vk::ImageUsageFlags {
+        TracerHeadlessFront::get_required_image_usage_flags(capabilities)
+    }
+
+    unsafe fn get_required_device_extensions(
+        &self,
+        available: &Vec<String>,
+        capabilities: &mut DeviceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        self.headless
+            .get_required_device_extensions(available, capabilities)
+    }
+
+    unsafe fn find_queue_families(
+        &self,
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> TracerResult<HeadlessQueueFamilyIndices> {
+        self.headless
+            .find_queue_families(entry, instance, physical_device)
+    }
+
+    unsafe fn patch_create_device_info(
+        &self,
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        device_capabilities: &DeviceCapabilities,
+        create_info: vk::DeviceCreateInfo,
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
+        self.headless.patch_create_device_info(
+            entry,
+            instance,
+            physical_device,
+            device_capabilities,
+            create_info,
+            on_patched,
+        )
+    }
+
+    unsafe fn init(&mut self, bundle: Bundle, queues: HeadlessQueues) -> TracerResult<()> {
+        self.headless.init(bundle, queues)
+    }
+
+    unsafe fn destroy(&mut self, bundle: Bundle) {
+        self.server.stop();
+        self.headless.destroy(bundle);
+    }
+
+    unsafe fn present(
+        &mut self,
+        bundle: Bundle,
+        w: Option<&winit::window::Window>,
+        slot: TracerSlot,
+    ) -> TracerResult<Option<SyncPoint>> {
+        self.headless.present(bundle, w, slot)
+    }
+
+    unsafe fn flush(&mut self, bundle: Bundle) -> TracerResult<()> {
+        self.headless.flush(bundle)
+    }
+}
@@ -0,0 +1,25 @@
+use crate::assets::AssetManager;
+use crate::config::TracerConfig;
+use crate::error::TracerResult;
+use crate::front::stream::front::TracerStreamFront;
+use crate::tracer::Tracer;
+use build_info::BuildInfo;
+use glam::UVec2;
+
+mod front;
+mod server;
+
+/// Tracer without a window serving its output at `address`,
+/// see `StreamServer` for the endpoints
+pub unsafe fn stream_tracer(
+    config: TracerConfig,
+    asset_manager: AssetManager,
+    viewport: UVec2,
+    bi: BuildInfo,
+    address: String,
+    max_fps: f32,
+) -> TracerResult<Tracer<TracerStreamFront>> {
+    Tracer::<TracerStreamFront>::new(config, asset_manager, viewport, bi, |_, _| {
+        TracerStreamFront::new(address, max_fps)
+    })
+}
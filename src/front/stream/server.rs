@@ -0,0 +1,152 @@
+use crate::common::http::{respond, HttpRequest, HttpServer};
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+const BOUNDARY: &str = "pathrs-frame";
+
+const INDEX_PAGE: &str = "<!DOCTYPE html>\
+<html><head><title>pathrs</title></head>\
+<body style=\"margin:0;background:#000\">\
+<img src=\"/stream\" style=\"display:block;margin:auto;max-width:100%\">\
+</body></html>";
+
+#[derive(Default)]
+struct LatestFrame {
+    // Incremented on every published frame, 0 means none yet
+    sequence: u64,
+    png: Arc<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    frame: Mutex<LatestFrame>,
+    published: Condvar,
+    clients: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+/// Minimal HTTP server streaming the latest frame to every connected client
+/// as a `multipart/x-mixed-replace` response, which browsers display as a
+/// continuously updating image. Endpoints:
+///
+/// - `/`: page embedding the stream
+/// - `/stream`: the stream itself
+/// - `/frame.png`: the latest frame
+pub struct StreamServer {
+    server: HttpServer,
+    shared: Arc<Shared>,
+}
+
+impl StreamServer {
+    pub fn bind(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let server = {
+            let shared = shared.clone();
+            HttpServer::bind(address, "stream", move |stream, request| {
+                Self::serve(stream, request, &shared)
+            })?
+        };
+
+        Ok(Self { server, shared })
+    }
+
+    pub(crate) fn handle(&self) -> StreamHandle {
+        StreamHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        // Streaming clients are waiting for the next frame, wake them up
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        self.shared.published.notify_all();
+        self.server.stop();
+    }
+
+    fn serve(stream: &mut TcpStream, request: HttpRequest, shared: &Shared) -> anyhow::Result<()> {
+        if request.method != "GET" {
+            return respond(stream, "405 Method Not Allowed", "text/plain", b"");
+        }
+
+        match request.path.as_str() {
+            "/" => respond(stream, "200 OK", "text/html", INDEX_PAGE.as_bytes()),
+            "/frame.png" => {
+                let png = shared.frame.lock().unwrap().png.clone();
+                if png.is_empty() {
+                    respond(stream, "503 Service Unavailable", "text/plain", b"")
+                } else {
+                    respond(stream, "200 OK", "image/png", &png)
+                }
+            }
+            "/stream" => {
+                shared.clients.fetch_add(1, Ordering::Relaxed);
+                let result = Self::stream(stream, shared);
+                shared.clients.fetch_sub(1, Ordering::Relaxed);
+                result
+            }
+            _ => respond(stream, "404 Not Found", "text/plain", b""),
+        }
+    }
+
+    fn stream(stream: &mut TcpStream, shared: &Shared) -> anyhow::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\n\
+             Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            BOUNDARY
+        )?;
+
+        let mut sent = 0;
+        loop {
+            let png = {
+                let mut frame = shared.frame.lock().unwrap();
+                while frame.sequence == sent && !shared.stopped.load(Ordering::Relaxed) {
+                    frame = shared.published.wait(frame).unwrap();
+                }
+                if shared.stopped.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                sent = frame.sequence;
+                frame.png.clone()
+            };
+
+            write!(
+                stream,
+                "--{}\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                png.len()
+            )?;
+            stream.write_all(&png)?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+    }
+}
+
+impl Drop for StreamServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Publishing side of the server, handed over to the frame callback
+#[derive(Clone)]
+pub(crate) struct StreamHandle {
+    shared: Arc<Shared>,
+}
+
+impl StreamHandle {
+    /// Whether anyone is watching, encoding is skipped otherwise
+    pub fn has_clients(&self) -> bool {
+        self.shared.clients.load(Ordering::Relaxed) > 0
+    }
+
+    pub fn publish(&self, png: Vec<u8>) {
+        let mut frame = self.shared.frame.lock().unwrap();
+        frame.sequence += 1;
+        frame.png = Arc::new(png);
+        self.shared.published.notify_all();
+    }
+}
@@ -1,6 +1,9 @@
 use crate::config::Camera;
+use crate::front::windowed::gamepad::GamepadInput;
+use crate::front::windowed::touch::{TouchGesture, TouchInput};
 use glam::{FloatExt, Vec2, Vec3};
-use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use winit::event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::keyboard::{Key, NamedKey};
 
 #[derive(Clone, Copy, Debug)]
@@ -53,9 +56,125 @@ impl PartialEq<Self> for CameraData {
     }
 }
 
+// Angle in (-PI, PI], equivalent to the given one
+fn wrap_angle(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Moves and looks around freely
+    #[default]
+    Fly,
+    /// Turns around a target point, looking at it
+    Orbit,
+}
+
+/// Camera of the orbit mode, placed on a sphere around the target
+#[derive(Clone, Copy, Debug)]
+struct Orbit {
+    target: Vec3,
+    distance: f32,
+    // Around the Y axis, zero along X
+    azimuth: f32,
+    // Above the XZ plane
+    elevation: f32,
+}
+
+impl Orbit {
+    // Closest the camera gets to the target
+    const MIN_DISTANCE: f32 = 0.01;
+    // Just short of the poles, where the azimuth is undefined
+    const MAX_ELEVATION: f32 = FRAC_PI_2 - 0.01;
+    // In radians per pixel of a finger drag
+    const TOUCH_ROTATE_SPEED: f32 = 0.005;
+    // Fraction of the distance moved per pixel of a two-finger drag
+    const TOUCH_PAN_SPEED: f32 = 0.002;
+
+    /// Orbit around the point the camera looks at the given distance away
+    fn around(camera: &CameraData, distance: f32) -> Self {
+        let direction = camera.as_direction();
+        Self {
+            target: camera.position + direction * distance,
+            distance,
+            azimuth: (-direction.z).atan2(-direction.x),
+            elevation: (-direction.y)
+                .asin()
+                .clamp(-Self::MAX_ELEVATION, Self::MAX_ELEVATION),
+        }
+    }
+
+    // From the target to the camera, normalized
+    fn offset(&self) -> Vec3 {
+        Vec3::new(
+            self.elevation.cos() * self.azimuth.cos(),
+            self.elevation.sin(),
+            self.elevation.cos() * self.azimuth.sin(),
+        )
+    }
+
+    fn rotate(&mut self, azimuth: f32, elevation: f32) {
+        self.azimuth = wrap_angle(self.azimuth + azimuth);
+        self.elevation =
+            (self.elevation + elevation).clamp(-Self::MAX_ELEVATION, Self::MAX_ELEVATION);
+    }
+
+    /// Moves closer for the factors above 1
+    fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance / factor).max(Self::MIN_DISTANCE);
+    }
+
+    /// One finger orbits, two fingers pinch and move the target along
+    fn apply_touch(&mut self, touch: &TouchGesture) {
+        self.zoom(touch.zoom);
+        self.rotate(
+            -touch.orbit.x * Self::TOUCH_ROTATE_SPEED,
+            touch.orbit.y * Self::TOUCH_ROTATE_SPEED,
+        );
+
+        // The target follows the fingers, `up` points down the screen like
+        // the pixel coordinates
+        let direction = -self.offset();
+        let right = direction.cross(Vec3::Y).normalize();
+        let up = direction.cross(right).normalize();
+        self.target -=
+            (right * touch.pan.x + up * touch.pan.y) * self.distance * Self::TOUCH_PAN_SPEED;
+    }
+
+    /// Camera data of the orbit. `CameraData::new` may produce the angles
+    /// mirrored over the pole, so the ones closest to the previous data are
+    /// picked, which also keeps the interpolation from taking the long way.
+    fn camera(&self, previous: &CameraData) -> CameraData {
+        let offset = self.offset();
+        let direction = -offset;
+        let pitch = direction.y.asin();
+        let yaw = direction.z.atan2(direction.x);
+        let (pitch, yaw) = [(pitch, yaw), (PI - pitch, yaw + PI)]
+            .into_iter()
+            .map(|(pitch, yaw)| {
+                (
+                    previous.pitch + wrap_angle(pitch - previous.pitch),
+                    previous.yaw + wrap_angle(yaw - previous.yaw),
+                )
+            })
+            .min_by(|a, b| {
+                let distance = |(pitch, _): &(f32, f32)| (pitch - previous.pitch).abs();
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap();
+        CameraData {
+            position: self.target + offset * self.distance,
+            pitch,
+            yaw,
+        }
+    }
+}
+
 struct InputState {
     mouse_pos: Vec2,
     mouse_button_pressed: bool,
+    // Wheel lines since the last tick, away from the user is positive
+    scroll: f32,
 
     forward_pressed: bool,
     back_pressed: bool,
@@ -63,6 +182,9 @@ struct InputState {
     right_pressed: bool,
     up_pressed: bool,
     down_pressed: bool,
+
+    gamepad: GamepadInput,
+    touch: TouchInput,
 }
 
 pub struct FreeCamera {
@@ -70,32 +192,55 @@ pub struct FreeCamera {
     data: CameraData,
     instant: CameraData,
     input_state: InputState,
+    mode: CameraMode,
+    // Followed in the orbit mode. In the fly mode only the distance is
+    // kept, the touch gestures orbit the point that far in front of the
+    // camera. Starts at the focus distance.
+    orbit: Orbit,
 }
 
 impl FreeCamera {
-    pub fn new(initial: Camera) -> Self {
+    // Room around the bounds of the framed object
+    const FRAME_MARGIN: f32 = 1.2;
+
+    pub fn new(initial: Camera, mode: CameraMode) -> Self {
+        let instant = CameraData::new(initial.clone());
+        let orbit = Orbit::around(&instant, initial.focus_distance.max(Orbit::MIN_DISTANCE));
         Self {
             click_pos: Vec2::ZERO,
-            data: CameraData::new(initial.clone()),
-            instant: CameraData::new(initial),
+            data: instant,
+            instant,
+            mode,
+            orbit,
             input_state: InputState {
                 mouse_pos: Default::default(),
                 mouse_button_pressed: false,
+                scroll: 0.0,
                 forward_pressed: false,
                 back_pressed: false,
                 left_pressed: false,
                 right_pressed: false,
                 up_pressed: false,
                 down_pressed: false,
+                gamepad: GamepadInput::default(),
+                touch: TouchInput::default(),
             },
         }
     }
 
     pub fn on_window_event(&mut self, event: &WindowEvent) {
         match &event {
+            WindowEvent::Touch(touch) => self.input_state.touch.on_touch(touch),
             WindowEvent::CursorMoved { position, .. } => {
                 self.input_state.mouse_pos = Vec2::new(position.x as f32, position.y as f32);
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.input_state.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => *lines,
+                    // Roughly a line of text
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            }
             WindowEvent::MouseInput {
                 state: ElementState::Pressed,
                 button: MouseButton::Right,
@@ -141,12 +286,66 @@ impl FreeCamera {
         }
     }
 
+    /// Gamepad state of the next tick
+    pub fn set_gamepad(&mut self, input: GamepadInput) {
+        self.input_state.gamepad = input;
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Switching to the orbit mode orbits the point the camera looks at,
+    /// the view is kept
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.orbit = Orbit::around(&self.instant, self.orbit.distance);
+        }
+        self.mode = mode;
+    }
+
+    /// Orbits the bounding sphere, moved away just enough for it to fit
+    /// the vertical field of view. The camera keeps looking from the same
+    /// side and glides there.
+    pub fn frame(&mut self, center: Vec3, radius: f32, vertical_fov: f32) {
+        let distance = radius / (vertical_fov * 0.5).sin() * Self::FRAME_MARGIN;
+        let mut orbit = Orbit::around(&self.instant, self.orbit.distance);
+        orbit.target = center;
+        orbit.distance = distance.max(Orbit::MIN_DISTANCE);
+        self.orbit = orbit;
+        self.mode = CameraMode::Orbit;
+        self.instant = self.orbit.camera(&self.instant);
+    }
+
     pub fn tick_handler(&mut self) -> Option<CameraData> {
-        const MOVE_SPEED: f32 = 3.0;
-        const ROTATE_SPEED: f32 = 0.001;
         const LERP: f32 = 0.00001;
 
         let delta = 1.0 / 60.0; // Assume a fixed timestep for simplicity
+        match self.mode {
+            CameraMode::Fly => self.fly(delta),
+            CameraMode::Orbit => self.orbit(delta),
+        }
+
+        // Smoothly interpolate position and rotation, which also eases
+        // the switches between the modes
+        let factor = 1.0 - LERP.powf(delta);
+        let data = self.data.lerp(&self.instant, factor.clamp(0.0, 1.0));
+        if self.data != data {
+            self.data = data;
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    fn fly(&mut self, delta: f32) {
+        const MOVE_SPEED: f32 = 3.0;
+        const ROTATE_SPEED: f32 = 0.001;
+        // In radians per second at full stick deflection
+        const STICK_ROTATE_SPEED: f32 = 2.0;
+
+        // Only the orbit mode zooms with the wheel
+        self.input_state.scroll = 0.0;
         let direction = self.instant.as_direction();
         let right = direction.cross(Vec3::Y).normalize();
         let up = direction.cross(right).normalize();
@@ -179,15 +378,67 @@ impl FreeCamera {
             // Clamp pitch to prevent gimbal lock
             self.instant.pitch = self.instant.pitch - pos_delta.y * ROTATE_SPEED;
         }
+        // An idle gamepad leaves the camera as is, so the image keeps
+        // accumulating
+        let gamepad = self.input_state.gamepad;
+        if !gamepad.is_idle() {
+            let movement = right * gamepad.movement.x - up * gamepad.movement.y
+                + direction * gamepad.movement.z;
+            self.instant.position += movement * delta * MOVE_SPEED * gamepad.speed;
+            self.instant.yaw -= gamepad.look.x * delta * STICK_ROTATE_SPEED;
+            self.instant.pitch += gamepad.look.y * delta * STICK_ROTATE_SPEED;
+        }
+        let touch = self.input_state.touch.take();
+        if !touch.is_idle() {
+            let mut orbit = Orbit::around(&self.instant, self.orbit.distance);
+            orbit.apply_touch(&touch);
+            self.orbit.distance = orbit.distance;
+            self.instant = orbit.camera(&self.instant);
+        }
+    }
 
-        // Smoothly interpolate position and rotation
-        let factor = 1.0 - LERP.powf(delta);
-        let data = self.data.lerp(&self.instant, factor.clamp(0.0, 1.0));
-        if self.data != data {
-            self.data = data;
-            Some(data)
-        } else {
-            None
+    fn orbit(&mut self, delta: f32) {
+        // In radians per second
+        const KEY_ROTATE_SPEED: f32 = 1.5;
+        const STICK_ROTATE_SPEED: f32 = 2.0;
+        // In radians per pixel of a mouse drag
+        const MOUSE_ROTATE_SPEED: f32 = 0.005;
+        // Distance factor per second of a held key or full stick deflection
+        const ZOOM_SPEED: f32 = 3.0;
+        // Distance factor per line of the wheel
+        const SCROLL_ZOOM: f32 = 1.1;
+
+        let input = &mut self.input_state;
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        self.orbit.rotate(
+            axis(input.left_pressed, input.right_pressed) * delta * KEY_ROTATE_SPEED,
+            axis(input.up_pressed, input.down_pressed) * delta * KEY_ROTATE_SPEED,
+        );
+        self.orbit.zoom(
+            ZOOM_SPEED.powf(axis(input.forward_pressed, input.back_pressed) * delta)
+                * SCROLL_ZOOM.powf(input.scroll),
+        );
+        input.scroll = 0.0;
+        if input.mouse_button_pressed {
+            let pos_delta = input.mouse_pos - self.click_pos;
+            self.click_pos = input.mouse_pos;
+            self.orbit.rotate(
+                -pos_delta.x * MOUSE_ROTATE_SPEED,
+                pos_delta.y * MOUSE_ROTATE_SPEED,
+            );
         }
+        let gamepad = input.gamepad;
+        if !gamepad.is_idle() {
+            self.orbit.rotate(
+                -gamepad.look.x * delta * STICK_ROTATE_SPEED,
+                -gamepad.look.y * delta * STICK_ROTATE_SPEED,
+            );
+            self.orbit
+                .zoom(ZOOM_SPEED.powf(gamepad.movement.z * delta * gamepad.speed));
+        }
+        let touch = input.touch.take();
+        self.orbit.apply_touch(&touch);
+
+        self.instant = self.orbit.camera(&self.instant);
     }
 }
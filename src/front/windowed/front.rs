@@ -1,20 +1,21 @@
 use crate::assets::AssetManager;
 use crate::back::TracerSlot;
 use crate::common::capabilities::{DeviceCapabilities, InstanceCapabilities};
+use crate::common::frame_graph::SyncPoint;
 use crate::common::queue::QueueFamily;
-use crate::front::windowed::pipeline::PresentationPipeline;
+use crate::error::{Context, TracerError, TracerResult};
+use crate::front::windowed::pipeline::{PresentOptions, PresentationPipeline};
 use crate::front::windowed::ui::UICompositor;
 use crate::front::{Front, QueueFamilyIndices};
 use crate::tracer::Bundle;
-use anyhow::Context;
 use ash::{vk, Device, Entry, Instance};
-use log::{debug, warn};
+use log::{debug, info, warn};
 use std::cell::RefCell;
 use std::ffi::{c_char, c_void};
 use std::rc::Rc;
 use winit::raw_window_handle::{
-    DisplayHandle, RawDisplayHandle, RawWindowHandle, WindowHandle, XlibDisplayHandle,
-    XlibWindowHandle,
+    DisplayHandle, RawDisplayHandle, RawWindowHandle, WindowHandle, XcbDisplayHandle,
+    XcbWindowHandle, XlibDisplayHandle, XlibWindowHandle,
 };
 
 #[derive(Debug, Clone)]
@@ -30,6 +31,32 @@ pub struct WindowedQueues {
     pub present_queue: vk::Queue,
 }
 
+impl WindowedQueueFamilyIndices {
+    /// Picks the first graphics family able to present, the swapchain
+    /// images are then never shared between families
+    pub fn select(
+        properties: &[vk::QueueFamilyProperties],
+        supports_present: impl Fn(u32) -> bool,
+    ) -> TracerResult<Self> {
+        let mut graphics_family = None;
+        let mut present_family = None;
+
+        for (i, queue_family) in properties.iter().enumerate() {
+            let is_graphics = queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
+            if is_graphics && supports_present(i as u32) {
+                graphics_family = Some(i as u32);
+                present_family = Some(i as u32);
+                break;
+            }
+        }
+
+        Ok(Self {
+            graphics_family: graphics_family.ok_or(TracerError::NoQueueFamily("graphics"))?,
+            present_family: present_family.ok_or(TracerError::NoQueueFamily("present"))?,
+        })
+    }
+}
+
 impl QueueFamilyIndices for WindowedQueueFamilyIndices {
     type Queues = WindowedQueues;
 
@@ -46,7 +73,7 @@ impl QueueFamilyIndices for WindowedQueueFamilyIndices {
         ]
     }
 
-    unsafe fn into_queues(self, device: &Device) -> anyhow::Result<Self::Queues> {
+    unsafe fn into_queues(self, device: &Device) -> TracerResult<Self::Queues> {
         let graphics_queue = device.get_device_queue(self.graphics_family, 0);
         let presentation_queue = device.get_device_queue(self.present_family, 0);
 
@@ -63,6 +90,11 @@ pub enum Mode {
         window: XlibWindowHandle,
         display: XlibDisplayHandle,
     },
+    Xcb {
+        window: vk::xcb_window_t,
+        connection: *mut vk::xcb_connection_t,
+        visual_id: vk::xcb_visualid_t,
+    },
     Wayland {
         window: *mut c_void,
         display: *mut c_void,
@@ -71,10 +103,12 @@ pub enum Mode {
         hwnd: *mut c_void,
         hinstance: *mut c_void,
     },
+    #[cfg(target_os = "macos")]
+    Metal { layer: raw_window_metal::Layer },
 }
 
 impl Mode {
-    pub fn from_handles(window: WindowHandle, display: DisplayHandle) -> anyhow::Result<Self> {
+    pub fn from_handles(window: WindowHandle, display: DisplayHandle) -> TracerResult<Self> {
         match (window.as_raw(), display.as_raw()) {
             (RawWindowHandle::Xlib(xlib_window), RawDisplayHandle::Xlib(xlib_display)) => {
                 Ok(Mode::XLib {
@@ -82,6 +116,9 @@ impl Mode {
                     display: xlib_display,
                 })
             }
+            (RawWindowHandle::Xcb(xcb_window), RawDisplayHandle::Xcb(xcb_display)) => {
+                Self::from_xcb(xcb_window, xcb_display)
+            }
             (
                 RawWindowHandle::Wayland(wayland_window),
                 RawDisplayHandle::Wayland(wayland_display),
@@ -93,12 +130,36 @@ impl Mode {
                 hwnd: windows_window.hwnd.get() as *mut c_void,
                 hinstance: windows_window.hinstance.unwrap().get() as *mut c_void,
             }),
-            _ => {
-                anyhow::bail!("Unsupported window/display handle combination");
-            }
+            #[cfg(target_os = "macos")]
+            (RawWindowHandle::AppKit(appkit_window), _) => Ok(Mode::Metal {
+                // Attaches a CAMetalLayer to the view (or reuses the existing one)
+                layer: unsafe { raw_window_metal::Layer::from_ns_view(appkit_window.ns_view) },
+            }),
+            _ => Err(TracerError::Unsupported(
+                "window/display handle combination".to_string(),
+            )),
         }
     }
 
+    fn from_xcb(window: XcbWindowHandle, display: XcbDisplayHandle) -> TracerResult<Self> {
+        let connection = display
+            .connection
+            .ok_or_else(|| {
+                TracerError::Unsupported("XCB display without a connection".to_string())
+            })?
+            .as_ptr() as *mut vk::xcb_connection_t;
+        let visual_id = match window.visual_id {
+            Some(id) => id.get(),
+            None => unsafe { xcb::root_visual(connection, display.screen) }?,
+        };
+
+        Ok(Mode::Xcb {
+            window: window.window.get(),
+            connection,
+            visual_id,
+        })
+    }
+
     pub unsafe fn supports_present(
         &self,
         entry: &Entry,
@@ -116,6 +177,19 @@ impl Mode {
                     window.visual_id as vk::VisualID,
                 )
             }
+            Mode::Xcb {
+                connection,
+                visual_id,
+                ..
+            } => {
+                let loader = ash::khr::xcb_surface::Instance::new(entry, instance);
+                loader.get_physical_device_xcb_presentation_support(
+                    physical_device,
+                    queue_family_index,
+                    &mut **connection,
+                    *visual_id,
+                )
+            }
             Mode::Wayland { window: _, display } => {
                 let loader = ash::khr::wayland_surface::Instance::new(entry, instance);
                 loader.get_physical_device_wayland_presentation_support(
@@ -134,6 +208,10 @@ impl Mode {
                     queue_family_index,
                 )
             }
+            // VK_EXT_metal_surface has no presentation support query,
+            // every graphics queue of MoltenVK can present
+            #[cfg(target_os = "macos")]
+            Mode::Metal { .. } => true,
         }
     }
 
@@ -141,7 +219,7 @@ impl Mode {
         &self,
         entry: &Entry,
         instance: &Instance,
-    ) -> anyhow::Result<vk::SurfaceKHR> {
+    ) -> TracerResult<vk::SurfaceKHR> {
         match self {
             Mode::XLib { window, display } => {
                 let loader = ash::khr::xlib_surface::Instance::new(entry, instance);
@@ -150,6 +228,15 @@ impl Mode {
                     .dpy(display.display.unwrap().as_ptr() as *mut vk::Display);
                 Ok(loader.create_xlib_surface(&create_info, None)?)
             }
+            Mode::Xcb {
+                window, connection, ..
+            } => {
+                let loader = ash::khr::xcb_surface::Instance::new(entry, instance);
+                let create_info = vk::XcbSurfaceCreateInfoKHR::default()
+                    .window(*window)
+                    .connection(*connection);
+                Ok(loader.create_xcb_surface(&create_info, None)?)
+            }
             Mode::Wayland { window, display } => {
                 let loader = ash::khr::wayland_surface::Instance::new(entry, instance);
                 let create_info = vk::WaylandSurfaceCreateInfoKHR::default()
@@ -164,18 +251,124 @@ impl Mode {
                     .hinstance(*hinstance as isize);
                 Ok(loader.create_win32_surface(&create_info, None)?)
             }
+            #[cfg(target_os = "macos")]
+            Mode::Metal { layer } => {
+                let loader = ash::ext::metal_surface::Instance::new(entry, instance);
+                let create_info = vk::MetalSurfaceCreateInfoEXT::default()
+                    .layer(layer.as_ptr().as_ptr() as *const vk::CAMetalLayer);
+                Ok(loader.create_metal_surface(&create_info, None)?)
+            }
         }
     }
 }
 
+/// Root visual lookup for the XCB windows created without a visual id.
+/// libxcb is already loaded by the windowing library when it hands out a
+/// connection, so it is resolved at runtime instead of linked against
+mod xcb {
+    use crate::error::{TracerError, TracerResult};
+    use ash::vk;
+    use std::ffi::{c_int, c_void, CStr};
+
+    #[repr(C)]
+    struct ScreenIterator {
+        data: *const Screen,
+        rem: c_int,
+        index: c_int,
+    }
+
+    // Leading fields of xcb_screen_t up to the root visual
+    #[repr(C)]
+    struct Screen {
+        root: u32,
+        default_colormap: u32,
+        white_pixel: u32,
+        black_pixel: u32,
+        current_input_masks: u32,
+        width_in_pixels: u16,
+        height_in_pixels: u16,
+        width_in_millimeters: u16,
+        height_in_millimeters: u16,
+        min_installed_maps: u16,
+        max_installed_maps: u16,
+        root_visual: u32,
+    }
+
+    type GetSetup = unsafe extern "C" fn(*mut vk::xcb_connection_t) -> *const c_void;
+    type RootsIterator = unsafe extern "C" fn(*const c_void) -> ScreenIterator;
+    type ScreenNext = unsafe extern "C" fn(*mut ScreenIterator);
+
+    #[cfg(unix)]
+    unsafe fn symbol<T>(library: *mut c_void, name: &CStr) -> TracerResult<T> {
+        let symbol = libc::dlsym(library, name.as_ptr());
+        if symbol.is_null() {
+            return Err(TracerError::Unsupported(format!(
+                "libxcb without {}",
+                name.to_string_lossy()
+            )));
+        }
+        Ok(std::mem::transmute_copy(&symbol))
+    }
+
+    #[cfg(unix)]
+    pub unsafe fn root_visual(
+        connection: *mut vk::xcb_connection_t,
+        screen: c_int,
+    ) -> TracerResult<vk::xcb_visualid_t> {
+        let library = libc::dlopen(c"libxcb.so.1".as_ptr(), libc::RTLD_LAZY | libc::RTLD_NOLOAD);
+        if library.is_null() {
+            return Err(TracerError::Unsupported(
+                "XCB window without a visual id and no libxcb loaded".to_string(),
+            ));
+        }
+
+        let lookup = || -> TracerResult<vk::xcb_visualid_t> {
+            let get_setup: GetSetup = symbol(library, c"xcb_get_setup")?;
+            let roots_iterator: RootsIterator = symbol(library, c"xcb_setup_roots_iterator")?;
+            let screen_next: ScreenNext = symbol(library, c"xcb_screen_next")?;
+
+            let mut iterator = roots_iterator(get_setup(connection));
+            for _ in 0..screen.max(0) {
+                if iterator.rem == 0 {
+                    break;
+                }
+                screen_next(&mut iterator);
+            }
+            if iterator.rem == 0 || iterator.data.is_null() {
+                return Err(TracerError::Unsupported(format!(
+                    "XCB screen {} does not exist",
+                    screen
+                )));
+            }
+            Ok((*iterator.data).root_visual)
+        };
+        let visual = lookup();
+        libc::dlclose(library);
+        visual
+    }
+
+    #[cfg(not(unix))]
+    pub unsafe fn root_visual(
+        _connection: *mut vk::xcb_connection_t,
+        _screen: c_int,
+    ) -> TracerResult<vk::xcb_visualid_t> {
+        Err(TracerError::Unsupported(
+            "XCB window without a visual id".to_string(),
+        ))
+    }
+}
+
 pub struct TracerWindowedFront {
     asset_manager: AssetManager,
     surface: vk::SurfaceKHR,
     viewport: glam::UVec2,
     platform: Mode,
+    // Kept for the presentation of the windows attached later
+    queues: Option<WindowedQueues>,
     runtime: Option<PresentationPipeline>,
     destroyed: bool,
     ui: Rc<RefCell<UICompositor>>,
+    options: PresentOptions,
 }
 
 impl TracerWindowedFront {
@@ -187,7 +380,8 @@ impl TracerWindowedFront {
         window: WindowHandle,
         display: DisplayHandle,
         ui: Rc<RefCell<UICompositor>>,
-    ) -> anyhow::Result<Self> {
+        options: PresentOptions,
+    ) -> TracerResult<Self> {
         let mode = Mode::from_handles(window, display)?;
 
         Ok(Self {
@@ -195,9 +389,11 @@ impl TracerWindowedFront {
             surface: mode.create_surface(entry, instance)?,
             viewport,
             platform: mode,
+            queues: None,
             runtime: None,
             destroyed: false,
             ui,
+            options,
         })
     }
 
@@ -206,7 +402,7 @@ impl TracerWindowedFront {
         entry: &Entry,
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<bool> {
+    ) -> TracerResult<bool> {
         let surface_loader = ash::khr::surface::Instance::new(entry, instance);
         let formats =
             surface_loader.get_physical_device_surface_formats(physical_device, self.surface)?;
@@ -220,6 +416,69 @@ impl TracerWindowedFront {
 
         Ok(!formats.is_empty() && !modes.is_empty())
     }
+
+    unsafe fn create_runtime(&mut self, bundle: Bundle) -> TracerResult<()> {
+        let queues = self.queues.clone().unwrap();
+        self.runtime = Some(
+            PresentationPipeline::new(
+                bundle,
+                self.asset_manager.clone(),
+                self.viewport,
+                self.surface,
+                queues,
+                self.ui.clone(),
+                self.options,
+            )
+            .context("Failed to create windowed runtime")?,
+        );
+        Ok(())
+    }
+
+    /// Destroys the presentation and the surface of a window about to go
+    /// away. The device and the back-end stay alive until `attach`.
+    pub unsafe fn detach(&mut self, bundle: Bundle) {
+        if let Some(mut runtime) = self.runtime.take() {
+            debug!("Destroying windowed runtime");
+            runtime.destroy(bundle);
+        }
+
+        if self.surface != vk::SurfaceKHR::null() {
+            debug!("Destroying windowed surface");
+            let surface = ash::khr::surface::Instance::new(bundle.entry, bundle.instance);
+            surface.destroy_surface(self.surface, None);
+            self.surface = vk::SurfaceKHR::null();
+        }
+    }
+
+    /// Presents into a new window from now on. The queue family picked for
+    /// the first window must be able to present to it as well.
+    pub unsafe fn attach(
+        &mut self,
+        bundle: Bundle,
+        viewport: glam::UVec2,
+        window: WindowHandle,
+        display: DisplayHandle,
+    ) -> TracerResult<()> {
+        self.detach(bundle);
+
+        let mode = Mode::from_handles(window, display)?;
+        let present_family = self.queues.as_ref().unwrap().indices.present_family;
+        if !mode.supports_present(
+            bundle.entry,
+            bundle.instance,
+            bundle.physical_device,
+            present_family,
+        ) {
+            return Err(TracerError::Unsupported(
+                "presentation to the new window".to_string(),
+            ));
+        }
+
+        self.surface = mode.create_surface(bundle.entry, bundle.instance)?;
+        self.platform = mode;
+        self.viewport = viewport;
+        self.create_runtime(bundle)
+    }
 }
 
 impl Front for TracerWindowedFront {
@@ -234,7 +493,7 @@ impl Front for TracerWindowedFront {
     unsafe fn get_required_instance_extensions(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![
             vk::KHR_SURFACE_NAME.as_ptr(),
             #[cfg(target_os = "windows")]
@@ -253,16 +512,74 @@ impl Front for TracerWindowedFront {
     unsafe fn get_required_instance_layers(
         _available: &Vec<String>,
         _capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         Ok(vec![])
     }
 
     unsafe fn get_required_device_extensions(
         &self,
-        _available: &Vec<String>,
-        _capabilities: &mut DeviceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
-        Ok(vec![vk::KHR_SWAPCHAIN_NAME.as_ptr()])
+        available: &Vec<String>,
+        capabilities: &mut DeviceCapabilities,
+    ) -> TracerResult<Vec<*const c_char>> {
+        let mut required = vec![vk::KHR_SWAPCHAIN_NAME.as_ptr()];
+
+        // Optional, presents are paced against the display with them
+        let present_wait = [ash::khr::present_id::NAME, ash::khr::present_wait::NAME];
+        let mut supported = true;
+        for name in present_wait {
+            supported &= available.contains(&name.to_str()?.to_string());
+        }
+        if supported {
+            info!("Present wait extensions required");
+            required.extend(present_wait.iter().map(|name| name.as_ptr()));
+            capabilities.present_wait = true;
+        }
+
+        // The UI renderer is built for either dynamic rendering or render
+        // passes, so with the feature the extension is mandatory. Without it
+        // the presentation uses a render pass.
+        #[cfg(feature = "dynamic-rendering")]
+        {
+            let name = ash::khr::dynamic_rendering::NAME;
+            if !available.contains(&name.to_str()?.to_string()) {
+                return Err(TracerError::Unsupported(format!(
+                    "device without {:?}, the UI is built for it, rebuild without the dynamic-rendering feature",
+                    name
+                )));
+            }
+            debug!("Dynamic rendering extension required");
+            required.push(name.as_ptr());
+            capabilities.dynamic_rendering = true;
+        }
+
+        Ok(required)
+    }
+
+    unsafe fn patch_create_device_info(
+        &self,
+        _entry: &Entry,
+        _instance: &Instance,
+        _physical_device: vk::PhysicalDevice,
+        device_capabilities: &DeviceCapabilities,
+        create_info: vk::DeviceCreateInfo,
+        on_patched: &mut impl FnMut(vk::DeviceCreateInfo) -> TracerResult<Device>,
+    ) -> TracerResult<Device> {
+        let mut dynamic_rendering_features =
+            vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true);
+        let mut present_id_features =
+            vk::PhysicalDevicePresentIdFeaturesKHR::default().present_id(true);
+        let mut present_wait_features =
+            vk::PhysicalDevicePresentWaitFeaturesKHR::default().present_wait(true);
+        let mut create_info = create_info;
+        if device_capabilities.dynamic_rendering {
+            create_info = create_info.push_next(&mut dynamic_rendering_features);
+        }
+        if device_capabilities.present_wait {
+            create_info = create_info
+                .push_next(&mut present_id_features)
+                .push_next(&mut present_wait_features);
+        }
+        on_patched(create_info)
     }
 
     unsafe fn is_device_suitable(
@@ -270,7 +587,7 @@ impl Front for TracerWindowedFront {
         entry: &Entry,
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<bool> {
+    ) -> TracerResult<bool> {
         let queues_ok = self
             .find_queue_families(entry, instance, physical_device)
             .is_ok();
@@ -290,67 +607,31 @@ impl Front for TracerWindowedFront {
         entry: &Entry,
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<WindowedQueueFamilyIndices> {
-        let mut graphics_family = None;
-        let mut present_family = None;
-
+    ) -> TracerResult<WindowedQueueFamilyIndices> {
         let queue_family_properties =
             instance.get_physical_device_queue_family_properties(physical_device);
-
-        for (i, queue_family) in queue_family_properties.iter().enumerate() {
-            let is_graphics = queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-            let present_support =
-                self.platform
-                    .supports_present(entry, instance, physical_device, i as u32);
-            // TODO: Check the presentation mode and formats
-
-            if is_graphics && present_support {
-                graphics_family = Some(i as u32);
-                present_family = Some(i as u32);
-                break;
-            }
-        }
-
-        Ok(WindowedQueueFamilyIndices {
-            graphics_family: graphics_family
-                .ok_or_else(|| anyhow::anyhow!("No suitable graphics queue family found"))?,
-            present_family: present_family
-                .ok_or_else(|| anyhow::anyhow!("No suitable present queue family found"))?,
+        // TODO: Check the presentation mode and formats
+        WindowedQueueFamilyIndices::select(&queue_family_properties, |index| {
+            self.platform
+                .supports_present(entry, instance, physical_device, index)
         })
     }
 
-    unsafe fn init(&mut self, bundle: Bundle, queues: WindowedQueues) -> anyhow::Result<()> {
-        self.runtime = Some(
-            PresentationPipeline::new(
-                bundle,
-                self.asset_manager.clone(),
-                self.viewport,
-                self.surface,
-                queues,
-                self.ui.clone(),
-            )
-            .context("Failed to create windowed runtime")?,
-        );
-        Ok(())
+    unsafe fn init(&mut self, bundle: Bundle, queues: WindowedQueues) -> TracerResult<()> {
+        self.queues = Some(queues);
+        self.create_runtime(bundle)
     }
 
     unsafe fn destroy(&mut self, bundle: Bundle) {
         if !self.destroyed {
-            if let Some(mut runtime) = self.runtime.take() {
-                debug!("Destroying windowed runtime");
-                runtime.destroy(bundle);
-            }
-
-            debug!("Destroying windowed surface");
-            let surface = ash::khr::surface::Instance::new(bundle.entry, bundle.instance);
-            surface.destroy_surface(self.surface, None);
+            self.detach(bundle);
             self.destroyed = true;
         } else {
             warn!("Front already destroyed");
         }
     }
 
-    unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> anyhow::Result<()> {
+    unsafe fn resize(&mut self, bundle: Bundle, size: glam::UVec2) -> TracerResult<()> {
         if let Some(runtime) = &mut self.runtime {
             runtime
                 .resize(bundle, self.surface, size)
@@ -365,15 +646,28 @@ impl Front for TracerWindowedFront {
         bundle: Bundle,
         w: Option<&winit::window::Window>,
         tracer_slot: TracerSlot,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<Option<SyncPoint>> {
         if let Some(runtime) = &mut self.runtime {
             runtime
                 .present(bundle, w.unwrap(), self.surface, tracer_slot)
                 .context("Failed to present windowed runtime")
         } else {
-            Ok(())
+            Ok(None)
         }
     }
+
+    fn gpu_times(&self) -> Vec<(&'static str, f32)> {
+        self.runtime
+            .as_ref()
+            .map(PresentationPipeline::gpu_times)
+            .unwrap_or_default()
+    }
+
+    fn display_latency(&self) -> Option<f32> {
+        self.runtime
+            .as_ref()
+            .and_then(PresentationPipeline::display_latency)
+    }
 }
 
 impl Drop for TracerWindowedFront {
@@ -383,3 +677,91 @@ impl Drop for TracerWindowedFront {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::BackQueueFamilyIndices;
+    use crate::common::queue::test_family;
+
+    #[test]
+    fn selects_first_graphics_family_able_to_present() {
+        let properties = [
+            test_family(vk::QueueFlags::COMPUTE, 1),
+            test_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE, 1),
+            test_family(vk::QueueFlags::GRAPHICS, 1),
+        ];
+        let indices = WindowedQueueFamilyIndices::select(&properties, |_| true).unwrap();
+        assert_eq!(indices.graphics_family, 1);
+        assert_eq!(indices.present_family, 1);
+    }
+
+    #[test]
+    fn skips_graphics_family_unable_to_present() {
+        let properties = [
+            test_family(vk::QueueFlags::GRAPHICS, 1),
+            test_family(vk::QueueFlags::GRAPHICS, 1),
+        ];
+        let indices = WindowedQueueFamilyIndices::select(&properties, |i| i == 1).unwrap();
+        assert_eq!(indices.graphics_family, 1);
+        assert_eq!(indices.present_family, 1);
+    }
+
+    #[test]
+    fn fails_when_only_non_graphics_family_presents() {
+        let properties = [
+            test_family(vk::QueueFlags::GRAPHICS, 1),
+            test_family(vk::QueueFlags::COMPUTE, 1),
+        ];
+        let result = WindowedQueueFamilyIndices::select(&properties, |i| i == 1);
+        assert!(matches!(
+            result,
+            Err(TracerError::NoQueueFamily("graphics"))
+        ));
+    }
+
+    // The back-end shares the families with the front, all of them
+    // have to end up in a single request per family
+    fn merged(properties: &[vk::QueueFamilyProperties]) -> Vec<QueueFamily> {
+        let back = BackQueueFamilyIndices::select(properties, true).unwrap();
+        let front = WindowedQueueFamilyIndices::select(properties, |i| i == 0).unwrap();
+        let mut requested = back.as_families();
+        requested.extend(front.as_families());
+        QueueFamily::merge_queues(requested, properties).unwrap()
+    }
+
+    #[test]
+    fn shared_family_merges_with_back() {
+        let properties = [test_family(
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER,
+            2,
+        )];
+
+        let merged = merged(&properties);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].index, 0);
+        // Main queue shared by everything, background compute below it
+        assert_eq!(merged[0].priorities.len(), 2);
+        assert_eq!(merged[0].priorities[0], 1.0);
+        assert!(merged[0].priorities[1] < 1.0);
+    }
+
+    #[test]
+    fn discrete_families_merge_with_back() {
+        let properties = [
+            test_family(
+                vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER,
+                4,
+            ),
+            test_family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 4),
+            test_family(vk::QueueFlags::TRANSFER, 4),
+        ];
+
+        let merged = merged(&properties);
+        let indices: Vec<u32> = merged.iter().map(|family| family.index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert_eq!(merged[0].priorities, vec![1.0]);
+        assert_eq!(merged[1].priorities.len(), 2);
+        assert_eq!(merged[2].priorities, vec![1.0]);
+    }
+}
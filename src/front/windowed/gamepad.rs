@@ -0,0 +1,102 @@
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use glam::{Vec2, Vec3};
+use log::{info, warn};
+
+// Stick deflection ignored around the center, worn sticks never rest at zero
+const DEAD_ZONE: f32 = 0.15;
+// Move speed multipliers of the fully pressed triggers
+const FAST: f32 = 4.0;
+const SLOW: f32 = 0.25;
+
+/// State of the sticks, triggers and shoulder buttons for a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadInput {
+    // Along right, up and forward in [-1, 1]
+    pub movement: Vec3,
+    // Yaw and pitch rates in [-1, 1], up looks up
+    pub look: Vec2,
+    // Multiplier of the move speed, the right trigger speeds up and the
+    // left one slows down
+    pub speed: f32,
+}
+
+impl GamepadInput {
+    /// Nothing is deflected, the camera stays and the samples accumulate
+    pub fn is_idle(&self) -> bool {
+        self.movement == Vec3::ZERO && self.look == Vec2::ZERO
+    }
+}
+
+/// Flies the free camera with a gamepad: the left stick moves, the right
+/// stick looks around, the shoulder buttons move down and up and the
+/// triggers change the speed. The gamepad used last is followed.
+pub struct GamepadInputs {
+    // None if the platform has no gamepad support
+    gilrs: Option<Gilrs>,
+    active: Option<GamepadId>,
+}
+
+impl Default for GamepadInputs {
+    fn default() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                warn!("Gamepads are not available: {}", e);
+                None
+            }
+        };
+        Self {
+            gilrs,
+            active: None,
+        }
+    }
+}
+
+impl GamepadInputs {
+    /// Processes the events since the last call and reads the active gamepad
+    pub fn poll(&mut self) -> GamepadInput {
+        let Some(gilrs) = &mut self.gilrs else {
+            return GamepadInput::default();
+        };
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    info!("Gamepad connected: {}", gilrs.gamepad(event.id).name());
+                    self.active = Some(event.id);
+                }
+                EventType::Disconnected => {
+                    info!("Gamepad disconnected");
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                    }
+                }
+                _ => self.active = Some(event.id),
+            }
+        }
+
+        let Some(gamepad) = self.active.and_then(|id| gilrs.connected_gamepad(id)) else {
+            return GamepadInput::default();
+        };
+        let stick = |x: Axis, y: Axis| {
+            let value = Vec2::new(gamepad.value(x), gamepad.value(y));
+            let length = value.length();
+            if length <= DEAD_ZONE {
+                Vec2::ZERO
+            } else {
+                // Rescaled to start from zero at the edge of the dead zone
+                value / length * ((length - DEAD_ZONE) / (1.0 - DEAD_ZONE)).min(1.0)
+            }
+        };
+        let trigger = |button: Button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+        let pressed = |button: Button| if gamepad.is_pressed(button) { 1.0 } else { 0.0 };
+
+        let walk = stick(Axis::LeftStickX, Axis::LeftStickY);
+        let rise = pressed(Button::RightTrigger) - pressed(Button::LeftTrigger);
+        GamepadInput {
+            movement: Vec3::new(walk.x, rise, walk.y),
+            look: stick(Axis::RightStickX, Axis::RightStickY),
+            speed: FAST.powf(trigger(Button::RightTrigger2))
+                * SLOW.powf(trigger(Button::LeftTrigger2)),
+        }
+    }
+}
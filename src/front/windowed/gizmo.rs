@@ -0,0 +1,244 @@
+use crate::camera;
+use crate::config::{Camera, Object};
+use crate::front::windowed::ui::RenderViewport;
+use glam::{UVec2, Vec2, Vec3};
+
+// Pointer distance in points within which a handle is grabbed
+const GRAB_DISTANCE: f32 = 8.0;
+// Axis length as a fraction of the half screen height, so the gizmo
+// keeps its size on the screen regardless of the object distance
+const AXIS_SCREEN_LENGTH: f32 = 0.25;
+const MIN_RADIUS: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Handle {
+    // Translation along the world axis X, Y or Z
+    Axis(usize),
+    // Sphere radius, dragged as the silhouette ring
+    Radius,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Drag {
+    handle: Handle,
+    object: usize,
+    start_pointer: Vec2,
+    start_center: Vec3,
+    start_radius: f32,
+    // Screen offset of the axis end in points, and its world length
+    axis: Vec2,
+    axis_length: f32,
+}
+
+/// Maps the world to the viewport points the same way the tracer maps
+/// pixels to rays, including the letterboxing of the traced image
+struct Projection {
+    position: Vec3,
+    right: Vec3,
+    up: Vec3,
+    back: Vec3,
+    scale: f32,
+    aspect: f32,
+    offset: Vec2,
+    size: Vec2,
+}
+
+impl Projection {
+    fn new(
+        camera: &Camera,
+        viewport: RenderViewport,
+        render_size: Option<UVec2>,
+        pixels_per_point: f32,
+    ) -> Self {
+        let transform = camera.as_transform();
+        let image = camera::fit_aspect(render_size.unwrap_or(viewport.size), camera.aspect_ratio);
+        let (offset, size) = camera::letterbox(viewport.size, image);
+        let offset = viewport.offset + offset;
+        Self {
+            position: camera.position,
+            right: transform.x_axis.truncate(),
+            up: transform.y_axis.truncate(),
+            back: transform.z_axis.truncate(),
+            scale: (camera.vertical_fov() * 0.5).tan(),
+            aspect: size.x as f32 / size.y.max(1) as f32,
+            offset: offset.as_vec2() / pixels_per_point,
+            size: size.as_vec2() / pixels_per_point,
+        }
+    }
+
+    // None behind the camera
+    fn project(&self, point: Vec3) -> Option<Vec2> {
+        let relative = point - self.position;
+        let view = Vec3::new(
+            relative.dot(self.right),
+            relative.dot(self.up),
+            relative.dot(self.back),
+        );
+        if view.z >= 0.0 {
+            return None;
+        }
+
+        let mut ndc = Vec2::new(view.x, view.y) / (-view.z * self.scale);
+        ndc.x /= self.aspect;
+        Some(self.offset + (ndc * 0.5 + 0.5) * self.size)
+    }
+
+    // World length covering the fraction of the half screen height at the point
+    fn world_length(&self, point: Vec3, fraction: f32) -> f32 {
+        -(point - self.position).dot(self.back) * self.scale * fraction
+    }
+}
+
+/// Translate and radius handles drawn over the selected object
+#[derive(Default)]
+pub struct Gizmo {
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    const AXIS_COLORS: [egui::Color32; 3] = [
+        egui::Color32::from_rgb(230, 70, 70),
+        egui::Color32::from_rgb(70, 200, 70),
+        egui::Color32::from_rgb(70, 110, 240),
+    ];
+
+    /// The pointer is held on a handle, clicks belong to the gizmo
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Draws the handles of the object and applies the drag to it.
+    /// `viewport` is where the image is presented in the window,
+    /// `render_size` the fixed size of the traced one, if any.
+    /// Returns true if the object has moved.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        camera: &Camera,
+        viewport: RenderViewport,
+        render_size: Option<UVec2>,
+        index: usize,
+        object: &mut Object,
+    ) -> bool {
+        let projection = Projection::new(camera, viewport, render_size, ctx.pixels_per_point());
+        let (center, mut radius) = match object {
+            Object::Sphere { center, radius, .. } => (center, Some(radius)),
+            Object::Sdf { center, .. } => (center, None),
+        };
+
+        let (pointer, pressed, down) = ctx.input(|i| {
+            (
+                i.pointer.interact_pos().map(|pos| Vec2::new(pos.x, pos.y)),
+                i.pointer.primary_pressed(),
+                i.pointer.primary_down(),
+            )
+        });
+        if !down || self.drag.is_some_and(|drag| drag.object != index) {
+            self.drag = None;
+        }
+
+        let before = (*center, radius.as_deref().copied());
+        if let (Some(drag), Some(pointer)) = (self.drag, pointer) {
+            let delta = pointer - drag.start_pointer;
+            match drag.handle {
+                Handle::Axis(axis) => {
+                    let along = delta.dot(drag.axis) / drag.axis.length_squared().max(1.0);
+                    let mut direction = Vec3::ZERO;
+                    direction[axis] = 1.0;
+                    *center = drag.start_center + direction * along * drag.axis_length;
+                }
+                Handle::Radius => {
+                    if let (Some(radius), Some(origin)) =
+                        (radius.as_deref_mut(), projection.project(drag.start_center))
+                    {
+                        let scale = (pointer - origin).length()
+                            / (drag.start_pointer - origin).length().max(1.0);
+                        *radius = (drag.start_radius * scale).max(MIN_RADIUS);
+                    }
+                }
+            }
+        }
+        let changed = before != (*center, radius.as_deref().copied());
+
+        let Some(origin) = projection.project(*center) else {
+            return changed;
+        };
+        let axis_length = projection.world_length(*center, AXIS_SCREEN_LENGTH);
+        let axes: Vec<(usize, Vec2)> = (0..3)
+            .filter_map(|axis| {
+                let mut direction = Vec3::ZERO;
+                direction[axis] = axis_length;
+                projection
+                    .project(*center + direction)
+                    .map(|end| (axis, end))
+            })
+            .collect();
+        // Silhouette of the sphere, approximated by its radius across the view
+        let ring = radius.as_deref().and_then(|radius| {
+            projection
+                .project(*center + projection.right * *radius)
+                .map(|edge| (edge - origin).length())
+        });
+
+        let hovered = pointer.and_then(|pointer| {
+            let axis = axes
+                .iter()
+                .map(|(axis, end)| (*axis, distance_to_segment(pointer, origin, *end)))
+                .filter(|(_, distance)| *distance < GRAB_DISTANCE)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(axis, _)| Handle::Axis(axis));
+            axis.or_else(|| {
+                ring.filter(|ring| ((pointer - origin).length() - ring).abs() < GRAB_DISTANCE)
+                    .map(|_| Handle::Radius)
+            })
+        });
+        if pressed && self.drag.is_none() && !ctx.is_pointer_over_area() {
+            if let (Some(handle), Some(pointer)) = (hovered, pointer) {
+                self.drag = Some(Drag {
+                    handle,
+                    object: index,
+                    start_pointer: pointer,
+                    start_center: *center,
+                    start_radius: radius.as_deref().copied().unwrap_or(0.0),
+                    axis: axes
+                        .iter()
+                        .find(|(axis, _)| handle == Handle::Axis(*axis))
+                        .map_or(Vec2::ZERO, |(_, end)| *end - origin),
+                    axis_length,
+                });
+            }
+        }
+
+        let active = self.drag.map(|drag| drag.handle).or(hovered);
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Background,
+            egui::Id::new("gizmo"),
+        ));
+        let pos = |point: Vec2| egui::pos2(point.x, point.y);
+        if let Some(ring) = ring {
+            let color = if active == Some(Handle::Radius) {
+                egui::Color32::YELLOW
+            } else {
+                egui::Color32::from_white_alpha(160)
+            };
+            painter.circle_stroke(pos(origin), ring, egui::Stroke::new(1.5, color));
+        }
+        for (axis, end) in &axes {
+            let color = if active == Some(Handle::Axis(*axis)) {
+                egui::Color32::YELLOW
+            } else {
+                Self::AXIS_COLORS[*axis]
+            };
+            painter.line_segment([pos(origin), pos(*end)], egui::Stroke::new(3.0, color));
+            painter.circle_filled(pos(*end), 5.0, color);
+        }
+
+        changed
+    }
+}
+
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((point - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    point.distance(a + ab * t)
+}
@@ -1,41 +1,64 @@
 use crate::assets::AssetManager;
-use crate::config::TracerConfig;
-use crate::fps::{FPSResult, Fps};
+use crate::config::{Camera, TracerConfig};
+use crate::crash;
+use crate::demo::FallingSpheres;
+use crate::error::TracerError;
+use crate::fps::{FPSResult, Fps, FrameStats};
+use crate::front::headless::{sequence_frame_path, FloatDump, Turntable};
 use crate::front::windowed::front::TracerWindowedFront;
-use crate::front::windowed::ui::UICompositor;
+use crate::front::windowed::pipeline::PresentOptions;
+use crate::front::windowed::ring::FrameRing;
+use crate::front::windowed::ui::{TurntableSettings, UICompositor};
+use crate::remote::RemoteServer;
+use crate::script::SceneScript;
+use crate::settings::Settings;
 use crate::tracer::Tracer;
 use build_info::BuildInfo;
-use glam::UVec2;
-use log::info;
+use glam::{IVec2, UVec2};
+use log::{error, info, warn};
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use winit::application::ApplicationHandler;
-use winit::dpi::{PhysicalSize, Size};
-use winit::event::{KeyEvent, WindowEvent};
+use winit::dpi::{PhysicalPosition, PhysicalSize, Size};
+use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
 use winit::keyboard::{Key, NamedKey};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::window::{Window, WindowAttributes, WindowId};
 
 mod front;
+mod gizmo;
+mod overlay;
 mod pipeline;
+mod present_wait;
 mod quad;
+mod ring;
 mod ui;
 mod free_cam;
+mod gamepad;
+mod touch;
 
 struct Context {
     fps: Fps,
-    window: Window,
+    // Dropped before the window, the surface is created from it
     tracer: Tracer<TracerWindowedFront>,
+    window: Window,
     ui: Rc<RefCell<UICompositor>>,
+    // Last size the back-end was asked to trace at, see `follow_render_viewport`
+    back_viewport: UVec2,
 }
 
 impl Context {
-    fn title(build_info: &BuildInfo, fps: Option<f32>) -> String {
+    fn title(build_info: &BuildInfo, fps: Option<(f32, FrameStats)>) -> String {
         match fps {
-            Some(fps) => format!(
-                "{} (v{}) - {:.2} FPS",
-                build_info.crate_info.name, build_info.crate_info.version, fps
+            Some((fps, stats)) => format!(
+                "{} (v{}) - {:.2} FPS (1% low {:.2}, p99 {:.2} ms)",
+                build_info.crate_info.name,
+                build_info.crate_info.version,
+                fps,
+                stats.low_1_fps,
+                stats.p99_ms
             ),
             None => format!(
                 "{} (v{})",
@@ -43,14 +66,91 @@ impl Context {
             ),
         }
     }
+
+    /// The back-end traces the part of the window left by the UI panels.
+    /// Dragging them rebuilds it once, after they settle.
+    fn follow_render_viewport(&mut self, viewport: UVec2) {
+        let Some(render_viewport) = self.ui.borrow().render_viewport() else {
+            return;
+        };
+        let size = render_viewport.within(viewport).size;
+        if size != self.back_viewport {
+            self.back_viewport = size;
+            self.tracer.resize_back_deferred(size);
+        }
+    }
+}
+
+/// Turntable traced in the window, an image is written once enough frames
+/// accumulated and the camera moves on
+struct TurntableCapture {
+    turntable: Turntable,
+    frames: u64,
+    index: usize,
+    // Numbered like the headless sequences
+    path: PathBuf,
+}
+
+impl TurntableCapture {
+    /// Images go into a new directory named after the current time
+    fn new(camera: Camera, settings: TurntableSettings) -> std::io::Result<Self> {
+        let dir = PathBuf::from(
+            chrono::Local::now()
+                .format("pathrs-turntable-%Y%m%d-%H%M%S")
+                .to_string(),
+        );
+        std::fs::create_dir_all(&dir)?;
+        let turntable = Turntable::new(camera, settings.duration, settings.fps);
+        info!(
+            "Capturing {} turntable images into {}",
+            turntable.count(),
+            dir.display()
+        );
+        Ok(Self {
+            turntable,
+            frames: settings.frames,
+            index: 0,
+            path: dir.join("turntable.png"),
+        })
+    }
+
+    unsafe fn save(&self, tracer: &mut Tracer<TracerWindowedFront>) -> anyhow::Result<()> {
+        let path = sequence_frame_path(&self.path, self.index);
+        if let Some(output) = tracer.snapshot()? {
+            info!(
+                "Saving turntable image {} to {}",
+                self.index,
+                path.display()
+            );
+            std::fs::write(&path, output.encode_png()?)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct TracerApp {
     build_info: BuildInfo,
     asset_manager: AssetManager,
+    // Zero while the window is minimized
     viewport: UVec2,
     config: TracerConfig,
     context: Option<Context>,
+    // Tracer and UI of the destroyed window while the application is
+    // suspended, attached to the window created on resume
+    parked: Option<(Tracer<TracerWindowedFront>, Rc<RefCell<UICompositor>>)>,
+    remote: Option<RemoteServer>,
+    // Recent frames dumped with F9, None unless enabled
+    frame_ring: Option<FrameRing>,
+    // 0 for unlimited
+    max_fps: f32,
+    // Updated with the window geometry and saved on exit
+    settings: Settings,
+    // Simulated before every frame, stopped by loading another scene
+    demo: Option<FallingSpheres>,
+    // Script of the config, run before every frame
+    script: Option<SceneScript>,
+    // Started from the Camera menu, stopped by loading another scene
+    turntable: Option<TurntableCapture>,
 }
 
 impl TracerApp {
@@ -59,13 +159,111 @@ impl TracerApp {
         asset_manager: AssetManager,
         initial_viewport: UVec2,
         bi: BuildInfo,
+        remote: Option<RemoteServer>,
+        frame_ring: usize,
+        max_fps: f32,
+        settings: Settings,
+        demo: bool,
     ) -> Self {
+        let demo = demo.then(|| FallingSpheres::new(&config.0.borrow()));
         Self {
             viewport: initial_viewport,
             build_info: bi,
             context: None,
+            parked: None,
             config,
             asset_manager,
+            remote,
+            frame_ring: (frame_ring > 0).then(|| FrameRing::new(frame_ring)),
+            max_fps,
+            settings,
+            demo,
+            script: None,
+            turntable: None,
+        }
+    }
+}
+
+impl TracerApp {
+    /// Nothing is traced or presented until the window is restored. Takes
+    /// the viewport alone, the caller may be holding the context.
+    fn is_minimized(viewport: UVec2) -> bool {
+        viewport.x == 0 || viewport.y == 0
+    }
+
+    /// Writes the recent frames and the current config for a bug report
+    fn dump_frames(&self) {
+        let Some(ring) = &self.frame_ring else {
+            warn!("Frame ring is disabled, start with --frame-ring to keep recent frames");
+            return;
+        };
+        match serde_json::to_string_pretty(&self.config) {
+            Ok(config) => ring.dump(Path::new("."), config),
+            Err(e) => warn!("Failed to serialize config: {}", e),
+        }
+    }
+
+    /// Writes the traced image with its exact values for analysis
+    unsafe fn dump_float(tracer: &mut Tracer<TracerWindowedFront>, config: &TracerConfig) {
+        let path = chrono::Local::now()
+            .format("pathrs-float-%Y%m%d-%H%M%S.bin")
+            .to_string();
+        match FloatDump::capture(tracer, config) {
+            Ok(Some(dump)) => {
+                if let Err(e) = dump.write(Path::new(&path)) {
+                    warn!("Failed to write float dump {}: {:#}", path, e);
+                }
+            }
+            Ok(None) => warn!("No frame has been traced yet"),
+            Err(e) => warn!("Failed to capture float dump: {:#}", e),
+        }
+    }
+
+    /// Moves the camera of the config, the free camera is moved along so
+    /// it does not drag the view back
+    fn set_camera(config: &TracerConfig, ui: &mut UICompositor, camera: Camera) {
+        {
+            let mut cfg = config.0.borrow_mut();
+            cfg.camera = camera;
+            cfg.updated = true;
+        }
+        ui.reset_camera();
+    }
+
+    /// Runs the script of the config, loading it first if the config
+    /// references another one
+    fn run_script(&mut self) {
+        let path = self.config.0.borrow().script.clone();
+        let current = self.script.as_ref().map(SceneScript::path);
+        if current != path.as_deref().map(Path::new) {
+            self.script = path.map(|path| SceneScript::new(Path::new(&path)));
+        }
+        if let Some(script) = &mut self.script {
+            script.update(&mut self.config.0.borrow_mut());
+        }
+    }
+
+    /// Replaces the config with the scene file. Scenes are configs, so
+    /// anything `TracerConfig::load` understands can be opened.
+    fn load_scene(&mut self, path: &Path) {
+        let loaded = match TracerConfig::load(path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!("Failed to load scene {}: {:#}", path.display(), e);
+                return;
+            }
+        };
+
+        info!("Loaded scene {}", path.display());
+        self.config.replace(loaded.0.borrow().clone());
+        self.settings.add_recent_scene(path);
+        // The simulated spheres are gone
+        self.demo = None;
+        self.turntable = None;
+        if let Some(context) = &self.context {
+            let mut ui = context.ui.borrow_mut();
+            ui.reset_camera();
+            ui.set_recent_scenes(self.settings.recent_scenes.clone());
         }
     }
 }
@@ -73,8 +271,11 @@ impl TracerApp {
 impl ApplicationHandler for TracerApp {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let size = Size::Physical(PhysicalSize::new(self.viewport.x, self.viewport.y));
-        let attributes =
+        let mut attributes =
             WindowAttributes::default().with_title(Context::title(&self.build_info, None));
+        if let Some(position) = self.settings.window_position {
+            attributes = attributes.with_position(PhysicalPosition::new(position.x, position.y));
+        }
 
         #[cfg(target_os = "linux")]
         let attributes = {
@@ -93,9 +294,52 @@ impl ApplicationHandler for TracerApp {
         let context = UICompositor::new_context();
         let id = context.viewport_id();
         let state = egui_winit::State::new(context, id, &window, None, None, None);
+
+        if let Some((mut tracer, ui)) = self.parked.take() {
+            ui.borrow_mut().reattach(state);
+            unsafe {
+                tracer
+                    .with_front(|bundle, front| {
+                        front.attach(
+                            bundle,
+                            self.viewport,
+                            window.window_handle()?,
+                            window.display_handle()?,
+                        )
+                    })
+                    .unwrap();
+                tracer.resize_front(self.viewport).unwrap();
+            }
+
+            self.context = Some(Context {
+                fps: Fps::new(),
+                window,
+                tracer,
+                ui,
+                // Unknown, the layout of the first frame resizes it
+                back_viewport: UVec2::ZERO,
+            });
+            info!("Attached tracer to the new window");
+            return;
+        }
+
         let ui = Rc::new(RefCell::new(UICompositor::new(state, self.config.clone())));
+        ui.borrow_mut()
+            .set_recent_scenes(self.settings.recent_scenes.clone());
+        if self.config.0.borrow().profiler_friendly {
+            ui.borrow_mut().set_visible(false);
+        }
 
-        let tracer = unsafe {
+        let options = {
+            let config = self.config.0.borrow();
+            PresentOptions {
+                vsync: self.settings.vsync.unwrap_or(true),
+                frames_in_flight: config.frames_in_flight as usize,
+                swapchain_images: config.swapchain_images,
+                profiler_friendly: config.profiler_friendly,
+            }
+        };
+        let mut tracer = unsafe {
             Tracer::<TracerWindowedFront>::new(
                 self.config.clone(),
                 self.asset_manager.clone(),
@@ -110,17 +354,21 @@ impl ApplicationHandler for TracerApp {
                         window.window_handle()?,
                         window.display_handle()?,
                         ui.clone(),
+                        options,
                     )
                 },
             )
             .unwrap()
         };
+        // Shows the scene while the environment map is still loading
+        tracer.set_async_assets(true);
 
         self.context = Some(Context {
             fps: Fps::new(),
             window,
             tracer,
             ui,
+            back_viewport: self.viewport,
         });
 
         info!("Initialized windowed tracer");
@@ -128,6 +376,7 @@ impl ApplicationHandler for TracerApp {
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
         let context = self.context.as_mut().unwrap();
+        let mut scene = None;
 
         {
             let ui = &mut context.ui.borrow_mut();
@@ -139,30 +388,111 @@ impl ApplicationHandler for TracerApp {
             WindowEvent::Resized(physical_size) => unsafe {
                 info!("Window resized to {:?}", physical_size);
                 self.viewport = UVec2::new(physical_size.width, physical_size.height);
-                context.tracer.resize(self.viewport).unwrap();
+                if Self::is_minimized(self.viewport) {
+                    // Keep the images of the last size for the restore
+                    info!("Window minimized, suspending tracing");
+                    return;
+                }
+                // The back-end follows the render viewport of the next layout
+                context.tracer.resize_front(self.viewport).unwrap();
             },
+            WindowEvent::RedrawRequested if Self::is_minimized(self.viewport) => {}
             WindowEvent::RedrawRequested => unsafe {
-                context.tracer.trace(Some(&context.window)).unwrap();
+                context.fps.pace(self.max_fps);
+                if let Some(demo) = &mut self.demo {
+                    demo.update(&mut self.config.0.borrow_mut());
+                }
+                self.run_script();
+                context.follow_render_viewport(self.viewport);
+                match context.tracer.trace(Some(&context.window)) {
+                    Err(e) if matches!(e.root_cause(), TracerError::DeviceHung(_)) => {
+                        // Nothing can be drawn anymore, the title is all that is left
+                        error!("{}, exiting", e.root_cause());
+                        crash::report(&e.into());
+                        context.window.set_title(&format!(
+                            "{} - GPU not responding",
+                            Context::title(&self.build_info, None)
+                        ));
+                        event_loop.exit();
+                        return;
+                    }
+                    result => result.unwrap(),
+                }
+                if let Some(remote) = &self.remote {
+                    remote.poll(&mut context.tracer, &self.config);
+                }
+                if let Some(ring) = self.frame_ring.as_mut().filter(|ring| ring.should_capture()) {
+                    match context.tracer.snapshot_raw() {
+                        Ok(Some((dimensions, pixels))) => ring.capture(dimensions, pixels),
+                        Ok(None) => {}
+                        Err(e) => warn!("Failed to capture frame: {:#}", e),
+                    }
+                }
 
                 match context.fps.update() {
                     FPSResult::Updated(fps) => {
+                        let stats = context.fps.stats();
                         context
                             .window
-                            .set_title(&Context::title(&self.build_info, Some(fps)));
+                            .set_title(&Context::title(&self.build_info, Some((fps, stats))));
+                        context.ui.borrow_mut().set_frame_stats(stats);
                     }
                     FPSResult::Cached(fps) => {
                         context.ui.borrow_mut().set_fps(fps);
                     }
                 }
-                context
-                    .ui
-                    .borrow_mut()
-                    .set_tracer_profile(context.tracer.get_profile());
+                let mut ui = context.ui.borrow_mut();
+                ui.set_tracer_profile(context.tracer.get_profile());
+                scene = ui.take_scene_request();
+                if ui.take_float_dump_request() {
+                    Self::dump_float(&mut context.tracer, &self.config);
+                }
+                if let Some(settings) = ui.take_turntable_request() {
+                    let camera = self.config.0.borrow().camera.clone();
+                    match TurntableCapture::new(camera, settings) {
+                        Ok(capture) => {
+                            Self::set_camera(&self.config, &mut ui, capture.turntable.camera(0));
+                            self.turntable = Some(capture);
+                        }
+                        Err(e) => warn!("Failed to start turntable capture: {}", e),
+                    }
+                } else if let Some(capture) = self
+                    .turntable
+                    .as_mut()
+                    .filter(|capture| context.tracer.frame_index() >= capture.frames)
+                {
+                    if let Err(e) = capture.save(&mut context.tracer) {
+                        warn!("Failed to save turntable image {}: {:#}", capture.index, e);
+                    }
+                    capture.index += 1;
+                    if capture.index < capture.turntable.count() {
+                        let camera = capture.turntable.camera(capture.index);
+                        Self::set_camera(&self.config, &mut ui, camera);
+                    } else {
+                        info!("Captured {} turntable images", capture.index);
+                        let camera = capture.turntable.start().clone();
+                        Self::set_camera(&self.config, &mut ui, camera);
+                        self.turntable = None;
+                    }
+                }
             },
+            WindowEvent::DroppedFile(path) => {
+                info!("Dropped file {}", path.display());
+                scene = Some(path);
+            }
             WindowEvent::CloseRequested => {
                 info!("Close requested, exiting event loop");
                 event_loop.exit();
             }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: Key::Named(NamedKey::F9),
+                        state: ElementState::Released,
+                        ..
+                    },
+                ..
+            } => self.dump_frames(),
             // Close on escape
             WindowEvent::KeyboardInput {
                 event:
@@ -181,16 +511,69 @@ impl ApplicationHandler for TracerApp {
                 context.window.request_redraw();
             }
         }
+
+        if let Some(path) = scene {
+            self.load_scene(&path);
+        }
     }
 
     fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+        // Sleep until the window is restored
+        if Self::is_minimized(self.viewport) {
+            return;
+        }
         if let Some(context) = self.context.as_mut() {
             context.window.request_redraw();
         }
     }
 
-    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let minimized = Self::is_minimized(self.viewport);
+        if let Some(context) = &self.context {
+            context.ui.borrow().save_settings();
+        }
+        if let Some((_, ui)) = &self.parked {
+            ui.borrow().save_settings();
+        }
+        // A minimized window has no geometry worth restoring
+        if let Some(context) = self.context.as_ref().filter(|_| !minimized) {
+            let size = context.window.inner_size();
+            self.settings.window_size = Some(UVec2::new(size.width, size.height));
+            // Not available on every platform, e.g. Wayland
+            self.settings.window_position = context
+                .window
+                .outer_position()
+                .ok()
+                .map(|position| IVec2::new(position.x, position.y));
+        }
+        self.settings.resolution_scale = Some(self.config.0.borrow().resolution_scale);
+        if let Err(e) = self.settings.save() {
+            warn!("Failed to save settings: {:#}", e);
+        }
+        // Tear the tracer down while the window and the display are alive
         self.context = None;
+        self.parked = None;
+        info!("Destroyed window on exit");
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(context) = self.context.take() else {
+            return;
+        };
+
+        // Only the surface goes away with the window, the device and the
+        // back-end are kept for the next one
+        let Context {
+            mut tracer,
+            window,
+            ui,
+            ..
+        } = context;
+        unsafe {
+            tracer.with_front(|bundle, front| front.detach(bundle));
+        }
+        drop(window);
+        self.parked = Some((tracer, ui));
         info!("Suspended application and destroyed window");
     }
 }
@@ -0,0 +1,520 @@
+use crate::assets::AssetManager;
+use crate::common::command_buffer::CommandBuffer;
+use crate::common::shader::Shader;
+use crate::config::{Camera, TracerConfigInner};
+use crate::error::{Context, TracerResult};
+use crate::tracer::Bundle;
+use ash::vk;
+use glam::{Mat4, Vec3, Vec4};
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use log::{debug, warn};
+use std::mem::offset_of;
+
+const VERTEX_SHADER: &str = "overlay_vertex";
+const FRAGMENT_SHADER: &str = "overlay_fragment";
+// Lines closer to the camera are clipped
+const NEAR: f32 = 0.001;
+const CIRCLE_SEGMENTS: usize = 48;
+const BOUNDS_COLOR: Vec4 = Vec4::new(0.3, 0.9, 0.5, 0.6);
+const SELECTED_COLOR: Vec4 = Vec4::new(1.0, 0.6, 0.1, 1.0);
+// Ground grid around the camera, in world units
+const GRID_SPACING: f32 = 1.0;
+const GRID_HALF_LINES: i32 = 20;
+const GRID_COLOR: Vec4 = Vec4::new(0.6, 0.6, 0.6, 0.35);
+const AXIS_LENGTH: f32 = 1.0;
+const AXIS_COLORS: [Vec4; 3] = [
+    Vec4::new(0.9, 0.2, 0.2, 1.0),
+    Vec4::new(0.2, 0.9, 0.2, 1.0),
+    Vec4::new(0.2, 0.4, 1.0, 1.0),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct OverlayVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl OverlayVertex {
+    fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<OverlayVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(OverlayVertex, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(OverlayVertex, color) as u32,
+            },
+        ]
+    }
+}
+
+/// Shape drawn around every uploaded object
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverlayBounds {
+    #[default]
+    Spheres,
+    Boxes,
+}
+
+/// What the overlay draws, nothing by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OverlaySettings {
+    pub bounds: Option<OverlayBounds>,
+    // Grid on the y = 0 plane, following the camera
+    pub grid: bool,
+    // World axes at the origin, X red, Y green and Z blue
+    pub axes: bool,
+}
+
+impl OverlaySettings {
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none() && !self.grid && !self.axes
+    }
+}
+
+/// Line list of a frame in world space, with the mapping to the traced image
+pub struct OverlayLines {
+    view_projection: Mat4,
+    vertices: Vec<OverlayVertex>,
+}
+
+impl OverlayLines {
+    /// Lines enabled in the settings. The bounds are of the objects the
+    /// tracer sees, the selected one highlighted and outlined. `aspect` is
+    /// the width over the height of the traced image.
+    pub fn new(
+        cfg: &TracerConfigInner,
+        settings: &OverlaySettings,
+        selected: Option<usize>,
+        aspect: f32,
+    ) -> Self {
+        let mut lines = Self {
+            view_projection: Self::view_projection(&cfg.camera, aspect),
+            vertices: vec![],
+        };
+        if settings.grid {
+            lines.grid(cfg.camera.position);
+        }
+        if settings.axes {
+            lines.axes();
+        }
+        if let Some(bounds) = settings.bounds {
+            lines.bounds(cfg, bounds, selected);
+        }
+        lines
+    }
+
+    fn bounds(&mut self, cfg: &TracerConfigInner, bounds: OverlayBounds, selected: Option<usize>) {
+        let transforms = cfg.instance_transforms();
+        for index in cfg.visible_objects() {
+            let color = if selected == Some(index) {
+                SELECTED_COLOR
+            } else {
+                BOUNDS_COLOR
+            };
+            let (center, radius) = cfg.objects[index].bounding_sphere();
+            let spheres: Vec<_> = if cfg.is_instanced(index) {
+                transforms
+                    .iter()
+                    .filter(|(object, _)| *object == index)
+                    .map(|(_, transform)| {
+                        TracerConfigInner::place_sphere(transform, center, radius)
+                    })
+                    .collect()
+            } else {
+                vec![(center, radius)]
+            };
+
+            for (center, radius) in spheres {
+                match bounds {
+                    OverlayBounds::Spheres => self.sphere(center, radius, color),
+                    OverlayBounds::Boxes => self.cuboid(center, Vec3::splat(radius), color),
+                }
+                if selected == Some(index) {
+                    self.outline(cfg.camera.position, center, radius, color);
+                }
+            }
+        }
+    }
+
+    // Snapped to the spacing so the lines stay put while the camera moves
+    fn grid(&mut self, eye: Vec3) {
+        let origin = (Vec3::new(eye.x, 0.0, eye.z) / GRID_SPACING).round() * GRID_SPACING;
+        let extent = GRID_HALF_LINES as f32 * GRID_SPACING;
+        for step in -GRID_HALF_LINES..=GRID_HALF_LINES {
+            let offset = step as f32 * GRID_SPACING;
+            self.line(
+                origin + Vec3::new(offset, 0.0, -extent),
+                origin + Vec3::new(offset, 0.0, extent),
+                GRID_COLOR,
+            );
+            self.line(
+                origin + Vec3::new(-extent, 0.0, offset),
+                origin + Vec3::new(extent, 0.0, offset),
+                GRID_COLOR,
+            );
+        }
+    }
+
+    fn axes(&mut self) {
+        for (axis, color) in [Vec3::X, Vec3::Y, Vec3::Z].into_iter().zip(AXIS_COLORS) {
+            self.line(Vec3::ZERO, axis * AXIS_LENGTH, color);
+        }
+    }
+
+    // Maps the world the same way the tracer maps the pixels to rays, the
+    // depth is constant since the lines are drawn over the image anyway
+    fn view_projection(camera: &Camera, aspect: f32) -> Mat4 {
+        let scale = (camera.vertical_fov() * 0.5).tan();
+        let projection = Mat4::from_cols(
+            Vec4::new(1.0 / (scale * aspect), 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0 / scale, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, -1.0),
+            Vec4::new(0.0, 0.0, NEAR, 0.0),
+        );
+        projection * camera.as_transform().inverse()
+    }
+
+    fn line(&mut self, from: Vec3, to: Vec3, color: Vec4) {
+        for position in [from, to] {
+            self.vertices.push(OverlayVertex {
+                position: position.to_array(),
+                color: color.to_array(),
+            });
+        }
+    }
+
+    // The axes are scaled by the radius
+    fn circle(&mut self, center: Vec3, u: Vec3, v: Vec3, color: Vec4) {
+        let point = |segment: usize| {
+            let angle = std::f32::consts::TAU * segment as f32 / CIRCLE_SEGMENTS as f32;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(point(segment), point(segment + 1), color);
+        }
+    }
+
+    // Great circles in the three axis planes
+    fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        let (x, y, z) = (Vec3::X * radius, Vec3::Y * radius, Vec3::Z * radius);
+        self.circle(center, x, y, color);
+        self.circle(center, y, z, color);
+        self.circle(center, z, x, color);
+    }
+
+    fn cuboid(&mut self, center: Vec3, half_size: Vec3, color: Vec4) {
+        let corner = |index: usize| {
+            let sign = |bit: usize| if index & bit != 0 { 1.0 } else { -1.0 };
+            center + half_size * Vec3::new(sign(1), sign(2), sign(4))
+        };
+        // Corners differing in a single bit share an edge
+        for index in 0..8 {
+            for bit in [1, 2, 4] {
+                if index & bit == 0 {
+                    self.line(corner(index), corner(index | bit), color);
+                }
+            }
+        }
+    }
+
+    // Silhouette of the sphere seen from the eye, nothing from inside it
+    fn outline(&mut self, eye: Vec3, center: Vec3, radius: f32, color: Vec4) {
+        let offset = center - eye;
+        let distance = offset.length();
+        if distance <= radius {
+            return;
+        }
+        let normal = offset / distance;
+        let (u, v) = normal.any_orthonormal_pair();
+        let circle_radius = radius * (distance * distance - radius * radius).sqrt() / distance;
+        let circle_center = center - normal * (radius * radius / distance);
+        self.circle(circle_center, u * circle_radius, v * circle_radius, color);
+    }
+}
+
+struct VertexBuffer {
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    // In vertices
+    capacity: usize,
+}
+
+/// Lines drawn over the traced image in the presentation render pass,
+/// between the image and the UI
+pub struct OverlayRenderer {
+    vert_shader: Shader,
+    frag_shader: Shader,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    // One per frame in flight, written while the others may still be read.
+    // Grown to fit the lines of the frame.
+    buffers: Vec<Option<VertexBuffer>>,
+    destroyed: bool,
+}
+
+impl OverlayRenderer {
+    pub unsafe fn new(
+        bundle: Bundle,
+        asset_manager: &AssetManager,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+        frames_in_flight: usize,
+    ) -> TracerResult<Self> {
+        debug!("Creating overlay renderer");
+        let vert_shader = asset_manager
+            .load_shader(VERTEX_SHADER)
+            .context("Failed to load overlay vertex shader asset")?;
+        let vert_shader = Shader::new_from_spirv(bundle, vert_shader.get_spirv()?)
+            .context("Failed to create overlay vertex shader")?;
+        let frag_shader = asset_manager
+            .load_shader(FRAGMENT_SHADER)
+            .context("Failed to load overlay fragment shader asset")?;
+        let frag_shader = Shader::new_from_spirv(bundle, frag_shader.get_spirv()?)
+            .context("Failed to create overlay fragment shader")?;
+
+        let ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(size_of::<Mat4>() as u32)];
+        let pipeline_layout = bundle.device.create_pipeline_layout(
+            &vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&ranges),
+            None,
+        )?;
+
+        let mut renderer = Self {
+            vert_shader,
+            frag_shader,
+            pipeline_layout,
+            pipeline: vk::Pipeline::null(),
+            buffers: (0..frames_in_flight).map(|_| None).collect(),
+            destroyed: false,
+        };
+        renderer.pipeline = renderer
+            .create_pipeline(bundle, render_pass, format)
+            .context("Failed to create overlay pipeline")?;
+        Ok(renderer)
+    }
+
+    /// The render pass or the format of the swapchain changed
+    pub unsafe fn recreate_pipeline(
+        &mut self,
+        bundle: Bundle,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+    ) -> TracerResult<()> {
+        bundle.device.destroy_pipeline(self.pipeline, None);
+        self.pipeline = vk::Pipeline::null();
+        self.pipeline = self
+            .create_pipeline(bundle, render_pass, format)
+            .context("Failed to create overlay pipeline")?;
+        Ok(())
+    }
+
+    unsafe fn create_pipeline(
+        &self,
+        bundle: Bundle,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+    ) -> TracerResult<vk::Pipeline> {
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(self.vert_shader.module)
+                .name(c"main"),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(self.frag_shader.module)
+                .name(c"main"),
+        ];
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let vertex_binding_descriptors = [OverlayVertex::get_binding_description()];
+        let vertex_attribute_descriptors = OverlayVertex::get_attribute_descriptions();
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&vertex_attribute_descriptors)
+            .vertex_binding_descriptions(&vertex_binding_descriptors);
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::LINE_LIST)
+            .primitive_restart_enable(false);
+        // Set with the dynamic state, only the counts matter
+        let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_info = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .line_width(1.0);
+        let multisample_info = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)];
+        let color_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_blend_attachments);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterization_info)
+            .multisample_state(&multisample_info)
+            .color_blend_state(&color_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(self.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        // Without a render pass the attachment formats are specified directly
+        let color_attachment_formats = [format];
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&color_attachment_formats);
+        let pipeline_info = if render_pass == vk::RenderPass::null() {
+            pipeline_info.push_next(&mut rendering_info)
+        } else {
+            pipeline_info
+        };
+        Ok(bundle
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+            .map_err(|(_, e)| e)?
+            .remove(0))
+    }
+
+    unsafe fn create_buffer(bundle: Bundle, capacity: usize) -> TracerResult<VertexBuffer> {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size((size_of::<OverlayVertex>() * capacity) as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = bundle.device.create_buffer(&buffer_info, None)?;
+        let requirements = bundle.device.get_buffer_memory_requirements(buffer);
+        let allocation = bundle.allocator().allocate(&AllocationCreateDesc {
+            name: "Overlay Vertex Buffer",
+            requirements,
+            location: MemoryLocation::CpuToGpu,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+        bundle
+            .device
+            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
+        Ok(VertexBuffer {
+            buffer,
+            allocation: Some(allocation),
+            capacity,
+        })
+    }
+
+    unsafe fn destroy_buffer(bundle: Bundle, mut buffer: VertexBuffer) {
+        if let Some(allocation) = buffer.allocation.take() {
+            if let Err(e) = bundle.allocator().free(allocation) {
+                warn!("Failed to free overlay vertex buffer memory: {}", e);
+            }
+        }
+        bundle.device.destroy_buffer(buffer.buffer, None);
+    }
+
+    /// Draws the lines into the render pass, with the viewport covering the
+    /// traced image. `frame` is the frame in flight, whose previous commands
+    /// have completed.
+    pub unsafe fn record(
+        &mut self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        frame: usize,
+        lines: &OverlayLines,
+    ) -> TracerResult<()> {
+        if lines.vertices.is_empty() {
+            return Ok(());
+        }
+
+        let slot = &mut self.buffers[frame];
+        if slot
+            .as_ref()
+            .is_none_or(|buffer| buffer.capacity < lines.vertices.len())
+        {
+            if let Some(buffer) = slot.take() {
+                Self::destroy_buffer(bundle, buffer);
+            }
+            let capacity = lines.vertices.len().next_power_of_two();
+            debug!("Creating overlay vertex buffer for {} vertices", capacity);
+            *slot = Some(Self::create_buffer(bundle, capacity)?);
+        }
+        let buffer = slot.as_ref().unwrap();
+        let mapped = buffer
+            .allocation
+            .as_ref()
+            .and_then(Allocation::mapped_ptr)
+            .expect("CpuToGpu allocation must be mappable");
+        (mapped.as_ptr() as *mut OverlayVertex)
+            .copy_from_nonoverlapping(lines.vertices.as_ptr(), lines.vertices.len());
+
+        let cmd = command_buffer.as_inner();
+        bundle
+            .device
+            .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        bundle.device.cmd_push_constants(
+            cmd,
+            self.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            std::slice::from_raw_parts(
+                (&lines.view_projection as *const Mat4) as *const u8,
+                size_of::<Mat4>(),
+            ),
+        );
+        bundle
+            .device
+            .cmd_bind_vertex_buffers(cmd, 0, &[buffer.buffer], &[0]);
+        bundle
+            .device
+            .cmd_draw(cmd, lines.vertices.len() as u32, 1, 0, 0);
+        Ok(())
+    }
+
+    pub unsafe fn destroy(&mut self, bundle: Bundle) {
+        if self.destroyed {
+            return;
+        }
+        for buffer in self.buffers.drain(..).flatten() {
+            Self::destroy_buffer(bundle, buffer);
+        }
+        bundle.device.destroy_pipeline(self.pipeline, None);
+        bundle
+            .device
+            .destroy_pipeline_layout(self.pipeline_layout, None);
+        self.vert_shader.destroy(bundle);
+        self.frag_shader.destroy(bundle);
+        self.destroyed = true;
+    }
+}
+
+impl Drop for OverlayRenderer {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            warn!("OverlayRenderer was not destroyed before being dropped");
+        }
+    }
+}
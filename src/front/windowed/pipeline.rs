@@ -1,12 +1,20 @@
 use crate::assets::AssetManager;
 use crate::back::TracerSlot;
+use crate::camera;
+use crate::common::color::is_srgb_format;
 use crate::common::command_buffer::CommandBuffer;
+use crate::common::debug_label::DebugLabels;
+use crate::common::descriptor::DescriptorAllocator;
+use crate::common::frame_graph::{Pass, PassSubmit, SyncPoint, Timeline};
+use crate::common::gpu_timer::GpuTimer;
 use crate::common::shader::Shader;
+use crate::error::{Context, TracerError, TracerResult};
 use crate::front::windowed::front::WindowedQueues;
+use crate::front::windowed::overlay::OverlayRenderer;
+use crate::front::windowed::present_wait::PresentWait;
 use crate::front::windowed::quad::{QuadBuffer, QuadVertex};
-use crate::front::windowed::ui::UICompositor;
+use crate::front::windowed::ui::{RenderViewport, UICompositor};
 use crate::tracer::Bundle;
-use anyhow::Context;
 use ash::vk;
 use egui::{FullOutput, TextureId};
 use glam::UVec2;
@@ -16,13 +24,44 @@ use std::rc::Rc;
 use std::vec;
 use winit::window::Window;
 
-const FRAGMENT_ASSET: &str = "shaders/triangle.frag.spv";
-const VERTEX_ASSET: &str = "shaders/triangle.vert.spv";
-const MAX_FRAMES_IN_FLIGHT: usize = 2;
+const FRAGMENT_SHADER: &str = "present_fragment";
+const VERTEX_SHADER: &str = "present_vertex";
+// Whole presentation render pass and the egui part of it
+const RENDER_PASS_SCOPE: &str = "render_pass";
+const EGUI_SCOPE: &str = "egui";
+const GPU_SCOPES: [&str; 2] = [RENDER_PASS_SCOPE, EGUI_SCOPE];
+
+// Push constant of the presentation fragment shader
+#[repr(C)]
+struct PresentConstants {
+    // Position of the A/B split
+    divider: f32,
+    // The shader encodes the linear image for UNORM swapchains
+    encode_srgb: u32,
+}
+
+/// How the frames are handed to the display, fixed for the window
+#[derive(Debug, Clone, Copy)]
+pub struct PresentOptions {
+    // Prefer the FIFO present modes that wait for the vertical blank
+    pub vsync: bool,
+    // Frames recorded while the previous ones are still on the GPU, one
+    // for the lowest latency, more for throughput
+    pub frames_in_flight: usize,
+    // Requested swapchain images, clamped to what the surface supports.
+    // None asks for one more than the surface minimum.
+    pub swapchain_images: Option<u32>,
+    // Label the passes and every presented frame for GPU profilers
+    pub profiler_friendly: bool,
+}
 
 pub struct PresentationPipeline {
     queues: WindowedQueues,
     viewport: glam::UVec2,
+    options: PresentOptions,
+    // The surface has no area (minimized window), the old swapchain is kept
+    // and nothing is presented until the surface is restored
+    suspended: bool,
     destroyed: bool,
 
     ui_renderer: egui_ash_renderer::Renderer,
@@ -30,24 +69,35 @@ pub struct PresentationPipeline {
     textures_to_free: Option<Vec<TextureId>>,
 
     swapchain_loader: ash::khr::swapchain::Device,
+    dynamic_rendering_loader: ash::khr::dynamic_rendering::Device,
+    // Render straight into the swapchain images without render pass and
+    // framebuffers. In that case render_pass is null and there are no framebuffers.
+    dynamic_rendering: bool,
     swapchain: vk::SwapchainKHR,
     chain_images: Vec<vk::Image>,
     chain_image_views: Vec<vk::ImageView>,
     chain_image_format: vk::Format,
     chain_extent: vk::Extent2D,
 
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    // Layout only, the tracer slot sets are owned by the back-end
+    descriptors: DescriptorAllocator,
     pipeline_layout: vk::PipelineLayout,
     render_pass: vk::RenderPass,
     pipeline: vk::Pipeline,
 
-    image_available_semaphores: Vec<vk::Semaphore>, // size = MAX_FRAMES_IN_FLIGHT
+    image_available_semaphores: Vec<vk::Semaphore>, // size = frames_in_flight
     render_finished_semaphores: Vec<vk::Semaphore>, // size = chain_images.len()
-    in_flight_fences: Vec<vk::Fence>,               // size = MAX_FRAMES_IN_FLIGHT
-    images_in_flight: Vec<vk::Fence>,               // size = chain_images.len(),
+    timeline: Timeline,
+    frames_in_flight: Vec<u64>, // size = frames_in_flight
+    images_in_flight: Vec<u64>, // size = chain_images.len(),
     current_frame: usize,
+    gpu_timer: GpuTimer,
+    labels: DebugLabels,
+    // None without the present id and present wait extensions
+    present_wait: Option<PresentWait>,
 
     quad: QuadBuffer,
+    overlay: OverlayRenderer,
 
     command_pool: vk::CommandPool,
     command_buffers: Vec<CommandBuffer>,
@@ -58,6 +108,7 @@ pub struct PresentationPipeline {
 }
 
 impl PresentationPipeline {
+    #[tracing::instrument(name = "PresentationPipeline::new", skip_all)]
     pub(crate) unsafe fn new(
         bundle: Bundle,
         asset_manager: AssetManager,
@@ -65,29 +116,37 @@ impl PresentationPipeline {
         surface: vk::SurfaceKHR,
         queues: WindowedQueues,
         ui: Rc<RefCell<UICompositor>>,
-    ) -> anyhow::Result<Self> {
+        options: PresentOptions,
+    ) -> TracerResult<Self> {
         debug!("Creating swapchain");
         let (swapchain, images, format, extent) =
-            Self::create_swapchain(bundle, viewport, surface, &queues, None)?;
+            Self::create_swapchain(bundle, viewport, surface, &queues, options, None)?;
 
         debug!("Creating image views");
         let image_views = Self::create_image_views(bundle, &images, format)?;
 
         debug!("Creating shaders");
         let vert_shader = asset_manager
-            .load_asset(VERTEX_ASSET)
+            .load_shader(VERTEX_SHADER)
             .context("Failed to load vertex shader asset")?;
         let vert_shader = Shader::new_from_spirv(bundle, vert_shader.get_spirv()?)
             .context("Failed to create vertex shader")?;
         let frag_shader = asset_manager
-            .load_asset(FRAGMENT_ASSET)
+            .load_shader(FRAGMENT_SHADER)
             .context("Failed to load fragment shader asset")?;
         let frag_shader = Shader::new_from_spirv(bundle, frag_shader.get_spirv()?)
             .context("Failed to create fragment shader")?;
 
-        debug!("Creating pipeline layout and render pass");
-        let render_pass =
-            Self::create_render_pass(bundle, format).context("Failed to create render pass")?;
+        let dynamic_rendering = bundle.device_capabilities.dynamic_rendering;
+        debug!(
+            "Creating pipeline layout and render pass (dynamic rendering: {})",
+            dynamic_rendering
+        );
+        let render_pass = if dynamic_rendering {
+            vk::RenderPass::null()
+        } else {
+            Self::create_render_pass(bundle, format).context("Failed to create render pass")?
+        };
 
         let stages = vec![
             vk::PipelineShaderStageCreateInfo::default()
@@ -99,34 +158,71 @@ impl PresentationPipeline {
                 .module(frag_shader.module)
                 .name(c"main"),
         ];
-        let (descriptor_set_layout, pipeline_layout, pipeline) =
-            Self::create_pipeline(bundle, extent, render_pass, &stages)
-                .context("Failed to create pipeline")?;
+        let descriptors = DescriptorAllocator::new(
+            bundle,
+            &TracerSlot::descriptor_set_layout_bindings(),
+            &[],
+            0,
+        )
+        .context("Failed to create descriptor set layout")?;
+        let (pipeline_layout, pipeline) = Self::create_pipeline(
+            bundle,
+            extent,
+            render_pass,
+            format,
+            descriptors.layout,
+            &stages,
+        )
+        .context("Failed to create pipeline")?;
 
-        debug!("Creating framebuffers");
-        let swapchain_framebuffers =
+        let swapchain_framebuffers = if dynamic_rendering {
+            vec![]
+        } else {
+            debug!("Creating framebuffers");
             Self::create_framebuffers(bundle, &image_views, render_pass, extent)
-                .context("Failed to create framebuffers")?;
+                .context("Failed to create framebuffers")?
+        };
 
         debug!("Creating command pool and buffers");
-        let (command_pool, command_buffers) = Self::create_command_buffers(bundle, &queues)
-            .context("Failed to create command buffer")?;
+        let (command_pool, command_buffers) =
+            Self::create_command_buffers(bundle, &queues, options.frames_in_flight)
+                .context("Failed to create command buffer")?;
 
         debug!("Creating quad buffers");
         let quad_buffer = QuadBuffer::new(bundle, command_pool, queues.graphics_queue)
             .context("Failed to create quad buffers")?;
 
+        let overlay = OverlayRenderer::new(
+            bundle,
+            &asset_manager,
+            render_pass,
+            format,
+            options.frames_in_flight,
+        )
+        .context("Failed to create overlay renderer")?;
+
         debug!("Creating synchronization objects");
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ) = Self::create_sync_objects(bundle, images.len())
-            .context("Failed to create synchronization objects")?;
+        let (image_available_semaphores, render_finished_semaphores) =
+            Self::create_sync_objects(bundle, options.frames_in_flight, images.len())
+                .context("Failed to create synchronization objects")?;
+        let timeline = Timeline::new(bundle, Pass::UI).context("Failed to create UI timeline")?;
+        let images_in_flight = vec![0; images.len()];
+        let labels = DebugLabels::new(bundle, options.profiler_friendly);
+        let gpu_timer = GpuTimer::new(
+            bundle,
+            &GPU_SCOPES,
+            options.frames_in_flight,
+            labels.clone(),
+        )
+        .context("Failed to create GPU timer")?;
 
         Ok(PresentationPipeline {
             swapchain_loader: ash::khr::swapchain::Device::new(bundle.instance, bundle.device),
+            dynamic_rendering_loader: ash::khr::dynamic_rendering::Device::new(
+                bundle.instance,
+                bundle.device,
+            ),
+            dynamic_rendering,
 
             queues,
             swapchain,
@@ -135,18 +231,26 @@ impl PresentationPipeline {
             chain_image_format: format,
             chain_extent: extent,
 
-            descriptor_set_layout,
+            descriptors,
             pipeline_layout,
             render_pass,
             pipeline,
 
             image_available_semaphores,
             render_finished_semaphores,
-            in_flight_fences,
+            timeline,
+            frames_in_flight: vec![0; options.frames_in_flight],
             images_in_flight,
             current_frame: 0,
+            gpu_timer,
+            labels,
+            present_wait: bundle
+                .device_capabilities
+                .present_wait
+                .then(|| PresentWait::new(bundle)),
 
             quad: quad_buffer,
+            overlay,
 
             command_pool,
             command_buffers,
@@ -156,23 +260,25 @@ impl PresentationPipeline {
             frag_shader,
 
             destroyed: false,
-            ui_renderer: egui_ash_renderer::Renderer::with_gpu_allocator(
-                bundle.allocator.clone(),
-                bundle.device.clone(),
+            ui_renderer: Self::create_ui_renderer(
+                bundle,
                 render_pass,
-                egui_ash_renderer::Options {
-                    in_flight_frames: MAX_FRAMES_IN_FLIGHT,
-                    ..Default::default()
-                },
-            )?,
+                format,
+                options.frames_in_flight,
+            )
+            .context("Failed to create UI renderer")?,
             ui,
             textures_to_free: None,
             viewport,
+            options,
+            suspended: false,
         })
     }
 
     pub unsafe fn swapchain_cleanup(&mut self, bundle: Bundle) {
-        bundle.device.device_wait_idle().unwrap();
+        if let Err(e) = bundle.device.device_wait_idle() {
+            warn!("Failed to wait for device idle: {}", e);
+        }
 
         for fb in &self.swapchain_framebuffers {
             bundle.device.destroy_framebuffer(*fb, None);
@@ -191,11 +297,23 @@ impl PresentationPipeline {
         self.images_in_flight.clear();
     }
 
+    pub fn gpu_times(&self) -> Vec<(&'static str, f32)> {
+        self.gpu_timer.times()
+    }
+
+    /// Time from queueing a frame to it showing up on the display, in
+    /// milliseconds. None without present wait support.
+    pub fn display_latency(&self) -> Option<f32> {
+        self.present_wait.as_ref().and_then(PresentWait::latency)
+    }
+
     pub unsafe fn destroy(&mut self, bundle: Bundle) {
         if !self.destroyed {
             // Wait for all in-flight frames to finish
             debug!("Waiting for device to be idle before destroying runtime");
-            bundle.device.device_wait_idle().unwrap();
+            if let Err(e) = bundle.device.device_wait_idle() {
+                warn!("Failed to wait for device idle: {}", e);
+            }
 
             debug!("Destroying synchronization objects");
             for semaphore in &self.image_available_semaphores {
@@ -204,9 +322,8 @@ impl PresentationPipeline {
             for semaphore in &self.render_finished_semaphores {
                 bundle.device.destroy_semaphore(*semaphore, None);
             }
-            for fence in &self.in_flight_fences {
-                bundle.device.destroy_fence(*fence, None);
-            }
+            self.timeline.destroy(bundle);
+            self.gpu_timer.destroy(bundle);
 
             debug!("Destroying command pool and buffers");
             for cmd_buf in &mut self.command_buffers {
@@ -216,6 +333,7 @@ impl PresentationPipeline {
 
             debug!("Destroying buffers");
             self.quad.destroy(bundle);
+            self.overlay.destroy(bundle);
 
             debug!("Destroying swapchain framebuffers");
             for framebuffer in &self.swapchain_framebuffers {
@@ -234,9 +352,7 @@ impl PresentationPipeline {
                 .destroy_pipeline_layout(self.pipeline_layout, None);
 
             debug!("Destroying descriptor set layout");
-            bundle
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.descriptors.destroy(bundle);
 
             debug!("Destroying shaders");
             self.vert_shader.destroy(bundle);
@@ -256,7 +372,7 @@ impl PresentationPipeline {
         }
     }
 
-    fn choose_surface_format(formats: &[vk::SurfaceFormatKHR]) -> anyhow::Result<usize> {
+    fn choose_surface_format(formats: &[vk::SurfaceFormatKHR]) -> TracerResult<usize> {
         let mut best_format = None;
         let mut best_score = 0;
 
@@ -287,27 +403,29 @@ impl PresentationPipeline {
         }
 
         if best_score <= 0 || best_format.is_none() {
-            anyhow::bail!(
-                "No suitable surface format found. Best score: {} / {:?}",
-                best_score,
-                best_format
-            )
+            Err(TracerError::Unsupported(format!(
+                "surface formats, best score: {} / {:?}",
+                best_score, best_format
+            )))
         } else {
             Ok(best_format.unwrap())
         }
     }
 
-    fn choose_present_mode(modes: &[vk::PresentModeKHR]) -> Option<usize> {
+    fn choose_present_mode(modes: &[vk::PresentModeKHR], vsync: bool) -> Option<usize> {
         let mut best_mode = None;
         let mut best_score = 0;
 
-        // Support only FIFO for now
         for (i, mode) in modes.iter().enumerate() {
-            let score = match *mode {
-                vk::PresentModeKHR::IMMEDIATE => 10,
-                vk::PresentModeKHR::MAILBOX => 8,
-                vk::PresentModeKHR::FIFO => 16,
-                vk::PresentModeKHR::FIFO_RELAXED => 15,
+            let score = match (*mode, vsync) {
+                (vk::PresentModeKHR::FIFO, true) => 16,
+                (vk::PresentModeKHR::FIFO_RELAXED, true) => 15,
+                (vk::PresentModeKHR::MAILBOX, false) => 16,
+                (vk::PresentModeKHR::IMMEDIATE, false) => 15,
+                // FIFO is always supported, the fallback either way
+                (vk::PresentModeKHR::FIFO, false) => 5,
+                (vk::PresentModeKHR::IMMEDIATE | vk::PresentModeKHR::MAILBOX, true) => 5,
+                (vk::PresentModeKHR::FIFO_RELAXED, false) => 4,
                 _ => 0,
             };
 
@@ -355,13 +473,37 @@ impl PresentationPipeline {
         }
     }
 
+    fn choose_images_count(
+        requested: Option<u32>,
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+    ) -> u32 {
+        // No maximum is 0
+        let max = if capabilities.max_image_count > 0 {
+            capabilities.max_image_count
+        } else {
+            u32::MAX
+        };
+        let Some(requested) = requested else {
+            return (capabilities.min_image_count + 1).min(max);
+        };
+        let count = requested.clamp(capabilities.min_image_count, max);
+        if count != requested {
+            warn!(
+                "Surface supports {} to {} swapchain images, using {} instead of {}",
+                capabilities.min_image_count, max, count, requested
+            );
+        }
+        count
+    }
+
     unsafe fn create_swapchain(
         bundle: Bundle,
         viewport: glam::UVec2,
         surface: vk::SurfaceKHR,
         queues: &WindowedQueues,
+        options: PresentOptions,
         old_swapchain: Option<vk::SwapchainKHR>,
-    ) -> anyhow::Result<(vk::SwapchainKHR, Vec<vk::Image>, vk::Format, vk::Extent2D)> {
+    ) -> TracerResult<(vk::SwapchainKHR, Vec<vk::Image>, vk::Format, vk::Extent2D)> {
         let surface_loader = ash::khr::surface::Instance::new(bundle.entry, bundle.instance);
         let swapchain_loader = ash::khr::swapchain::Device::new(bundle.instance, bundle.device);
 
@@ -378,19 +520,14 @@ impl PresentationPipeline {
         let format =
             Self::choose_surface_format(&formats).context("No suitable surface format found")?;
         debug!("Chosen surface format: {:?}", formats[format]);
-        let present_mode =
-            Self::choose_present_mode(&present_modes).context("No suitable present mode found")?;
+        let present_mode = Self::choose_present_mode(&present_modes, options.vsync).ok_or(
+            TracerError::Unsupported("surface present modes".to_string()),
+        )?;
         debug!("Chosen present mode: {:?}", present_modes[present_mode]);
         let extent = Self::choose_extent(viewport, &capabilities);
         debug!("Chosen swapchain extent: {:?}", extent);
 
-        let images_count = capabilities.min_image_count + 1;
-        let images_count =
-            if capabilities.max_image_count > 0 && images_count > capabilities.max_image_count {
-                capabilities.max_image_count
-            } else {
-                images_count
-            };
+        let images_count = Self::choose_images_count(options.swapchain_images, &capabilities);
         debug!("Chosen swapchain image count: {}", images_count);
 
         let mut create_info = vk::SwapchainCreateInfoKHR::default()
@@ -437,7 +574,7 @@ impl PresentationPipeline {
     unsafe fn get_swapchain_images(
         bundle: Bundle,
         swapchain: vk::SwapchainKHR,
-    ) -> anyhow::Result<Vec<vk::Image>> {
+    ) -> TracerResult<Vec<vk::Image>> {
         let swapchain_loader = ash::khr::swapchain::Device::new(bundle.instance, bundle.device);
         let images = swapchain_loader.get_swapchain_images(swapchain)?;
         Ok(images)
@@ -447,7 +584,7 @@ impl PresentationPipeline {
         bundle: Bundle,
         images: &[vk::Image],
         format: vk::Format,
-    ) -> anyhow::Result<Vec<vk::ImageView>> {
+    ) -> TracerResult<Vec<vk::ImageView>> {
         let mut views = Vec::with_capacity(images.len());
         for image in images {
             let create_info = vk::ImageViewCreateInfo::default()
@@ -481,7 +618,7 @@ impl PresentationPipeline {
     unsafe fn create_render_pass(
         bundle: Bundle,
         format: vk::Format,
-    ) -> anyhow::Result<vk::RenderPass> {
+    ) -> TracerResult<vk::RenderPass> {
         let color_attachments = vec![vk::AttachmentDescription::default()
             .format(format)
             .samples(vk::SampleCountFlags::TYPE_1)
@@ -512,12 +649,57 @@ impl PresentationPipeline {
         Ok(bundle.device.create_render_pass(&render_pass_info, None)?)
     }
 
+    #[allow(unused_variables)]
+    unsafe fn create_ui_renderer(
+        bundle: Bundle,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+        frames_in_flight: usize,
+    ) -> TracerResult<egui_ash_renderer::Renderer> {
+        Ok(egui_ash_renderer::Renderer::with_gpu_allocator(
+            bundle.allocator.clone(),
+            bundle.device.clone(),
+            #[cfg(not(feature = "dynamic-rendering"))]
+            render_pass,
+            #[cfg(feature = "dynamic-rendering")]
+            egui_ash_renderer::DynamicRendering {
+                color_attachment_format: format,
+                depth_attachment_format: None,
+            },
+            egui_ash_renderer::Options {
+                in_flight_frames: frames_in_flight,
+                // egui colors are sRGB, decoded before an SRGB target encodes them again
+                srgb_framebuffer: is_srgb_format(format),
+                ..Default::default()
+            },
+        )?)
+    }
+
+    #[allow(unused_variables)]
+    unsafe fn update_ui_renderer(
+        &mut self,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+    ) -> TracerResult<()> {
+        #[cfg(not(feature = "dynamic-rendering"))]
+        self.ui_renderer.set_render_pass(render_pass)?;
+        #[cfg(feature = "dynamic-rendering")]
+        self.ui_renderer
+            .set_dynamic_rendering(egui_ash_renderer::DynamicRendering {
+                color_attachment_format: format,
+                depth_attachment_format: None,
+            })?;
+        Ok(())
+    }
+
     unsafe fn create_pipeline(
         bundle: Bundle,
         extent: vk::Extent2D,
         render_pass: vk::RenderPass,
+        format: vk::Format,
+        descriptor_set_layout: vk::DescriptorSetLayout,
         shader_stages: &[vk::PipelineShaderStageCreateInfo],
-    ) -> anyhow::Result<(vk::DescriptorSetLayout, vk::PipelineLayout, vk::Pipeline)> {
+    ) -> TracerResult<(vk::PipelineLayout, vk::Pipeline)> {
         let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
             .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
         let vertex_binding_descriptors = vec![QuadVertex::get_binding_description()];
@@ -571,18 +753,15 @@ impl PresentationPipeline {
             .logic_op(vk::LogicOp::COPY)
             .attachments(&color_blend_attachments);
 
-        let bindings = [vk::DescriptorSetLayoutBinding::default()
-            .binding(0)
-            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-            .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT)];
-        let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
-        let descriptor_set_layout = bundle
-            .device
-            .create_descriptor_set_layout(&layout_info, None)?;
-
+        // The main image and the compared one of the A/B view
+        let layouts = [descriptor_set_layout, descriptor_set_layout];
+        let ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<PresentConstants>() as u32)];
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
-            .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+            .set_layouts(&layouts)
+            .push_constant_ranges(&ranges);
         let pipline_layout = bundle
             .device
             .create_pipeline_layout(&pipeline_layout_info, None)?;
@@ -599,13 +778,23 @@ impl PresentationPipeline {
             .layout(pipline_layout)
             .render_pass(render_pass)
             .subpass(0);
+
+        // Without a render pass the attachment formats are specified directly
+        let color_attachment_formats = [format];
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&color_attachment_formats);
+        let pipeline_info = if render_pass == vk::RenderPass::null() {
+            pipeline_info.push_next(&mut rendering_info)
+        } else {
+            pipeline_info
+        };
         let pipeline = bundle
             .device
             .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
             .map_err(|(_, e)| e)?
             .remove(0);
 
-        Ok((descriptor_set_layout, pipline_layout, pipeline))
+        Ok((pipline_layout, pipeline))
     }
 
     unsafe fn create_framebuffers(
@@ -613,7 +802,7 @@ impl PresentationPipeline {
         swapchain_image_views: &[vk::ImageView],
         render_pass: vk::RenderPass,
         extent: vk::Extent2D,
-    ) -> anyhow::Result<Vec<vk::Framebuffer>> {
+    ) -> TracerResult<Vec<vk::Framebuffer>> {
         let mut framebuffers = Vec::with_capacity(swapchain_image_views.len());
         for view in swapchain_image_views {
             let attachments = vec![*view];
@@ -633,7 +822,8 @@ impl PresentationPipeline {
     unsafe fn create_command_buffers(
         bundle: Bundle,
         queues: &WindowedQueues,
-    ) -> anyhow::Result<(vk::CommandPool, Vec<CommandBuffer>)> {
+        frames_in_flight: usize,
+    ) -> TracerResult<(vk::CommandPool, Vec<CommandBuffer>)> {
         let command_pool_info = vk::CommandPoolCreateInfo::default()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(queues.indices.graphics_family);
@@ -641,31 +831,28 @@ impl PresentationPipeline {
             .device
             .create_command_pool(&command_pool_info, None)?;
 
-        let command_buffer = (0..MAX_FRAMES_IN_FLIGHT)
+        let command_buffer = (0..frames_in_flight)
             .map(|_| CommandBuffer::new_from_pool(bundle, command_pool))
-            .collect::<anyhow::Result<Vec<CommandBuffer>>>()?;
+            .collect::<TracerResult<Vec<CommandBuffer>>>()?;
 
         Ok((command_pool, command_buffer))
     }
 
     unsafe fn create_sync_objects(
         bundle: Bundle,
+        frames_in_flight: usize,
         chain_images_len: usize,
-    ) -> anyhow::Result<(
+    ) -> TracerResult<(
         Vec<vk::Semaphore>, // image_available_semaphores
         Vec<vk::Semaphore>, // render_finished_semaphores (per-image)
-        Vec<vk::Fence>,     // in_flight_fences
-        Vec<vk::Fence>,     // images_in_flight
     )> {
+        // Swapchain acquire and present accept only binary semaphores
         let sem_info = vk::SemaphoreCreateInfo::default();
-        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
 
         // Per frame
-        let mut image_available = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        let mut in_flight = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let mut image_available = Vec::with_capacity(frames_in_flight);
+        for _ in 0..frames_in_flight {
             image_available.push(bundle.device.create_semaphore(&sem_info, None)?);
-            in_flight.push(bundle.device.create_fence(&fence_info, None)?);
         }
 
         // Per image
@@ -673,14 +860,8 @@ impl PresentationPipeline {
         for _ in 0..chain_images_len {
             render_finished.push(bundle.device.create_semaphore(&sem_info, None)?);
         }
-        let images_in_flight = vec![vk::Fence::null(); chain_images_len];
 
-        Ok((
-            image_available,
-            render_finished,
-            in_flight,
-            images_in_flight,
-        ))
+        Ok((image_available, render_finished))
     }
 
     unsafe fn record_egui_buffer(
@@ -688,7 +869,7 @@ impl PresentationPipeline {
         bundle: Bundle,
         w: &Window,
         command_buffer: &CommandBuffer,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         // Free last frames textures after the previous frame is done rendering
         if let Some(textures) = self.textures_to_free.take() {
             self.ui_renderer
@@ -706,7 +887,8 @@ impl PresentationPipeline {
             pixels_per_point,
             ..
         } = (*ui).egui.egui_ctx().run(raw_input, |ctx| {
-            (*ui).render(bundle, ctx);
+            let viewport = glam::UVec2::new(self.chain_extent.width, self.chain_extent.height);
+            (*ui).render(bundle, ctx, viewport);
         });
 
         if !textures_delta.free.is_empty() {
@@ -743,20 +925,41 @@ impl PresentationPipeline {
         bundle: Bundle,
         command_buffer: &CommandBuffer,
         tracer_slot: &TracerSlot,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         bundle.device.cmd_bind_pipeline(
             command_buffer.as_inner(),
             vk::PipelineBindPoint::GRAPHICS,
             self.pipeline,
         );
+        // Without a compared image the main one fills the whole quad
+        let (compare_set, divider) = tracer_slot
+            .compare
+            .as_ref()
+            .map_or((tracer_slot.descriptor_set, 1.0f32), |compare| {
+                (compare.descriptor_set, compare.divider)
+            });
         bundle.device.cmd_bind_descriptor_sets(
             command_buffer.as_inner(),
             vk::PipelineBindPoint::GRAPHICS,
             self.pipeline_layout,
             0,
-            &[tracer_slot.descriptor_set],
+            &[tracer_slot.descriptor_set, compare_set],
             &[],
         );
+        let constants = PresentConstants {
+            divider,
+            encode_srgb: !is_srgb_format(self.chain_image_format) as u32,
+        };
+        bundle.device.cmd_push_constants(
+            command_buffer.as_inner(),
+            self.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            std::slice::from_raw_parts(
+                (&constants as *const PresentConstants) as *const u8,
+                size_of::<PresentConstants>(),
+            ),
+        );
 
         self.quad.draw(bundle, command_buffer);
 
@@ -768,7 +971,7 @@ impl PresentationPipeline {
         bundle: Bundle,
         surface: vk::SurfaceKHR,
         viewport: glam::UVec2,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         if self.viewport != viewport {
             debug!(
                 "Resizing swapchain from {:?} to {:?}",
@@ -788,39 +991,90 @@ impl PresentationPipeline {
         command_buffer: &CommandBuffer,
         image_index: usize,
         tracer_slot: &TracerSlot,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         command_buffer.reset(bundle)?;
         command_buffer.begin(bundle)?;
+        self.gpu_timer
+            .reset(bundle, command_buffer, self.current_frame)?;
+        self.gpu_timer
+            .begin(bundle, command_buffer, RENDER_PASS_SCOPE);
 
         let clear_values = vec![vk::ClearValue {
             color: vk::ClearColorValue {
                 float32: [0.0, 0.0, 0.0, 1.0],
             },
         }];
-        let render_pass_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.swapchain_framebuffers[image_index])
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.chain_extent,
-            })
-            .clear_values(&clear_values);
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.chain_extent,
+        };
+        // The quad keeps the aspect ratio of the traced image inside the
+        // part of the window left by the UI panels, the bars around it are
+        // left with the clear color
+        let chain = UVec2::new(self.chain_extent.width, self.chain_extent.height);
+        let area = self
+            .ui
+            .borrow()
+            .render_viewport()
+            .unwrap_or(RenderViewport::whole(chain))
+            .within(chain);
+        let (offset, size) = camera::letterbox(area.size, tracer_slot.image.dimensions);
+        let offset = area.offset + offset;
         let viewport = vk::Viewport::default()
-            .x(0.0)
-            .y(0.0)
-            .width(self.chain_extent.width as f32)
-            .height(self.chain_extent.height as f32)
+            .x(offset.x as f32)
+            .y(offset.y as f32)
+            .width(size.x as f32)
+            .height(size.y as f32)
             .min_depth(0.0)
             .max_depth(1.0);
         let scissor = vk::Rect2D::default()
-            .offset(vk::Offset2D { x: 0, y: 0 })
-            .extent(self.chain_extent);
+            .offset(vk::Offset2D {
+                x: offset.x as i32,
+                y: offset.y as i32,
+            })
+            .extent(vk::Extent2D {
+                width: size.x,
+                height: size.y,
+            });
+
+        if self.dynamic_rendering {
+            // Layout transitions are no longer done by the render pass
+            self.chain_image_barrier(
+                bundle,
+                command_buffer,
+                image_index,
+                (vk::ImageLayout::UNDEFINED, vk::AccessFlags::empty()),
+                (
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ),
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            );
 
-        bundle.device.cmd_begin_render_pass(
-            command_buffer.as_inner(),
-            &render_pass_info,
-            vk::SubpassContents::INLINE,
-        );
+            let color_attachment = vk::RenderingAttachmentInfo::default()
+                .image_view(self.chain_image_views[image_index])
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(clear_values[0]);
+            let rendering_info = vk::RenderingInfo::default()
+                .render_area(render_area)
+                .layer_count(1)
+                .color_attachments(std::slice::from_ref(&color_attachment));
+            self.dynamic_rendering_loader
+                .cmd_begin_rendering(command_buffer.as_inner(), &rendering_info);
+        } else {
+            let render_pass_info = vk::RenderPassBeginInfo::default()
+                .render_pass(self.render_pass)
+                .framebuffer(self.swapchain_framebuffers[image_index])
+                .render_area(render_area)
+                .clear_values(&clear_values);
+            bundle.device.cmd_begin_render_pass(
+                command_buffer.as_inner(),
+                &render_pass_info,
+                vk::SubpassContents::INLINE,
+            );
+        }
         bundle
             .device
             .cmd_set_viewport(command_buffer.as_inner(), 0, &[viewport]);
@@ -830,31 +1084,121 @@ impl PresentationPipeline {
             .cmd_set_scissor(command_buffer.as_inner(), 0, &[scissor]);
 
         self.record_command_buffer(bundle, command_buffer, tracer_slot)?;
+        // Same viewport as the quad, so the lines follow the traced image
+        let aspect = size.x as f32 / size.y.max(1) as f32;
+        if let Some(lines) = self.ui.borrow().overlay(aspect) {
+            self.overlay
+                .record(bundle, command_buffer, self.current_frame, &lines)?;
+        }
+        self.gpu_timer.begin(bundle, command_buffer, EGUI_SCOPE);
         self.record_egui_buffer(bundle, w, command_buffer)?;
-
-        bundle.device.cmd_end_render_pass(command_buffer.as_inner());
+        self.gpu_timer.end(bundle, command_buffer, EGUI_SCOPE);
+
+        if self.dynamic_rendering {
+            self.dynamic_rendering_loader
+                .cmd_end_rendering(command_buffer.as_inner());
+            self.chain_image_barrier(
+                bundle,
+                command_buffer,
+                image_index,
+                (
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ),
+                (vk::ImageLayout::PRESENT_SRC_KHR, vk::AccessFlags::empty()),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            );
+        } else {
+            bundle.device.cmd_end_render_pass(command_buffer.as_inner());
+        }
+        self.gpu_timer
+            .end(bundle, command_buffer, RENDER_PASS_SCOPE);
         command_buffer.end(bundle)?;
 
         Ok(())
     }
 
+    unsafe fn chain_image_barrier(
+        &self,
+        bundle: Bundle,
+        command_buffer: &CommandBuffer,
+        image_index: usize,
+        (old_layout, src_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        (new_layout, dst_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        dst_stage_mask: vk::PipelineStageFlags,
+    ) {
+        let barrier = vk::ImageMemoryBarrier::default()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.chain_images[image_index])
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            );
+        bundle.device.cmd_pipeline_barrier(
+            command_buffer.as_inner(),
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+
+    // Recreates the swapchain
+    #[tracing::instrument(name = "PresentationPipeline::on_suboptimal", skip_all)]
     pub unsafe fn on_suboptimal(
         &mut self,
         bundle: Bundle,
         surface: vk::SurfaceKHR,
         viewport: glam::UVec2,
-    ) -> anyhow::Result<()> {
+    ) -> TracerResult<()> {
         debug!("Swapchain is suboptimal, needs recreation");
 
+        // No swapchain can be created for a zero sized surface
+        if Self::is_surface_empty(bundle, surface, viewport)? {
+            if !self.suspended {
+                debug!("Surface has no area, suspending presentation");
+            }
+            self.suspended = true;
+            return Ok(());
+        }
+        self.suspended = false;
+
         // Cleanup old swapchain
         self.swapchain_cleanup(bundle);
+        if let Some(present_wait) = &mut self.present_wait {
+            present_wait.reset();
+        }
 
         // Create new swapchain
         let old_swapchain = self.swapchain;
-        let (swapchain, images, format, extent) =
-            Self::create_swapchain(bundle, viewport, surface, &self.queues, Some(old_swapchain))?;
+        let (swapchain, images, format, extent) = Self::create_swapchain(
+            bundle,
+            viewport,
+            surface,
+            &self.queues,
+            self.options,
+            Some(old_swapchain),
+        )?;
 
         let format_changed = format != self.chain_image_format;
+        if is_srgb_format(format) != is_srgb_format(self.chain_image_format) {
+            // The presented image follows, egui keeps the encoding it was created with
+            warn!(
+                "Swapchain format changed from {:?} to {:?}, UI colors may be off",
+                self.chain_image_format, format
+            );
+        }
         self.swapchain = swapchain;
         self.chain_images = images;
         self.chain_image_format = format;
@@ -874,12 +1218,12 @@ impl PresentationPipeline {
             bundle
                 .device
                 .destroy_pipeline_layout(self.pipeline_layout, None);
-            bundle
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
 
-            let render_pass =
-                Self::create_render_pass(bundle, format).context("Failed to create render pass")?;
+            let render_pass = if self.dynamic_rendering {
+                vk::RenderPass::null()
+            } else {
+                Self::create_render_pass(bundle, format).context("Failed to create render pass")?
+            };
 
             let stages = vec![
                 vk::PipelineShaderStageCreateInfo::default()
@@ -891,48 +1235,86 @@ impl PresentationPipeline {
                     .module(self.frag_shader.module)
                     .name(c"main"),
             ];
-            let (descriptor_set_layout, pipeline_layout, pipeline) =
-                Self::create_pipeline(bundle, extent, render_pass, &stages)
-                    .context("Failed to create pipeline")?;
+            let (pipeline_layout, pipeline) = Self::create_pipeline(
+                bundle,
+                extent,
+                render_pass,
+                format,
+                self.descriptors.layout,
+                &stages,
+            )
+            .context("Failed to create pipeline")?;
 
-            self.descriptor_set_layout = descriptor_set_layout;
             self.pipeline_layout = pipeline_layout;
             self.render_pass = render_pass;
             self.pipeline = pipeline;
+            self.update_ui_renderer(render_pass, format)
+                .context("Failed to update UI renderer")?;
+            self.overlay
+                .recreate_pipeline(bundle, render_pass, format)
+                .context("Failed to recreate overlay pipeline")?;
         }
 
-        // New framebuffers
-        self.swapchain_framebuffers = Self::create_framebuffers(
-            bundle,
-            &self.chain_image_views,
-            self.render_pass,
-            self.chain_extent,
-        )?;
+        // New framebuffers. Dynamic rendering attaches the image views directly
+        if !self.dynamic_rendering {
+            self.swapchain_framebuffers = Self::create_framebuffers(
+                bundle,
+                &self.chain_image_views,
+                self.render_pass,
+                self.chain_extent,
+            )?;
+        }
 
         // Recreate per-image semaphores
         let sem_info = vk::SemaphoreCreateInfo::default();
         self.render_finished_semaphores = (0..self.chain_images.len())
             .map(|_| bundle.device.create_semaphore(&sem_info, None))
             .collect::<Result<_, _>>()?;
-        self.images_in_flight = vec![vk::Fence::null(); self.chain_images.len()];
+        self.images_in_flight = vec![0; self.chain_images.len()];
         self.current_frame = 0;
 
         Ok(())
     }
 
+    unsafe fn is_surface_empty(
+        bundle: Bundle,
+        surface: vk::SurfaceKHR,
+        viewport: glam::UVec2,
+    ) -> TracerResult<bool> {
+        if viewport.x == 0 || viewport.y == 0 {
+            return Ok(true);
+        }
+
+        let surface_loader = ash::khr::surface::Instance::new(bundle.entry, bundle.instance);
+        let capabilities = surface_loader
+            .get_physical_device_surface_capabilities(bundle.physical_device, surface)?;
+        Ok(capabilities.current_extent.width == 0 || capabilities.current_extent.height == 0)
+    }
+
+    #[tracing::instrument(name = "PresentationPipeline::present", skip_all)]
     pub unsafe fn present(
         &mut self,
         bundle: Bundle,
         w: &Window,
         surface: vk::SurfaceKHR,
         tracer_slot: TracerSlot,
-    ) -> anyhow::Result<()> {
-        // Wait for the fence to be signaled
-        bundle.device.wait_for_fences(
-            &[self.in_flight_fences[self.current_frame]],
-            true,
-            u64::MAX,
-        )?;
+    ) -> TracerResult<Option<SyncPoint>> {
+        // Retry the swapchain recreation until the surface is restored
+        if self.suspended {
+            self.on_suboptimal(bundle, surface, self.viewport)?;
+            if self.suspended {
+                return Ok(None);
+            }
+        }
+
+        // Wait for the previous frame using the same command buffer
+        self.timeline
+            .wait(bundle, self.frames_in_flight[self.current_frame])?;
+        self.gpu_timer.fetch(bundle)?;
+        // With vsync the frame starts once the previous one is on screen
+        if let Some(present_wait) = &mut self.present_wait {
+            present_wait.wait(self.swapchain, self.options.vsync);
+        }
 
         // Acquire next image
         let index = match self.swapchain_loader.acquire_next_image(
@@ -943,76 +1325,77 @@ impl PresentationPipeline {
         ) {
             Ok((index, false)) => index as usize,
             Ok((_, true)) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return self.on_suboptimal(bundle, surface, self.viewport);
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Failed to acquire next swapchain image: {:?}",
-                    e
-                ));
+                self.on_suboptimal(bundle, surface, self.viewport)?;
+                return Ok(None);
             }
+            Err(vk::Result::ERROR_SURFACE_LOST_KHR) => return Err(TracerError::SwapchainLost),
+            Err(e) => return Err(e).context("Failed to acquire next swapchain image"),
         };
 
         // Wait for the image to be available
-        if self.images_in_flight[index] != vk::Fence::null()
-            && self.images_in_flight[index] != self.in_flight_fences[self.current_frame]
-        {
-            bundle
-                .device
-                .wait_for_fences(&[self.images_in_flight[index]], true, u64::MAX)?;
-        }
-        self.images_in_flight[index] = self.in_flight_fences[self.current_frame];
+        self.timeline.wait(bundle, self.images_in_flight[index])?;
 
         // Record command buffer
         let buffer_ptr: *mut CommandBuffer = &mut self.command_buffers[self.current_frame];
         self.render(bundle, w, buffer_ptr.as_ref().unwrap(), index, &tracer_slot)?;
-        bundle
-            .device
-            .reset_fences(&[self.in_flight_fences[self.current_frame]])?;
 
-        // Submit
-        let wait_semaphores = vec![self.image_available_semaphores[self.current_frame]];
-        let wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        // Submit after the swapchain image is acquired and the tracer images are ready
+        let point = self.timeline.advance();
+        let mut submit = PassSubmit::new()
+            .after_binary(
+                self.image_available_semaphores[self.current_frame],
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            )
+            .after(tracer_slot.ready, Pass::UI.wait_stage());
+        if let Some(compare) = &tracer_slot.compare {
+            submit = submit.after(compare.ready, Pass::UI.wait_stage());
+        }
+        submit
+            .command_buffer(&self.command_buffers[self.current_frame])
+            .signal(point)
+            .signal_binary(self.render_finished_semaphores[index])
+            .submit(bundle, self.queues.graphics_queue)?;
+        self.frames_in_flight[self.current_frame] = point.value;
+        self.images_in_flight[index] = point.value;
+        // One boundary per present, numbered after the UI submissions
+        self.labels.insert(
+            self.queues.graphics_queue,
+            &format!("Frame {}", point.value),
+        );
 
         let signal_semaphores = [self.render_finished_semaphores[index]];
-        let command_buffers = vec![self.command_buffers[self.current_frame].as_inner()];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_stages)
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
-        bundle.device.queue_submit(
-            self.queues.graphics_queue,
-            &[submit_info],
-            self.in_flight_fences[self.current_frame],
-        )?;
 
         // Present
         let swapchains = vec![self.swapchain];
         let image_indices = [index as u32];
-        let present_info = vk::PresentInfoKHR::default()
+        let present_ids = [self.present_wait.as_mut().map_or(0, PresentWait::next_id)];
+        let mut present_id = vk::PresentIdKHR::default().present_ids(&present_ids);
+        let mut present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&signal_semaphores)
             .swapchains(&swapchains)
             .image_indices(&image_indices);
+        if self.present_wait.is_some() {
+            present_info = present_info.push_next(&mut present_id);
+        }
 
-        match self
+        let presented = self
             .swapchain_loader
-            .queue_present(self.queues.present_queue, &present_info)
-        {
+            .queue_present(self.queues.present_queue, &present_info);
+        if let (Some(present_wait), Ok(_)) = (&mut self.present_wait, presented) {
+            present_wait.queued(present_ids[0]);
+        }
+        match presented {
             Ok(false) => {}
             Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return self.on_suboptimal(bundle, surface, self.viewport);
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Failed to present swapchain image: {:?}",
-                    e
-                ));
+                self.on_suboptimal(bundle, surface, self.viewport)?;
+                return Ok(Some(point));
             }
+            Err(vk::Result::ERROR_SURFACE_LOST_KHR) => return Err(TracerError::SwapchainLost),
+            Err(e) => return Err(e).context("Failed to present swapchain image"),
         };
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
-        Ok(())
+        self.current_frame = (self.current_frame + 1) % self.options.frames_in_flight;
+        Ok(Some(point))
     }
 }
 
@@ -0,0 +1,89 @@
+use crate::tracer::Bundle;
+use ash::vk;
+use log::debug;
+use std::time::{Duration, Instant};
+
+// Longest wait for a present to show up, e.g. an occluded window is
+// never shown and must not stall the loop
+const PRESENT_TIMEOUT: Duration = Duration::from_millis(100);
+// Weight of a new measurement in the moving average of the latency
+const LATENCY_SMOOTHING: f32 = 0.1;
+
+/// Display-synced pacing with VK_KHR_present_id and VK_KHR_present_wait.
+/// Every present gets an id. With vsync the next frame is not started
+/// before the previous one is on screen, so no frame waits in the queue
+/// of the compositor and the input is sampled as late as possible.
+/// The time from queueing a present to the image showing up is the
+/// display latency.
+pub struct PresentWait {
+    loader: ash::khr::present_wait::Device,
+    last_id: u64,
+    // Last queued present and when it was queued
+    pending: Option<(u64, Instant)>,
+    // Moving average, in milliseconds
+    latency: Option<f32>,
+}
+
+impl PresentWait {
+    pub fn new(bundle: Bundle) -> Self {
+        Self {
+            loader: ash::khr::present_wait::Device::new(bundle.instance, bundle.device),
+            last_id: 0,
+            pending: None,
+            latency: None,
+        }
+    }
+
+    /// Id of the next present, ids only grow, even across swapchains
+    pub fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    pub fn queued(&mut self, id: u64) {
+        self.pending = Some((id, Instant::now()));
+    }
+
+    /// Waits until the last queued present is displayed. Without `block`
+    /// only checks whether it is, the latency is then measured at the
+    /// granularity of the frames.
+    pub unsafe fn wait(&mut self, swapchain: vk::SwapchainKHR, block: bool) {
+        let Some((id, queued)) = self.pending else {
+            return;
+        };
+        let timeout = if block {
+            PRESENT_TIMEOUT.as_nanos() as u64
+        } else {
+            0
+        };
+        match self.loader.wait_for_present(swapchain, id, timeout) {
+            Ok(()) => {
+                self.pending = None;
+                let ms = queued.elapsed().as_secs_f32() * 1000.0;
+                self.latency = Some(
+                    self.latency
+                        .map_or(ms, |average| average + (ms - average) * LATENCY_SMOOTHING),
+                );
+            }
+            // Still queued, checked again with the next frame
+            Err(vk::Result::TIMEOUT) if !block => {}
+            Err(vk::Result::TIMEOUT) => {
+                self.pending = None;
+            }
+            // An out of date swapchain is recreated after the acquire
+            Err(e) => {
+                debug!("Failed to wait for present {}: {}", id, e);
+                self.pending = None;
+            }
+        }
+    }
+
+    /// The pending present belongs to a destroyed swapchain
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn latency(&self) -> Option<f32> {
+        self.latency
+    }
+}
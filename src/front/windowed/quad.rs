@@ -1,8 +1,10 @@
 use crate::common::buffer::create_device_local_buffer_with_data;
 use crate::common::command_buffer::CommandBuffer;
+use crate::error::TracerResult;
 use crate::tracer::Bundle;
 use ash::vk;
 use gpu_allocator::vulkan::Allocation;
+use log::warn;
 use std::mem::offset_of;
 
 // Fullscreen quad vertices
@@ -66,7 +68,7 @@ impl QuadBuffer {
         bundle: Bundle,
         command_pool: vk::CommandPool,
         queue: vk::Queue,
-    ) -> anyhow::Result<Self> {
+    ) -> TracerResult<Self> {
         let (vertex_buffer, vertex_alloc) = create_device_local_buffer_with_data(
             bundle,
             command_pool,
@@ -144,7 +146,9 @@ impl QuadBuffer {
 
 impl Drop for QuadBuffer {
     fn drop(&mut self) {
-        if !self.destroyed {
+        if !self.destroyed && std::thread::panicking() {
+            warn!("Leaked QuadBuffer");
+        } else if !self.destroyed {
             panic!("QuadBuffer was not destroyed before being dropped");
         }
     }
@@ -0,0 +1,138 @@
+use crate::front::headless::TracerHeadlessOutput;
+use ash::vk;
+use glam::UVec2;
+use log::{debug, error, info, warn};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Shortest time between two captured frames. A capture waits for the
+// frame to finish and copies it back, so it is not done every frame.
+const CAPTURE_INTERVAL: Duration = Duration::from_millis(250);
+
+enum RingMessage {
+    // Linear R32G32B32A32_SFLOAT pixels
+    Frame(UVec2, Vec<u8>),
+    Dump { dir: PathBuf, config: String },
+}
+
+/// Recent traced frames, kept for bug reports. The frames are converted
+/// and written on a worker thread, only the copy back from the GPU is
+/// done by the caller.
+pub struct FrameRing {
+    sender: Option<Sender<RingMessage>>,
+    thread: Option<JoinHandle<()>>,
+    last_capture: Option<Instant>,
+}
+
+impl FrameRing {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = channel();
+        let thread = std::thread::Builder::new()
+            .name("frame-ring".to_string())
+            .spawn(move || Self::run(receiver, capacity))
+            .map_err(|e| warn!("Failed to start frame ring: {}", e))
+            .ok();
+
+        Self {
+            sender: thread.as_ref().map(|_| sender),
+            thread,
+            last_capture: None,
+        }
+    }
+
+    /// Whether the next traced frame should be captured
+    pub fn should_capture(&self) -> bool {
+        self.sender.is_some()
+            && self
+                .last_capture
+                .is_none_or(|last| last.elapsed() >= CAPTURE_INTERVAL)
+    }
+
+    pub fn capture(&mut self, dimensions: UVec2, pixels: Vec<u8>) {
+        self.last_capture = Some(Instant::now());
+        self.send(RingMessage::Frame(dimensions, pixels));
+    }
+
+    /// Writes the kept frames as PNGs into a new directory under `parent`,
+    /// oldest first, along with the config they were traced with
+    pub fn dump(&self, parent: &Path, config: String) {
+        let name = chrono::Local::now()
+            .format("pathrs-frames-%Y%m%d-%H%M%S")
+            .to_string();
+        self.send(RingMessage::Dump {
+            dir: parent.join(name),
+            config,
+        });
+    }
+
+    fn send(&self, message: RingMessage) {
+        if let Some(sender) = &self.sender {
+            if sender.send(message).is_err() {
+                warn!("Frame ring thread has exited");
+            }
+        }
+    }
+
+    fn run(receiver: Receiver<RingMessage>, capacity: usize) {
+        debug!("Frame ring started, keeping {} frames", capacity);
+        let mut frames = VecDeque::with_capacity(capacity);
+        while let Ok(message) = receiver.recv() {
+            match message {
+                RingMessage::Frame(dimensions, pixels) => {
+                    match TracerHeadlessOutput::from_memory(
+                        dimensions,
+                        vk::Format::R32G32B32A32_SFLOAT,
+                        &pixels,
+                    ) {
+                        Ok(frame) => {
+                            if frames.len() == capacity {
+                                frames.pop_front();
+                            }
+                            frames.push_back(frame);
+                        }
+                        Err(e) => warn!("Failed to convert captured frame: {}", e),
+                    }
+                }
+                RingMessage::Dump { dir, config } => {
+                    // Taken, a second dump only gets the frames captured since
+                    match Self::write(&dir, std::mem::take(&mut frames), &config) {
+                        Ok(count) => info!("Dumped {} frames to {}", count, dir.display()),
+                        Err(e) => error!("Failed to dump frames to {}: {:#}", dir.display(), e),
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(
+        dir: &Path,
+        frames: VecDeque<TracerHeadlessOutput>,
+        config: &str,
+    ) -> anyhow::Result<usize> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("config.json"), config)?;
+        let count = frames.len();
+        for (index, frame) in frames.into_iter().enumerate() {
+            std::fs::write(
+                dir.join(format!("frame_{:02}.png", index)),
+                frame.encode_png()?,
+            )?;
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for FrameRing {
+    fn drop(&mut self) {
+        // Lets the thread finish a dump in progress and exit
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Frame ring thread panicked");
+            }
+        }
+    }
+}
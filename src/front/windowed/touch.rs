@@ -0,0 +1,86 @@
+use glam::Vec2;
+use std::collections::BTreeMap;
+use winit::event::{Touch, TouchPhase};
+
+// Fingers closer than this in pixels are too close to measure a pinch
+const MIN_PINCH_DISTANCE: f32 = 1.0;
+
+/// Camera motion of the touch gestures since the last tick, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TouchGesture {
+    // Single-finger drag
+    pub orbit: Vec2,
+    // Two-finger drag, the motion of the point between the fingers
+    pub pan: Vec2,
+    // Ratio of the distance between two fingers, above 1 when spreading
+    pub zoom: f32,
+}
+
+impl Default for TouchGesture {
+    fn default() -> Self {
+        Self {
+            orbit: Vec2::ZERO,
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl TouchGesture {
+    pub fn is_idle(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Fingers on the touch screen, turned into gestures. Pens come as touches
+/// too, so they orbit like a single finger. Three or more fingers are
+/// ignored.
+#[derive(Default)]
+pub struct TouchInput {
+    // Last position of every finger down, by the id of the touch
+    touches: BTreeMap<u64, Vec2>,
+    gesture: TouchGesture,
+}
+
+impl TouchInput {
+    pub fn on_touch(&mut self, touch: &Touch) {
+        let position = Vec2::new(touch.location.x as f32, touch.location.y as f32);
+        match touch.phase {
+            TouchPhase::Started => {
+                self.touches.insert(touch.id, position);
+            }
+            TouchPhase::Moved => {
+                let Some(previous) = self.touches.insert(touch.id, position) else {
+                    return;
+                };
+                let others: Vec<_> = self
+                    .touches
+                    .iter()
+                    .filter(|(id, _)| **id != touch.id)
+                    .map(|(_, other)| *other)
+                    .collect();
+                match others[..] {
+                    [] => self.gesture.orbit += position - previous,
+                    [other] => {
+                        // Both fingers moving add up to the motion of the
+                        // point between them
+                        self.gesture.pan += (position - previous) * 0.5;
+                        let before = previous.distance(other);
+                        if before > MIN_PINCH_DISTANCE {
+                            self.gesture.zoom *= position.distance(other) / before;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+            }
+        }
+    }
+
+    /// Gestures since the last call
+    pub fn take(&mut self) -> TouchGesture {
+        std::mem::take(&mut self.gesture)
+    }
+}
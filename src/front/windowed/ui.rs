@@ -1,23 +1,291 @@
-use crate::config::TracerConfig;
-use crate::front::windowed::free_cam::FreeCamera;
-use crate::tracer::{Bundle, TracerProfile};
+use crate::back::MIN_RESOLUTION_SCALE;
+use crate::camera;
+use crate::config::{
+    AdaptiveSamples, AutoExposure, CompareConfig, Interpolation, Keyframe, Light, Object,
+    PathVertex, PickResult, PostPass, QualityPreset, TracerConfig, TracerConfigInner,
+};
+use crate::fps::FrameStats;
+use crate::front::windowed::free_cam::{CameraMode, FreeCamera};
+use crate::front::windowed::gamepad::GamepadInputs;
+use crate::front::windowed::gizmo::Gizmo;
+use crate::front::windowed::overlay::{OverlayBounds, OverlayLines, OverlaySettings};
+use crate::settings::config_dir;
+use crate::tracer::{Bundle, DeviceInfo, TracerProfile};
+use anyhow::Context;
 use egui::Widget;
+use egui_dock::{DockArea, DockState, NodeIndex, Style, TabViewer};
 use gpu_allocator::vulkan::AllocatorVisualizer;
-use log::info;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::keyboard::{Key, NamedKey};
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UiTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Tab {
+    Stats,
+    TracerControls,
+    Materials,
+    Objects,
+    SceneGraph,
+    Lights,
+    RayDebugger,
+    Animation,
+    PostProcessing,
+    Allocator,
+    SceneStats,
+    Settings,
+}
+
+impl Tab {
+    const ALL: [Tab; 12] = [
+        Tab::Stats,
+        Tab::TracerControls,
+        Tab::Materials,
+        Tab::Objects,
+        Tab::SceneGraph,
+        Tab::Lights,
+        Tab::RayDebugger,
+        Tab::Animation,
+        Tab::PostProcessing,
+        Tab::Allocator,
+        Tab::SceneStats,
+        Tab::Settings,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Tab::Stats => "Stats",
+            Tab::TracerControls => "Tracer Controls",
+            Tab::Materials => "Materials",
+            Tab::Objects => "Objects",
+            Tab::SceneGraph => "Scene Graph",
+            Tab::Lights => "Lights",
+            Tab::RayDebugger => "Ray Debugger",
+            Tab::Animation => "Animation",
+            Tab::PostProcessing => "Post Processing",
+            Tab::Allocator => "Allocator",
+            Tab::SceneStats => "Scene Stats",
+            Tab::Settings => "Settings",
+        }
+    }
+}
+
+/// UI state kept between runs
+#[derive(Serialize, Deserialize)]
+struct UiSettings {
+    theme: UiTheme,
+    dock: DockState<Tab>,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            theme: UiTheme::default(),
+            dock: Self::default_dock(),
+        }
+    }
+}
+
+impl UiSettings {
+    fn default_dock() -> DockState<Tab> {
+        let mut dock = DockState::new(vec![Tab::Stats]);
+        dock.main_surface_mut()
+            .split_below(NodeIndex::root(), 0.25, Tab::ALL[1..].to_vec());
+        dock
+    }
+
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("ui.json"))
+    }
+
+    fn load() -> Self {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Self::default();
+        };
+
+        match std::fs::read(&path)
+            .context("Failed to read")
+            .and_then(|data| serde_json::from_slice(&data).context("Failed to parse"))
+        {
+            Ok(mut settings) => {
+                info!("Loaded UI settings from {}", path.display());
+                // Layouts saved by older versions miss the newer tabs
+                for tab in Tab::ALL {
+                    if settings.dock.find_tab(&tab).is_none() {
+                        settings.dock.push_to_first_leaf(tab);
+                    }
+                }
+                settings
+            }
+            Err(e) => {
+                warn!("Ignoring UI settings {}: {:#}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().context("No config directory on this platform")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        info!("Saved UI settings to {}", path.display());
+        Ok(())
+    }
+}
+
+/// State of the panels, everything except their layout
+struct Panels {
+    // Clicking the viewport sets the focus distance
+    click_to_focus: bool,
+    // Lines drawn over the traced image
+    overlay: OverlaySettings,
+    // Size of the render viewport, the size locking the render size keeps
+    viewport: glam::UVec2,
+    // Clicking the viewport shows the path traced through the pixel
+    ray_debugger: bool,
+    debug_pick: Option<PickResult>,
+    // Clicking the viewport selects the object under the cursor
+    click_to_select: bool,
+    // Index in the objects shown in the inspector
+    selected_object: Option<usize>,
+    // Selected from the viewport, the list scrolls to it once
+    scroll_to_selected: bool,
+    // Position of the animation time slider in seconds
+    animation_time: f32,
+    // The animation time advances with the frames
+    playing: bool,
+    // Playback starts over at the end instead of stopping
+    looping: bool,
+    allocator_visualizer: AllocatorVisualizer,
+    fps: f32,
+    frame_stats: FrameStats,
+    tracer_profile: Option<TracerProfile>,
+    // Queried once the scene stats are first shown
+    device_info: Option<DeviceInfo>,
+    // Watchdog report hidden by the user
+    dismissed_stall: Option<u64>,
+}
+
+/// Part of the window the traced image is presented in, in physical
+/// pixels. The rest of it is covered by the UI panels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderViewport {
+    pub offset: glam::UVec2,
+    pub size: glam::UVec2,
+}
+
+impl RenderViewport {
+    pub fn whole(viewport: glam::UVec2) -> Self {
+        Self {
+            offset: glam::UVec2::ZERO,
+            size: viewport.max(glam::UVec2::ONE),
+        }
+    }
+
+    /// Clamped into the window, the layout lags a frame behind its size
+    pub fn within(self, viewport: glam::UVec2) -> Self {
+        let viewport = viewport.max(glam::UVec2::ONE);
+        let offset = self.offset.min(viewport - glam::UVec2::ONE);
+        Self {
+            offset,
+            size: self.size.min(viewport - offset).max(glam::UVec2::ONE),
+        }
+    }
+
+    /// Position in physical pixels normalized to the viewport, None outside
+    pub fn normalize(&self, position: glam::Vec2) -> Option<glam::Vec2> {
+        let normalized = (position - self.offset.as_vec2()) / self.size.as_vec2();
+        (normalized.cmpge(glam::Vec2::ZERO).all() && normalized.cmplt(glam::Vec2::ONE).all())
+            .then_some(normalized)
+    }
+}
+
+/// Turntable captured from the window, see `Turntable`
+#[derive(Clone, Copy, Debug)]
+pub struct TurntableSettings {
+    // In seconds
+    pub duration: f32,
+    pub fps: f32,
+    // Accumulated for every image
+    pub frames: u64,
+}
+
+impl Default for TurntableSettings {
+    fn default() -> Self {
+        Self {
+            duration: 8.0,
+            fps: 30.0,
+            frames: 64,
+        }
+    }
+}
+
+/// Draws the tabs for a single frame
+struct PanelViewer<'a, 'b> {
+    panels: &'a mut Panels,
+    cfg: &'a mut TracerConfigInner,
+    bundle: Bundle<'b>,
+    theme: &'a mut UiTheme,
+    reset_layout: bool,
+    // Camera is replaced from the animation, the free camera has to follow
+    camera_set: bool,
+    changed: bool,
+    objects_changed: bool,
+}
+
 pub struct UICompositor {
     config: TracerConfig,
     free_camera: FreeCamera,
+    gamepad: GamepadInputs,
+    // Handles of the selected object
+    gizmo: Gizmo,
     visible: bool,
+    // Left by the panels of the last frame, see `render_viewport`
+    render_viewport: Option<RenderViewport>,
+    settings: UiSettings,
+    panels: Panels,
+    // Most recent first, shown in the File menu
+    recent_scenes: Vec<PathBuf>,
+    // Scene picked in the File menu, see `take_scene_request`
+    scene_request: Option<PathBuf>,
+    // Float dump asked for in the File menu, see `take_float_dump_request`
+    float_dump_request: bool,
+    // Edited in the Camera menu
+    turntable: TurntableSettings,
+    // Capture asked for in the Camera menu, see `take_turntable_request`
+    turntable_request: Option<TurntableSettings>,
 
     pub egui: egui_winit::State,
-    pub allocator_visualizer: AllocatorVisualizer,
-    pub fps: f32,
-    pub tracer_profile: Option<TracerProfile>,
 }
 
+// Samples per pixel a tile converges at, set when skipping them is enabled
+const DEFAULT_CONVERGED_SAMPLES: u32 = 1024;
+
+// Largest locked render size offered in the UI, per side
+const MAX_RENDER_SIZE: u32 = 8192;
+
+// Aspect ratio overrides offered in the UI, None follows the window
+const ASPECT_RATIOS: [(&str, Option<f32>); 5] = [
+    ("Window", None),
+    ("16:9", Some(16.0 / 9.0)),
+    ("3:2", Some(3.0 / 2.0)),
+    ("2.39:1", Some(2.39)),
+    ("1:1", Some(1.0)),
+];
+
 macro_rules! float_slider {
     ($val:expr, $range:expr, $text:expr, $ui:expr, $changed:expr) => {
         if egui::Slider::new($val, $range)
@@ -34,39 +302,155 @@ macro_rules! float_slider {
 impl UICompositor {
     pub(crate) fn new_context() -> egui::Context {
         let egui = egui::Context::default();
-        let mut visuals = egui::Visuals::dark();
-        visuals.window_fill = egui::Color32::from_rgba_unmultiplied(
-            visuals.window_fill.r(),
-            visuals.window_fill.g(),
-            visuals.window_fill.b(),
-            255,
-        );
-        visuals.panel_fill = visuals.window_fill;
-
-        egui.set_visuals(visuals);
         egui.set_zoom_factor(1.1);
         egui
     }
 
     pub(crate) fn new(egui: egui_winit::State, config: TracerConfig) -> Self {
         let initial_camera = config.0.borrow().camera.clone();
+        let settings = UiSettings::load();
+        Self::apply_theme(egui.egui_ctx(), settings.theme);
         Self {
             egui,
-            allocator_visualizer: AllocatorVisualizer::new(),
             config,
-            fps: 0.0,
-            tracer_profile: None,
+            settings,
+            panels: Panels {
+                click_to_focus: false,
+                overlay: OverlaySettings::default(),
+                viewport: glam::UVec2::ONE,
+                ray_debugger: false,
+                debug_pick: None,
+                click_to_select: true,
+                selected_object: None,
+                scroll_to_selected: false,
+                animation_time: 0.0,
+                playing: false,
+                looping: false,
+                allocator_visualizer: AllocatorVisualizer::new(),
+                fps: 0.0,
+                frame_stats: FrameStats::default(),
+                tracer_profile: None,
+                device_info: None,
+                dismissed_stall: None,
+            },
             visible: true,
-            free_camera: FreeCamera::new(initial_camera),
+            render_viewport: None,
+            free_camera: FreeCamera::new(initial_camera, CameraMode::default()),
+            gamepad: GamepadInputs::default(),
+            gizmo: Gizmo::default(),
+            recent_scenes: vec![],
+            scene_request: None,
+            float_dump_request: false,
+            turntable: TurntableSettings::default(),
+            turntable_request: None,
+        }
+    }
+
+    /// Moves the UI to a new window. The egui context starts over, the
+    /// textures it had uploaded went away with the old presentation.
+    pub(crate) fn reattach(&mut self, egui: egui_winit::State) {
+        Self::apply_theme(egui.egui_ctx(), self.settings.theme);
+        self.egui = egui;
+    }
+
+    fn apply_theme(ctx: &egui::Context, theme: UiTheme) {
+        let mut visuals = match theme {
+            UiTheme::Dark => egui::Visuals::dark(),
+            UiTheme::Light => egui::Visuals::light(),
+        };
+        visuals.window_fill = egui::Color32::from_rgba_unmultiplied(
+            visuals.window_fill.r(),
+            visuals.window_fill.g(),
+            visuals.window_fill.b(),
+            255,
+        );
+        visuals.panel_fill = visuals.window_fill;
+        ctx.set_visuals(visuals);
+    }
+
+    /// Writes the panel layout and the theme to the settings file,
+    /// they are restored on the next run
+    pub fn save_settings(&self) {
+        if let Err(e) = self.settings.save() {
+            warn!("Failed to save UI settings: {:#}", e);
         }
     }
 
     pub fn set_fps(&mut self, fps: f32) {
-        self.fps = fps;
+        self.panels.fps = fps;
+    }
+
+    pub fn set_frame_stats(&mut self, stats: FrameStats) {
+        self.panels.frame_stats = stats;
     }
 
     pub fn set_tracer_profile(&mut self, profile: TracerProfile) {
-        self.tracer_profile = Some(profile);
+        self.panels.tracer_profile = Some(profile);
+    }
+
+    /// Hides the overlay as if F1 was pressed
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Part of the window the traced image goes to, as laid out by the
+    /// last frame. The whole window while the UI is hidden, None before
+    /// the first frame.
+    pub fn render_viewport(&self) -> Option<RenderViewport> {
+        self.render_viewport
+    }
+
+    /// Lines drawn over the traced image of the given aspect ratio,
+    /// hidden together with the rest of the UI
+    pub fn overlay(&self, aspect: f32) -> Option<OverlayLines> {
+        if !self.visible || self.panels.overlay.is_empty() {
+            return None;
+        }
+        Some(OverlayLines::new(
+            &self.config.0.borrow(),
+            &self.panels.overlay,
+            self.panels.selected_object,
+            aspect,
+        ))
+    }
+
+    pub fn set_recent_scenes(&mut self, scenes: Vec<PathBuf>) {
+        self.recent_scenes = scenes;
+    }
+
+    /// Scene the user asked to open since the last call
+    pub fn take_scene_request(&mut self) -> Option<PathBuf> {
+        self.scene_request.take()
+    }
+
+    /// Whether the user asked for a float dump since the last call
+    pub fn take_float_dump_request(&mut self) -> bool {
+        std::mem::take(&mut self.float_dump_request)
+    }
+
+    /// Turntable the user asked to capture since the last call
+    pub fn take_turntable_request(&mut self) -> Option<TurntableSettings> {
+        self.turntable_request.take()
+    }
+
+    /// Moves the free camera to the camera of the config, so it does not
+    /// drag the view back to where it was in the previous scene
+    pub fn reset_camera(&mut self) {
+        self.free_camera = FreeCamera::new(
+            self.config.0.borrow().camera.clone(),
+            self.free_camera.mode(),
+        );
+        self.panels.debug_pick = None;
+    }
+
+    /// Orbits the object, moving the camera so it fills the view
+    fn frame_object(free_camera: &mut FreeCamera, cfg: &TracerConfigInner, index: usize) {
+        if index >= cfg.objects.len() {
+            return;
+        }
+        info!("Framing object {}", index);
+        let (center, radius) = cfg.object_bounds(index);
+        free_camera.frame(center, radius, cfg.camera.vertical_fov());
     }
 
     pub fn on_window_event(&mut self, event: &WindowEvent) {
@@ -82,6 +466,12 @@ impl UICompositor {
                     info!("Toggling UI visibility");
                     self.visible = !self.visible;
                 }
+                (Key::Character(s), ElementState::Released) if s == "f" => {
+                    if let Some(index) = self.panels.selected_object {
+                        let cfg = self.config.0.borrow();
+                        Self::frame_object(&mut self.free_camera, &cfg, index);
+                    }
+                }
                 _ => {}
             },
 
@@ -89,76 +479,1114 @@ impl UICompositor {
         }
     }
 
-    pub(crate) fn render(&mut self, bundle: Bundle, ctx: &egui::Context) {
-        let mut changed = false;
-        let mut objects_changed = false;
-        let cfg = &mut self.config.0.borrow_mut();
+    /// `viewport` is the size of the presented image in physical pixels
+    pub(crate) fn render(&mut self, bundle: Bundle, ctx: &egui::Context, viewport: glam::UVec2) {
+        let cfg = &mut *self.config.0.borrow_mut();
+        let panels = &mut self.panels;
+        let render_viewport = self
+            .render_viewport
+            .unwrap_or(RenderViewport::whole(viewport))
+            .within(viewport);
+        panels.viewport = render_viewport.size;
 
+        self.free_camera.set_gamepad(self.gamepad.poll());
         if let Some(camera_data) = self.free_camera.tick_handler() {
             cfg.camera.position = camera_data.position;
             cfg.camera.direction = camera_data.as_direction();
             cfg.updated = true;
         }
 
+        // Releasing a gizmo handle is not a click on the scene
+        if (panels.click_to_focus || panels.ray_debugger || panels.click_to_select)
+            && !ctx.is_pointer_over_area()
+            && !self.gizmo.is_dragging()
+        {
+            let clicked = ctx.input(|i| {
+                i.pointer
+                    .primary_clicked()
+                    .then(|| i.pointer.interact_pos())
+                    .flatten()
+            });
+            if let Some(position) = clicked {
+                let position = glam::Vec2::new(position.x, position.y) * ctx.pixels_per_point();
+                if let Some(position) = render_viewport.normalize(position) {
+                    cfg.pick_request = Some(position);
+                }
+            }
+        }
+        if let Some(picked) = cfg.picked.take() {
+            if let Some(depth) = picked.depth.filter(|_| panels.click_to_focus) {
+                info!("Focusing at {:.2}", depth);
+                cfg.camera.focus_distance = depth;
+                cfg.updated = true;
+            }
+            if panels.click_to_select {
+                panels.selected_object = picked.object;
+                panels.scroll_to_selected = true;
+                // Bring the inspector to front
+                if let Some(tab) = self.settings.dock.find_tab(&Tab::Objects) {
+                    self.settings.dock.set_active_tab(tab);
+                }
+            }
+            if panels.ray_debugger {
+                panels.debug_pick = Some(picked);
+            }
+        }
+
+        // Played with the UI hidden too
+        if panels.playing {
+            let duration = cfg.animation.duration();
+            panels.animation_time += ctx.input(|i| i.stable_dt);
+            if panels.animation_time > duration {
+                if panels.looping && duration > 0.0 {
+                    panels.animation_time %= duration;
+                } else {
+                    panels.animation_time = duration;
+                    panels.playing = false;
+                }
+            }
+            if cfg.animate(panels.animation_time) {
+                self.free_camera = FreeCamera::new(cfg.camera.clone(), self.free_camera.mode());
+            }
+        }
+
+        // Nothing is drawn at all, so screenshots have no overlays
         if !self.visible {
+            self.render_viewport = Some(RenderViewport::whole(viewport));
             return;
         }
 
+        if let Some(index) = panels.selected_object {
+            if let Some(object) = cfg.objects.get_mut(index) {
+                let render_size = cfg.render_size;
+                if self.gizmo.show(
+                    ctx,
+                    &cfg.camera,
+                    render_viewport,
+                    render_size,
+                    index,
+                    object,
+                ) {
+                    // Only the moved object is uploaded, the scene stays
+                    cfg.objects_edited.push(index);
+                }
+            }
+        }
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    ui.menu_button("Recent Scenes", |ui| {
+                        if self.recent_scenes.is_empty() {
+                            ui.label("No recent scenes");
+                        }
+                        for scene in &self.recent_scenes {
+                            if ui.button(scene.display().to_string()).clicked() {
+                                self.scene_request = Some(scene.clone());
+                                ui.close();
+                            }
+                        }
+                    });
+                    ui.label("Drop a scene file onto the window to open it");
+                    ui.separator();
+                    if ui
+                        .button("Dump Float Frame")
+                        .on_hover_text("Write the traced image as raw float32 data")
+                        .clicked()
+                    {
+                        self.float_dump_request = true;
+                        ui.close();
+                    }
+                });
+                ui.menu_button("Camera", |ui| {
+                    let mut mode = self.free_camera.mode();
+                    ui.radio_value(&mut mode, CameraMode::Fly, "Fly")
+                        .on_hover_text("Move with WASD, look around with the right mouse button");
+                    ui.radio_value(&mut mode, CameraMode::Orbit, "Orbit")
+                        .on_hover_text(
+                            "Turn around the target with WASD or the right mouse button, \
+                         zoom with the wheel",
+                        );
+                    if mode != self.free_camera.mode() {
+                        info!("Switching to {:?} camera", mode);
+                        self.free_camera.set_mode(mode);
+                    }
+                    ui.separator();
+                    if ui
+                        .add_enabled(
+                            panels.selected_object.is_some(),
+                            egui::Button::new("Frame Selected"),
+                        )
+                        .on_hover_text("Orbit the selected object (F)")
+                        .clicked()
+                    {
+                        if let Some(index) = panels.selected_object {
+                            Self::frame_object(&mut self.free_camera, cfg, index);
+                        }
+                        ui.close();
+                    }
+                    ui.menu_button("Turntable", |ui| {
+                        let turntable = &mut self.turntable;
+                        egui::Slider::new(&mut turntable.duration, 1.0..=60.0)
+                            .text("Duration (s)")
+                            .ui(ui);
+                        egui::Slider::new(&mut turntable.fps, 1.0..=60.0)
+                            .text("Images per second")
+                            .ui(ui);
+                        egui::Slider::new(&mut turntable.frames, 1..=4096)
+                            .logarithmic(true)
+                            .text("Frames per image")
+                            .ui(ui);
+                        if ui
+                            .button("Capture")
+                            .on_hover_text(
+                                "Orbit the point in focus once, writing every image as a PNG",
+                            )
+                            .clicked()
+                        {
+                            self.turntable_request = Some(*turntable);
+                            ui.close();
+                        }
+                    });
+                });
+            });
+        });
+
+        let stall = panels
+            .tracer_profile
+            .as_ref()
+            .and_then(|profile| profile.gpu_stall.clone())
+            .filter(|stall| panels.dismissed_stall != Some(stall.value));
+        if let Some(stall) = stall {
+            egui::TopBottomPanel::top("gpu_stall").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!(
+                            "GPU took {:.1?} to trace a frame, try fewer samples or bounces",
+                            stall.elapsed
+                        ),
+                    );
+                    if ui.button("Dismiss").clicked() {
+                        panels.dismissed_stall = Some(stall.value);
+                    }
+                });
+            });
+        }
+
+        let invalid_pixels = panels
+            .tracer_profile
+            .as_ref()
+            .and_then(|profile| profile.invalid_pixels.clone());
+        if let Some(invalid) = invalid_pixels {
+            egui::TopBottomPanel::top("invalid_pixels").show(ctx, |ui| {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!(
+                        "{} NaN and {} infinite pixels, first at {}x{}",
+                        invalid.nan_count, invalid.inf_count, invalid.first.x, invalid.first.y
+                    ),
+                );
+            });
+        }
+
+        // The scene is traced meanwhile, the assets are added once loaded
+        let loading = panels
+            .tracer_profile
+            .as_ref()
+            .map(|profile| profile.loading.clone())
+            .unwrap_or_default();
+        if !loading.is_empty() {
+            egui::Window::new("Loading")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+                .show(ctx, |ui| {
+                    for asset in &loading {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(asset);
+                        });
+                    }
+                });
+        }
+
+        let mut theme = self.settings.theme;
+        let mut viewer = PanelViewer {
+            panels,
+            cfg,
+            bundle,
+            theme: &mut theme,
+            reset_layout: false,
+            camera_set: false,
+            changed: false,
+            objects_changed: false,
+        };
         egui::SidePanel::left("side_panel")
             .resizable(true)
             .show(ctx, |ui| {
-                ui.label(format!("FPS: {:.2}", self.fps));
-                if let Some(profile) = &self.tracer_profile {
-                    ui.label(format!("Traces per sec: {:.2}", profile.fps.fps()));
-                    ui.label(format!("Render time: {:.2}", profile.render_time));
-                }
-
-                ui.separator();
-                ui.label("Press F1 to toggle UI visibility");
-                ui.label("Use WASD + Space/Shift to move camera");
-                ui.separator();
-
-                ui.collapsing("Tracer Controls", |ui| {
-                    const PI: f32 = std::f32::consts::PI;
-                    float_slider!(&mut cfg.camera.fov, 0.0..=PI, "FOV", ui, changed);
-                    float_slider!(&mut cfg.samples_count, 1..=150, "Samples Count", ui, changed);
-                    float_slider!(
-                        &mut cfg.max_bounces,
-                        1..=16,
-                        "Max Bounces",
-                        ui, changed
-                    );
+                DockArea::new(&mut self.settings.dock)
+                    .style(Style::from_egui(ui.style().as_ref()))
+                    .show_close_buttons(false)
+                    .show_inside(ui, &mut viewer);
+            });
+
+        // What the panels left is traced, floating windows stay on top of it
+        let available = ctx.available_rect();
+        let pixels_per_point = ctx.pixels_per_point();
+        let offset = glam::Vec2::new(available.min.x, available.min.y) * pixels_per_point;
+        let size = glam::Vec2::new(available.width(), available.height()) * pixels_per_point;
+        let render_viewport = RenderViewport {
+            offset: offset.round().as_uvec2(),
+            size: size.round().as_uvec2(),
+        };
+        self.render_viewport = Some(render_viewport.within(viewport));
+
+        let PanelViewer {
+            cfg,
+            reset_layout,
+            camera_set,
+            changed,
+            objects_changed,
+            ..
+        } = viewer;
+        if camera_set {
+            self.free_camera = FreeCamera::new(cfg.camera.clone(), self.free_camera.mode());
+            cfg.updated = true;
+        }
+        if reset_layout {
+            info!("Resetting UI layout");
+            self.settings.dock = UiSettings::default_dock();
+        }
+        if theme != self.settings.theme {
+            info!("Switching to {:?} UI theme", theme);
+            self.settings.theme = theme;
+            Self::apply_theme(ctx, theme);
+        }
+
+        if changed {
+            cfg.updated = true;
+        }
+        if objects_changed {
+            // The config holds the number of lights
+            cfg.updated = true;
+            cfg.objects_updated = true;
+        }
+    }
+}
+
+impl TabViewer for PanelViewer<'_, '_> {
+    type Tab = Tab;
+
+    fn title(&mut self, tab: &mut Tab) -> egui::WidgetText {
+        tab.name().into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Tab) {
+        egui::ScrollArea::vertical().show(ui, |ui| match tab {
+            Tab::Stats => self.stats(ui),
+            Tab::TracerControls => self.tracer_controls(ui),
+            Tab::Materials => self.materials(ui),
+            Tab::Objects => self.objects(ui),
+            Tab::SceneGraph => self.scene_graph(ui),
+            Tab::Lights => self.lights(ui),
+            Tab::RayDebugger => self.ray_debugger(ui),
+            Tab::Animation => self.animation(ui),
+            Tab::PostProcessing => self.post_processing(ui),
+            Tab::Allocator => self.allocator(ui),
+            Tab::SceneStats => self.scene_stats(ui),
+            Tab::Settings => self.settings(ui),
+        });
+    }
+}
+
+impl PanelViewer<'_, '_> {
+    fn stats(&mut self, ui: &mut egui::Ui) {
+        let panels = &self.panels;
+        ui.label(format!("FPS: {:.2}", panels.fps));
+        ui.label(format!("1% low: {:.2} FPS", panels.frame_stats.low_1_fps));
+        ui.label(format!(
+            "Frame time: {:.2} ms (p99 {:.2} ms)",
+            panels.frame_stats.avg_ms, panels.frame_stats.p99_ms
+        ));
+        if let Some(profile) = &panels.tracer_profile {
+            ui.label(format!("Traces per sec: {:.2}", profile.fps.fps()));
+            ui.label(format!("Render time: {:.2}", profile.render_time));
+            if let Some(latency) = profile.display_latency {
+                ui.label(format!("Display latency: {:.2} ms", latency));
+            }
+            if let Some(samples) = profile.adaptive_samples {
+                ui.label(format!("Samples per frame: {} (adaptive)", samples));
+            }
+            ui.collapsing("GPU Times", |ui| {
+                for (name, ms) in &profile.gpu_times {
+                    ui.label(format!("{}: {:.3} ms", name, ms));
+                }
+            });
+        }
+
+        ui.separator();
+        ui.label("Press F1 to toggle UI visibility");
+        ui.label("Use WASD + Space/Shift to move camera");
+    }
+
+    fn allocator(&mut self, ui: &mut egui::Ui) {
+        const MIB: f64 = 1024.0 * 1024.0;
+        let report = self.bundle.allocator().generate_report();
+        ui.label(format!(
+            "Allocated: {:.1} of {:.1} MiB reserved",
+            report.total_allocated_bytes as f64 / MIB,
+            report.total_capacity_bytes as f64 / MIB
+        ));
+        if let Some(profile) = &self.panels.tracer_profile {
+            ui.label(format!(
+                "Pooled images: {:.1} MiB",
+                profile.pooled_image_bytes as f64 / MIB
+            ));
+        }
+        if ui
+            .button("Defragment")
+            .on_hover_text("Recreate the images in compacted memory, restarts the accumulation")
+            .clicked()
+        {
+            self.cfg.defragment_request = true;
+        }
+        ui.separator();
+
+        self.panels
+            .allocator_visualizer
+            .render_breakdown_ui(ui, &self.bundle.allocator());
+    }
+
+    fn scene_stats(&mut self, ui: &mut egui::Ui) {
+        let bundle = self.bundle;
+        let device = self.panels.device_info.get_or_insert_with(|| unsafe {
+            DeviceInfo::query(bundle.instance, bundle.physical_device)
+        });
+        ui.label(format!("Device: {}", device.name));
+        ui.label(format!(
+            "Driver: {} ({}), API {}",
+            device.driver_name, device.driver_info, device.api_version
+        ));
+        ui.separator();
+
+        let cfg = &*self.cfg;
+        let mut objects = BTreeMap::new();
+        for object in &cfg.objects {
+            *objects.entry(object.name()).or_insert(0) += 1;
+        }
+        ui.label(format!("Objects: {}", cfg.objects.len()));
+        for (name, count) in objects {
+            ui.label(format!("    {}: {}", name, count));
+        }
+        let mut lights = BTreeMap::new();
+        for light in &cfg.lights {
+            *lights.entry(light.name()).or_insert(0) += 1;
+        }
+        ui.label(format!("Lights: {}", cfg.lights.len()));
+        for (name, count) in lights {
+            ui.label(format!("    {}: {}", name, count));
+        }
+        ui.label(format!("Instances: {}", cfg.instances.len()));
+        ui.label(format!("Materials: {}", cfg.materials.len()));
+        ui.label(format!("Volumes: {}", cfg.volumes.len()));
+        ui.label(format!("Nodes: {}", cfg.nodes.len()));
+        // No acceleration structure yet, every ray tests every object
+        ui.label("BVH: none");
+
+        if let Some(profile) = &self.panels.tracer_profile {
+            const KIB: f64 = 1024.0;
+            let total: u64 = profile.scene_bytes.iter().map(|(_, bytes)| bytes).sum();
+            ui.separator();
+            ui.label(format!("Scene memory: {:.1} KiB", total as f64 / KIB));
+            for (name, bytes) in &profile.scene_bytes {
+                ui.label(format!("    {}: {:.1} KiB", name, *bytes as f64 / KIB));
+            }
+        }
+    }
+
+    fn tracer_controls(&mut self, ui: &mut egui::Ui) {
+        const PI: f32 = std::f32::consts::PI;
+        let cfg = &mut *self.cfg;
+        let mut changed = false;
+        let camera = &mut cfg.camera;
+        match &mut camera.focal_length {
+            Some(focal_length) => {
+                float_slider!(focal_length, 10.0..=300.0, "Focal Length (mm)", ui, changed);
+            }
+            None => float_slider!(&mut camera.fov, 0.0..=PI, "FOV", ui, changed),
+        }
+        ui.horizontal(|ui| {
+            ui.label("Lens");
+            for preset in camera::LENS_PRESETS {
+                changed |= ui
+                    .selectable_value(
+                        &mut camera.focal_length,
+                        Some(preset),
+                        format!("{}mm", preset),
+                    )
+                    .changed();
+            }
+            // Keep the current view when leaving the lens
+            let fov = camera.vertical_fov();
+            if ui
+                .selectable_value(&mut camera.focal_length, None, "Free")
+                .changed()
+            {
+                camera.fov = fov;
+                changed = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Aspect");
+            for (name, aspect_ratio) in ASPECT_RATIOS {
+                changed |= ui
+                    .selectable_value(&mut camera.aspect_ratio, aspect_ratio, name)
+                    .changed();
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut locked = cfg.render_size.is_some();
+            if ui.checkbox(&mut locked, "Lock Size").changed() {
+                // Keeps the size traced so far
+                cfg.render_size = locked.then_some(self.panels.viewport);
+            }
+            if let Some(size) = &mut cfg.render_size {
+                ui.add(egui::DragValue::new(&mut size.x).range(1..=MAX_RENDER_SIZE));
+                ui.label("x");
+                ui.add(egui::DragValue::new(&mut size.y).range(1..=MAX_RENDER_SIZE));
+            }
+        });
+        let quality = QualityPreset::matching(cfg);
+        egui::ComboBox::from_label("Quality")
+            .selected_text(quality.map_or("Custom", |preset| preset.name()))
+            .show_ui(ui, |ui| {
+                for preset in QualityPreset::ALL {
                     if ui
-                        .color_edit_button_rgb(&mut cfg.sky_color_top.as_mut())
-                        .changed()
+                        .selectable_label(quality == Some(preset), preset.name())
+                        .clicked()
                     {
+                        info!("Applying {} quality preset", preset.name());
+                        preset.apply(cfg);
                         changed = true;
                     }
-                    if ui
-                        .color_edit_button_rgb(&mut cfg.sky_color_bottom.as_mut())
-                        .changed()
-                    {
-                        changed = true;
+                }
+            });
+        float_slider!(
+            &mut cfg.samples_count,
+            1..=150,
+            "Samples Count",
+            ui,
+            changed
+        );
+        float_slider!(&mut cfg.max_bounces, 1..=16, "Max Bounces", ui, changed);
+        // Cadence only, the accumulated image stays valid
+        let mut adaptive = cfg.adaptive_samples.is_some();
+        if ui.checkbox(&mut adaptive, "Adaptive Samples").changed() {
+            cfg.adaptive_samples = adaptive.then(AdaptiveSamples::default);
+        }
+        if let Some(adaptive) = &mut cfg.adaptive_samples {
+            ui.add(egui::Slider::new(&mut adaptive.target_ms, 1.0..=100.0).text("Target ms"));
+            ui.add(egui::Slider::new(&mut adaptive.min_samples, 1..=256).text("Min Samples"));
+            ui.add(egui::Slider::new(&mut adaptive.max_samples, 1..=256).text("Max Samples"));
+            adaptive.max_samples = adaptive.max_samples.max(adaptive.min_samples);
+        } else {
+            ui.add(
+                egui::Slider::new(&mut cfg.dispatches_per_frame, 1..=16)
+                    .text("Dispatches per Frame"),
+            );
+        }
+        ui.add(egui::Slider::new(&mut cfg.frames_per_dispatch, 1..=16).text("Frames per Dispatch"));
+        float_slider!(
+            &mut cfg.resolution_scale,
+            MIN_RESOLUTION_SCALE..=1.0,
+            "Resolution Scale",
+            ui,
+            changed
+        );
+        if ui.checkbox(&mut cfg.blue_noise, "Blue Noise").changed() {
+            changed = true;
+        }
+        if ui
+            .checkbox(&mut cfg.temporal_reprojection, "Temporal Reprojection")
+            .changed()
+        {
+            changed = true;
+        }
+        if ui
+            .checkbox(&mut cfg.temporal_accumulation, "Temporal Accumulation")
+            .changed()
+        {
+            changed = true;
+        }
+        let mut skip_converged = cfg.converged_samples.is_some();
+        if ui
+            .checkbox(&mut skip_converged, "Skip Converged Tiles")
+            .changed()
+        {
+            cfg.converged_samples = skip_converged.then_some(DEFAULT_CONVERGED_SAMPLES);
+            changed = true;
+        }
+        if let Some(samples) = &mut cfg.converged_samples {
+            float_slider!(samples, 1..=4096, "Converged Samples", ui, changed);
+        }
+        float_slider!(
+            &mut cfg.jitter_strength,
+            0.0..=2.0,
+            "Jitter Strength",
+            ui,
+            changed
+        );
+        float_slider!(&mut cfg.camera.aperture, 0.0..=0.5, "Aperture", ui, changed);
+        float_slider!(
+            &mut cfg.camera.focus_distance,
+            0.1..=100.0,
+            "Focus Distance",
+            ui,
+            changed
+        );
+        if let Some(environment) = &mut cfg.environment {
+            float_slider!(
+                &mut environment.intensity,
+                0.0..=10.0,
+                "Environment Intensity",
+                ui,
+                changed
+            );
+        }
+        // The compared side follows its own settings, the main one stays
+        let mut compare = cfg.compare.is_some();
+        if ui
+            .checkbox(&mut compare, "Compare")
+            .on_hover_text(
+                "Trace a second image with other settings, shown split with the main one",
+            )
+            .changed()
+        {
+            cfg.compare = compare.then(CompareConfig::default);
+        }
+        if let Some(compare) = &mut cfg.compare {
+            ui.add(egui::Slider::new(&mut compare.divider, 0.0..=1.0).text("Divider"));
+            ui.label("Compared settings, one key=value per line");
+            let mut overrides = compare.overrides.join("\n");
+            if ui.text_edit_multiline(&mut overrides).changed() {
+                compare.overrides = overrides.split('\n').map(str::to_string).collect();
+            }
+        }
+        ui.checkbox(&mut self.panels.click_to_focus, "Click to Focus");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.panels.overlay.grid, "Grid");
+            ui.checkbox(&mut self.panels.overlay.axes, "Axes");
+        });
+        let mut bounds = self.panels.overlay.bounds.is_some();
+        if ui.checkbox(&mut bounds, "Bounds Overlay").changed() {
+            self.panels.overlay.bounds = bounds.then(OverlayBounds::default);
+        }
+        if let Some(bounds) = &mut self.panels.overlay.bounds {
+            ui.horizontal(|ui| {
+                ui.radio_value(bounds, OverlayBounds::Spheres, "Spheres");
+                ui.radio_value(bounds, OverlayBounds::Boxes, "Boxes");
+            });
+        }
+        if ui
+            .color_edit_button_rgb(&mut cfg.sky_color_top.as_mut())
+            .changed()
+        {
+            changed = true;
+        }
+        if ui
+            .color_edit_button_rgb(&mut cfg.sky_color_bottom.as_mut())
+            .changed()
+        {
+            changed = true;
+        }
+        if ui
+            .color_edit_button_rgb(&mut cfg.ground_color.as_mut())
+            .changed()
+        {
+            changed = true;
+        }
+        self.changed |= changed;
+    }
+
+    fn materials(&mut self, ui: &mut egui::Ui) {
+        let mut objects_changed = false;
+        // Shared by all objects referencing them, so one edit
+        // changes all of them
+        for (name, material) in self.cfg.materials.iter_mut() {
+            ui.push_id(name.as_str(), |ui| {
+                ui.label(name.as_str());
+                ui.horizontal(|ui| {
+                    if ui.color_edit_button_rgb(material.albedo.as_mut()).changed() {
+                        objects_changed = true;
                     }
+                    ui.label("Albedo");
+                });
+                ui.horizontal(|ui| {
                     if ui
-                        .color_edit_button_rgb(&mut cfg.ground_color.as_mut())
+                        .color_edit_button_rgb(material.emission_color.as_mut())
                         .changed()
                     {
-                        changed = true;
+                        objects_changed = true;
                     }
+                    ui.label("Emission");
                 });
+                float_slider!(
+                    &mut material.emission_strength,
+                    0.0..=20.0,
+                    "Emission Strength",
+                    ui,
+                    objects_changed
+                );
+            });
+        }
+        self.objects_changed |= objects_changed;
+    }
+
+    fn objects(&mut self, ui: &mut egui::Ui) {
+        let panels = &mut *self.panels;
+        let cfg = &mut *self.cfg;
+        ui.checkbox(&mut panels.click_to_select, "Click to Select");
+        // An object may have been removed by a scene reload
+        if panels
+            .selected_object
+            .is_some_and(|index| index >= cfg.objects.len())
+        {
+            panels.selected_object = None;
+        }
 
-                ui.collapsing("Allocator Breakdown", |ui| {
-                    self.allocator_visualizer
-                        .render_breakdown_ui(ui, &bundle.allocator());
+        // Hiding and soloing change the uploaded objects, the scene is
+        // uploaded again
+        let mut objects_changed = false;
+        if cfg.objects.iter().any(Object::is_solo) && ui.button("Clear Solo").clicked() {
+            cfg.objects
+                .iter_mut()
+                .for_each(|object| object.set_solo(false));
+            objects_changed = true;
+        }
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for (index, object) in cfg.objects.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let mut visible = !object.is_hidden();
+                        if ui
+                            .checkbox(&mut visible, "")
+                            .on_hover_text("Visible")
+                            .changed()
+                        {
+                            object.set_hidden(!visible);
+                            objects_changed = true;
+                        }
+                        if ui
+                            .selectable_label(object.is_solo(), "S")
+                            .on_hover_text("Solo, show only the soloed objects")
+                            .clicked()
+                        {
+                            object.set_solo(!object.is_solo());
+                            objects_changed = true;
+                        }
+                        let label = format!("{} #{} ({})", object.name(), index, object.material());
+                        let response =
+                            ui.selectable_value(&mut panels.selected_object, Some(index), label);
+                        if panels.scroll_to_selected && panels.selected_object == Some(index) {
+                            response.scroll_to_me(Some(egui::Align::Center));
+                        }
+                    });
+                }
+            });
+        panels.scroll_to_selected = false;
+        self.objects_changed |= objects_changed;
+
+        ui.separator();
+        let Some(index) = panels.selected_object else {
+            ui.label("Click an object in the viewport or in the list to inspect it");
+            return;
+        };
+        let mut edited = false;
+        let materials: Vec<String> = cfg.materials.keys().cloned().collect();
+        let object = &mut cfg.objects[index];
+        ui.label(format!("{} #{}", object.name(), index));
+        match object {
+            Object::Sphere {
+                center,
+                radius,
+                material,
+                ..
+            } => {
+                edited |= Self::vec3_drag(ui, "Center", center);
+                float_slider!(radius, 0.01..=100.0, "Radius", ui, edited);
+                edited |= Self::material_combo(ui, material, &materials);
+            }
+            Object::Sdf {
+                center, material, ..
+            } => {
+                edited |= Self::vec3_drag(ui, "Center", center);
+                edited |= Self::material_combo(ui, material, &materials);
+            }
+        }
+        if let Some(node) = object.node() {
+            ui.label(format!("Moves with the {} node", node));
+        }
+        if edited {
+            // Only this object is uploaded, the rest of the scene stays
+            cfg.objects_edited.push(index);
+        }
+    }
+
+    fn material_combo(ui: &mut egui::Ui, material: &mut String, materials: &[String]) -> bool {
+        let mut changed = false;
+        egui::ComboBox::from_label("Material")
+            .selected_text(material.as_str())
+            .show_ui(ui, |ui| {
+                for name in materials {
+                    changed |= ui
+                        .selectable_value(material, name.clone(), name.as_str())
+                        .changed();
+                }
+            });
+        changed
+    }
+
+    fn scene_graph(&mut self, ui: &mut egui::Ui) {
+        let mut objects_changed = false;
+        if self.cfg.nodes.is_empty() {
+            ui.label("No nodes in the scene");
+        }
+        // Children follow their parents, so editing a node
+        // moves all the objects below it
+        for (name, node) in self.cfg.nodes.iter_mut() {
+            ui.push_id(name.as_str(), |ui| {
+                match &node.parent {
+                    Some(parent) => ui.label(format!("{} (child of {})", name, parent)),
+                    None => ui.label(name.as_str()),
+                };
+                let transform = &mut node.transform;
+                objects_changed |= Self::vec3_drag(ui, "Translation", &mut transform.translation);
+                objects_changed |= Self::vec3_drag(ui, "Rotation", &mut transform.rotation);
+                objects_changed |= Self::vec3_drag(ui, "Scale", &mut transform.scale);
+            });
+        }
+        self.objects_changed |= objects_changed;
+    }
+
+    fn lights(&mut self, ui: &mut egui::Ui) {
+        let cfg = &mut *self.cfg;
+        let mut objects_changed = false;
+        if ui
+            .checkbox(&mut cfg.next_event_estimation, "Next Event Estimation")
+            .changed()
+        {
+            self.changed = true;
+        }
+
+        let mut removed = None;
+        for (index, light) in cfg.lights.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} #{}", light.name(), index));
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
                 });
+                let (color, intensity) = light.as_color_mut();
+                if ui.color_edit_button_rgb(color.as_mut()).changed() {
+                    objects_changed = true;
+                }
+                float_slider!(intensity, 0.0..=100.0, "Intensity", ui, objects_changed);
             });
+        }
+        if let Some(index) = removed {
+            cfg.lights.remove(index);
+            objects_changed = true;
+        }
 
-        if changed {
-            cfg.updated = true;
+        ui.horizontal(|ui| {
+            let added = if ui.button("Add Point").clicked() {
+                Some(Light::Point {
+                    position: glam::Vec3::new(0.0, 2.0, 0.0),
+                    color: glam::Vec3::ONE,
+                    intensity: 10.0,
+                })
+            } else if ui.button("Add Directional").clicked() {
+                Some(Light::Directional {
+                    direction: glam::Vec3::new(-1.0, -1.0, -1.0),
+                    color: glam::Vec3::ONE,
+                    intensity: 2.0,
+                })
+            } else if ui.button("Add Area").clicked() {
+                Some(Light::Area {
+                    corner: glam::Vec3::new(-0.5, 2.0, -1.5),
+                    edge_u: glam::Vec3::X,
+                    edge_v: glam::Vec3::Z,
+                    color: glam::Vec3::ONE,
+                    intensity: 5.0,
+                })
+            } else {
+                None
+            };
+            if let Some(light) = added {
+                cfg.lights.push(light);
+                objects_changed = true;
+            }
+        });
+        self.objects_changed |= objects_changed;
+    }
+
+    fn ray_debugger(&mut self, ui: &mut egui::Ui) {
+        // Reported in a panel above the image, no update needed
+        ui.checkbox(&mut self.cfg.check_invalid_pixels, "Check for NaN/Inf");
+        ui.checkbox(&mut self.panels.ray_debugger, "Pick on Click");
+        match &self.panels.debug_pick {
+            Some(pick) => {
+                ui.label(format!("Pixel: {}x{}", pick.pixel.x, pick.pixel.y));
+                Self::path_tree(ui, &pick.path);
+            }
+            None => {
+                ui.label("Click a pixel to trace it");
+            }
         }
-        if objects_changed {
-            cfg.objects_updated = true;
+    }
+
+    fn animation(&mut self, ui: &mut egui::Ui) {
+        let animation = &mut self.cfg.animation;
+        ui.horizontal(|ui| {
+            ui.label("Interpolation");
+            ui.radio_value(
+                &mut animation.interpolation,
+                Interpolation::Linear,
+                "Linear",
+            );
+            ui.radio_value(
+                &mut animation.interpolation,
+                Interpolation::CatmullRom,
+                "Catmull-Rom",
+            );
+        });
+
+        let time = &mut self.panels.animation_time;
+        let mut scrubbed = egui::Slider::new(&mut *time, 0.0..=animation.duration())
+            .text("Time")
+            .suffix(" s")
+            .ui(ui)
+            .changed();
+
+        ui.horizontal(|ui| {
+            let playing = &mut self.panels.playing;
+            if ui.button(if *playing { "Pause" } else { "Play" }).clicked() {
+                *playing = !*playing;
+                // Starts over once at the end
+                if *playing && *time >= animation.duration() {
+                    *time = 0.0;
+                }
+            }
+            ui.checkbox(&mut self.panels.looping, "Loop");
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Add Keyframe").clicked() {
+                info!("Adding camera keyframe at {:.2}s", time);
+                animation.insert(Keyframe {
+                    time: *time,
+                    camera: self.cfg.camera.clone(),
+                });
+            }
+            if ui.button("+1 s").clicked() {
+                *time = animation.duration() + 1.0;
+            }
+        });
+
+        let mut removed = None;
+        for (index, keyframe) in animation.keyframes.iter().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.2} s", keyframe.time));
+                    if ui.button("Go To").clicked() {
+                        *time = keyframe.time;
+                        scrubbed = true;
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            });
+        }
+        if let Some(index) = removed {
+            animation.keyframes.remove(index);
+        }
+        if !animation.objects.is_empty() || !animation.materials.is_empty() {
+            ui.label(format!(
+                "Tracks: {} objects, {} materials",
+                animation.objects.len(),
+                animation.materials.len()
+            ));
+        }
+
+        if ui
+            .button("Copy as JSON")
+            .on_hover_text("Animation block of the config, render it with --sequence-fps")
+            .clicked()
+        {
+            match serde_json::to_string_pretty(animation) {
+                Ok(json) => ui.ctx().copy_text(json),
+                Err(e) => warn!("Failed to export the animation: {}", e),
+            }
+        }
+
+        if scrubbed && self.cfg.animate(self.panels.animation_time) {
+            self.camera_set = true;
         }
     }
+
+    // Applied to the presented image only, the accumulation goes on
+    fn post_processing(&mut self, ui: &mut egui::Ui) {
+        let post = &mut self.cfg.post;
+        let mut removed = None;
+        let mut raised = None;
+        for (index, pass) in post.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}. {}", index + 1, pass.name()));
+                    if ui.add_enabled(index > 0, egui::Button::new("Up")).clicked() {
+                        raised = Some(index);
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                match pass {
+                    PostPass::Tonemap {
+                        exposure,
+                        auto_exposure,
+                    } => {
+                        let label = if auto_exposure.is_some() {
+                            "Compensation"
+                        } else {
+                            "Exposure"
+                        };
+                        ui.add(egui::Slider::new(exposure, -5.0..=5.0).text(label));
+                        let mut auto = auto_exposure.is_some();
+                        if ui.checkbox(&mut auto, "Auto Exposure").changed() {
+                            *auto_exposure = auto.then(AutoExposure::default);
+                        }
+                        if let Some(auto_exposure) = auto_exposure {
+                            ui.add(
+                                egui::Slider::new(&mut auto_exposure.speed, 0.0..=10.0)
+                                    .text("Adaptation Speed"),
+                            );
+                            let max = auto_exposure.max_exposure;
+                            ui.add(
+                                egui::Slider::new(&mut auto_exposure.min_exposure, -16.0..=max)
+                                    .text("Min Exposure"),
+                            );
+                            let min = auto_exposure.min_exposure;
+                            ui.add(
+                                egui::Slider::new(&mut auto_exposure.max_exposure, min..=16.0)
+                                    .text("Max Exposure"),
+                            );
+                        }
+                    }
+                    PostPass::Bloom {
+                        threshold,
+                        intensity,
+                        radius,
+                    } => {
+                        ui.add(egui::Slider::new(threshold, 0.0..=10.0).text("Threshold"));
+                        ui.add(egui::Slider::new(intensity, 0.0..=1.0).text("Intensity"));
+                        ui.add(egui::Slider::new(radius, 1.0..=64.0).text("Radius"));
+                    }
+                    PostPass::Vignette { strength } => {
+                        ui.add(egui::Slider::new(strength, 0.0..=1.0).text("Strength"));
+                    }
+                    PostPass::Fxaa => {}
+                }
+            });
+        }
+        if let Some(index) = raised {
+            post.swap(index - 1, index);
+        }
+        if let Some(index) = removed {
+            post.remove(index);
+        }
+
+        if PostPass::is_bloom_after_tonemap(post) {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Bloom after Tonemap finds nothing above the threshold",
+            );
+        }
+
+        ui.horizontal(|ui| {
+            for pass in PostPass::ALL {
+                if ui.button(format!("Add {}", pass.name())).clicked() {
+                    // The bloom needs the HDR image
+                    let bloom = matches!(pass, PostPass::Bloom { .. });
+                    let tonemap = post
+                        .iter()
+                        .position(|pass| matches!(pass, PostPass::Tonemap { .. }));
+                    match tonemap.filter(|_| bloom) {
+                        Some(index) => post.insert(index, pass),
+                        None => post.push(pass),
+                    }
+                }
+            }
+        });
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            ui.radio_value(&mut *self.theme, UiTheme::Dark, "Dark");
+            ui.radio_value(&mut *self.theme, UiTheme::Light, "Light");
+        });
+        if ui.button("Reset Layout").clicked() {
+            self.reset_layout = true;
+        }
+    }
+
+    fn vec3_drag(ui: &mut egui::Ui, label: &str, value: &mut glam::Vec3) -> bool {
+        ui.horizontal(|ui| {
+            let mut changed = false;
+            for component in value.as_mut() {
+                changed |= egui::DragValue::new(component).speed(0.01).ui(ui).changed();
+            }
+            ui.label(label);
+            changed
+        })
+        .inner
+    }
+
+    // Each bounce is nested into the previous one, so the path reads as a tree
+    fn path_tree(ui: &mut egui::Ui, path: &[PathVertex]) {
+        let Some((vertex, rest)) = path.split_first() else {
+            return;
+        };
+
+        let title = match vertex.distance {
+            Some(distance) => format!("Hit object {} at {:.3}", vertex.object, distance),
+            None => "Miss".to_string(),
+        };
+        ui.push_id(path.len(), |ui| {
+            ui.collapsing(title, |ui| {
+                let vec3 = |v: glam::Vec3| format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z);
+                ui.label(format!("Origin: {}", vec3(vertex.origin)));
+                ui.label(format!("Direction: {}", vec3(vertex.direction)));
+                if vertex.distance.is_some() {
+                    ui.label(format!("Point: {}", vec3(vertex.point)));
+                    ui.label(format!(
+                        "Normal: {} ({} face)",
+                        vec3(vertex.normal),
+                        if vertex.front_face { "front" } else { "back" }
+                    ));
+                    ui.label(format!("Albedo: {}", vec3(vertex.albedo)));
+                    ui.label(format!("Cosine: {:.3}", vertex.cosine));
+                    ui.label(format!("Emission: {}", vec3(vertex.emission)));
+                }
+                ui.label(format!("Throughput: {}", vec3(vertex.throughput)));
+                ui.label(format!("Radiance: {}", vec3(vertex.radiance)));
+
+                Self::path_tree(ui, rest);
+            });
+        });
+    }
 }
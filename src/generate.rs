@@ -0,0 +1,181 @@
+use crate::config::{Camera, Light, Material, Object, TracerConfig, TracerConfigInner};
+use glam::{Vec2, Vec3};
+use log::info;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+// Radius of the small spheres, one per grid cell
+const SMALL_RADIUS: f32 = 0.2;
+const LARGE_RADIUS: f32 = 1.0;
+const GROUND_RADIUS: f32 = 1000.0;
+// Large spheres in the middle of the grid, the small ones keep clear of them
+const LARGE_CENTERS: [Vec3; 3] = [
+    Vec3::new(-4.0, LARGE_RADIUS, 0.0),
+    Vec3::new(0.0, LARGE_RADIUS, 0.0),
+    Vec3::new(4.0, LARGE_RADIUS, 0.0),
+];
+// Distance of the small sphere cells from the large sphere centers
+const CLEARANCE: f32 = LARGE_RADIUS + SMALL_RADIUS * 2.0;
+// Fraction of the small spheres glowing on their own
+const EMISSIVE_FRACTION: f32 = 0.05;
+// Grid of 22x22 cells the classic cover is framed for
+const COVER_EXTENT: f32 = 11.0;
+
+/// SplitMix64, the same seed gives the same scene on every platform
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // In [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub(crate) fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    fn color(&mut self) -> Vec3 {
+        Vec3::new(self.next_f32(), self.next_f32(), self.next_f32())
+    }
+}
+
+/// Centers of the grid cells free for small spheres, nearest to the
+/// middle first. The grid grows until `count` of them are free.
+fn free_cells(count: usize) -> Vec<Vec2> {
+    let mut half = 1;
+    loop {
+        let mut cells: Vec<Vec2> = (-half..half)
+            .flat_map(|x| (-half..half).map(move |z| Vec2::new(x as f32, z as f32) + 0.5))
+            .filter(|cell| {
+                LARGE_CENTERS
+                    .iter()
+                    .all(|center| cell.distance(Vec2::new(center.x, center.z)) > CLEARANCE)
+            })
+            .collect();
+        if cells.len() >= count {
+            cells.sort_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+            cells.truncate(count);
+            return cells;
+        }
+        half += 1;
+    }
+}
+
+/// Random scene in the manner of the Ray Tracing in One Weekend cover: a grid
+/// of small spheres of random colors around three large ones, on a ground
+/// sphere. The grid grows with the number of spheres.
+pub fn generate_scene(spheres: usize, seed: u64) -> TracerConfigInner {
+    let mut random = Random(seed);
+    let mut config = TracerConfigInner {
+        materials: BTreeMap::new(),
+        objects: vec![],
+        ..TracerConfigInner::default()
+    };
+    let add = |config: &mut TracerConfigInner, name: String, material, center, radius| {
+        config.materials.insert(name.clone(), material);
+        config.objects.push(Object::Sphere {
+            center,
+            radius,
+            material: name,
+            node: None,
+            textures: Default::default(),
+            hidden: false,
+            solo: false,
+        });
+    };
+    let diffuse = |albedo| Material {
+        albedo,
+        emission_color: Vec3::ZERO,
+        emission_strength: 0.0,
+    };
+
+    add(
+        &mut config,
+        "ground".to_string(),
+        diffuse(Vec3::splat(0.5)),
+        Vec3::new(0.0, -GROUND_RADIUS, 0.0),
+        GROUND_RADIUS,
+    );
+    for (i, center) in LARGE_CENTERS.into_iter().enumerate() {
+        add(
+            &mut config,
+            format!("large_{}", i),
+            diffuse(random.color()),
+            center,
+            LARGE_RADIUS,
+        );
+    }
+
+    let cells = free_cells(spheres);
+    let extent = cells
+        .iter()
+        .map(|cell| cell.abs().max_element())
+        .fold(COVER_EXTENT, f32::max);
+    for (i, cell) in cells.into_iter().enumerate() {
+        // Jittered within the cell, never touching the neighbours
+        let jitter = 0.5 - SMALL_RADIUS;
+        let center = Vec3::new(
+            cell.x + random.range(-jitter, jitter),
+            SMALL_RADIUS,
+            cell.y + random.range(-jitter, jitter),
+        );
+        let material = if random.next_f32() < EMISSIVE_FRACTION {
+            Material {
+                albedo: Vec3::ZERO,
+                emission_color: random.color().max(Vec3::splat(0.2)),
+                emission_strength: random.range(2.0, 6.0),
+            }
+        } else {
+            // Squared, so the colors lean towards the saturated ones
+            diffuse(random.color() * random.color())
+        };
+        add(
+            &mut config,
+            format!("sphere_{}", i),
+            material,
+            center,
+            SMALL_RADIUS,
+        );
+    }
+
+    // Framed the way the cover is, pulled back for larger grids
+    let target = Vec3::ZERO;
+    let position = Vec3::new(13.0, 2.0, 3.0) * (extent / COVER_EXTENT);
+    config.camera = Camera {
+        position,
+        direction: (target - position).normalize(),
+        fov: 20f32.to_radians(),
+        aperture: 0.1,
+        focus_distance: (LARGE_CENTERS[1] - position).length(),
+        ..Camera::default()
+    };
+    config.lights = vec![Light::Directional {
+        direction: Vec3::new(-1.0, -2.0, -1.0).normalize(),
+        color: Vec3::ONE,
+        intensity: 2.0,
+    }];
+    config
+}
+
+/// Writes the generated scene, the format follows the file extension
+pub fn write_scene(spheres: usize, seed: u64, path: &Path) -> anyhow::Result<()> {
+    let scene = generate_scene(spheres, seed);
+    scene.validate()?;
+    info!(
+        "Generated scene of {} objects with seed {}",
+        scene.objects.len(),
+        seed
+    );
+
+    TracerConfig(Rc::new(RefCell::new(scene))).dump(path)
+}
@@ -0,0 +1,196 @@
+use crate::assets::AssetManager;
+use crate::config::TracerConfig;
+use crate::front::headless::{headless_tracer, TracerHeadlessOutput};
+use anyhow::Context;
+use build_info::BuildInfo;
+use glam::UVec2;
+use image::{ImageBuffer, Rgb, RgbImage};
+use log::{error, info, warn};
+use std::path::{Path, PathBuf};
+
+pub const GOLDEN_DIR: &str = "tests/golden";
+pub const GOLDEN_OUTPUT_DIR: &str = "target/golden";
+// Root mean square error of the normalized channels
+pub const DEFAULT_THRESHOLD: f32 = 0.02;
+
+// Reference scenes are tiny and accumulate a fixed number of frames,
+// so the noise is the same on every run
+const GOLDEN_SIZE: UVec2 = UVec2::new(64, 64);
+const GOLDEN_FRAMES: usize = 64;
+// Differences are hard to see otherwise
+const DIFF_GAIN: f32 = 4.0;
+
+#[derive(Debug, Default)]
+pub struct GoldenSummary {
+    pub passed: Vec<String>,
+    pub failed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+/// Renders every scene config in `dir` and compares it against the golden
+/// image next to it (`<scene>.png`). The rendered images and the amplified
+/// differences of the failed scenes are written to `output`.
+/// With `update` the golden images are overwritten instead.
+pub unsafe fn run_golden_tests(
+    asset_manager: AssetManager,
+    bi: BuildInfo,
+    dir: &Path,
+    output: &Path,
+    threshold: f32,
+    update: bool,
+) -> anyhow::Result<GoldenSummary> {
+    let mut scenes = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read golden scenes from {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("json" | "toml" | "yaml" | "yml")
+            )
+        })
+        .collect::<Vec<PathBuf>>();
+    scenes.sort();
+    std::fs::create_dir_all(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+
+    let mut summary = GoldenSummary::default();
+    for scene in scenes {
+        let name = scene
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let actual = render_scene(asset_manager.clone(), bi.clone(), &scene)
+            .with_context(|| format!("Failed to render golden scene {}", name))?;
+        let golden_path = dir.join(format!("{}.png", name));
+
+        if update {
+            actual.save(&golden_path)?;
+            info!("Updated golden image {}", golden_path.display());
+            summary.updated.push(name);
+            continue;
+        }
+
+        actual.save(output.join(format!("{}.png", name)))?;
+        if !golden_path.exists() {
+            error!(
+                "{}: no golden image at {}, run with --update to create it",
+                name,
+                golden_path.display()
+            );
+            summary.failed.push(name);
+            continue;
+        }
+
+        let golden = image::open(&golden_path)
+            .with_context(|| format!("Failed to load golden image {}", golden_path.display()))?
+            .to_rgb8();
+        if golden.dimensions() != actual.dimensions() {
+            error!(
+                "{}: golden image is {:?}, rendered {:?}",
+                name,
+                golden.dimensions(),
+                actual.dimensions()
+            );
+            summary.failed.push(name);
+            continue;
+        }
+
+        let rmse = rmse(&golden, &actual);
+        if rmse <= threshold {
+            info!("{}: passed, RMSE {:.4}", name, rmse);
+            summary.passed.push(name);
+        } else {
+            let diff_path = output.join(format!("{}.diff.png", name));
+            diff(&golden, &actual).save(&diff_path)?;
+            error!(
+                "{}: RMSE {:.4} exceeds {:.4}, see {}",
+                name,
+                rmse,
+                threshold,
+                diff_path.display()
+            );
+            summary.failed.push(name);
+        }
+    }
+
+    if summary.passed.is_empty() && summary.failed.is_empty() && summary.updated.is_empty() {
+        warn!("No golden scenes found in {}", dir.display());
+    }
+
+    Ok(summary)
+}
+
+unsafe fn render_scene(
+    asset_manager: AssetManager,
+    bi: BuildInfo,
+    scene: &Path,
+) -> anyhow::Result<RgbImage> {
+    let config = TracerConfig::load(scene)?;
+    let mut tracer = headless_tracer(config, asset_manager, GOLDEN_SIZE, bi, |_| {})?;
+    for _ in 0..GOLDEN_FRAMES {
+        tracer.trace(None)?;
+        // Waiting for the frame makes every trace call dispatch exactly
+        // once, so the frame index sequence and the noise are deterministic
+        tracer.flush()?;
+    }
+
+    let TracerHeadlessOutput {
+        width,
+        height,
+        rgb888,
+    } = tracer
+        .snapshot()?
+        .ok_or_else(|| anyhow::anyhow!("No frame has been traced"))?;
+    ImageBuffer::from_raw(width, height, rgb888)
+        .ok_or_else(|| anyhow::anyhow!("Frame data does not match its dimensions"))
+}
+
+fn rmse(a: &RgbImage, b: &RgbImage) -> f32 {
+    let sum: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| {
+            let d = (*a as f64 - *b as f64) / 255.0;
+            d * d
+        })
+        .sum();
+    (sum / a.as_raw().len().max(1) as f64).sqrt() as f32
+}
+
+fn diff(a: &RgbImage, b: &RgbImage) -> RgbImage {
+    ImageBuffer::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        Rgb(std::array::from_fn(|i| {
+            ((pa[i] as f32 - pb[i] as f32).abs() * DIFF_GAIN).min(255.0) as u8
+        }))
+    })
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_images() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let asset_manager = AssetManager::new_from_pwd(root).unwrap();
+        let summary = unsafe {
+            run_golden_tests(
+                asset_manager,
+                crate::get_build_info().clone(),
+                &root.join(GOLDEN_DIR),
+                &root.join(GOLDEN_OUTPUT_DIR),
+                DEFAULT_THRESHOLD,
+                false,
+            )
+        }
+        .unwrap();
+        assert!(
+            summary.failed.is_empty(),
+            "Golden scenes failed: {:?}",
+            summary.failed
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use crate::back::{Back, TracerSlot};
+use crate::common::frame_graph::SyncPoint;
+use crate::error::{Context, TracerResult};
+use crate::front::{Front, QueueFamilyIndices};
+use crate::tracer::Bundle;
+use glam::UVec2;
+use log::{debug, info};
+use std::time::{Duration, Instant};
+
+// Time without resize events after which a deferred resize rebuilds the
+// back-end, so dragging the window edge does not rebuild it every event
+const RESIZE_SETTLE: Duration = Duration::from_millis(200);
+
+/// What the lifecycle needs of the back-end. `C` is whatever the calls
+/// need to reach the device, the `Bundle` outside of tests.
+pub trait BackLifecycle<C> {
+    type Slot;
+
+    fn slot_index(slot: &Self::Slot) -> usize;
+    unsafe fn resize(&mut self, ctx: C, size: UVec2) -> TracerResult<()>;
+    unsafe fn present(&mut self, ctx: C) -> TracerResult<Self::Slot>;
+    fn release(&mut self, index: usize, point: SyncPoint);
+    unsafe fn destroy(&mut self, ctx: C);
+}
+
+/// What the lifecycle needs of the front-end, presenting the slots `S`
+/// of the back-end
+pub trait FrontLifecycle<C, S> {
+    type Queues;
+
+    unsafe fn init(&mut self, ctx: C, queues: Self::Queues) -> TracerResult<()>;
+    unsafe fn resize(&mut self, ctx: C, size: UVec2) -> TracerResult<()>;
+    unsafe fn present(
+        &mut self,
+        ctx: C,
+        w: Option<&winit::window::Window>,
+        slot: S,
+    ) -> TracerResult<Option<SyncPoint>>;
+    unsafe fn destroy(&mut self, ctx: C);
+}
+
+impl<'a> BackLifecycle<Bundle<'a>> for Back {
+    type Slot = TracerSlot;
+
+    fn slot_index(slot: &TracerSlot) -> usize {
+        slot.index
+    }
+
+    unsafe fn resize(&mut self, bundle: Bundle<'a>, size: UVec2) -> TracerResult<()> {
+        Back::resize(self, bundle, size)
+    }
+
+    unsafe fn present(&mut self, bundle: Bundle<'a>) -> TracerResult<TracerSlot> {
+        Back::present(self, bundle)
+    }
+
+    fn release(&mut self, index: usize, point: SyncPoint) {
+        Back::release(self, index, point)
+    }
+
+    unsafe fn destroy(&mut self, bundle: Bundle<'a>) {
+        Back::destroy(self, bundle)
+    }
+}
+
+impl<'a, F: Front> FrontLifecycle<Bundle<'a>, TracerSlot> for F {
+    type Queues = <F::FrontQueueFamilyIndices as QueueFamilyIndices>::Queues;
+
+    unsafe fn init(&mut self, bundle: Bundle<'a>, queues: Self::Queues) -> TracerResult<()> {
+        Front::init(self, bundle, queues)
+    }
+
+    unsafe fn resize(&mut self, bundle: Bundle<'a>, size: UVec2) -> TracerResult<()> {
+        Front::resize(self, bundle, size)
+    }
+
+    unsafe fn present(
+        &mut self,
+        bundle: Bundle<'a>,
+        w: Option<&winit::window::Window>,
+        slot: TracerSlot,
+    ) -> TracerResult<Option<SyncPoint>> {
+        Front::present(self, bundle, w, slot)
+    }
+
+    unsafe fn destroy(&mut self, bundle: Bundle<'a>) {
+        Front::destroy(self, bundle)
+    }
+}
+
+/// Order in which the front-end and the back-end are created, resized,
+/// presented and destroyed, kept apart from the Vulkan setup of the
+/// `Tracer` so it can be checked without a device
+pub struct Lifecycle<F, B> {
+    // Size the back-end is resized to once no resize came for a while
+    pending_resize: Option<(UVec2, Instant)>,
+    resize_settle: Duration,
+
+    front: Option<F>,
+    back: Option<B>,
+}
+
+impl<F, B> Lifecycle<F, B> {
+    /// Creates the back-end, then initializes the front-end. The back-end
+    /// is destroyed again if the front-end fails to initialize.
+    pub unsafe fn new<C: Copy>(
+        ctx: C,
+        mut front: F,
+        front_queues: F::Queues,
+        new_back: impl FnOnce(C) -> TracerResult<B>,
+    ) -> TracerResult<Self>
+    where
+        B: BackLifecycle<C>,
+        F: FrontLifecycle<C, B::Slot>,
+    {
+        let mut back = new_back(ctx)?;
+
+        info!("Initializing front-end");
+        if let Err(e) = front.init(ctx, front_queues) {
+            back.destroy(ctx);
+            return Err(e);
+        }
+
+        Ok(Self {
+            pending_resize: None,
+            resize_settle: RESIZE_SETTLE,
+            front: Some(front),
+            back: Some(back),
+        })
+    }
+
+    pub fn front(&self) -> &F {
+        self.front.as_ref().unwrap()
+    }
+
+    pub fn front_mut(&mut self) -> &mut F {
+        self.front.as_mut().unwrap()
+    }
+
+    pub fn back(&self) -> &B {
+        self.back.as_ref().unwrap()
+    }
+
+    pub fn back_mut(&mut self) -> &mut B {
+        self.back.as_mut().unwrap()
+    }
+
+    /// Resizes the back-end once a deferred resize settled, then hands
+    /// the traced slot to the front-end and the slot back once the
+    /// front-end is done with it
+    pub unsafe fn trace<C: Copy>(
+        &mut self,
+        ctx: C,
+        w: Option<&winit::window::Window>,
+    ) -> TracerResult<()>
+    where
+        B: BackLifecycle<C>,
+        F: FrontLifecycle<C, B::Slot>,
+    {
+        if let Some((size, since)) = self.pending_resize {
+            if since.elapsed() >= self.resize_settle {
+                self.pending_resize = None;
+                self.back_mut()
+                    .resize(ctx, size)
+                    .with_context(|| format!("Failed to resize tracer back-end to {:?}", size))?;
+            }
+        }
+
+        let slot = self
+            .back_mut()
+            .present(ctx)
+            .context("Failed to present tracer back-end")?;
+
+        let index = B::slot_index(&slot);
+        let released = self
+            .front_mut()
+            .present(ctx, w, slot)
+            .context("Failed to present tracer front")?;
+        if let Some(point) = released {
+            self.back_mut().release(index, point);
+        }
+
+        Ok(())
+    }
+
+    pub unsafe fn resize<C: Copy>(&mut self, ctx: C, size: UVec2) -> TracerResult<()>
+    where
+        B: BackLifecycle<C>,
+        F: FrontLifecycle<C, B::Slot>,
+    {
+        self.pending_resize = None;
+
+        self.back_mut()
+            .resize(ctx, size)
+            .with_context(|| format!("Failed to resize tracer back-end to {:?}", size))?;
+
+        self.front_mut()
+            .resize(ctx, size)
+            .with_context(|| format!("Failed to resize tracer front to {:?}", size))?;
+
+        Ok(())
+    }
+
+    /// Resizes the front-end alone, the back-end keeps its size
+    pub unsafe fn resize_front<C: Copy>(&mut self, ctx: C, size: UVec2) -> TracerResult<()>
+    where
+        B: BackLifecycle<C>,
+        F: FrontLifecycle<C, B::Slot>,
+    {
+        self.front_mut()
+            .resize(ctx, size)
+            .with_context(|| format!("Failed to resize tracer front to {:?}", size))
+    }
+
+    /// The back-end is rebuilt by `trace` once the size settles, every
+    /// call restarts the wait
+    pub fn resize_back_deferred(&mut self, size: UVec2) {
+        self.pending_resize = Some((size, Instant::now()));
+    }
+
+    /// Destroys the back-end before the front-end, the front-end may still
+    /// hold on to resources the last presented slot was read into
+    pub unsafe fn destroy<C: Copy>(&mut self, ctx: C)
+    where
+        B: BackLifecycle<C>,
+        F: FrontLifecycle<C, B::Slot>,
+    {
+        if let Some(mut back) = self.back.take() {
+            debug!("Destroying back-end");
+            back.destroy(ctx);
+        }
+
+        if let Some(mut front) = self.front.take() {
+            debug!("Destroying front-end");
+            front.destroy(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::frame_graph::Pass;
+    use crate::error::TracerError;
+    use ash::vk;
+    use std::cell::RefCell;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Event {
+        BackNew,
+        BackResize(UVec2),
+        BackPresent(usize),
+        BackDestroy,
+        FrontInit,
+        FrontResize(UVec2),
+        FrontPresent(usize),
+        FrontDestroy,
+    }
+
+    // Calls made into the mocks, in order
+    type Log = RefCell<Vec<Event>>;
+
+    #[derive(Default)]
+    struct MockBack {
+        presented: usize,
+        released: Vec<usize>,
+    }
+
+    impl<'a> BackLifecycle<&'a Log> for MockBack {
+        type Slot = usize;
+
+        fn slot_index(slot: &usize) -> usize {
+            *slot
+        }
+
+        unsafe fn resize(&mut self, log: &'a Log, size: UVec2) -> TracerResult<()> {
+            log.borrow_mut().push(Event::BackResize(size));
+            Ok(())
+        }
+
+        unsafe fn present(&mut self, log: &'a Log) -> TracerResult<usize> {
+            let slot = self.presented % 2;
+            self.presented += 1;
+            log.borrow_mut().push(Event::BackPresent(slot));
+            Ok(slot)
+        }
+
+        fn release(&mut self, index: usize, _point: SyncPoint) {
+            self.released.push(index);
+        }
+
+        unsafe fn destroy(&mut self, log: &'a Log) {
+            log.borrow_mut().push(Event::BackDestroy);
+        }
+    }
+
+    #[derive(Default)]
+    struct MockFront {
+        fail_init: bool,
+        // Whether the slots are still read after present returns
+        holds_slots: bool,
+    }
+
+    impl<'a> FrontLifecycle<&'a Log, usize> for MockFront {
+        type Queues = ();
+
+        unsafe fn init(&mut self, log: &'a Log, _queues: ()) -> TracerResult<()> {
+            log.borrow_mut().push(Event::FrontInit);
+            if self.fail_init {
+                Err(TracerError::Unsupported("mock".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+
+        unsafe fn resize(&mut self, log: &'a Log, size: UVec2) -> TracerResult<()> {
+            log.borrow_mut().push(Event::FrontResize(size));
+            Ok(())
+        }
+
+        unsafe fn present(
+            &mut self,
+            log: &'a Log,
+            _w: Option<&winit::window::Window>,
+            slot: usize,
+        ) -> TracerResult<Option<SyncPoint>> {
+            log.borrow_mut().push(Event::FrontPresent(slot));
+            if self.holds_slots {
+                Ok(Some(SyncPoint {
+                    pass: Pass::Present,
+                    semaphore: vk::Semaphore::null(),
+                    value: 1,
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+
+        unsafe fn destroy(&mut self, log: &'a Log) {
+            log.borrow_mut().push(Event::FrontDestroy);
+        }
+    }
+
+    fn new(log: &Log, front: MockFront) -> TracerResult<Lifecycle<MockFront, MockBack>> {
+        unsafe {
+            Lifecycle::new(log, front, (), |log: &Log| {
+                log.borrow_mut().push(Event::BackNew);
+                Ok(MockBack::default())
+            })
+        }
+    }
+
+    #[test]
+    fn creates_back_before_front() {
+        let log = Log::default();
+        new(&log, MockFront::default()).unwrap();
+        assert_eq!(*log.borrow(), vec![Event::BackNew, Event::FrontInit]);
+    }
+
+    #[test]
+    fn destroys_back_when_front_fails_to_init() {
+        let log = Log::default();
+        let front = MockFront {
+            fail_init: true,
+            ..Default::default()
+        };
+        assert!(new(&log, front).is_err());
+        assert_eq!(
+            *log.borrow(),
+            vec![Event::BackNew, Event::FrontInit, Event::BackDestroy]
+        );
+    }
+
+    #[test]
+    fn skips_front_when_back_fails() {
+        let log = Log::default();
+        let result = unsafe {
+            Lifecycle::<MockFront, MockBack>::new(&log, MockFront::default(), (), |_: &Log| {
+                Err(TracerError::NoSuitableDevice)
+            })
+        };
+        assert!(result.is_err());
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn resize_reaches_back_then_front() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        log.borrow_mut().clear();
+
+        let size = UVec2::new(800, 600);
+        unsafe { lifecycle.resize(&log, size).unwrap() };
+        assert_eq!(
+            *log.borrow(),
+            vec![Event::BackResize(size), Event::FrontResize(size)]
+        );
+    }
+
+    #[test]
+    fn deferred_resize_waits_for_settle() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        lifecycle.resize_settle = Duration::from_secs(3600);
+        log.borrow_mut().clear();
+
+        let size = UVec2::new(800, 600);
+        unsafe {
+            lifecycle.resize_front(&log, size).unwrap();
+            lifecycle.resize_back_deferred(size);
+            lifecycle.trace(&log, None).unwrap();
+        }
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::FrontResize(size),
+                Event::BackPresent(0),
+                Event::FrontPresent(0)
+            ]
+        );
+
+        lifecycle.resize_settle = Duration::ZERO;
+        log.borrow_mut().clear();
+        unsafe {
+            lifecycle.trace(&log, None).unwrap();
+            lifecycle.trace(&log, None).unwrap();
+        }
+        // Resized once, before the first present after settling
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::BackResize(size),
+                Event::BackPresent(1),
+                Event::FrontPresent(1),
+                Event::BackPresent(0),
+                Event::FrontPresent(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn resize_cancels_deferred_resize() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        lifecycle.resize_settle = Duration::ZERO;
+
+        let size = UVec2::new(320, 240);
+        unsafe {
+            lifecycle.resize_back_deferred(UVec2::new(800, 600));
+            lifecycle.resize(&log, size).unwrap();
+            log.borrow_mut().clear();
+            lifecycle.trace(&log, None).unwrap();
+        }
+        assert_eq!(
+            *log.borrow(),
+            vec![Event::BackPresent(0), Event::FrontPresent(0)]
+        );
+    }
+
+    #[test]
+    fn front_resize_keeps_back() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        lifecycle.resize_settle = Duration::ZERO;
+        log.borrow_mut().clear();
+
+        let size = UVec2::new(800, 600);
+        unsafe {
+            lifecycle.resize_front(&log, size).unwrap();
+            lifecycle.trace(&log, None).unwrap();
+        }
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::FrontResize(size),
+                Event::BackPresent(0),
+                Event::FrontPresent(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_releases_slot_held_by_front() {
+        let log = Log::default();
+        let front = MockFront {
+            holds_slots: true,
+            ..Default::default()
+        };
+        let mut lifecycle = new(&log, front).unwrap();
+        log.borrow_mut().clear();
+
+        unsafe {
+            lifecycle.trace(&log, None).unwrap();
+            lifecycle.trace(&log, None).unwrap();
+        }
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::BackPresent(0),
+                Event::FrontPresent(0),
+                Event::BackPresent(1),
+                Event::FrontPresent(1)
+            ]
+        );
+        assert_eq!(lifecycle.back().released, vec![0, 1]);
+    }
+
+    #[test]
+    fn trace_keeps_slot_done_on_present() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        unsafe { lifecycle.trace(&log, None).unwrap() };
+        assert!(lifecycle.back().released.is_empty());
+    }
+
+    #[test]
+    fn destroys_back_before_front_once() {
+        let log = Log::default();
+        let mut lifecycle = new(&log, MockFront::default()).unwrap();
+        log.borrow_mut().clear();
+
+        unsafe {
+            lifecycle.destroy(&log);
+            lifecycle.destroy(&log);
+        }
+        assert_eq!(*log.borrow(), vec![Event::BackDestroy, Event::FrontDestroy]);
+    }
+}
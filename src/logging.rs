@@ -1,11 +1,18 @@
+use crate::crash;
 use crate::get_build_info;
+use anyhow::Context;
 use build_info::VersionControl;
 use log::{info, Level, LevelFilter};
 use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::OnceLock;
 use std::time::{Instant, SystemTime};
+#[cfg(not(feature = "tracy"))]
 use tracing::span::{Attributes, Record};
+#[cfg(not(feature = "tracy"))]
 use tracing::{Event, Id, Metadata, Subscriber};
 
 fn format_system_time(system_time: SystemTime) -> Option<String> {
@@ -107,8 +114,170 @@ where
     format_inner::<F, true>(message, record, callback);
 }
 
+fn format_json<'a, F>(message: &'a fmt::Arguments<'a>, record: &'a log::Record<'a>, callback: F)
+where
+    F: FnOnce(fmt::Arguments),
+{
+    let elapsed = START_TIME
+        .get()
+        .map(|start| start.elapsed())
+        .unwrap_or_default();
+
+    let line = serde_json::json!({
+        "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "elapsed": elapsed.as_secs_f64(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "file": record.file(),
+        "line": record.line(),
+        "thread": std::thread::current().name().unwrap_or("main"),
+        "message": message.to_string(),
+    });
+    callback(format_args!("{}", line))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line, for log collectors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => anyhow::bail!("Unknown log format: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub path: PathBuf,
+    // Size in bytes after which the file is rotated
+    pub max_size: u64,
+    // Number of rotated files kept next to the current one
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub level: LevelFilter,
+    // Per-module overrides of `level`, matched against the log target prefix
+    pub filters: Vec<(String, LevelFilter)>,
+    pub format: LogFormat,
+    pub colored: bool,
+    pub file: Option<LogFile>,
+}
+
+/// Parses a `MODULE=LEVEL` filter, e.g. `vulkan=warn` or `pathrs::back=debug`
+pub fn parse_filter(filter: &str) -> anyhow::Result<(String, LevelFilter)> {
+    let (module, level) = filter
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected MODULE=LEVEL, got {}", filter))?;
+    let level = LevelFilter::from_str(level.trim())
+        .with_context(|| format!("Invalid log level in filter {}", filter))?;
+    Ok((module.trim().to_string(), level))
+}
+
+/// Log file that is renamed to `<path>.1` once it grows past `max_size`,
+/// shifting the older ones up to `<path>.<keep>`
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+    // Rotating in the middle of a record would split it between two files
+    line_start: bool,
+}
+
+impl RotatingFile {
+    fn open(options: &LogFile) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&options.path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: options.path.clone(),
+            max_size: options.max_size,
+            keep: options.keep,
+            file,
+            size,
+            line_start: true,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.line_start && self.size >= self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        self.line_start = buf[..written].ends_with(b"\n");
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn formatted(format: LogFormat, colored: bool) -> fern::Dispatch {
+    let dispatch = fern::Dispatch::new();
+    match (format, colored) {
+        (LogFormat::Json, _) => {
+            dispatch.format(|cb, args, r| format_json(args, r, |fmt| cb.finish(fmt)))
+        }
+        (LogFormat::Text, true) => {
+            dispatch.format(|cb, args, r| format_colored(args, r, |fmt| cb.finish(fmt)))
+        }
+        (LogFormat::Text, false) => {
+            dispatch.format(|cb, args, r| format(args, r, |fmt| cb.finish(fmt)))
+        }
+    }
+}
+
+#[cfg(not(feature = "tracy"))]
 struct TracerSubscriber;
 
+#[cfg(not(feature = "tracy"))]
 impl Subscriber for TracerSubscriber {
     fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
         // Disable tracing for now since it's too verbose and not really useful
@@ -140,24 +309,38 @@ impl Subscriber for TracerSubscriber {
     }
 }
 
-pub fn setup_logging(level: LevelFilter, file_logging: Option<PathBuf>, colored: bool) {
+pub fn setup_logging(options: LogOptions) -> anyhow::Result<()> {
     START_TIME.set(Instant::now()).ok();
 
+    #[cfg(feature = "tracy")]
+    {
+        use tracing_subscriber::layer::SubscriberExt;
+        let subscriber = tracing_subscriber::registry().with(tracing_tracy::TracyLayer::default());
+        tracing::subscriber::set_global_default(subscriber).ok();
+    }
+    #[cfg(not(feature = "tracy"))]
     tracing::subscriber::set_global_default(TracerSubscriber).ok();
 
-    let mut dispatch = fern::Dispatch::new().level(level).chain(std::io::stdout());
-
-    if colored {
-        dispatch = dispatch.format(|cb, args, r| format_colored(args, r, |fmt| cb.finish(fmt)));
-    } else {
-        dispatch = dispatch.format(|cb, args, r| format(args, r, |fmt| cb.finish(fmt)));
+    let mut dispatch = fern::Dispatch::new().level(options.level);
+    for (module, level) in options.filters {
+        dispatch = dispatch.level_for(module, level);
     }
+    dispatch = dispatch.chain(formatted(options.format, options.colored).chain(std::io::stdout()));
+    // Tail kept in memory for the diagnostics report
+    dispatch = dispatch
+        .chain(formatted(LogFormat::Text, false).chain(fern::Output::call(crash::push_log_line)));
 
-    if let Some(path) = file_logging {
-        dispatch = dispatch.chain(fern::log_file(path).unwrap());
+    if let Some(file) = &options.file {
+        let writer = RotatingFile::open(file)
+            .with_context(|| format!("Failed to open log file {}", file.path.display()))?;
+        // Escape sequences are only useful on a terminal
+        dispatch = dispatch.chain(
+            formatted(options.format, false).chain(fern::Output::writer(Box::new(writer), "\n")),
+        );
     }
 
-    dispatch.apply().unwrap();
+    dispatch.apply()?;
 
     print_build_info();
+    Ok(())
 }
@@ -4,24 +4,55 @@
 #![allow(clippy::type_complexity)]
 
 use crate::assets::AssetManager;
-use crate::config::TracerConfig;
-use crate::front::headless::headless_tracer;
+use crate::batch::run_batch;
+use crate::benchmark::run_benchmark;
+use crate::common::interrupt::{install_interrupt_handler, interrupted};
+use crate::common::panic::{catch_panic, install_panic_hook};
+use crate::config::{ImportedTexture, QualityPreset, TracerConfig};
+use crate::demo::{demo_scene, DEMO_SEED, DEMO_SPHERES};
+use crate::device_info::print_device_info;
+use crate::front::headless::{
+    headless_tracer, sequence_frame_path, Checkpoint, FloatDump, ImageWriter, SplitFrameTracer,
+    TerminalProgress, Turntable,
+};
+#[cfg(unix)]
+use crate::front::share::share_tracer;
+use crate::front::stream::stream_tracer;
 use crate::front::windowed::TracerApp;
-use crate::logging::setup_logging;
+use crate::generate::write_scene;
+use crate::golden::{run_golden_tests, DEFAULT_THRESHOLD, GOLDEN_DIR, GOLDEN_OUTPUT_DIR};
+use crate::logging::{parse_filter, setup_logging, LogFile, LogFormat, LogOptions};
+use crate::remote::RemoteServer;
+use crate::settings::Settings;
 use clap::builder::PossibleValuesParser;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use glam::UVec2;
 use image::{ImageBuffer, Rgb};
 use log::{info, warn, LevelFilter};
+use std::cell::RefCell;
+use std::rc::Rc;
 use winit::event_loop::{ControlFlow, EventLoop};
 
 mod assets;
 mod back;
+mod batch;
+mod benchmark;
+mod camera;
 mod common;
 mod config;
+mod crash;
+mod demo;
+mod device_info;
+mod error;
 mod fps;
 mod front;
+mod generate;
+mod golden;
+mod lifecycle;
 mod logging;
+mod remote;
+mod script;
+mod settings;
 mod tracer;
 
 build_info::build_info!(pub fn get_build_info);
@@ -29,6 +60,9 @@ build_info::build_info!(pub fn get_build_info);
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[clap(
         short = 'l',
         long,
@@ -37,27 +71,75 @@ struct Arguments {
     )]
     log_level: Option<String>,
 
+    #[clap(
+        long = "log-filter",
+        value_name = "MODULE=LEVEL",
+        help = "Override the log level of a module, e.g. --log-filter vulkan=warn --log-filter pathrs::back=debug. Can be repeated"
+    )]
+    log_filters: Vec<String>,
+
+    #[clap(
+        long,
+        default_value = "text",
+        value_parser = PossibleValuesParser::new(["text", "json"]),
+        help = "Log format, json writes one object per line"
+    )]
+    log_format: String,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Also write the log to this file, rotating it once it grows past --log-max-size"
+    )]
+    log_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "MB",
+        default_value_t = 16,
+        help = "Size in megabytes at which the log file is rotated"
+    )]
+    log_max_size: u64,
+
+    #[clap(
+        long,
+        default_value_t = 4,
+        help = "Number of rotated log files to keep"
+    )]
+    log_keep: usize,
+
     #[clap(
         long,
         help = "Disable color output"
     )]
     no_color: bool,
 
+    #[clap(
+        long,
+        help = "Do not write a diagnostics report (log tail, config, devices and profile) into the crash-reports directory of the config directory on a fatal error"
+    )]
+    no_crash_report: bool,
+
     #[clap(
         short = 'x',
         long,
-        default_value_t = 1280,
-        help = "Width of the default viewport in pixels"
+        help = "Width of the default viewport in pixels. Defaults to the last window width or 1280"
     )]
-    width: u32,
+    width: Option<u32>,
 
     #[clap(
         short = 'y',
         long,
-        default_value_t = 720,
-        help = "Height of the default viewport in pixels"
+        help = "Height of the default viewport in pixels. Defaults to the last window height or 720"
+    )]
+    height: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "BOOL",
+        help = "Wait for the vertical blank when presenting. Remembered for the next runs, on by default"
     )]
-    height: u32,
+    vsync: Option<bool>,
 
     #[clap(
         short = 'd',
@@ -66,18 +148,259 @@ struct Arguments {
     )]
     headless: Option<String>,
 
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Number of frames accumulated in the headless mode. Ctrl-C stops early and saves the partial image"
+    )]
+    frames: usize,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Also save the partial image every N accumulated frames of a single headless image, numbered by the frame count, e.g. out_0016.png"
+    )]
+    save_every: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "FPS",
+        help = "Render the animation of the config in the headless mode instead of a single image, at the given images per second. The images are numbered after the output path, e.g. out_0000.png"
+    )]
+    sequence_fps: Option<f32>,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_seconds,
+        help = "Orbit the camera once around the point in focus in the headless mode, taking the given time, e.g. 8s. The images are numbered like the animation ones, at --sequence-fps or 30 images per second"
+    )]
+    turntable: Option<f32>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Also write the headless image as raw RGBA float32 data to the specified path, with a JSON header of its dimensions, frame index and config hash next to it, e.g. out.bin and out.json"
+    )]
+    dump_float: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Resume the accumulation of a single headless image from the checkpoint at PATH if it exists, and write the checkpoint there once the render stops, finished or interrupted. Only resumes with the same config"
+    )]
+    checkpoint: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "GPUS",
+        help = "Experimental, headless only: split the headless image into horizontal bands traced on this many GPUs in parallel and stitched together on the CPU. The windowed modes ignore it"
+    )]
+    split_gpus: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "If set, run the tracer without a window, streaming the output over HTTP on the specified address, e.g. 0.0.0.0:8080"
+    )]
+    stream: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 10.0,
+        help = "Maximum number of frames per second sent to the stream clients"
+    )]
+    stream_fps: f32,
+
+    #[clap(
+        long,
+        value_name = "NAME",
+        help = "If set, run the tracer without a window, copying the frames read back from the GPU into the POSIX shared memory object NAME for other processes, e.g. pathrs. The frames are copied, not exported as GPU memory"
+    )]
+    share: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 0.0,
+        help = "Maximum number of frames per second in the windowed mode, frames are paced evenly below it. 0 for unlimited"
+    )]
+    max_fps: f32,
+
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "If set, serve the remote control API over HTTP on the specified address, e.g. 127.0.0.1:8081. Not available in the headless mode"
+    )]
+    remote: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Number of recent frames kept in the windowed mode, written as PNGs along with the config on pressing F9. 0 disables it"
+    )]
+    frame_ring: usize,
+
+    #[clap(
+        long,
+        value_name = "REPORT",
+        help = "If set, benchmark the built-in scene headlessly and write the JSON report to the specified path. The config options are ignored"
+    )]
+    benchmark: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 256,
+        help = "Number of measured frames in the benchmark mode"
+    )]
+    benchmark_frames: usize,
+
+    #[clap(
+        long,
+        value_name = "JOBS",
+        help = "If set, render every job of the specified JSON manifest headlessly, one after another. The config options are ignored"
+    )]
+    batch: Option<String>,
+
     #[clap(
         short = 'c',
         long,
-        help = "Path to the config file in JSON format"
+        help = "Path to the config file in JSON, TOML or YAML format (chosen by extension)"
     )]
     config: Option<String>,
+
+    #[clap(
+        long,
+        help = "Write the effective config to the specified path and exit. Format is chosen by extension"
+    )]
+    dump_config: Option<String>,
+
+    #[clap(
+        long,
+        help = "Drop the spheres of the scene onto the ground and let them bounce off each other. Without --config a demo scene is used. Windowed mode only"
+    )]
+    demo: bool,
+
+    #[clap(
+        long,
+        help = "Make GPU profiler captures reproducible: hide the UI overlay, trace the same dispatches every frame and label the passes and frames"
+    )]
+    profiler_friendly: bool,
+
+    #[clap(
+        long,
+        help = "Print the extensions, queue families and limits of every Vulkan device and exit"
+    )]
+    print_device_info: bool,
+
+    #[clap(
+        long,
+        value_parser = PossibleValuesParser::new(["draft", "medium", "final"]),
+        help = "Overlay a quality preset on the config, setting the samples, bounces, resolution scale and noise handling. Applied before the --set overrides"
+    )]
+    quality: Option<String>,
+
+    #[clap(
+        long = "set",
+        value_name = "KEY=VALUE",
+        help = "Override a config value, e.g. --set camera.fov=1.2 --set samples_count=8. Can be repeated"
+    )]
+    overrides: Vec<String>,
+
+    #[clap(
+        long = "import-texture",
+        value_name = "NAME=FD:WIDTHxHEIGHT",
+        help = "Import an RGBA8 image another process exported as an opaque file descriptor inherited by the tracer, used by the objects as the external:NAME texture. The image is taken over once, the producer may not write into it afterwards. Can be repeated"
+    )]
+    import_textures: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(
+        about = "Render the golden scenes headlessly and compare them against the reference images"
+    )]
+    Test {
+        #[clap(
+            long,
+            default_value = GOLDEN_DIR,
+            help = "Directory with the scene configs and their golden images"
+        )]
+        dir: String,
+
+        #[clap(
+            long,
+            default_value = GOLDEN_OUTPUT_DIR,
+            help = "Directory to write the rendered images and the differences to"
+        )]
+        output: String,
+
+        #[clap(
+            long,
+            default_value_t = DEFAULT_THRESHOLD,
+            help = "Maximum root mean square error of a scene to pass"
+        )]
+        threshold: f32,
+
+        #[clap(long, help = "Overwrite the golden images with the rendered ones")]
+        update: bool,
+    },
+
+    #[command(
+        about = "Generate a random scene of spheres, arranged like the Ray Tracing in One Weekend cover"
+    )]
+    Generate {
+        #[clap(long, default_value_t = 500, help = "Number of small spheres")]
+        spheres: usize,
+
+        #[clap(long, default_value_t = 42, help = "Seed of the random arrangement")]
+        seed: u64,
+
+        #[clap(
+            long,
+            help = "Scene file to write, the format follows the extension (json, toml, yaml)"
+        )]
+        out: String,
+    },
+}
+
+const DEFAULT_VIEWPORT: UVec2 = UVec2::new(1280, 720);
+// Images per second of the turntable without --sequence-fps
+const DEFAULT_TURNTABLE_FPS: f32 = 30.0;
+
+/// Seconds with an optional unit, `8s` or `8`
+fn parse_seconds(value: &str) -> Result<f32, String> {
+    let seconds: f32 = value
+        .strip_suffix('s')
+        .unwrap_or(value)
+        .parse()
+        .map_err(|e| format!("{}", e))?;
+    if seconds > 0.0 {
+        Ok(seconds)
+    } else {
+        Err("Duration must be positive".to_string())
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Arguments::parse();
+    crash::set_enabled(!args.no_crash_report);
+    let result = run(args);
+    if let Err(e) = &result {
+        crash::report(e);
+    }
+    result
+}
 
-    let log_level = match args.log_level.as_deref() {
+fn run(args: Arguments) -> anyhow::Result<()> {
+    let mut settings = Settings::load();
+    if args.log_level.is_some() {
+        settings.log_level = args.log_level.clone();
+    }
+    if args.vsync.is_some() {
+        settings.vsync = args.vsync;
+    }
+
+    let log_level = match settings.log_level.as_deref() {
         Some("error") => LevelFilter::Error,
         Some("warn") => LevelFilter::Warn,
         Some("info") => LevelFilter::Info,
@@ -85,54 +408,417 @@ fn main() -> anyhow::Result<()> {
         Some("trace") => LevelFilter::Trace,
         _ => LevelFilter::Debug,
     };
-    setup_logging(log_level, None, !args.no_color);
+    setup_logging(LogOptions {
+        level: log_level,
+        filters: args
+            .log_filters
+            .iter()
+            .map(|filter| parse_filter(filter))
+            .collect::<anyhow::Result<_>>()?,
+        format: args.log_format.parse()?,
+        colored: !args.no_color,
+        file: args.log_file.as_ref().map(|path| LogFile {
+            path: path.into(),
+            max_size: args.log_max_size * 1024 * 1024,
+            keep: args.log_keep,
+        }),
+    })?;
+    install_panic_hook();
 
     info!("Starting application with args: {:?}", args);
+    info!("Using settings: {:?}", settings);
+
+    if let Some(Command::Generate { spheres, seed, out }) = &args.command {
+        write_scene(*spheres, *seed, std::path::Path::new(out))?;
+        info!("Wrote generated scene to {}", out);
+        return Ok(());
+    }
+
+    if let Some(Command::Test {
+        dir,
+        output,
+        threshold,
+        update,
+    }) = args.command
+    {
+        let asset_manager = AssetManager::new_from_pwd(&std::env::current_dir()?)?;
+        let summary = unsafe {
+            run_golden_tests(
+                asset_manager,
+                get_build_info().clone(),
+                std::path::Path::new(&dir),
+                std::path::Path::new(&output),
+                threshold,
+                update,
+            )?
+        };
+        info!(
+            "Golden tests: {} passed, {} failed, {} updated",
+            summary.passed.len(),
+            summary.failed.len(),
+            summary.updated.len()
+        );
+        if !summary.failed.is_empty() {
+            anyhow::bail!("Golden scenes failed: {}", summary.failed.join(", "));
+        }
+        return Ok(());
+    }
+
+    if args.print_device_info {
+        unsafe {
+            print_device_info(get_build_info().clone())?;
+        }
+        return Ok(());
+    }
 
     let config = if args.config.is_some() {
         let config_path = args.config.as_ref().unwrap();
         info!("Loading config from file: {}", config_path);
-        serde_json::from_str(&std::fs::read_to_string(config_path)?)?
+        let config = TracerConfig::load(std::path::Path::new(config_path))?;
+        settings.add_recent_scene(std::path::Path::new(config_path));
+        config
+    } else if args.demo {
+        info!("No config file provided, using the demo scene");
+        TracerConfig(Rc::new(RefCell::new(demo_scene(DEMO_SPHERES, DEMO_SEED))))
     } else {
         info!("No config file provided, using default config");
-        TracerConfig::default()
+        let config = TracerConfig::default();
+        if let Some(scale) = settings.resolution_scale {
+            if let Err(e) = config.set_value("resolution_scale", scale.into()) {
+                warn!("Ignoring resolution scale from the settings: {:#}", e);
+            }
+        }
+        config
     };
 
+    if let Some(quality) = &args.quality {
+        let preset: QualityPreset = quality.parse()?;
+        info!("Applying {} quality preset", preset.name());
+        preset.apply(&mut config.0.borrow_mut());
+    }
+    for assignment in &args.overrides {
+        info!("Applying config override: {}", assignment);
+        config.apply_override(assignment)?;
+    }
+    for import in &args.import_textures {
+        let texture: ImportedTexture = import.parse()?;
+        info!("Importing texture {} from fd {}", texture.name, texture.fd);
+        config.0.borrow_mut().import_requests.push(texture);
+    }
+    if args.profiler_friendly {
+        info!("Pinning the config for GPU profiling");
+        config.0.borrow_mut().pin_for_profiling();
+    }
+    crash::record_config(&config.0.borrow());
+
+    if let Some(path) = args.dump_config {
+        info!("Dumping effective config to: {}", path);
+        config.dump(std::path::Path::new(&path))?;
+        return Ok(());
+    }
+
     let asset_manager = AssetManager::new_from_pwd(&std::env::current_dir()?)?;
 
-    let viewport = UVec2::new(args.width, args.height);
+    let viewport_or = |fallback: UVec2| {
+        UVec2::new(
+            args.width.unwrap_or(fallback.x),
+            args.height.unwrap_or(fallback.y),
+        )
+    };
+    let viewport = viewport_or(DEFAULT_VIEWPORT);
+    if let Some(report) = args.benchmark {
+        unsafe {
+            run_benchmark(
+                asset_manager,
+                viewport,
+                get_build_info().clone(),
+                args.benchmark_frames,
+                std::path::Path::new(&report),
+            )?;
+        }
+        return Ok(());
+    }
+
+    if let Some(manifest) = args.batch {
+        let summary = unsafe {
+            run_batch(
+                asset_manager,
+                viewport,
+                get_build_info().clone(),
+                std::path::Path::new(&manifest),
+            )?
+        };
+        if !summary.failed.is_empty() {
+            anyhow::bail!("{} batch jobs failed", summary.failed.len());
+        }
+        return Ok(());
+    }
+
+    if args.demo && (args.headless.is_some() || args.stream.is_some() || args.share.is_some()) {
+        warn!("The demo is only simulated in the windowed mode, the spheres stay in the air");
+    }
+
+    let remote = match &args.remote {
+        Some(_) if args.headless.is_some() => {
+            warn!("Remote control is not available in the headless mode, ignoring");
+            None
+        }
+        Some(address) => Some(RemoteServer::bind(address)?),
+        None => None,
+    };
+
+    if args.split_gpus.is_some() && args.headless.is_none() {
+        warn!(
+            "Split-frame rendering is only available in the headless mode, ignoring --split-gpus"
+        );
+    }
+
     if let Some(path) = args.headless {
         let path = std::path::PathBuf::from(path);
         if path.extension() != Some(std::ffi::OsStr::new("png")) {
             warn!("Headless output path does not have a .png extension, the output image will still be saved as a PNG file");
         }
 
-        unsafe {
+        install_interrupt_handler();
+        let samples_per_frame = config.0.borrow().samples_count;
+        if args.dump_float.is_some()
+            && (args.split_gpus.is_some()
+                || args.sequence_fps.is_some()
+                || args.turntable.is_some())
+        {
+            warn!("Float dumps are only written for a single image, ignoring --dump-float");
+        }
+        if args.checkpoint.is_some()
+            && (args.split_gpus.is_some()
+                || args.sequence_fps.is_some()
+                || args.turntable.is_some())
+        {
+            warn!("Checkpoints are only kept for a single image, ignoring --checkpoint");
+        }
+        if let Some(devices) = args.split_gpus {
+            return catch_panic(|| unsafe {
+                let mut tracer = SplitFrameTracer::new(
+                    &config,
+                    asset_manager,
+                    viewport,
+                    get_build_info().clone(),
+                    devices,
+                )?;
+                tracer.render(
+                    args.frames,
+                    samples_per_frame,
+                    &mut TerminalProgress::default(),
+                    interrupted,
+                )?;
+                let output = tracer
+                    .snapshot()?
+                    .ok_or_else(|| anyhow::anyhow!("No frame has been traced"))?;
+                info!("Saving split-frame output to {}", path.display());
+                std::fs::write(&path, output.encode_png()?)?;
+                Ok(())
+            });
+        }
+
+        catch_panic(|| unsafe {
             let mut tracer = headless_tracer(
-                config,
+                config.clone(),
                 asset_manager,
                 viewport,
                 get_build_info().clone(),
-                move |output| {
+                |_| {},
+            )?;
+            if let Some(duration) = args.turntable {
+                let fps = args.sequence_fps.unwrap_or(DEFAULT_TURNTABLE_FPS);
+                anyhow::ensure!(fps > 0.0, "Sequence FPS must be positive");
+                let turntable = Turntable::new(config.0.borrow().camera.clone(), duration, fps);
+                let writer = ImageWriter::new()?;
+                let rendered = tracer.render_turntable(
+                    &config,
+                    &turntable,
+                    args.frames,
+                    samples_per_frame,
+                    &mut TerminalProgress::default(),
+                    interrupted,
+                    |index, output| {
+                        let path = sequence_frame_path(&path, index);
+                        info!("Saving turntable image {} to {}", index, path.display());
+                        writer.write(path, output)
+                    },
+                )?;
+                writer.finish()?;
+                info!("Rendered {} turntable images", rendered);
+                return Ok(());
+            }
+            if let Some(fps) = args.sequence_fps {
+                anyhow::ensure!(fps > 0.0, "Sequence FPS must be positive");
+                let writer = ImageWriter::new()?;
+                let rendered = tracer.render_sequence(
+                    &config,
+                    fps,
+                    args.frames,
+                    samples_per_frame,
+                    &mut TerminalProgress::default(),
+                    interrupted,
+                    |index, output| {
+                        let path = sequence_frame_path(&path, index);
+                        info!("Saving sequence image {} to {}", index, path.display());
+                        writer.write(path, output)
+                    },
+                )?;
+                writer.finish()?;
+                info!("Rendered {} sequence images", rendered);
+                return Ok(());
+            }
+
+            let checkpoint = args.checkpoint.as_deref().map(std::path::Path::new);
+            let resumed = match checkpoint.filter(|checkpoint| checkpoint.exists()) {
+                Some(checkpoint) => {
+                    let restored = Checkpoint::read(checkpoint).map_err(|e| {
+                        e.context(format!("Failed to resume from {}", checkpoint.display()))
+                    })?;
+                    if restored.header.frames >= args.frames {
+                        // The run that completed it already saved the image
+                        info!(
+                            "Checkpoint {} already holds {} of {} frames, nothing to trace",
+                            checkpoint.display(),
+                            restored.header.frames,
+                            args.frames
+                        );
+                        return Ok(());
+                    }
+                    let resumed = restored.restore(&mut tracer, &config).map_err(|e| {
+                        e.context(format!("Failed to resume from {}", checkpoint.display()))
+                    })?;
                     info!(
-                        "Received headless output: {}x{}, {} bytes",
-                        output.width,
-                        output.height,
-                        output.rgb888.len()
+                        "Resuming after {} frames from {}",
+                        resumed,
+                        checkpoint.display()
                     );
+                    resumed
+                }
+                None => 0,
+            };
+            let frames = args.frames - resumed;
 
-                    let image: ImageBuffer<Rgb<u8>, _> =
-                        ImageBuffer::from_raw(output.width, output.height, output.rgb888).unwrap();
-                    image.save(&path).unwrap();
-                },
+            let traced = match args.save_every {
+                Some(every) => {
+                    anyhow::ensure!(every > 0, "Saving every 0 frames");
+                    let writer = ImageWriter::new()?;
+                    let mut traced = 0;
+                    for output in tracer.frames(frames, interrupted) {
+                        let output = output?;
+                        traced += 1;
+                        if (resumed + traced).is_multiple_of(every) {
+                            let path = sequence_frame_path(&path, resumed + traced);
+                            info!(
+                                "Saving partial image of {} frames to {}",
+                                resumed + traced,
+                                path.display()
+                            );
+                            writer.write(path, output)?;
+                        }
+                    }
+                    writer.finish()?;
+                    traced
+                }
+                None => tracer.render(
+                    frames,
+                    samples_per_frame,
+                    &mut TerminalProgress::default(),
+                    interrupted,
+                )?,
+            };
+            if traced < frames {
+                warn!(
+                    "Interrupted after {} of {} frames, saving the partial image",
+                    resumed + traced,
+                    args.frames
+                );
+            }
+            if let Some(checkpoint) = checkpoint {
+                match Checkpoint::capture(&mut tracer, &config, resumed + traced)? {
+                    Some(captured) => captured.write(checkpoint)?,
+                    None => warn!("No frame has been traced, not writing the checkpoint"),
+                }
+            }
+
+            let output = tracer
+                .snapshot()?
+                .ok_or_else(|| anyhow::anyhow!("No frame has been traced"))?;
+            info!(
+                "Saving headless output: {}x{} to {}",
+                output.width,
+                output.height,
+                path.display()
+            );
+            let image: ImageBuffer<Rgb<u8>, _> =
+                ImageBuffer::from_raw(output.width, output.height, output.rgb888).unwrap();
+            image.save(&path)?;
+
+            if let Some(dump_path) = &args.dump_float {
+                let dump = FloatDump::capture(&mut tracer, &config)?
+                    .ok_or_else(|| anyhow::anyhow!("No frame has been traced"))?;
+                dump.write(std::path::Path::new(dump_path))?;
+            }
+            Ok(())
+        })?;
+    } else if let Some(address) = args.stream {
+        unsafe {
+            let mut tracer = stream_tracer(
+                config.clone(),
+                asset_manager,
+                viewport,
+                get_build_info().clone(),
+                address,
+                args.stream_fps,
+            )?;
+            info!("Streaming until interrupted");
+            loop {
+                tracer.trace(None)?;
+                if let Some(remote) = &remote {
+                    remote.poll(&mut tracer, &config);
+                }
+            }
+        }
+    } else if let Some(name) = args.share {
+        #[cfg(not(unix))]
+        anyhow::bail!("Sharing frames as {} needs POSIX shared memory", name);
+        #[cfg(unix)]
+        unsafe {
+            let mut tracer = share_tracer(
+                config.clone(),
+                asset_manager,
+                viewport,
+                get_build_info().clone(),
+                &name,
             )?;
-            tracer.trace(None)?;
+            // The shared memory object is only unlinked if the tracer is
+            // dropped, the default Ctrl-C would leak it
+            install_interrupt_handler();
+            info!("Sharing frames as {} until interrupted", name);
+            while !interrupted() {
+                tracer.trace(None)?;
+                if let Some(remote) = &remote {
+                    remote.poll(&mut tracer, &config);
+                }
+            }
+            info!("Interrupted, removing the shared frame {}", name);
         }
     } else {
         let event_loop = EventLoop::new()?;
         event_loop.set_control_flow(ControlFlow::Wait);
-        let mut app = TracerApp::new(config, asset_manager, viewport, get_build_info().clone());
-        event_loop.run_app(&mut app)?;
+        let mut app = TracerApp::new(
+            config,
+            asset_manager,
+            viewport_or(settings.window_size.unwrap_or(DEFAULT_VIEWPORT)),
+            get_build_info().clone(),
+            remote,
+            args.frame_ring,
+            args.max_fps,
+            settings,
+            args.demo,
+        );
+        catch_panic(|| Ok(event_loop.run_app(&mut app)?))?;
     }
 
     Ok(())
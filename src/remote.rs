@@ -0,0 +1,206 @@
+use crate::common::http::{respond, HttpRequest, HttpServer};
+use crate::config::{TracerConfig, TracerConfigInner};
+use crate::front::Front;
+use crate::tracer::Tracer;
+use anyhow::Context;
+use log::{debug, warn};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// How long a client waits for the render loop to pick its request up
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+enum RemoteRequest {
+    GetConfig,
+    // Whole config as JSON
+    SetConfig(Vec<u8>),
+    // JSON object of dot-separated keys to values, see `TracerConfig::set_value`
+    PatchConfig(Vec<u8>),
+    GetProfile,
+    Snapshot,
+}
+
+struct RemoteResponse {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl RemoteResponse {
+    fn json(value: &impl serde::Serialize) -> anyhow::Result<Self> {
+        Ok(Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::to_vec_pretty(value)?,
+        })
+    }
+
+    fn error(status: &'static str, error: anyhow::Error) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(&serde_json::json!({ "error": format!("{:#}", error) }))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+struct RemoteCall {
+    request: RemoteRequest,
+    reply: Sender<RemoteResponse>,
+}
+
+/// Embedded HTTP server to drive the tracer from scripts. Endpoints:
+///
+/// - `GET /config`: current config as JSON
+/// - `PUT /config`: replace the config with the JSON body
+/// - `PATCH /config`: set individual values, e.g. `{"camera.fov": 1.2}`
+/// - `GET /profile`: FPS and GPU time of every pass
+/// - `GET /snapshot`: last traced frame as PNG
+///
+/// The config and the tracer live on the render thread, so the requests
+/// are queued and answered from there by `poll`.
+pub struct RemoteServer {
+    _server: HttpServer,
+    calls: Receiver<RemoteCall>,
+}
+
+impl RemoteServer {
+    pub fn bind(address: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let (sender, calls) = channel();
+        let sender = Mutex::new(sender);
+        let server = HttpServer::bind(address, "remote", move |stream, request| {
+            let sender = sender.lock().unwrap().clone();
+            Self::serve(stream, request, sender)
+        })?;
+        if !server.address().ip().is_loopback() {
+            warn!(
+                "The remote control API on {} has no authentication, anyone reaching the address can change the config",
+                server.address()
+            );
+        }
+
+        Ok(Self {
+            _server: server,
+            calls,
+        })
+    }
+
+    fn serve(
+        stream: &mut TcpStream,
+        request: HttpRequest,
+        sender: Sender<RemoteCall>,
+    ) -> anyhow::Result<()> {
+        let request = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/config") => RemoteRequest::GetConfig,
+            ("PUT", "/config") => RemoteRequest::SetConfig(request.body),
+            ("PATCH", "/config") => RemoteRequest::PatchConfig(request.body),
+            ("GET", "/profile") => RemoteRequest::GetProfile,
+            ("GET", "/snapshot") => RemoteRequest::Snapshot,
+            (_, "/config" | "/profile" | "/snapshot") => {
+                return respond(stream, "405 Method Not Allowed", "text/plain", b"");
+            }
+            _ => return respond(stream, "404 Not Found", "text/plain", b""),
+        };
+
+        let (reply, response) = channel();
+        sender.send(RemoteCall { request, reply })?;
+        match response.recv_timeout(REPLY_TIMEOUT) {
+            Ok(response) => respond(
+                stream,
+                response.status,
+                response.content_type,
+                &response.body,
+            ),
+            Err(RecvTimeoutError::Timeout) => {
+                respond(stream, "503 Service Unavailable", "text/plain", b"")
+            }
+            Err(RecvTimeoutError::Disconnected) => Ok(()),
+        }
+    }
+
+    /// Answers the requests received since the last call.
+    /// Must be called between frames.
+    pub unsafe fn poll<F: Front>(&self, tracer: &mut Tracer<F>, config: &TracerConfig) {
+        while let Ok(call) = self.calls.try_recv() {
+            debug!("Handling remote request {:?}", call.request);
+            let response = Self::handle(call.request, tracer, config);
+            // The client may have timed out already
+            let _ = call.reply.send(response);
+        }
+    }
+
+    unsafe fn handle<F: Front>(
+        request: RemoteRequest,
+        tracer: &mut Tracer<F>,
+        config: &TracerConfig,
+    ) -> RemoteResponse {
+        let result = match request {
+            RemoteRequest::GetConfig => RemoteResponse::json(config),
+            RemoteRequest::SetConfig(body) => {
+                match serde_json::from_slice::<TracerConfigInner>(&body)
+                    .context("Invalid config")
+                    .and_then(|inner| inner.validate().map(|_| inner))
+                {
+                    Ok(inner) => {
+                        config.replace(inner);
+                        RemoteResponse::json(config)
+                    }
+                    Err(e) => return RemoteResponse::error("400 Bad Request", e),
+                }
+            }
+            RemoteRequest::PatchConfig(body) => {
+                let values =
+                    serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&body)
+                        .context("Expected a JSON object of config keys to values")
+                        .and_then(|values| {
+                            values
+                                .into_iter()
+                                .try_for_each(|(key, value)| config.set_value(&key, value))
+                        });
+                if let Err(e) = values {
+                    return RemoteResponse::error("400 Bad Request", e);
+                }
+                RemoteResponse::json(config)
+            }
+            RemoteRequest::GetProfile => {
+                let profile = tracer.get_profile();
+                RemoteResponse::json(&serde_json::json!({
+                    "fps": profile.fps.fps(),
+                    "render_time": profile.render_time,
+                    "adaptive_samples": profile.adaptive_samples,
+                    "gpu_times": profile
+                        .gpu_times
+                        .iter()
+                        .map(|(name, ms)| (name.to_string(), serde_json::json!(ms)))
+                        .collect::<serde_json::Map<_, _>>(),
+                }))
+            }
+            RemoteRequest::Snapshot => match tracer.snapshot() {
+                Ok(Some(output)) => output
+                    .encode_png()
+                    .map(|png| RemoteResponse {
+                        status: "200 OK",
+                        content_type: "image/png",
+                        body: png,
+                    })
+                    .map_err(Into::into),
+                Ok(None) => {
+                    return RemoteResponse::error(
+                        "503 Service Unavailable",
+                        anyhow::anyhow!("No frame has been traced yet"),
+                    )
+                }
+                Err(e) => Err(e.into()),
+            },
+        };
+
+        result.unwrap_or_else(|e| {
+            warn!("Failed to handle remote request: {:#}", e);
+            RemoteResponse::error("500 Internal Server Error", e)
+        })
+    }
+}
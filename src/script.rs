@@ -0,0 +1,230 @@
+use crate::config::{Camera, Material, Object, TracerConfigInner};
+use log::{info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// The script file is checked for changes at most this often
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+// Operations a single call may take, stops endless loops
+const MAX_OPERATIONS: u64 = 10_000_000;
+// Function of the script called every frame
+const CALLBACK: &str = "frame";
+
+/// Part of the config the scripts see, as nested maps and arrays.
+/// Vectors are arrays of three numbers.
+#[derive(Serialize, Deserialize)]
+struct ScriptScene {
+    camera: Camera,
+    objects: Vec<Object>,
+    materials: BTreeMap<String, Material>,
+}
+
+/// Rhai script manipulating the scene every frame, referenced by the
+/// `script` of the config. The script defines
+///
+/// ```rhai
+/// fn frame(time, dt) {
+///     this.objects[0].Sphere.center[1] = 1.0 + sin(time);
+/// }
+/// ```
+///
+/// where `this` holds the camera, the objects and the materials, and
+/// `time` is in seconds since the script was loaded. The changes are
+/// uploaded like the edits in the UI. The top level statements run once
+/// on load. The script is reloaded when the file changes, a script that
+/// fails to compile keeps the previous version running.
+pub struct SceneScript {
+    path: PathBuf,
+    engine: Engine,
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    // Of the loaded version
+    modified: Option<SystemTime>,
+    checked: Instant,
+    started: Instant,
+    last: Option<Instant>,
+    // Set once a call failed, not to flood the log every frame
+    failed: bool,
+}
+
+impl SceneScript {
+    pub fn new(path: &Path) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: "script", "{}", text));
+        engine.on_debug(|text, _, position| info!(target: "script", "{}: {}", position, text));
+
+        let mut script = Self {
+            path: path.to_path_buf(),
+            engine,
+            ast: None,
+            scope: Scope::new(),
+            modified: None,
+            checked: Instant::now(),
+            started: Instant::now(),
+            last: None,
+            failed: false,
+        };
+        script.reload();
+        script
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn reload(&mut self) {
+        self.modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let ast = match self.engine.compile_file(self.path.clone()) {
+            Ok(ast) => ast,
+            Err(e) => {
+                warn!("Failed to compile script {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+
+        let mut scope = Scope::new();
+        if let Err(e) = self.engine.run_ast_with_scope(&mut scope, &ast) {
+            warn!("Failed to run script {}: {}", self.path.display(), e);
+            return;
+        }
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == CALLBACK)
+        {
+            warn!(
+                "Script {} has no {} function, nothing is called",
+                self.path.display(),
+                CALLBACK
+            );
+        }
+        info!("Loaded script {}", self.path.display());
+        self.ast = Some(ast);
+        self.scope = scope;
+        self.failed = false;
+    }
+
+    /// Reloads the script if the file changed and calls it with the time
+    /// elapsed since the previous call
+    pub fn update(&mut self, config: &mut TracerConfigInner) {
+        if self.checked.elapsed() >= RELOAD_INTERVAL {
+            self.checked = Instant::now();
+            let modified = std::fs::metadata(&self.path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if modified != self.modified {
+                self.reload();
+            }
+        }
+
+        let now = Instant::now();
+        let dt = self.last.map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last = Some(now);
+        self.run(config, (now - self.started).as_secs_f32(), dt);
+    }
+
+    /// Calls the script at the time and flags what it changed for upload
+    pub fn run(&mut self, config: &mut TracerConfigInner, time: f32, dt: f32) {
+        let Some(ast) = &self.ast else {
+            return;
+        };
+        if self.failed
+            || !ast
+                .iter_functions()
+                .any(|function| function.name == CALLBACK)
+        {
+            return;
+        }
+
+        let scene = ScriptScene {
+            camera: config.camera.clone(),
+            objects: config.objects.clone(),
+            materials: config.materials.clone(),
+        };
+        let mut this = match rhai::serde::to_dynamic(&scene) {
+            Ok(this) => this,
+            Err(e) => {
+                warn!("Failed to pass the scene to the script: {}", e);
+                self.failed = true;
+                return;
+            }
+        };
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(true)
+            .bind_this_ptr(&mut this);
+        let called = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            ast,
+            CALLBACK,
+            (time as rhai::FLOAT, dt as rhai::FLOAT),
+        );
+        if let Err(e) = called {
+            warn!("Script {} failed: {}", self.path.display(), e);
+            self.failed = true;
+            return;
+        }
+        let scene: ScriptScene = match rhai::serde::from_dynamic(&this) {
+            Ok(scene) => scene,
+            Err(e) => {
+                warn!(
+                    "Script {} left an invalid scene: {}",
+                    self.path.display(),
+                    e
+                );
+                self.failed = true;
+                return;
+            }
+        };
+
+        Self::apply(config, scene);
+    }
+
+    fn apply(config: &mut TracerConfigInner, scene: ScriptScene) {
+        if let Some(object) = scene
+            .objects
+            .iter()
+            .find(|object| !scene.materials.contains_key(object.material()))
+        {
+            warn!("Script references unknown material {}", object.material());
+            return;
+        }
+
+        if scene.camera != config.camera {
+            config.camera = scene.camera;
+            config.updated = true;
+        }
+        // Added or removed objects, other textures and other materials
+        // change the layout of the scene, moved objects are uploaded alone
+        let retextured = || {
+            scene
+                .objects
+                .iter()
+                .zip(&config.objects)
+                .any(|(new, old)| new.textures() != old.textures())
+        };
+        if scene.objects.len() != config.objects.len()
+            || scene.materials != config.materials
+            || retextured()
+        {
+            config.objects = scene.objects;
+            config.materials = scene.materials;
+            config.objects_updated = true;
+            // The config holds the number of lights
+            config.updated = true;
+            return;
+        }
+        for (index, object) in scene.objects.into_iter().enumerate() {
+            if object != config.objects[index] {
+                config.objects[index] = object;
+                config.objects_edited.push(index);
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Context;
+use glam::{IVec2, UVec2};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE: &str = "settings.json";
+const MAX_RECENT_SCENES: usize = 10;
+
+/// Directory for the files kept between runs, e.g. `~/.config/pathrs`
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pathrs"))
+}
+
+/// User preferences kept between runs. Every value is only a fallback:
+/// command line arguments win over the config file, the config file wins
+/// over the settings and the settings win over the built-in defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Inner size and outer position of the window in physical pixels
+    pub window_size: Option<UVec2>,
+    pub window_position: Option<IVec2>,
+    pub vsync: Option<bool>,
+    pub log_level: Option<String>,
+    pub resolution_scale: Option<f32>,
+    // Most recent first
+    pub recent_scenes: Vec<PathBuf>,
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(SETTINGS_FILE))
+    }
+
+    /// Falls back to the defaults if the settings are missing or broken,
+    /// they are not worth failing the start for
+    pub fn load() -> Self {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Self::default();
+        };
+
+        match std::fs::read(&path)
+            .context("Failed to read")
+            .and_then(|data| serde_json::from_slice(&data).context("Failed to parse"))
+        {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("Ignoring settings file {}: {:#}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().context("No config directory on this platform")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write settings file {}", path.display()))?;
+        info!("Saved settings to {}", path.display());
+        Ok(())
+    }
+
+    /// Moves the scene to the top of the recent list
+    pub fn add_recent_scene(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.recent_scenes.retain(|scene| *scene != path);
+        self.recent_scenes.insert(0, path);
+        self.recent_scenes.truncate(MAX_RECENT_SCENES);
+    }
+}
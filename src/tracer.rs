@@ -1,23 +1,103 @@
 use crate::assets::AssetManager;
-use crate::back::{Back, BackQueues};
+use crate::back::{Back, BackQueues, FrameRegion};
 use crate::common::capabilities::{DeviceCapabilities, InstanceCapabilities};
+use crate::common::portability::Portability;
 use crate::common::queue::QueueFamily;
+use crate::common::watchdog::GpuStall;
 use crate::config::TracerConfig;
+use crate::crash;
+use crate::error::{Context, TracerError, TracerResult};
 use crate::fps::FPSResult;
+use crate::front::headless::TracerHeadlessOutput;
 use crate::front::{Front, QueueFamilyIndices};
-use anyhow::Context;
+use crate::lifecycle::Lifecycle;
 use ash::{vk, Device, Entry, Instance};
 use build_info::BuildInfo;
 use glam::UVec2;
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 use log::{debug, info, warn};
+use serde::Serialize;
 use std::ffi::{c_char, CStr, CString};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
 #[derive(Debug, Default, Clone)]
 pub struct TracerProfile {
     pub fps: FPSResult,
+    // GPU time of the tracing dispatch, in milliseconds
     pub render_time: f32,
+    // GPU time of every measured pass of the back- and front-end
+    pub gpu_times: Vec<(&'static str, f32)>,
+    // Last submission reported by the watchdog
+    pub gpu_stall: Option<GpuStall>,
+    // Device memory of released images kept for reuse, in bytes
+    pub pooled_image_bytes: u64,
+    // Device memory of the scene buffers and textures, in bytes
+    pub scene_bytes: Vec<(&'static str, u64)>,
+    // NaN and infinite pixels of the last scanned frame, None unless
+    // `check_invalid_pixels` is set or if the frame had none
+    pub invalid_pixels: Option<InvalidPixels>,
+    // Samples per traced frame picked for `adaptive_samples`, None unless set
+    pub adaptive_samples: Option<u32>,
+    // Assets still loading in the background, see `Tracer::set_async_assets`
+    pub loading: Vec<String>,
+    // Milliseconds from presenting a frame to it being displayed, None
+    // unless the device supports VK_KHR_present_wait
+    pub display_latency: Option<f32>,
+}
+
+/// Pixels of a traced frame holding NaN or infinite values
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPixels {
+    pub nan_count: u32,
+    pub inf_count: u32,
+    // First in reading order, in traced image pixels
+    pub first: glam::UVec2,
+}
+
+/// Identification of the physical device the tracer runs on
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_name: String,
+    // Human readable driver version, e.g. "Mesa 24.0.5"
+    pub driver_info: String,
+    // Raw, the encoding is vendor specific
+    pub driver_version: u32,
+    pub api_version: String,
+}
+
+impl DeviceInfo {
+    pub unsafe fn query(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut driver_properties = vk::PhysicalDeviceDriverProperties::default();
+        let mut properties =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut driver_properties);
+        instance.get_physical_device_properties2(physical_device, &mut properties);
+        let properties = properties.properties;
+
+        let api_version = properties.api_version;
+        Self {
+            name: CStr::from_ptr(properties.device_name.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_name: CStr::from_ptr(driver_properties.driver_name.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            driver_info: CStr::from_ptr(driver_properties.driver_info.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            driver_version: properties.driver_version,
+            api_version: format!(
+                "{}.{}.{}",
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version),
+                vk::api_version_patch(api_version)
+            ),
+        }
+    }
 }
 
 pub struct DebugMessenger {
@@ -39,7 +119,10 @@ pub struct Bundle<'a> {
 
 impl<'a> Bundle<'a> {
     pub(crate) fn allocator(&self) -> std::sync::MutexGuard<'a, Allocator> {
-        self.allocator.lock().unwrap()
+        // A panic while allocating must not prevent the teardown
+        self.allocator
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 }
 
@@ -73,10 +156,19 @@ impl DebugMessenger {
         };
 
         match level {
-            log::Level::Error => warn!("[vulkan] {}: {}", mtype, message.to_string_lossy()),
-            log::Level::Warn => info!("[vulkan] {}: {}", mtype, message.to_string_lossy()),
-            log::Level::Debug => debug!("[vulkan] {}: {}", mtype, message.to_string_lossy()),
-            log::Level::Info => info!("[vulkan] {}: {}", mtype, message.to_string_lossy()),
+            // Own target, so that `--log-filter vulkan=warn` can silence the chatter
+            log::Level::Error => {
+                warn!(target: "vulkan", "[vulkan] {}: {}", mtype, message.to_string_lossy())
+            }
+            log::Level::Warn => {
+                info!(target: "vulkan", "[vulkan] {}: {}", mtype, message.to_string_lossy())
+            }
+            log::Level::Debug => {
+                debug!(target: "vulkan", "[vulkan] {}: {}", mtype, message.to_string_lossy())
+            }
+            log::Level::Info => {
+                info!(target: "vulkan", "[vulkan] {}: {}", mtype, message.to_string_lossy())
+            }
             _ => unreachable!(),
         }
 
@@ -86,7 +178,7 @@ impl DebugMessenger {
     pub(super) unsafe fn get_required_instance_extensions(
         available: &[String],
         capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let mut required = vec![];
         if available.contains(&"VK_EXT_debug_utils".to_string()) {
             const VK_EXT_DEBUG_UTILS: &CStr = c"VK_EXT_debug_utils";
@@ -100,7 +192,7 @@ impl DebugMessenger {
     pub(super) unsafe fn get_required_instance_layers(
         available: &[String],
         capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let mut required = vec![];
         if available.contains(&"VK_LAYER_KHRONOS_validation".to_string()) {
             const VK_LAYER_KHRONOS_VALIDATION: &CStr = c"VK_LAYER_KHRONOS_validation";
@@ -125,7 +217,7 @@ impl DebugMessenger {
         }
     }
 
-    pub unsafe fn new(entry: &Entry, instance: &Instance) -> anyhow::Result<Self> {
+    pub unsafe fn new(entry: &Entry, instance: &Instance) -> TracerResult<Self> {
         let debug_utils_loader = ash::ext::debug_utils::Instance::new(entry, instance);
         let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
             .message_severity(
@@ -158,7 +250,7 @@ impl Drop for DebugMessenger {
         }
     }
 }
-unsafe fn is_subset(available: &[String], required: &Vec<*const c_char>) -> anyhow::Result<bool> {
+unsafe fn is_subset(available: &[String], required: &Vec<*const c_char>) -> TracerResult<bool> {
     for req in required {
         let req_str = CStr::from_ptr(*req).to_string_lossy();
         if !available.contains(&req_str.into_owned()) {
@@ -169,11 +261,18 @@ unsafe fn is_subset(available: &[String], required: &Vec<*const c_char>) -> anyh
     Ok(true)
 }
 
-pub struct Tracer<F: Front> {
-    viewport: UVec2,
+/// Index of the `skip`-th suitable device, in the enumeration order
+fn select_device(suitable: &[bool], skip: usize) -> Option<usize> {
+    suitable
+        .iter()
+        .enumerate()
+        .filter(|(_, suitable)| **suitable)
+        .nth(skip)
+        .map(|(index, _)| index)
+}
 
-    front: Option<F>,
-    back: Option<Back>,
+pub struct Tracer<F: Front> {
+    lifecycle: Lifecycle<F, Back>,
 
     entry: Entry,
     instance: Instance,
@@ -187,7 +286,7 @@ pub struct Tracer<F: Front> {
 }
 
 impl<F: Front> Tracer<F> {
-    unsafe fn get_instance_extensions(entry: &Entry) -> anyhow::Result<Vec<String>> {
+    unsafe fn get_instance_extensions(entry: &Entry) -> TracerResult<Vec<String>> {
         let extension_properties = entry
             .enumerate_instance_extension_properties(None)
             .context("Failed to enumerate instance extension properties")?;
@@ -201,7 +300,7 @@ impl<F: Front> Tracer<F> {
         Ok(extensions)
     }
 
-    unsafe fn get_instance_layers(entry: &Entry) -> anyhow::Result<Vec<String>> {
+    unsafe fn get_instance_layers(entry: &Entry) -> TracerResult<Vec<String>> {
         let layer_properties = entry
             .enumerate_instance_layer_properties()
             .context("Failed to enumerate instance layer properties")?;
@@ -218,7 +317,7 @@ impl<F: Front> Tracer<F> {
     unsafe fn get_device_extensions(
         instance: &Instance,
         device: vk::PhysicalDevice,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> TracerResult<Vec<String>> {
         let extension_properties = instance
             .enumerate_device_extension_properties(device)
             .context("Failed to enumerate device extension properties")?;
@@ -238,20 +337,24 @@ impl<F: Front> Tracer<F> {
         available: &Vec<String>,
         front: &F,
         capabilities: &mut DeviceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let mut required = vec![];
         required.extend(front.get_required_device_extensions(available, capabilities)?);
         required.extend(Back::get_required_device_extensions(
             available,
             capabilities,
         )?);
+        required.extend(Portability::get_required_device_extensions(
+            available,
+            capabilities,
+        )?);
         Ok(required)
     }
 
     unsafe fn get_required_instance_extensions(
         entry: &Entry,
         capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let extensions = Self::get_instance_extensions(entry)?;
         debug!("Available instance extensions: {:?}", extensions);
 
@@ -268,13 +371,17 @@ impl<F: Front> Tracer<F> {
             &extensions,
             capabilities,
         )?);
+        required.extend(Portability::get_required_instance_extensions(
+            &extensions,
+            capabilities,
+        )?);
         Ok(required)
     }
 
     unsafe fn get_required_instance_layers(
         entry: &Entry,
         capabilities: &mut InstanceCapabilities,
-    ) -> anyhow::Result<Vec<*const c_char>> {
+    ) -> TracerResult<Vec<*const c_char>> {
         let layers = Self::get_instance_layers(entry)?;
         debug!("Available instance layers: {:?}", layers);
 
@@ -289,14 +396,15 @@ impl<F: Front> Tracer<F> {
         Ok(required)
     }
 
-    unsafe fn new_entry() -> anyhow::Result<Entry> {
+    unsafe fn new_entry() -> TracerResult<Entry> {
         Ok(Entry::load()?)
     }
 
+    #[tracing::instrument(name = "Tracer::new_instance", skip_all)]
     pub unsafe fn new_instance(
         entry: &Entry,
         bi: BuildInfo,
-    ) -> anyhow::Result<(Instance, InstanceCapabilities)> {
+    ) -> TracerResult<(Instance, InstanceCapabilities)> {
         let app_name = CString::new(bi.crate_info.name)?;
         let app_version = bi.crate_info.version;
         let app_version = vk::make_api_version(
@@ -319,15 +427,20 @@ impl<F: Front> Tracer<F> {
         let mut capabilities = InstanceCapabilities::default();
         let instance_extensions = Self::get_required_instance_extensions(entry, &mut capabilities)?;
         let instance_layers = Self::get_required_instance_layers(entry, &mut capabilities)?;
+        crash::record_instance(
+            crash::names(&instance_extensions),
+            crash::names(&instance_layers),
+        );
         let create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
             .enabled_extension_names(&instance_extensions)
-            .enabled_layer_names(&instance_layers);
+            .enabled_layer_names(&instance_layers)
+            .flags(Portability::instance_create_flags(&capabilities));
 
         Ok((
             entry
                 .create_instance(&create_info, None)
-                .context("Failed to create Vulkan instance")?,
+                .map_err(TracerError::InstanceCreation)?,
             capabilities,
         ))
     }
@@ -365,28 +478,29 @@ impl<F: Front> Tracer<F> {
         entry: &Entry,
         instance: &Instance,
         front: &F,
-    ) -> anyhow::Result<vk::PhysicalDevice> {
+    ) -> TracerResult<vk::PhysicalDevice> {
         let devices = instance
             .enumerate_physical_devices()
             .context("Failed to enumerate physical devices")?;
 
-        for device in devices {
-            let mut capabilities = DeviceCapabilities::default();
-
-            // TODO: Implement some kind of scoring system for compatibility
-            if Self::is_device_suitable(entry, instance, front, &mut capabilities, device) {
-                return Ok(device);
-            }
-        }
-
-        Err(anyhow::anyhow!("No suitable physical device found"))
+        // TODO: Implement some kind of scoring system for compatibility
+        let suitable: Vec<bool> = devices
+            .iter()
+            .map(|device| {
+                let mut capabilities = DeviceCapabilities::default();
+                Self::is_device_suitable(entry, instance, front, &mut capabilities, *device)
+            })
+            .collect();
+        select_device(&suitable, front.device_index())
+            .map(|index| devices[index])
+            .ok_or(TracerError::NoSuitableDevice)
     }
 
     unsafe fn new_allocator(
         instance: Instance,
         device: Device,
         physical_device: vk::PhysicalDevice,
-    ) -> anyhow::Result<Arc<Mutex<Allocator>>> {
+    ) -> TracerResult<Arc<Mutex<Allocator>>> {
         Ok(Arc::new(Mutex::new(Allocator::new(
             &AllocatorCreateDesc {
                 instance,
@@ -399,11 +513,13 @@ impl<F: Front> Tracer<F> {
         )?)))
     }
 
+    #[tracing::instrument(name = "Tracer::new_device", skip_all)]
     pub unsafe fn new_device(
         entry: &Entry,
         instance: &Instance,
         front: &mut F,
-    ) -> anyhow::Result<(
+        low_latency: bool,
+    ) -> TracerResult<(
         DeviceCapabilities,
         Arc<Mutex<Allocator>>,
         BackQueues,
@@ -423,15 +539,17 @@ impl<F: Front> Tracer<F> {
             &mut capabilities,
         )?;
 
-        let back_queues = Back::find_queue_families(entry, instance, physical_device)?;
+        let back_queues = Back::find_queue_families(entry, instance, physical_device, low_latency)?;
         debug!("Using back queue families: {:?}", back_queues);
         let font_queues = front.find_queue_families(entry, instance, physical_device)?;
         debug!("Using front queue families: {:?}", font_queues);
 
-        let mut queue_family_infos = vec![];
-        queue_family_infos.extend(back_queues.as_families());
-        queue_family_infos.extend(font_queues.as_families());
-        QueueFamily::merge_queues(&mut queue_family_infos);
+        let mut requested = back_queues.as_families();
+        requested.extend(font_queues.as_families());
+        let queue_family_infos = QueueFamily::merge_queues(
+            requested,
+            &instance.get_physical_device_queue_family_properties(physical_device),
+        )?;
         debug!("Using queue families: {:?}", queue_family_infos);
 
         let queue_create_infos = queue_family_infos
@@ -471,6 +589,12 @@ impl<F: Front> Tracer<F> {
             },
         )?;
 
+        crash::record_device(
+            DeviceInfo::query(instance, physical_device),
+            instance.get_physical_device_properties(physical_device),
+            crash::names(&extensions),
+        );
+
         let back_queues = back_queues.into_queues(&logical_device)?;
         debug!("Acquired common queues: {:?}", back_queues);
         let front_queues = font_queues.into_queues(&logical_device)?;
@@ -490,13 +614,14 @@ impl<F: Front> Tracer<F> {
         ))
     }
 
+    #[tracing::instrument(name = "Tracer::new", skip_all)]
     pub(crate) unsafe fn new<D: Front>(
         config: TracerConfig,
         asset_manager: AssetManager,
         viewport: UVec2,
         bi: BuildInfo,
-        constructor: impl FnOnce(&Entry, &Instance) -> anyhow::Result<D>,
-    ) -> anyhow::Result<Tracer<D>> {
+        constructor: impl FnOnce(&Entry, &Instance) -> TracerResult<D>,
+    ) -> TracerResult<Tracer<D>> {
         info!("Creating Vulkan instance");
         let entry = Self::new_entry()?;
 
@@ -529,7 +654,7 @@ impl<F: Front> Tracer<F> {
             front_queues,
             physical_device,
             logical_device,
-        ) = Tracer::<D>::new_device(&entry, &instance, &mut front)?;
+        ) = Tracer::<D>::new_device(&entry, &instance, &mut front, config.0.borrow().low_latency)?;
 
         let bundle = Bundle {
             entry: &entry,
@@ -541,24 +666,21 @@ impl<F: Front> Tracer<F> {
             allocator: &allocator,
         };
 
-        info!("Initializing back-end");
-        let back = Back::new(
-            bundle,
-            asset_manager.clone(),
-            viewport,
-            back_queues,
-            config,
-            D::get_required_image_usage_flags(&device_capabilities),
-        )
-        .context("Failed to create tracer pipeline")?;
-
-        info!("Initializing front-end");
-        front.init(bundle, front_queues)?;
+        let lifecycle = Lifecycle::new(bundle, front, front_queues, |bundle| {
+            info!("Initializing back-end");
+            Back::new(
+                bundle,
+                asset_manager.clone(),
+                viewport,
+                back_queues,
+                config,
+                D::get_required_image_usage_flags(&device_capabilities),
+            )
+            .context("Failed to create tracer pipeline")
+        })?;
 
         Ok(Tracer {
-            viewport,
-            front: Some(front),
-            back: Some(back),
+            lifecycle,
             entry,
             instance,
             debug_messenger,
@@ -570,7 +692,8 @@ impl<F: Front> Tracer<F> {
         })
     }
 
-    pub unsafe fn trace(&mut self, w: Option<&winit::window::Window>) -> anyhow::Result<()> {
+    #[tracing::instrument(name = "Tracer::trace", skip_all)]
+    pub unsafe fn trace(&mut self, w: Option<&winit::window::Window>) -> TracerResult<()> {
         let allocator = self.allocator.as_mut().unwrap();
         let bundle = Bundle {
             entry: &self.entry,
@@ -582,23 +705,16 @@ impl<F: Front> Tracer<F> {
             allocator,
         };
 
-        let slot = self
-            .back
-            .as_mut()
-            .unwrap()
-            .present(bundle)
-            .context("Failed to present tracer back-end")?;
+        self.lifecycle.trace(bundle, w)?;
+        crash::record_profile(|| self.get_profile());
 
-        self.front
-            .as_mut()
-            .unwrap()
-            .present(bundle, w, slot)
-            .context("Failed to present tracer front")?;
+        #[cfg(feature = "tracy")]
+        tracing_tracy::client::frame_mark();
 
         Ok(())
     }
 
-    pub unsafe fn resize(&mut self, size: UVec2) -> anyhow::Result<()> {
+    pub unsafe fn flush(&mut self) -> TracerResult<()> {
         let allocator = self.allocator.as_mut().unwrap();
         let bundle = Bundle {
             entry: &self.entry,
@@ -610,25 +726,172 @@ impl<F: Front> Tracer<F> {
             allocator,
         };
 
-        self.viewport = size;
+        self.lifecycle
+            .front_mut()
+            .flush(bundle)
+            .context("Failed to flush tracer front")
+    }
 
-        self.back
-            .as_mut()
-            .unwrap()
-            .resize(bundle, size)
-            .with_context(|| format!("Failed to resize tracer back-end to {:?}", size))?;
+    /// Last traced frame, regardless of the front-end
+    pub unsafe fn snapshot(&mut self) -> TracerResult<Option<TracerHeadlessOutput>> {
+        match self.snapshot_raw()? {
+            Some((dimensions, pixels)) => Ok(Some(TracerHeadlessOutput::from_memory(
+                dimensions,
+                vk::Format::R32G32B32A32_SFLOAT,
+                &pixels,
+            )?)),
+            None => Ok(None),
+        }
+    }
 
-        self.front
-            .as_mut()
-            .unwrap()
-            .resize(bundle, size)
-            .with_context(|| format!("Failed to resize tracer front to {:?}", size))?;
+    /// Last traced frame as linear R32G32B32A32_SFLOAT pixels, converting
+    /// them is left to the caller
+    pub unsafe fn snapshot_raw(&mut self) -> TracerResult<Option<(UVec2, Vec<u8>)>> {
+        let allocator = self.allocator.as_mut().unwrap();
+        let bundle = Bundle {
+            entry: &self.entry,
+            instance: &self.instance,
+            device: &self.logical_device,
+            physical_device: self.physical_device,
+            device_capabilities: &self.device_capabilities,
+            instance_capabilities: &self.instance_capabilities,
+            allocator,
+        };
 
-        Ok(())
+        self.lifecycle
+            .back_mut()
+            .snapshot(bundle)
+            .context("Failed to take tracer snapshot")
+    }
+
+    /// Dispatches accumulated into the last traced frame
+    pub fn frame_index(&self) -> u64 {
+        self.lifecycle.back().frame_index()
+    }
+
+    /// Size the next frame is traced at, before any resize
+    pub fn traced_size(&self) -> UVec2 {
+        self.lifecycle.back().traced_size()
+    }
+
+    /// Accumulated samples of the last traced frame as RGBA32F pixels,
+    /// before the post passes, see `restore_accumulation`
+    pub unsafe fn accumulation_raw(&mut self) -> TracerResult<Option<(UVec2, Vec<u8>)>> {
+        let allocator = self.allocator.as_mut().unwrap();
+        let bundle = Bundle {
+            entry: &self.entry,
+            instance: &self.instance,
+            device: &self.logical_device,
+            physical_device: self.physical_device,
+            device_capabilities: &self.device_capabilities,
+            instance_capabilities: &self.instance_capabilities,
+            allocator,
+        };
+
+        self.lifecycle
+            .back_mut()
+            .accumulation(bundle)
+            .context("Failed to read accumulation")
+    }
+
+    /// Continues from an accumulation taken by `accumulation_raw` after
+    /// `frame_index` dispatches, with the next traced frame
+    pub fn restore_accumulation(
+        &mut self,
+        size: UVec2,
+        pixels: Vec<u8>,
+        frame_index: u64,
+    ) -> TracerResult<()> {
+        self.lifecycle
+            .back_mut()
+            .restore_accumulation(size, pixels, frame_index)
+    }
+
+    #[tracing::instrument(name = "Tracer::resize", skip_all)]
+    pub unsafe fn resize(&mut self, size: UVec2) -> TracerResult<()> {
+        let allocator = self.allocator.as_mut().unwrap();
+        let bundle = Bundle {
+            entry: &self.entry,
+            instance: &self.instance,
+            device: &self.logical_device,
+            physical_device: self.physical_device,
+            device_capabilities: &self.device_capabilities,
+            instance_capabilities: &self.instance_capabilities,
+            allocator,
+        };
+
+        self.lifecycle.resize(bundle, size)
+    }
+
+    /// Resizes the front-end alone, the back-end keeps tracing at its size
+    #[tracing::instrument(name = "Tracer::resize_front", skip_all)]
+    pub unsafe fn resize_front(&mut self, size: UVec2) -> TracerResult<()> {
+        let allocator = self.allocator.as_mut().unwrap();
+        let bundle = Bundle {
+            entry: &self.entry,
+            instance: &self.instance,
+            device: &self.logical_device,
+            physical_device: self.physical_device,
+            device_capabilities: &self.device_capabilities,
+            instance_capabilities: &self.instance_capabilities,
+            allocator,
+        };
+
+        self.lifecycle.resize_front(bundle, size)
+    }
+
+    /// The back-end is rebuilt by `trace` once the size settles. Until then
+    /// the last traced image is scaled into the new viewport.
+    pub fn resize_back_deferred(&mut self, size: UVec2) {
+        self.lifecycle.resize_back_deferred(size);
+    }
+
+    /// Traces the scene while its assets are loaded on worker threads,
+    /// adding each one once it is ready. Otherwise a frame waits for them.
+    pub fn set_async_assets(&mut self, async_assets: bool) {
+        self.lifecycle.back_mut().set_async_assets(async_assets);
+    }
+
+    /// Traces only the region of a larger frame, the viewport being the
+    /// size of the region. None traces the whole frame again.
+    pub fn set_region(&mut self, region: Option<FrameRegion>) {
+        self.lifecycle.back_mut().set_region(region);
     }
 
     pub fn get_profile(&self) -> TracerProfile {
-        self.back.as_ref().unwrap().get_profile()
+        let mut profile = self.lifecycle.back().get_profile();
+        profile.gpu_times.extend(self.lifecycle.front().gpu_times());
+        profile.display_latency = self.lifecycle.front().display_latency();
+        profile
+    }
+
+    pub fn record_render_times(&mut self) {
+        self.lifecycle.back_mut().record_render_times();
+    }
+
+    pub fn take_render_times(&mut self) -> Vec<f32> {
+        self.lifecycle.back_mut().take_render_times()
+    }
+
+    pub unsafe fn get_device_info(&self) -> DeviceInfo {
+        DeviceInfo::query(&self.instance, self.physical_device)
+    }
+
+    /// Runs front-end specific work needing the device, e.g. moving the
+    /// windowed front to a new window
+    pub unsafe fn with_front<R>(&mut self, f: impl FnOnce(Bundle, &mut F) -> R) -> R {
+        let allocator = self.allocator.as_mut().unwrap();
+        let bundle = Bundle {
+            entry: &self.entry,
+            instance: &self.instance,
+            device: &self.logical_device,
+            physical_device: self.physical_device,
+            device_capabilities: &self.device_capabilities,
+            instance_capabilities: &self.instance_capabilities,
+            allocator,
+        };
+
+        f(bundle, self.lifecycle.front_mut())
     }
 }
 
@@ -646,21 +909,14 @@ impl<F: Front> Drop for Tracer<F> {
                 allocator,
             };
 
-            if let Some(mut back) = self.back.take() {
-                debug!("Destroying back-end");
-                back.destroy(bundle);
-            }
-
-            if let Some(mut front) = self.front.take() {
-                debug!("Destroying front-end");
-                front.destroy(bundle);
-            }
+            self.lifecycle.destroy(bundle);
 
             debug!("Destroying allocator");
             if let Some(allocator) = self.allocator.take() {
-                let mutex = Arc::try_unwrap(allocator).unwrap();
-                let allocator = mutex.into_inner().unwrap();
-                drop(allocator);
+                match Arc::try_unwrap(allocator) {
+                    Ok(mutex) => drop(mutex.into_inner().unwrap_or_else(PoisonError::into_inner)),
+                    Err(_) => warn!("Allocator is still referenced, leaking it"),
+                }
             }
 
             debug!("Destroying logical device");
@@ -676,3 +932,26 @@ impl<F: Front> Drop for Tracer<F> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_first_suitable_device() {
+        assert_eq!(select_device(&[false, true, true], 0), Some(1));
+        assert_eq!(select_device(&[true], 0), Some(0));
+    }
+
+    #[test]
+    fn skips_suitable_devices() {
+        assert_eq!(select_device(&[true, false, true], 1), Some(2));
+        assert_eq!(select_device(&[true, false, true], 2), None);
+    }
+
+    #[test]
+    fn no_suitable_device() {
+        assert_eq!(select_device(&[], 0), None);
+        assert_eq!(select_device(&[false, false], 0), None);
+    }
+}